@@ -0,0 +1,676 @@
+///LinkPrimitives: The shared timeline of a link session. A timeline maps
+///time to beats with a tempo and the time beat 0 was at (its origin), and
+///is versioned so that peers agree on which change came last: the highest
+///(version, author) pair wins.
+#[allow(non_snake_case)]
+pub mod LinkPrimitives
+{
+    #[derive(Clone, Copy, Debug, PartialEq)]
+    pub struct Timeline
+    {
+        pub bpm: f64,
+        ///Time of beat 0 in microseconds on the local clock.
+        pub origin: i64,
+        pub version: u64,
+        ///Id of the peer that made the change.
+        pub author: u64,
+    }
+
+    impl Timeline
+    {
+        ///Timeline.beat_at(time: i64) -> f64: Beats since the origin at time
+        ///(microseconds).
+        pub fn beat_at(&self, time: i64) -> f64
+        {
+            (time - self.origin) as f64 * self.bpm / 60_000_000.0
+        }
+
+        ///Timeline.rebase(time: i64, bpm: f64, author: u64) -> Timeline: The
+        ///timeline at a new tempo, keeping the beat at time where it is so
+        ///that changing tempo never jumps.
+        pub fn rebase(&self, time: i64, bpm: f64, author: u64) -> Timeline
+        {
+            let beat = self.beat_at(time);
+            Timeline{
+                bpm,
+                origin: time - (beat * 60_000_000.0 / bpm) as i64,
+                version: self.version + 1,
+                author,
+            }
+        }
+
+        ///Timeline.supersedes(other: &Timeline) -> bool
+        pub fn supersedes(&self, other: &Timeline) -> bool
+        {
+            (self.version, self.author) > (other.version, other.author)
+        }
+    }
+
+    ///LinkPrimitives::phase(beat: f64, quantum: f64) -> f64: Position of beat
+    ///within a bar of quantum beats.
+    pub fn phase(beat: f64, quantum: f64) -> f64
+    {
+        if quantum <= 0.0
+        {
+            return 0.0;
+        }
+        let ret = beat % quantum;
+        if ret < 0.0 { ret + quantum } else { ret }
+    }
+}
+
+///LinkSession: Tempo and beat phase shared between instances on the local
+///network, in the spirit of Ableton Link. Peers announce their timeline to
+///a multicast group a few times a second, measure the offset between their
+///clocks with ping/pong round trips and adopt any timeline that supersedes
+///their own. A new peer listens for JOIN_MS before announcing and adopts
+///the first session it hears, so joining never moves an existing session.
+///
+///Several instances may run on one machine: each binds the first free port
+///of a small range and announces to every port of it. Setting the group to
+///a unicast address such as 127.0.0.1 keeps a session on one machine.
+#[allow(non_snake_case)]
+pub mod LinkSession
+{
+    use control::link::LinkPrimitives;
+    use control::link::LinkPrimitives::Timeline;
+    use control::osc::OscPrimitives::OscArgument;
+    use control::osc::OscPrimitives::OscMessage;
+    use control::osc::OscPrimitives::OscPacket;
+    use control::osc::OscSocket::MAX_PACKET;
+    use instrumentation::interface::instrument::Instrument;
+    use sequencing::sequencing::partialsequencer::PartialSequencer::PartialSequencer;
+    use sequencing::timing::timing::Beat::BeatValue;
+    use sequencing::timing::transport::Transport::Transport;
+    use std::collections::HashMap;
+    use std::collections::hash_map::RandomState;
+    use std::hash::BuildHasher;
+    use std::hash::Hasher;
+    use std::io;
+    use std::net::IpAddr;
+    use std::net::Ipv4Addr;
+    use std::net::SocketAddr;
+    use std::net::UdpSocket;
+    use std::sync::Arc;
+    use std::sync::Mutex;
+    use std::sync::atomic::AtomicBool;
+    use std::sync::atomic::Ordering;
+    use std::thread;
+    use std::time::Duration;
+    use std::time::Instant;
+
+    pub const DEFAULT_GROUP: Ipv4Addr = Ipv4Addr::new(239, 255, 77, 77);
+    pub const DEFAULT_PORT: u16 = 20909;
+    ///Ports tried, and announced to, starting at the session port.
+    pub const DEFAULT_PORTS: u16 = 8;
+    pub const DEFAULT_BPM: f64 = 120.0;
+    ///How often the timeline is announced.
+    pub const ANNOUNCE_MS: u64 = 250;
+    ///How long a new peer listens for a session before founding its own.
+    pub const JOIN_MS: u64 = 500;
+    ///How often clock offsets are measured again.
+    pub const PING_MS: u64 = 1000;
+    ///Peers not heard from for this long have left.
+    pub const PEER_TIMEOUT_MS: u64 = 2000;
+    ///Round trips kept per peer; the fastest one gives the offset.
+    const OFFSET_SAMPLES: usize = 8;
+    ///Largest phase difference in ms sync leaves alone.
+    pub const SYNC_TOLERANCE_MS: f64 = 2.0;
+
+    #[derive(Clone, Copy, Debug)]
+    pub struct LinkSettings
+    {
+        pub group: Ipv4Addr,
+        pub port: u16,
+        pub ports: u16,
+        pub bpm: f64,
+    }
+
+    impl LinkSettings
+    {
+        pub fn new() -> LinkSettings
+        {
+            LinkSettings{
+                group: DEFAULT_GROUP,
+                port: DEFAULT_PORT,
+                ports: DEFAULT_PORTS,
+                bpm: DEFAULT_BPM,
+            }
+        }
+
+        ///LinkSettings.group(group: Ipv4Addr, port: u16) -> LinkSettings
+        pub fn group(mut self, group: Ipv4Addr, port: u16) -> LinkSettings
+        {
+            self.group = group;
+            self.port = port;
+            self
+        }
+
+        ///LinkSettings.bpm(bpm: f64) -> LinkSettings: Tempo used if no session
+        ///is found to join.
+        pub fn bpm(mut self, bpm: f64) -> LinkSettings
+        {
+            self.bpm = bpm;
+            self
+        }
+    }
+
+    impl Default for LinkSettings
+    {
+        fn default() -> LinkSettings
+        {
+            LinkSettings::new()
+        }
+    }
+
+    struct Peer
+    {
+        address: SocketAddr,
+        seen: Instant,
+        pinged: Option<Instant>,
+        //(round trip, offset) in microseconds, offset being their clock
+        //minus ours.
+        offsets: Vec<(i64, i64)>,
+        //Their last announced timeline, on their clock.
+        timeline: Option<Timeline>,
+    }
+
+    impl Peer
+    {
+        fn offset(&self) -> Option<i64>
+        {
+            self.offsets.iter().min_by_key(|x| x.0).map(|x| x.1)
+        }
+    }
+
+    struct State
+    {
+        timeline: Timeline,
+        peers: HashMap<u64, Peer>,
+        joining: bool,
+        dirty: bool,
+    }
+
+    impl State
+    {
+        //Adopts the timeline announced by peer if it supersedes ours, or if
+        //we are still joining, or if it is a newer reading of the timeline
+        //from its author.
+        fn consider(&mut self, peer: u64)
+        {
+            let candidate = match self.peers.get(&peer)
+            {
+                Some(x) => match (x.timeline, x.offset())
+                {
+                    (Some(timeline), Some(offset)) => Timeline{ origin: timeline.origin - offset, ..timeline },
+                    _ => return,
+                },
+                None => return,
+            };
+            let refresh = candidate.author == peer && candidate.version == self.timeline.version && candidate.author == self.timeline.author;
+            if self.joining || refresh || candidate.supersedes(&self.timeline)
+            {
+                self.timeline = candidate;
+                self.joining = false;
+            }
+        }
+    }
+
+    pub struct LinkSession
+    {
+        id: u64,
+        epoch: Instant,
+        port: u16,
+        state: Arc<Mutex<State>>,
+        running: Arc<AtomicBool>,
+    }
+
+    fn micros(epoch: Instant) -> i64
+    {
+        let elapsed = epoch.elapsed();
+        elapsed.as_secs() as i64 * 1_000_000 + elapsed.subsec_micros() as i64
+    }
+
+    fn long(message: &OscMessage, index: usize) -> Option<i64>
+    {
+        match message.arguments.get(index)
+        {
+            Some(&OscArgument::Long(x)) => Some(x),
+            _ => None,
+        }
+    }
+
+    fn double(message: &OscMessage, index: usize) -> Option<f64>
+    {
+        match message.arguments.get(index)
+        {
+            Some(&OscArgument::Double(x)) => Some(x),
+            _ => None,
+        }
+    }
+
+    impl LinkSession
+    {
+        ///LinkSession::start(settings: LinkSettings) -> io::Result<LinkSession>:
+        ///Binds a port and starts looking for a session on a thread of its
+        ///own. The thread ends when the session is dropped.
+        pub fn start(settings: LinkSettings) -> io::Result<LinkSession>
+        {
+            let mut socket = None;
+            for port in settings.port..settings.port.saturating_add(settings.ports.max(1))
+            {
+                if let Ok(x) = UdpSocket::bind((Ipv4Addr::new(0, 0, 0, 0), port))
+                {
+                    socket = Some((x, port));
+                    break;
+                }
+            }
+            let (socket, port) = match socket
+            {
+                Some(x) => x,
+                None => return Err(io::Error::new(io::ErrorKind::AddrInUse, "every link port is taken")),
+            };
+            if settings.group.is_multicast()
+            {
+                socket.join_multicast_v4(&settings.group, &Ipv4Addr::new(0, 0, 0, 0))?;
+                socket.set_multicast_loop_v4(true)?;
+            }
+            socket.set_read_timeout(Some(Duration::from_millis(10)))?;
+
+            let mut hasher = RandomState::new().build_hasher();
+            hasher.write_u16(port);
+            let id = hasher.finish();
+            let epoch = Instant::now();
+            let state = Arc::new(Mutex::new(State{
+                timeline: Timeline{
+                    bpm: settings.bpm,
+                    origin: 0,
+                    version: 0,
+                    author: id,
+                },
+                peers: HashMap::new(),
+                joining: true,
+                dirty: false,
+            }));
+            let running = Arc::new(AtomicBool::new(true));
+
+            let worker = Worker{
+                id,
+                epoch,
+                socket,
+                targets: (settings.port..settings.port.saturating_add(settings.ports.max(1)))
+                    .map(|x| SocketAddr::new(IpAddr::V4(settings.group), x))
+                    .collect(),
+                state: state.clone(),
+                running: running.clone(),
+            };
+            thread::spawn(move || worker.run());
+
+            Ok(LinkSession{
+                id,
+                epoch,
+                port,
+                state,
+                running,
+            })
+        }
+
+        pub fn id(&self) -> u64
+        {
+            self.id
+        }
+
+        ///LinkSession.port() -> u16: The port this peer listens on.
+        pub fn port(&self) -> u16
+        {
+            self.port
+        }
+
+        ///LinkSession.peers() -> usize: Other peers in the session.
+        pub fn peers(&self) -> usize
+        {
+            self.state.lock().map(|x| x.peers.len()).unwrap_or(0)
+        }
+
+        ///LinkSession.is_joining() -> bool: True while still looking for a
+        ///session to join.
+        pub fn is_joining(&self) -> bool
+        {
+            self.state.lock().map(|x| x.joining).unwrap_or(false)
+        }
+
+        ///LinkSession.timeline() -> Timeline: The session timeline on our
+        ///clock, see time.
+        pub fn timeline(&self) -> Timeline
+        {
+            self.state.lock().unwrap().timeline
+        }
+
+        ///LinkSession.time() -> i64: Microseconds on the clock timelines are
+        ///measured with.
+        pub fn time(&self) -> i64
+        {
+            micros(self.epoch)
+        }
+
+        ///LinkSession.tempo() -> f64
+        pub fn tempo(&self) -> f64
+        {
+            self.timeline().bpm
+        }
+
+        ///LinkSession.set_tempo(bpm: f64): Changes the tempo of the whole
+        ///session from now on.
+        pub fn set_tempo(&self, bpm: f64)
+        {
+            if bpm <= 0.0
+            {
+                return;
+            }
+            let now = self.time();
+            let mut state = self.state.lock().unwrap();
+            state.timeline = state.timeline.rebase(now, bpm, self.id);
+            state.joining = false;
+            state.dirty = true;
+        }
+
+        ///LinkSession.beat() -> f64: The session beat right now.
+        pub fn beat(&self) -> f64
+        {
+            self.timeline().beat_at(self.time())
+        }
+
+        ///LinkSession.phase(quantum: f64) -> f64: Where in a bar of quantum
+        ///beats the session is right now.
+        pub fn phase(&self, quantum: f64) -> f64
+        {
+            LinkPrimitives::phase(self.beat(), quantum)
+        }
+
+        ///LinkSession.beat_value() -> BeatValue: The session tempo.
+        pub fn beat_value(&self) -> BeatValue
+        {
+            BeatValue::from_bpm(self.tempo() as f32)
+        }
+
+        ///LinkSession.apply<T>(sequencer: &mut PartialSequencer<T>) -> bool:
+        ///Moves the sequencer to the session tempo if it differs by more
+        ///than 0.01 bpm.
+        pub fn apply<T: Instrument::PlayableInstrument>(&self, sequencer: &mut PartialSequencer<T>) -> bool
+        {
+            let bpm = self.tempo() as f32;
+            if (bpm - sequencer.local_beat_builder.bpm()).abs() > 0.01
+            {
+                sequencer.set_tempo(bpm);
+                return true;
+            }
+            false
+        }
+
+        ///LinkSession.sync(transport: &mut Transport, quantum: f64) -> bool:
+        ///Nudges transport so that its phase within a bar of quantum beats
+        ///matches the session's, keeping it in the bar it is in (or the next
+        ///one, near the start). True if transport was moved.
+        pub fn sync(&self, transport: &mut Transport, quantum: f64) -> bool
+        {
+            let beat_ms = 60_000.0 / self.tempo();
+            let position = transport.position() as f64;
+            let local = LinkPrimitives::phase(position / beat_ms, quantum);
+            let mut difference = self.phase(quantum) - local;
+            if difference > quantum / 2.0
+            {
+                difference -= quantum;
+            }
+            else if difference < -quantum / 2.0
+            {
+                difference += quantum;
+            }
+            if (difference * beat_ms).abs() <= SYNC_TOLERANCE_MS
+            {
+                return false;
+            }
+            let mut target = position + difference * beat_ms;
+            if target < 0.0
+            {
+                target += quantum * beat_ms;
+            }
+            transport.locate(target as u64);
+            true
+        }
+    }
+
+    impl Drop for LinkSession
+    {
+        fn drop(&mut self)
+        {
+            self.running.store(false, Ordering::SeqCst);
+        }
+    }
+
+    struct Worker
+    {
+        id: u64,
+        epoch: Instant,
+        socket: UdpSocket,
+        targets: Vec<SocketAddr>,
+        state: Arc<Mutex<State>>,
+        running: Arc<AtomicBool>,
+    }
+
+    impl Worker
+    {
+        fn send(&self, message: OscMessage, to: &SocketAddr)
+        {
+            self.socket.send_to(&message.to_bytes(), to).ok();
+        }
+
+        fn announce(&self, timeline: &Timeline)
+        {
+            let message = OscMessage::new("/link/state")
+                .arg(OscArgument::Long(self.id as i64))
+                .arg(OscArgument::Long(timeline.version as i64))
+                .arg(OscArgument::Long(timeline.author as i64))
+                .arg(OscArgument::Double(timeline.bpm))
+                .arg(OscArgument::Long(timeline.origin));
+            for i in self.targets.iter()
+            {
+                self.send(message.clone(), i);
+            }
+        }
+
+        fn ping(&self, peer: u64, to: &SocketAddr)
+        {
+            self.send(OscMessage::new("/link/ping")
+                .arg(OscArgument::Long(self.id as i64))
+                .arg(OscArgument::Long(peer as i64))
+                .arg(OscArgument::Long(micros(self.epoch))), to);
+        }
+
+        fn handle(&self, message: &OscMessage, from: SocketAddr)
+        {
+            let sender = match long(message, 0)
+            {
+                Some(x) if x as u64 != self.id => x as u64,
+                _ => return,
+            };
+            let now = micros(self.epoch);
+            let mut state = self.state.lock().unwrap();
+            let fresh = !state.peers.contains_key(&sender);
+            {
+                let peer = state.peers.entry(sender).or_insert_with(|| Peer{
+                    address: from,
+                    seen: Instant::now(),
+                    pinged: None,
+                    offsets: Vec::new(),
+                    timeline: None,
+                });
+                peer.address = from;
+                peer.seen = Instant::now();
+            }
+            if fresh
+            {
+                self.ping(sender, &from);
+                state.peers.get_mut(&sender).unwrap().pinged = Some(Instant::now());
+            }
+
+            match message.address.as_str()
+            {
+                "/link/state" =>
+                {
+                    if let (Some(version), Some(author), Some(bpm), Some(origin)) = (long(message, 1), long(message, 2), double(message, 3), long(message, 4))
+                    {
+                        state.peers.get_mut(&sender).unwrap().timeline = Some(Timeline{
+                            bpm,
+                            origin,
+                            version: version as u64,
+                            author: author as u64,
+                        });
+                        state.consider(sender);
+                    }
+                },
+                "/link/ping" =>
+                {
+                    if let (Some(target), Some(sent)) = (long(message, 1), long(message, 2))
+                    {
+                        if target as u64 == self.id
+                        {
+                            self.send(OscMessage::new("/link/pong")
+                                .arg(OscArgument::Long(self.id as i64))
+                                .arg(OscArgument::Long(sent))
+                                .arg(OscArgument::Long(now)), &from);
+                        }
+                    }
+                },
+                "/link/pong" =>
+                {
+                    if let (Some(sent), Some(remote)) = (long(message, 1), long(message, 2))
+                    {
+                        let round_trip = now - sent;
+                        if round_trip >= 0
+                        {
+                            let peer = state.peers.get_mut(&sender).unwrap();
+                            if peer.offsets.len() >= OFFSET_SAMPLES
+                            {
+                                peer.offsets.remove(0);
+                            }
+                            peer.offsets.push((round_trip, remote - (sent + now) / 2));
+                        }
+                        state.consider(sender);
+                    }
+                },
+                _ =>
+                {
+                },
+            }
+        }
+
+        fn run(self)
+        {
+            let started = Instant::now();
+            let mut announced = Instant::now();
+            let mut buffer = vec![0u8; MAX_PACKET];
+
+            while self.running.load(Ordering::SeqCst)
+            {
+                if let Ok((size, from)) = self.socket.recv_from(&mut buffer)
+                {
+                    if let Ok(packet) = OscPacket::from_bytes(&buffer[..size])
+                    {
+                        for message in packet.messages()
+                        {
+                            self.handle(&message, from);
+                        }
+                    }
+                }
+
+                let mut state = self.state.lock().unwrap();
+                if state.joining && started.elapsed() >= Duration::from_millis(JOIN_MS)
+                {
+                    //Nobody to join, so this peer founds the session.
+                    state.joining = false;
+                    state.timeline.origin = micros(self.epoch);
+                    state.dirty = true;
+                }
+
+                state.peers.retain(|_, x| x.seen.elapsed() < Duration::from_millis(PEER_TIMEOUT_MS));
+                let due: Vec<(u64, SocketAddr)> = state.peers.iter()
+                    .filter(|x| x.1.pinged.is_none_or(|t| t.elapsed() >= Duration::from_millis(PING_MS)))
+                    .map(|x| (*x.0, x.1.address))
+                    .collect();
+                for (peer, address) in due
+                {
+                    self.ping(peer, &address);
+                    state.peers.get_mut(&peer).unwrap().pinged = Some(Instant::now());
+                }
+
+                if !state.joining && (state.dirty || announced.elapsed() >= Duration::from_millis(ANNOUNCE_MS))
+                {
+                    state.dirty = false;
+                    announced = Instant::now();
+                    self.announce(&state.timeline);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use control::link::LinkPrimitives;
+    use control::link::LinkPrimitives::Timeline;
+    use control::link::LinkSession::JOIN_MS;
+    use control::link::LinkSession::LinkSession;
+    use control::link::LinkSession::LinkSettings;
+    use std::net::Ipv4Addr;
+    use std::thread;
+    use std::time::Duration;
+    use std::time::Instant;
+
+    //Polls until done returns true, for at most ms.
+    fn wait<F: Fn() -> bool>(ms: u64, done: F) -> bool
+    {
+        let started = Instant::now();
+        while started.elapsed() < Duration::from_millis(ms)
+        {
+            if done()
+            {
+                return true;
+            }
+            thread::sleep(Duration::from_millis(10));
+        }
+        done()
+    }
+
+    #[test]
+    fn rebasing_keeps_the_beat_and_wins()
+    {
+        let timeline = Timeline{ bpm: 120.0, origin: 0, version: 0, author: 1 };
+        assert_eq!(timeline.beat_at(1_000_000), 2.0);
+        let faster = timeline.rebase(1_000_000, 240.0, 2);
+        assert_eq!(faster.beat_at(1_000_000), 2.0);
+        assert_eq!(faster.beat_at(1_250_000), 3.0);
+        assert!(faster.supersedes(&timeline));
+        assert!(!timeline.supersedes(&faster));
+        assert_eq!(LinkPrimitives::phase(5.5, 4.0), 1.5);
+        assert_eq!(LinkPrimitives::phase(-0.5, 4.0), 3.5);
+    }
+
+    #[test]
+    fn second_session_joins_and_follows_tempo()
+    {
+        let settings = LinkSettings::new().group(Ipv4Addr::new(127, 0, 0, 1), 29470);
+        let first = LinkSession::start(settings.bpm(100.0)).unwrap();
+        assert!(wait(JOIN_MS * 4, || !first.is_joining()));
+
+        let second = LinkSession::start(settings.bpm(140.0)).unwrap();
+        assert_ne!(first.port(), second.port());
+        assert!(wait(3000, || !second.is_joining() && first.peers() == 1 && second.peers() == 1));
+        assert_eq!(second.tempo(), 100.0);
+        assert!(wait(3000, || (first.beat() - second.beat()).abs() < 0.05));
+
+        second.set_tempo(130.0);
+        assert!(wait(3000, || first.tempo() == 130.0));
+        assert!(wait(3000, || (first.beat() - second.beat()).abs() < 0.05));
+    }
+}
@@ -0,0 +1,681 @@
+///OscPrimitives: Open Sound Control 1.0 messages and bundles and their
+///wire encoding. Every field is big endian and padded to four bytes.
+#[allow(non_snake_case)]
+pub mod OscPrimitives
+{
+    use std::fmt::Display;
+    use std::fmt::Error;
+    use std::fmt::Formatter;
+    use std::io;
+
+    ///OscError: Reason a packet could not be sent, received or decoded.
+    #[derive(Debug)]
+    pub enum OscError
+    {
+        Io(io::Error),
+        Malformed(String),
+    }
+
+    impl Display for OscError
+    {
+        fn fmt(&self, f: &mut Formatter) -> Result<(), Error>
+        {
+            match *self
+            {
+                OscError::Io(ref e) => write!(f, "{}", e),
+                OscError::Malformed(ref e) => write!(f, "malformed packet: {}", e),
+            }
+        }
+    }
+
+    impl From<io::Error> for OscError
+    {
+        fn from(e: io::Error) -> OscError
+        {
+            OscError::Io(e)
+        }
+    }
+
+    #[derive(Clone, Debug, PartialEq)]
+    pub enum OscArgument
+    {
+        Int(i32),
+        Float(f32),
+        Str(String),
+        Blob(Vec<u8>),
+        Long(i64),
+        Double(f64),
+        True,
+        False,
+        Nil,
+        Impulse,
+    }
+
+    impl OscArgument
+    {
+        ///OscArgument.as_f32() -> Option<f32>: Numeric value of the argument.
+        ///Controllers tend to send everything as floats, so ints, floats and
+        ///booleans are all accepted.
+        pub fn as_f32(&self) -> Option<f32>
+        {
+            match *self
+            {
+                OscArgument::Int(x) => Some(x as f32),
+                OscArgument::Float(x) => Some(x),
+                OscArgument::Long(x) => Some(x as f32),
+                OscArgument::Double(x) => Some(x as f32),
+                OscArgument::True => Some(1.0),
+                OscArgument::False => Some(0.0),
+                _ => None,
+            }
+        }
+
+        fn tag(&self) -> char
+        {
+            match *self
+            {
+                OscArgument::Int(_) => 'i',
+                OscArgument::Float(_) => 'f',
+                OscArgument::Str(_) => 's',
+                OscArgument::Blob(_) => 'b',
+                OscArgument::Long(_) => 'h',
+                OscArgument::Double(_) => 'd',
+                OscArgument::True => 'T',
+                OscArgument::False => 'F',
+                OscArgument::Nil => 'N',
+                OscArgument::Impulse => 'I',
+            }
+        }
+    }
+
+    #[derive(Clone, Debug, PartialEq)]
+    pub struct OscMessage
+    {
+        pub address: String,
+        pub arguments: Vec<OscArgument>,
+    }
+
+    ///OscPacket: A single message or a bundle of packets to be handled at
+    ///time (NTP format, 1 meaning immediately).
+    #[derive(Clone, Debug, PartialEq)]
+    pub enum OscPacket
+    {
+        Message(OscMessage),
+        Bundle(u64, Vec<OscPacket>),
+    }
+
+    fn pad(bytes: &mut Vec<u8>)
+    {
+        while !bytes.len().is_multiple_of(4)
+        {
+            bytes.push(0);
+        }
+    }
+
+    fn push_string(bytes: &mut Vec<u8>, s: &str)
+    {
+        bytes.extend_from_slice(s.as_bytes());
+        bytes.push(0);
+        pad(bytes);
+    }
+
+    impl OscMessage
+    {
+        ///OscMessage::new(address: &str) -> OscMessage: Message without
+        ///arguments.
+        pub fn new(address: &str) -> OscMessage
+        {
+            OscMessage{
+                address: address.to_string(),
+                arguments: Vec::new(),
+            }
+        }
+
+        ///OscMessage.arg(argument: OscArgument) -> OscMessage: Builder style
+        ///argument.
+        pub fn arg(mut self, argument: OscArgument) -> OscMessage
+        {
+            self.arguments.push(argument);
+            self
+        }
+
+        ///OscMessage.int(x: i32) -> OscMessage
+        pub fn int(self, x: i32) -> OscMessage
+        {
+            self.arg(OscArgument::Int(x))
+        }
+
+        ///OscMessage.float(x: f32) -> OscMessage
+        pub fn float(self, x: f32) -> OscMessage
+        {
+            self.arg(OscArgument::Float(x))
+        }
+
+        ///OscMessage.string(x: &str) -> OscMessage
+        pub fn string(self, x: &str) -> OscMessage
+        {
+            self.arg(OscArgument::Str(x.to_string()))
+        }
+
+        ///OscMessage.float_at(index: usize) -> Option<f32>: Numeric value of
+        ///the argument at index, see OscArgument.as_f32.
+        pub fn float_at(&self, index: usize) -> Option<f32>
+        {
+            self.arguments.get(index).and_then(|x| x.as_f32())
+        }
+
+        ///OscMessage.to_bytes() -> Vec<u8>
+        pub fn to_bytes(&self) -> Vec<u8>
+        {
+            let mut ret = Vec::new();
+            push_string(&mut ret, &self.address);
+            let tags: String = Some(',').into_iter().chain(self.arguments.iter().map(|x| x.tag())).collect();
+            push_string(&mut ret, &tags);
+            for i in self.arguments.iter()
+            {
+                match *i
+                {
+                    OscArgument::Int(x) => ret.extend_from_slice(&(x as u32).to_be_bytes()),
+                    OscArgument::Float(x) => ret.extend_from_slice(&x.to_bits().to_be_bytes()),
+                    OscArgument::Long(x) => ret.extend_from_slice(&(x as u64).to_be_bytes()),
+                    OscArgument::Double(x) => ret.extend_from_slice(&x.to_bits().to_be_bytes()),
+                    OscArgument::Str(ref x) => push_string(&mut ret, x),
+                    OscArgument::Blob(ref x) =>
+                    {
+                        ret.extend_from_slice(&(x.len() as u32).to_be_bytes());
+                        ret.extend_from_slice(x);
+                        pad(&mut ret);
+                    },
+                    _ =>
+                    {
+                    },
+                }
+            }
+            ret
+        }
+    }
+
+    impl OscPacket
+    {
+        ///OscPacket.to_bytes() -> Vec<u8>
+        pub fn to_bytes(&self) -> Vec<u8>
+        {
+            match *self
+            {
+                OscPacket::Message(ref message) => message.to_bytes(),
+                OscPacket::Bundle(time, ref packets) =>
+                {
+                    let mut ret = Vec::new();
+                    push_string(&mut ret, "#bundle");
+                    ret.extend_from_slice(&time.to_be_bytes());
+                    for i in packets.iter()
+                    {
+                        let element = i.to_bytes();
+                        ret.extend_from_slice(&(element.len() as u32).to_be_bytes());
+                        ret.extend_from_slice(&element);
+                    }
+                    ret
+                },
+            }
+        }
+
+        ///OscPacket::from_bytes(bytes: &[u8]) -> Result<OscPacket, OscError>
+        pub fn from_bytes(bytes: &[u8]) -> Result<OscPacket, OscError>
+        {
+            let mut reader = Reader{ bytes, position: 0 };
+            reader.packet()
+        }
+
+        ///OscPacket.messages() -> Vec<OscMessage>: Every message of the
+        ///packet with bundles flattened, in order.
+        pub fn messages(self) -> Vec<OscMessage>
+        {
+            match self
+            {
+                OscPacket::Message(message) => vec![message],
+                OscPacket::Bundle(_, packets) => packets.into_iter().flat_map(|x| x.messages()).collect(),
+            }
+        }
+    }
+
+    struct Reader<'a>
+    {
+        bytes: &'a [u8],
+        position: usize,
+    }
+
+    impl<'a> Reader<'a>
+    {
+        fn take(&mut self, count: usize) -> Result<&'a [u8], OscError>
+        {
+            if self.position + count > self.bytes.len()
+            {
+                return Err(OscError::Malformed(format!("unexpected end at byte {}", self.position)));
+            }
+            let ret = &self.bytes[self.position..self.position + count];
+            self.position += count;
+            Ok(ret)
+        }
+
+        fn u32(&mut self) -> Result<u32, OscError>
+        {
+            let x = self.take(4)?;
+            Ok((x[0] as u32) << 24 | (x[1] as u32) << 16 | (x[2] as u32) << 8 | x[3] as u32)
+        }
+
+        fn u64(&mut self) -> Result<u64, OscError>
+        {
+            Ok((self.u32()? as u64) << 32 | self.u32()? as u64)
+        }
+
+        fn string(&mut self) -> Result<String, OscError>
+        {
+            let rest = &self.bytes[self.position.min(self.bytes.len())..];
+            let end = match rest.iter().position(|x| *x == 0)
+            {
+                Some(x) => x,
+                None => return Err(OscError::Malformed(format!("unterminated string at byte {}", self.position))),
+            };
+            let ret = String::from_utf8_lossy(&rest[..end]).into_owned();
+            self.take((end + 4) / 4 * 4)?;
+            Ok(ret)
+        }
+
+        fn packet(&mut self) -> Result<OscPacket, OscError>
+        {
+            if self.bytes[self.position..].starts_with(b"#bundle\0")
+            {
+                self.take(8)?;
+                let time = self.u64()?;
+                let mut packets = Vec::new();
+                while self.position < self.bytes.len()
+                {
+                    let size = self.u32()? as usize;
+                    let mut element = Reader{ bytes: self.take(size)?, position: 0 };
+                    packets.push(element.packet()?);
+                }
+                return Ok(OscPacket::Bundle(time, packets));
+            }
+
+            let address = self.string()?;
+            if !address.starts_with('/')
+            {
+                return Err(OscError::Malformed(format!("bad address {:?}", address)));
+            }
+            let mut message = OscMessage::new(&address);
+            if self.position >= self.bytes.len()
+            {
+                return Ok(OscPacket::Message(message));
+            }
+            let tags = self.string()?;
+            if !tags.starts_with(',')
+            {
+                return Err(OscError::Malformed(format!("bad type tags {:?}", tags)));
+            }
+            for tag in tags.chars().skip(1)
+            {
+                let argument = match tag
+                {
+                    'i' => OscArgument::Int(self.u32()? as i32),
+                    'f' => OscArgument::Float(f32::from_bits(self.u32()?)),
+                    'h' => OscArgument::Long(self.u64()? as i64),
+                    'd' => OscArgument::Double(f64::from_bits(self.u64()?)),
+                    's' | 'S' => OscArgument::Str(self.string()?),
+                    'b' =>
+                    {
+                        let size = self.u32()? as usize;
+                        let blob = self.take(size)?.to_vec();
+                        self.take((4 - size % 4) % 4)?;
+                        OscArgument::Blob(blob)
+                    },
+                    'T' => OscArgument::True,
+                    'F' => OscArgument::False,
+                    'N' => OscArgument::Nil,
+                    'I' => OscArgument::Impulse,
+                    _ => return Err(OscError::Malformed(format!("unsupported type tag {:?}", tag))),
+                };
+                message.arguments.push(argument);
+            }
+            Ok(OscPacket::Message(message))
+        }
+    }
+}
+
+///OscSocket: Sending and receiving OSC packets over UDP.
+#[allow(non_snake_case)]
+pub mod OscSocket
+{
+    use control::osc::OscPrimitives::OscError;
+    use control::osc::OscPrimitives::OscMessage;
+    use control::osc::OscPrimitives::OscPacket;
+    use std::io;
+    use std::net::SocketAddr;
+    use std::net::ToSocketAddrs;
+    use std::net::UdpSocket;
+
+    ///Largest packet we accept.
+    pub const MAX_PACKET: usize = 65507;
+
+    pub struct OscSender
+    {
+        socket: UdpSocket,
+        target: SocketAddr,
+    }
+
+    impl OscSender
+    {
+        ///OscSender::connect<A: ToSocketAddrs>(target: A) -> io::Result<OscSender>:
+        ///Sends from an ephemeral port to target.
+        pub fn connect<A: ToSocketAddrs>(target: A) -> io::Result<OscSender>
+        {
+            let target = match target.to_socket_addrs()?.next()
+            {
+                Some(x) => x,
+                None => return Err(io::Error::new(io::ErrorKind::InvalidInput, "no address to send to")),
+            };
+            let local = if target.is_ipv4() { "0.0.0.0:0" } else { "[::]:0" };
+            Ok(OscSender{
+                socket: UdpSocket::bind(local)?,
+                target,
+            })
+        }
+
+        ///OscSender.send(message: &OscMessage) -> io::Result<()>
+        pub fn send(&self, message: &OscMessage) -> io::Result<()>
+        {
+            self.send_packet(&OscPacket::Message(message.clone()))
+        }
+
+        ///OscSender.send_packet(packet: &OscPacket) -> io::Result<()>
+        pub fn send_packet(&self, packet: &OscPacket) -> io::Result<()>
+        {
+            self.socket.send_to(&packet.to_bytes(), self.target).map(|_| ())
+        }
+
+        pub fn target(&self) -> SocketAddr
+        {
+            self.target
+        }
+    }
+
+    pub struct OscReceiver
+    {
+        socket: UdpSocket,
+        buffer: Vec<u8>,
+    }
+
+    impl OscReceiver
+    {
+        ///OscReceiver::bind<A: ToSocketAddrs>(address: A) -> io::Result<OscReceiver>:
+        ///Listens on address. Port 0 picks a free port, see local_addr.
+        pub fn bind<A: ToSocketAddrs>(address: A) -> io::Result<OscReceiver>
+        {
+            Ok(OscReceiver{
+                socket: UdpSocket::bind(address)?,
+                buffer: vec![0; MAX_PACKET],
+            })
+        }
+
+        pub fn local_addr(&self) -> io::Result<SocketAddr>
+        {
+            self.socket.local_addr()
+        }
+
+        ///OscReceiver.receive() -> Result<(SocketAddr, Vec<OscMessage>), OscError>:
+        ///Blocks until the next packet arrives and returns its messages with
+        ///bundles flattened, along with who sent it.
+        pub fn receive(&mut self) -> Result<(SocketAddr, Vec<OscMessage>), OscError>
+        {
+            let (size, from) = self.socket.recv_from(&mut self.buffer)?;
+            Ok((from, OscPacket::from_bytes(&self.buffer[..size])?.messages()))
+        }
+    }
+}
+
+///OscControl: Remote control of a running sequencer. The server decodes
+///incoming messages into commands on a thread of its own and hands them to
+///the playback loop over a channel, which applies them to whatever they
+///target between blocks:
+///
+///```text
+///    /transport/play, /transport/stop, /transport/continue
+///    /transport/locate <ms>
+///    /tempo <bpm>
+///    /track/<n>/mute <0|1>, /track/<n>/solo <0|1>, /track/<n>/gain <gain>
+///    /pattern/<n>
+///```
+#[allow(non_snake_case)]
+pub mod OscControl
+{
+    use control::osc::OscPrimitives::OscMessage;
+    use control::osc::OscSocket::OscReceiver;
+    use instrumentation::interface::instrument::Instrument;
+    use processing::mixing::mixer::Mixer::Mixer;
+    use sequencing::sequencing::partialsequencer::PartialSequencer::PartialSequencer;
+    use sequencing::timing::transport::Transport::Transport;
+    use std::io;
+    use std::net::SocketAddr;
+    use std::net::ToSocketAddrs;
+    use std::sync::mpsc::Receiver;
+    use std::sync::mpsc::channel;
+    use std::thread;
+
+    #[derive(Clone, Copy, Debug, PartialEq)]
+    pub enum Command
+    {
+        Play,
+        Stop,
+        Continue,
+        Locate(u64),
+        Tempo(f32),
+        Mute(usize, bool),
+        Solo(usize, bool),
+        Gain(usize, f32),
+        TriggerPattern(usize),
+    }
+
+    impl Command
+    {
+        ///Command::from_message(message: &OscMessage) -> Option<Command>: None
+        ///for addresses we don't know or missing arguments. Buttons send 1
+        ///when pressed and 0 when released, so play, stop, continue and
+        ///pattern commands ignore a 0 argument.
+        pub fn from_message(message: &OscMessage) -> Option<Command>
+        {
+            let parts: Vec<&str> = message.address.split('/').skip(1).collect();
+            let value = message.float_at(0);
+            let button = matches!(parts.as_slice(), ["transport", "play"] | ["transport", "stop"] | ["transport", "continue"] | ["pattern", _]);
+            if button && value == Some(0.0)
+            {
+                return None;
+            }
+            match (parts.as_slice(), value)
+            {
+                (&["transport", "play"], _) => Some(Command::Play),
+                (&["transport", "stop"], _) => Some(Command::Stop),
+                (&["transport", "continue"], _) => Some(Command::Continue),
+                (&["transport", "locate"], Some(ms)) => Some(Command::Locate(ms.max(0.0) as u64)),
+                (&["tempo"], Some(bpm)) if bpm > 0.0 => Some(Command::Tempo(bpm)),
+                (&["track", track, field], Some(x)) =>
+                {
+                    let track = match track.parse::<usize>()
+                    {
+                        Ok(x) => x,
+                        Err(_) => return None,
+                    };
+                    match field
+                    {
+                        "mute" => Some(Command::Mute(track, x != 0.0)),
+                        "solo" => Some(Command::Solo(track, x != 0.0)),
+                        "gain" => Some(Command::Gain(track, x.max(0.0))),
+                        _ => None,
+                    }
+                },
+                (&["pattern", pattern], _) => pattern.parse::<usize>().ok().map(Command::TriggerPattern),
+                _ => None,
+            }
+        }
+
+        ///Command.to_message() -> OscMessage: The message that decodes into
+        ///this command.
+        pub fn to_message(&self) -> OscMessage
+        {
+            let flag = |x: bool| if x { 1 } else { 0 };
+            match *self
+            {
+                Command::Play => OscMessage::new("/transport/play"),
+                Command::Stop => OscMessage::new("/transport/stop"),
+                Command::Continue => OscMessage::new("/transport/continue"),
+                Command::Locate(ms) => OscMessage::new("/transport/locate").float(ms as f32),
+                Command::Tempo(bpm) => OscMessage::new("/tempo").float(bpm),
+                Command::Mute(track, x) => OscMessage::new(&format!("/track/{}/mute", track)).int(flag(x)),
+                Command::Solo(track, x) => OscMessage::new(&format!("/track/{}/solo", track)).int(flag(x)),
+                Command::Gain(track, x) => OscMessage::new(&format!("/track/{}/gain", track)).float(x),
+                Command::TriggerPattern(pattern) => OscMessage::new(&format!("/pattern/{}", pattern)),
+            }
+        }
+
+        ///Command.apply_transport(transport: &mut Transport) -> bool: False if
+        ///the command is not a transport command.
+        pub fn apply_transport(&self, transport: &mut Transport) -> bool
+        {
+            match *self
+            {
+                Command::Play => transport.start(),
+                Command::Stop => transport.stop(),
+                Command::Continue => transport.resume(),
+                Command::Locate(ms) => transport.locate(ms),
+                _ => return false,
+            }
+            true
+        }
+
+        ///Command.apply_mixer(mixer: &mut Mixer) -> bool: False if the command
+        ///is not a track command or the track does not exist.
+        pub fn apply_mixer(&self, mixer: &mut Mixer) -> bool
+        {
+            let (track, command) = match *self
+            {
+                Command::Mute(track, _) | Command::Solo(track, _) | Command::Gain(track, _) => (track, *self),
+                _ => return false,
+            };
+            match (mixer.track(track), command)
+            {
+                (Some(strip), Command::Mute(_, x)) => strip.mute = x,
+                (Some(strip), Command::Solo(_, x)) => strip.solo = x,
+                (Some(strip), Command::Gain(_, x)) => strip.gain = x,
+                _ => return false,
+            }
+            true
+        }
+
+        ///Command.apply_sequencer(sequencer: &mut PartialSequencer<T>) -> bool:
+        ///Applies tempo changes and pattern triggers. False for other
+        ///commands or patterns the sequencer does not have.
+        pub fn apply_sequencer<T: Instrument::PlayableInstrument>(&self, sequencer: &mut PartialSequencer<T>) -> bool
+        {
+            match *self
+            {
+                Command::Tempo(bpm) =>
+                {
+                    sequencer.set_tempo(bpm);
+                    true
+                },
+                Command::TriggerPattern(pattern) => sequencer.trigger_pattern(pattern),
+                _ => false,
+            }
+        }
+    }
+
+    pub struct OscServer
+    {
+        receiver: OscReceiver,
+    }
+
+    impl OscServer
+    {
+        ///OscServer::bind<A: ToSocketAddrs>(address: A) -> io::Result<OscServer>
+        pub fn bind<A: ToSocketAddrs>(address: A) -> io::Result<OscServer>
+        {
+            Ok(OscServer{
+                receiver: OscReceiver::bind(address)?,
+            })
+        }
+
+        pub fn local_addr(&self) -> io::Result<SocketAddr>
+        {
+            self.receiver.local_addr()
+        }
+
+        ///OscServer.listen() -> Receiver<Command>: Receives on a thread of its
+        ///own. Malformed packets and unknown addresses are dropped. The
+        ///thread ends when the receiver is dropped and the next packet
+        ///arrives.
+        pub fn listen(mut self) -> Receiver<Command>
+        {
+            let (sender, receiver) = channel();
+            thread::spawn(move || {
+                loop
+                {
+                    let messages = match self.receiver.receive()
+                    {
+                        Ok((_, messages)) => messages,
+                        Err(_) => continue,
+                    };
+                    for command in messages.iter().filter_map(Command::from_message)
+                    {
+                        if sender.send(command).is_err()
+                        {
+                            return;
+                        }
+                    }
+                }
+            });
+            receiver
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use control::osc::OscControl::Command;
+    use control::osc::OscControl::OscServer;
+    use control::osc::OscPrimitives::OscMessage;
+    use control::osc::OscSocket::OscSender;
+    use std::time::Duration;
+
+    #[test]
+    fn buttons_ignore_release()
+    {
+        assert_eq!(Command::from_message(&OscMessage::new("/transport/play").int(0)), None);
+        assert_eq!(Command::from_message(&OscMessage::new("/transport/play").int(1)), Some(Command::Play));
+        assert_eq!(Command::from_message(&OscMessage::new("/pattern/2").float(0.0)), None);
+        assert_eq!(Command::from_message(&OscMessage::new("/transport/locate").float(0.0)), Some(Command::Locate(0)));
+    }
+
+    #[test]
+    fn commands_round_trip_over_udp()
+    {
+        let commands = vec![
+            Command::Play,
+            Command::Stop,
+            Command::Continue,
+            Command::Locate(0),
+            Command::Locate(1500),
+            Command::Tempo(128.0),
+            Command::Mute(1, true),
+            Command::Solo(0, false),
+            Command::Gain(2, 0.5),
+            Command::TriggerPattern(3),
+        ];
+        let server = OscServer::bind("127.0.0.1:0").unwrap();
+        let sender = OscSender::connect(server.local_addr().unwrap()).unwrap();
+        let receiver = server.listen();
+        for command in commands.iter()
+        {
+            sender.send(&command.to_message()).unwrap();
+            assert_eq!(receiver.recv_timeout(Duration::from_secs(2)), Ok(*command));
+        }
+    }
+}
@@ -0,0 +1,825 @@
+///MidiMessage: Channel and system realtime messages and their wire
+///encoding. Channels run from 0 to 15.
+#[allow(non_snake_case)]
+pub mod MidiMessage
+{
+    #[derive(Clone, Copy, Debug, Eq, PartialEq)]
+    pub enum MidiMessage
+    {
+        NoteOff{ channel: u8, key: u8, velocity: u8 },
+        NoteOn{ channel: u8, key: u8, velocity: u8 },
+        PolyPressure{ channel: u8, key: u8, pressure: u8 },
+        ControlChange{ channel: u8, controller: u8, value: u8 },
+        ProgramChange{ channel: u8, program: u8 },
+        ChannelPressure{ channel: u8, pressure: u8 },
+        ///value runs from -8192 to 8191, 0 being centered.
+        PitchBend{ channel: u8, value: i16 },
+        ///Song position in midi beats (sixteenth notes).
+        SongPosition(u16),
+        Clock,
+        Start,
+        Continue,
+        Stop,
+        ActiveSensing,
+        Reset,
+    }
+
+    ///Controller that silences every note of a channel.
+    pub const ALL_NOTES_OFF: u8 = 123;
+    ///Controller that silences a channel immediately, releases included.
+    pub const ALL_SOUND_OFF: u8 = 120;
+
+    impl MidiMessage
+    {
+        ///MidiMessage.to_bytes() -> Vec<u8>: Encodes the message with its
+        ///status byte. Data bytes are masked to 7 bits.
+        pub fn to_bytes(&self) -> Vec<u8>
+        {
+            let status = |kind: u8, channel: u8| kind | (channel & 0x0f);
+            match *self
+            {
+                MidiMessage::NoteOff{ channel, key, velocity } => vec![status(0x80, channel), key & 0x7f, velocity & 0x7f],
+                MidiMessage::NoteOn{ channel, key, velocity } => vec![status(0x90, channel), key & 0x7f, velocity & 0x7f],
+                MidiMessage::PolyPressure{ channel, key, pressure } => vec![status(0xa0, channel), key & 0x7f, pressure & 0x7f],
+                MidiMessage::ControlChange{ channel, controller, value } => vec![status(0xb0, channel), controller & 0x7f, value & 0x7f],
+                MidiMessage::ProgramChange{ channel, program } => vec![status(0xc0, channel), program & 0x7f],
+                MidiMessage::ChannelPressure{ channel, pressure } => vec![status(0xd0, channel), pressure & 0x7f],
+                MidiMessage::PitchBend{ channel, value } =>
+                {
+                    let raw = (value.clamp(-8192, 8191) as i32 + 8192) as u16;
+                    vec![status(0xe0, channel), (raw & 0x7f) as u8, (raw >> 7) as u8]
+                },
+                MidiMessage::SongPosition(position) => vec![0xf2, (position & 0x7f) as u8, ((position >> 7) & 0x7f) as u8],
+                MidiMessage::Clock => vec![0xf8],
+                MidiMessage::Start => vec![0xfa],
+                MidiMessage::Continue => vec![0xfb],
+                MidiMessage::Stop => vec![0xfc],
+                MidiMessage::ActiveSensing => vec![0xfe],
+                MidiMessage::Reset => vec![0xff],
+            }
+        }
+    }
+}
+
+///MidiParser: Turns a raw MIDI byte stream back into messages, handling
+///running status and realtime bytes arriving in the middle of a message.
+///System exclusive data is skipped.
+#[allow(non_snake_case)]
+pub mod MidiParser
+{
+    use instrumentation::external::midi::MidiMessage::MidiMessage;
+
+    #[derive(Clone, Debug)]
+    pub struct MidiParser
+    {
+        status: Option<u8>,
+        data: Vec<u8>,
+        sysex: bool,
+    }
+
+    ///data_length(status: u8) -> usize: Data bytes following a status byte.
+    fn data_length(status: u8) -> usize
+    {
+        match status & 0xf0
+        {
+            0xc0 | 0xd0 => 1,
+            0xf0 => match status
+            {
+                0xf1 | 0xf3 => 1,
+                0xf2 => 2,
+                _ => 0,
+            },
+            _ => 2,
+        }
+    }
+
+    impl MidiParser
+    {
+        pub fn new() -> MidiParser
+        {
+            MidiParser{
+                status: None,
+                data: Vec::new(),
+                sysex: false,
+            }
+        }
+
+        ///MidiParser.push(byte: u8) -> Option<MidiMessage>: Feeds one byte,
+        ///returning a message once one is complete.
+        pub fn push(&mut self, byte: u8) -> Option<MidiMessage>
+        {
+            //Realtime messages can appear anywhere, even inside sysex.
+            if byte >= 0xf8
+            {
+                return match byte
+                {
+                    0xf8 => Some(MidiMessage::Clock),
+                    0xfa => Some(MidiMessage::Start),
+                    0xfb => Some(MidiMessage::Continue),
+                    0xfc => Some(MidiMessage::Stop),
+                    0xfe => Some(MidiMessage::ActiveSensing),
+                    0xff => Some(MidiMessage::Reset),
+                    _ => None,
+                };
+            }
+            if byte & 0x80 != 0
+            {
+                self.data.clear();
+                self.sysex = byte == 0xf0;
+                //System common messages cancel running status.
+                self.status = if byte >= 0xf0 { if data_length(byte) > 0 { Some(byte) } else { None } } else { Some(byte) };
+                return None;
+            }
+            if self.sysex
+            {
+                return None;
+            }
+
+            let status = self.status?;
+            self.data.push(byte);
+            if self.data.len() < data_length(status)
+            {
+                return None;
+            }
+            let data = self.data.clone();
+            self.data.clear();
+            if status >= 0xf0
+            {
+                self.status = None;
+            }
+
+            let channel = status & 0x0f;
+            match status & 0xf0
+            {
+                0x80 => Some(MidiMessage::NoteOff{ channel, key: data[0], velocity: data[1] }),
+                0x90 => Some(MidiMessage::NoteOn{ channel, key: data[0], velocity: data[1] }),
+                0xa0 => Some(MidiMessage::PolyPressure{ channel, key: data[0], pressure: data[1] }),
+                0xb0 => Some(MidiMessage::ControlChange{ channel, controller: data[0], value: data[1] }),
+                0xc0 => Some(MidiMessage::ProgramChange{ channel, program: data[0] }),
+                0xd0 => Some(MidiMessage::ChannelPressure{ channel, pressure: data[0] }),
+                0xe0 => Some(MidiMessage::PitchBend{ channel, value: (((data[1] as i32) << 7 | data[0] as i32) - 8192) as i16 }),
+                _ if status == 0xf2 => Some(MidiMessage::SongPosition((data[1] as u16) << 7 | data[0] as u16)),
+                _ => None,
+            }
+        }
+    }
+
+    impl Default for MidiParser
+    {
+        fn default() -> MidiParser
+        {
+            MidiParser::new()
+        }
+    }
+}
+
+///MidiOutput: Raw MIDI output. On Linux every ALSA raw MIDI port is a
+///character device (/dev/snd/midiCxDy); loading snd-virmidi adds virtual
+///ones that other programs can connect to through the ALSA sequencer.
+#[allow(non_snake_case)]
+pub mod MidiOutput
+{
+    use instrumentation::external::midi::MidiMessage::MidiMessage;
+    use std::fs;
+    use std::fs::File;
+    use std::fs::OpenOptions;
+    use std::io;
+    use std::io::Write;
+
+    ///MidiOutput::ports() -> Vec<String>: Paths of the raw MIDI devices
+    ///present on this machine.
+    pub fn ports() -> Vec<String>
+    {
+        let mut ret: Vec<String> = match fs::read_dir("/dev/snd")
+        {
+            Ok(entries) => entries
+                .filter_map(|x| x.ok())
+                .map(|x| x.file_name().to_string_lossy().into_owned())
+                .filter(|x| x.starts_with("midiC"))
+                .map(|x| format!("/dev/snd/{}", x))
+                .collect(),
+            Err(_) => Vec::new(),
+        };
+        ret.sort();
+        ret
+    }
+
+    ///MidiOut: Writes messages to any byte sink, usually a raw MIDI device.
+    pub struct MidiOut<W: Write>
+    {
+        port: W,
+    }
+
+    impl MidiOut<File>
+    {
+        ///MidiOut::open(path: &str) -> io::Result<MidiOut<File>>: Opens a raw
+        ///MIDI device for writing.
+        pub fn open(path: &str) -> io::Result<MidiOut<File>>
+        {
+            Ok(MidiOut::new(OpenOptions::new().write(true).open(path)?))
+        }
+    }
+
+    impl<W: Write> MidiOut<W>
+    {
+        pub fn new(port: W) -> MidiOut<W>
+        {
+            MidiOut{
+                port,
+            }
+        }
+
+        ///MidiOut.send(message: MidiMessage) -> io::Result<()>: Writes and
+        ///flushes one message.
+        pub fn send(&mut self, message: MidiMessage) -> io::Result<()>
+        {
+            self.port.write_all(&message.to_bytes())?;
+            self.port.flush()
+        }
+
+        pub fn port(&mut self) -> &mut W
+        {
+            &mut self.port
+        }
+    }
+}
+
+///SeqOutput: MIDI output through the ALSA sequencer. The port is listed
+///next to the hardware ones (aconnect -o) and sends to whoever subscribes
+///to it, so no raw device or snd-virmidi is needed.
+#[cfg(feature = "alsa")]
+#[allow(non_snake_case)]
+pub mod SeqOutput
+{
+    use alsa;
+    use alsa::Direction;
+    use alsa::seq::Addr;
+    use alsa::seq::EvCtrl;
+    use alsa::seq::EvNote;
+    use alsa::seq::EvQueueControl;
+    use alsa::seq::Event;
+    use alsa::seq::EventType;
+    use alsa::seq::PortCap;
+    use alsa::seq::PortSubscribe;
+    use alsa::seq::PortType;
+    use alsa::seq::Seq;
+    use instrumentation::external::midi::MidiMessage::MidiMessage;
+    use instrumentation::external::midi::MidiOutput::MidiOut;
+    use instrumentation::external::midi::MidiParser::MidiParser;
+    use std::ffi::CString;
+    use std::io;
+    use std::io::Write;
+
+    fn seq_error(e: alsa::Error) -> io::Error
+    {
+        io::Error::other(e.to_string())
+    }
+
+    ///to_event(message: MidiMessage) -> Event: The sequencer event for a
+    ///message.
+    fn to_event(message: MidiMessage) -> Event<'static>
+    {
+        let note = |channel: u8, key: u8, velocity: u8| EvNote{ channel, note: key, velocity, off_velocity: 0, duration: 0 };
+        let ctrl = |channel: u8, param: u32, value: i32| EvCtrl{ channel, param, value };
+        let queue = EvQueueControl{ queue: 0, value: () };
+        match message
+        {
+            MidiMessage::NoteOff{ channel, key, velocity } => Event::new(EventType::Noteoff, &note(channel, key, velocity)),
+            MidiMessage::NoteOn{ channel, key, velocity } => Event::new(EventType::Noteon, &note(channel, key, velocity)),
+            MidiMessage::PolyPressure{ channel, key, pressure } => Event::new(EventType::Keypress, &note(channel, key, pressure)),
+            MidiMessage::ControlChange{ channel, controller, value } => Event::new(EventType::Controller, &ctrl(channel, controller as u32, value as i32)),
+            MidiMessage::ProgramChange{ channel, program } => Event::new(EventType::Pgmchange, &ctrl(channel, 0, program as i32)),
+            MidiMessage::ChannelPressure{ channel, pressure } => Event::new(EventType::Chanpress, &ctrl(channel, 0, pressure as i32)),
+            MidiMessage::PitchBend{ channel, value } => Event::new(EventType::Pitchbend, &ctrl(channel, 0, value as i32)),
+            MidiMessage::SongPosition(position) => Event::new(EventType::Songpos, &ctrl(0, 0, position as i32)),
+            MidiMessage::Clock => Event::new(EventType::Clock, &queue),
+            MidiMessage::Start => Event::new(EventType::Start, &queue),
+            MidiMessage::Continue => Event::new(EventType::Continue, &queue),
+            MidiMessage::Stop => Event::new(EventType::Stop, &queue),
+            MidiMessage::ActiveSensing => Event::new(EventType::Sensing, &()),
+            MidiMessage::Reset => Event::new(EventType::Reset, &()),
+        }
+    }
+
+    ///SeqOut: A sequencer client with one output port. It also takes raw
+    ///MIDI bytes through Write, so it can stand in for a device file in
+    ///MidiOut and MidiInstrument.
+    pub struct SeqOut
+    {
+        seq: Seq,
+        port: i32,
+        parser: MidiParser,
+    }
+
+    impl SeqOut
+    {
+        ///SeqOut::open(name: &str) -> io::Result<SeqOut>: Registers a client
+        ///called name with an output port of the same name.
+        pub fn open(name: &str) -> io::Result<SeqOut>
+        {
+            let name = CString::new(name).map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+            let seq = Seq::open(None, Some(Direction::Playback), false).map_err(seq_error)?;
+            seq.set_client_name(&name).map_err(seq_error)?;
+            let port = seq.create_simple_port(&name, PortCap::READ | PortCap::SUBS_READ,
+                PortType::MIDI_GENERIC | PortType::APPLICATION).map_err(seq_error)?;
+            Ok(SeqOut{
+                seq,
+                port,
+                parser: MidiParser::new(),
+            })
+        }
+
+        ///SeqOut.address() -> io::Result<Addr>: Client and port other
+        ///programs connect to.
+        pub fn address(&self) -> io::Result<Addr>
+        {
+            Ok(Addr{
+                client: self.seq.client_id().map_err(seq_error)?,
+                port: self.port,
+            })
+        }
+
+        ///SeqOut.connect(destination: Addr) -> io::Result<()>: Subscribes
+        ///destination to the port, as aconnect would.
+        pub fn connect(&self, destination: Addr) -> io::Result<()>
+        {
+            let subscription = PortSubscribe::empty().map_err(seq_error)?;
+            subscription.set_sender(self.address()?);
+            subscription.set_dest(destination);
+            self.seq.subscribe_port(&subscription).map_err(seq_error)
+        }
+
+        ///SeqOut.send(message: MidiMessage) -> io::Result<()>: Delivers one
+        ///message to the subscribers straight away.
+        pub fn send(&mut self, message: MidiMessage) -> io::Result<()>
+        {
+            let mut event = to_event(message);
+            event.set_source(self.port);
+            event.set_subs();
+            event.set_direct();
+            self.seq.event_output_direct(&mut event).map_err(seq_error)?;
+            Ok(())
+        }
+    }
+
+    impl Write for SeqOut
+    {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize>
+        {
+            for &byte in buf.iter()
+            {
+                if let Some(message) = self.parser.push(byte)
+                {
+                    self.send(message)?;
+                }
+            }
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()>
+        {
+            self.seq.drain_output().map_err(seq_error)?;
+            Ok(())
+        }
+    }
+
+    impl MidiOut<SeqOut>
+    {
+        ///MidiOut::open_seq(name: &str) -> io::Result<MidiOut<SeqOut>>:
+        ///Sends through a new sequencer port instead of a device.
+        pub fn open_seq(name: &str) -> io::Result<MidiOut<SeqOut>>
+        {
+            Ok(MidiOut::new(SeqOut::open(name)?))
+        }
+    }
+}
+
+///MidiInput: Raw MIDI input, read from a device such as /dev/snd/midiC1D0
+///or from any other byte source.
+#[allow(non_snake_case)]
+pub mod MidiInput
+{
+    use instrumentation::external::midi::MidiMessage::MidiMessage;
+    use instrumentation::external::midi::MidiParser::MidiParser;
+    use std::fs::File;
+    use std::io;
+    use std::io::Read;
+    use std::sync::mpsc::Receiver;
+    use std::sync::mpsc::channel;
+    use std::thread;
+    use std::time::Instant;
+
+    pub struct MidiIn<R: Read>
+    {
+        port: R,
+        parser: MidiParser,
+    }
+
+    impl MidiIn<File>
+    {
+        ///MidiIn::open(path: &str) -> io::Result<MidiIn<File>>: Opens a raw
+        ///MIDI device for reading.
+        pub fn open(path: &str) -> io::Result<MidiIn<File>>
+        {
+            Ok(MidiIn::new(File::open(path)?))
+        }
+    }
+
+    impl<R: Read> MidiIn<R>
+    {
+        pub fn new(port: R) -> MidiIn<R>
+        {
+            MidiIn{
+                port,
+                parser: MidiParser::new(),
+            }
+        }
+
+        ///MidiIn.read() -> io::Result<Option<MidiMessage>>: Blocks until the
+        ///next message arrives. None once the source is exhausted.
+        pub fn read(&mut self) -> io::Result<Option<MidiMessage>>
+        {
+            let mut byte = [0u8; 1];
+            loop
+            {
+                if self.port.read(&mut byte)? == 0
+                {
+                    return Ok(None);
+                }
+                if let Some(message) = self.parser.push(byte[0])
+                {
+                    return Ok(Some(message));
+                }
+            }
+        }
+    }
+
+    impl<R: Read + Send + 'static> MidiIn<R>
+    {
+        ///MidiIn.listen() -> Receiver<(Instant, MidiMessage)>: Reads on a
+        ///thread of its own, stamping each message with when it arrived.
+        ///The thread ends when the source does or the receiver is dropped.
+        pub fn listen(mut self) -> Receiver<(Instant, MidiMessage)>
+        {
+            let (sender, receiver) = channel();
+            thread::spawn(move || {
+                while let Ok(Some(message)) = self.read()
+                {
+                    if sender.send((Instant::now(), message)).is_err()
+                    {
+                        break;
+                    }
+                }
+            });
+            receiver
+        }
+    }
+}
+
+///Loopback: An in-process MIDI cable. Bytes written to the output end
+///come out of the input end, so MidiOut and MidiIn can be connected to
+///each other without a device, as in tests. Nothing outside the program
+///can see it; SeqOutput makes a port other programs can connect to.
+#[allow(non_snake_case)]
+pub mod Loopback
+{
+    use std::io;
+    use std::io::Read;
+    use std::io::Write;
+    use std::sync::mpsc::Receiver;
+    use std::sync::mpsc::Sender;
+    use std::sync::mpsc::channel;
+
+    pub struct LoopbackOut
+    {
+        sender: Sender<Vec<u8>>,
+    }
+
+    pub struct LoopbackIn
+    {
+        receiver: Receiver<Vec<u8>>,
+        pending: Vec<u8>,
+    }
+
+    ///pair() -> (LoopbackOut, LoopbackIn): A connected output and input.
+    ///Reading blocks until bytes are written and returns end of file once
+    ///every LoopbackOut has been dropped.
+    pub fn pair() -> (LoopbackOut, LoopbackIn)
+    {
+        let (sender, receiver) = channel();
+        (LoopbackOut{ sender }, LoopbackIn{ receiver, pending: Vec::new() })
+    }
+
+    impl Clone for LoopbackOut
+    {
+        fn clone(&self) -> LoopbackOut
+        {
+            LoopbackOut{
+                sender: self.sender.clone(),
+            }
+        }
+    }
+
+    impl Write for LoopbackOut
+    {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize>
+        {
+            match self.sender.send(buf.to_vec())
+            {
+                Ok(()) => Ok(buf.len()),
+                Err(_) => Err(io::Error::new(io::ErrorKind::BrokenPipe, "loopback closed")),
+            }
+        }
+
+        fn flush(&mut self) -> io::Result<()>
+        {
+            Ok(())
+        }
+    }
+
+    impl Read for LoopbackIn
+    {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize>
+        {
+            while self.pending.is_empty()
+            {
+                match self.receiver.recv()
+                {
+                    Ok(bytes) => self.pending = bytes,
+                    Err(_) => return Ok(0),
+                }
+            }
+            let count = buf.len().min(self.pending.len());
+            buf[..count].copy_from_slice(&self.pending[..count]);
+            self.pending.drain(..count);
+            Ok(count)
+        }
+    }
+}
+
+///MidiInstrument: External instrument that turns sequencer events into
+///MIDI messages on one channel. Notes played with play are released by a
+///timer thread after their length, so the port is shared behind a mutex.
+///Every key has a generation that each new event on it bumps, so a timer
+///whose note was retriggered or released meanwhile sends nothing.
+#[allow(non_snake_case)]
+pub mod MidiInstrument
+{
+    use instrumentation::external::midi::MidiMessage::ALL_NOTES_OFF;
+    use instrumentation::external::midi::MidiMessage::MidiMessage;
+    use instrumentation::external::midi::MidiOutput::MidiOut;
+    use instrumentation::interface::instrument::Instrument;
+    use instrumentation::interface::instrument::Instrument::PlayError;
+    use sequencing::tonation::note::NoteCollections::RawNote;
+    use std::fs::File;
+    use std::io;
+    use std::io::Write;
+    use std::sync::Arc;
+    use std::sync::Mutex;
+    use std::thread;
+    use std::time::Duration;
+
+    pub struct MidiInstrument<W: Write + Send + 'static>
+    {
+        output: Arc<Mutex<MidiOut<W>>>,
+        generations: Arc<Mutex<Vec<u64>>>,
+        pub channel: u8,
+    }
+
+    impl MidiInstrument<File>
+    {
+        ///MidiInstrument::open(path: &str, channel: u8) -> io::Result<MidiInstrument<File>>
+        pub fn open(path: &str, channel: u8) -> io::Result<MidiInstrument<File>>
+        {
+            Ok(MidiInstrument::new(MidiOut::open(path)?, channel))
+        }
+    }
+
+    impl<W: Write + Send + 'static> MidiInstrument<W>
+    {
+        pub fn new(output: MidiOut<W>, channel: u8) -> MidiInstrument<W>
+        {
+            MidiInstrument{
+                output: Arc::new(Mutex::new(output)),
+                generations: Arc::new(Mutex::new(vec![0; 128])),
+                channel: channel & 0x0f,
+            }
+        }
+
+        ///MidiInstrument.bump(key: u8) -> u64: Starts a new generation of
+        ///key, making pending note offs for it stale, and returns it.
+        fn bump(&self, key: u8) -> u64
+        {
+            match self.generations.lock()
+            {
+                Ok(mut x) =>
+                {
+                    x[key as usize] += 1;
+                    x[key as usize]
+                },
+                Err(_) => 0,
+            }
+        }
+
+        ///MidiInstrument.output() -> Arc<Mutex<MidiOut<W>>>: The shared port,
+        ///for sending messages the instrument interface has no call for.
+        pub fn output(&self) -> Arc<Mutex<MidiOut<W>>>
+        {
+            self.output.clone()
+        }
+
+        ///MidiInstrument.send(message: MidiMessage) -> Result<(), PlayError>
+        pub fn send(&self, message: MidiMessage) -> Result<(), PlayError>
+        {
+            match self.output.lock()
+            {
+                Ok(mut x) => x.send(message).map_err(|_| PlayError::DeviceUnavailable),
+                Err(_) => Err(PlayError::DeviceUnavailable),
+            }
+        }
+    }
+
+    impl<W: Write + Send + 'static> Instrument::PlayableInstrument for MidiInstrument<W>
+    {
+        ///Sends a note on and schedules its note off note.length ms later,
+        ///unless key sees another event first.
+        fn play(&mut self, note: &RawNote) -> Result<(), PlayError>
+        {
+            let (channel, key) = (self.channel, note.key());
+            if key > 127
+            {
+                return Err(PlayError::OutOfRange(key));
+            }
+            self.send(MidiMessage::NoteOn{ channel, key, velocity: note.velocity().max(1) })?;
+
+            let generation = self.bump(key);
+            let generations = self.generations.clone();
+            let output = self.output.clone();
+            let length = note.length as u64;
+            thread::spawn(move || {
+                thread::sleep(Duration::from_millis(length));
+                //The generation stays locked while sending, so a note on
+                //for key cannot slip in between the check and the note off.
+                if let Ok(x) = generations.lock()
+                {
+                    if x[key as usize] == generation
+                    {
+                        if let Ok(mut x) = output.lock()
+                        {
+                            x.send(MidiMessage::NoteOff{ channel, key, velocity: 0 }).ok();
+                        }
+                    }
+                }
+            });
+            Ok(())
+        }
+
+        fn note_on(&mut self, key: u8, velocity: u8) -> Result<(), PlayError>
+        {
+            if key > 127
+            {
+                return Err(PlayError::OutOfRange(key));
+            }
+            self.bump(key);
+            self.send(MidiMessage::NoteOn{ channel: self.channel, key, velocity: velocity.max(1) })
+        }
+
+        fn note_off(&mut self, key: u8)
+        {
+            if key <= 127
+            {
+                self.bump(key);
+            }
+            self.send(MidiMessage::NoteOff{ channel: self.channel, key, velocity: 0 }).ok();
+        }
+
+        fn all_notes_off(&mut self)
+        {
+            self.send(MidiMessage::ControlChange{ channel: self.channel, controller: ALL_NOTES_OFF, value: 0 }).ok();
+        }
+
+        ///Voices in their release tail were already sent a note off.
+        fn stop(&mut self, key: u8, held: bool)
+        {
+            if held
+            {
+                self.note_off(key);
+            }
+        }
+
+        ///"cc.N" sends controller N (value 0 to 127), "program" a program
+        ///change and "pitch_bend" a bend from -1.0 to 1.0.
+        fn set_parameter(&mut self, name: &str, value: f32) -> bool
+        {
+            let channel = self.channel;
+            let message = match name
+            {
+                "program" => MidiMessage::ProgramChange{ channel, program: value.clamp(0.0, 127.0) as u8 },
+                "pitch_bend" => MidiMessage::PitchBend{ channel, value: (value.clamp(-1.0, 1.0) * 8191.0) as i16 },
+                _ if name.starts_with("cc.") =>
+                {
+                    match name[3..].parse::<u8>()
+                    {
+                        Ok(controller) if controller < 128 => MidiMessage::ControlChange{ channel, controller, value: value.clamp(0.0, 127.0) as u8 },
+                        _ => return false,
+                    }
+                },
+                _ => return false,
+            };
+            self.send(message).is_ok()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use instrumentation::external::midi::MidiInstrument::MidiInstrument;
+    use instrumentation::external::midi::MidiMessage::ALL_NOTES_OFF;
+    use instrumentation::external::midi::MidiMessage::MidiMessage;
+    use instrumentation::external::midi::MidiOutput::MidiOut;
+    use instrumentation::external::midi::MidiParser::MidiParser;
+    use instrumentation::interface::instrument::Instrument::PlayError;
+    use instrumentation::interface::instrument::Instrument::PlayableInstrument;
+    use sequencing::tonation::note::NoteCollections::RawNote;
+    use sequencing::tonation::note::NotePrimitives::Register;
+    use sequencing::tonation::note::NotePrimitives::Semitone;
+    use std::thread;
+    use std::time::Duration;
+
+    fn parse(bytes: &[u8]) -> Vec<MidiMessage>
+    {
+        let mut parser = MidiParser::new();
+        bytes.iter().filter_map(|x| parser.push(*x)).collect()
+    }
+
+    #[test]
+    fn note_on_and_off_round_trip_through_the_parser()
+    {
+        let messages = vec![
+            MidiMessage::NoteOn{ channel: 3, key: 60, velocity: 100 },
+            MidiMessage::NoteOff{ channel: 3, key: 60, velocity: 0 },
+            MidiMessage::NoteOn{ channel: 15, key: 127, velocity: 1 },
+            MidiMessage::NoteOff{ channel: 0, key: 0, velocity: 64 },
+        ];
+        let mut output = MidiOut::new(Vec::new());
+        for message in messages.iter()
+        {
+            output.send(*message).unwrap();
+        }
+        assert_eq!(parse(output.port()), messages);
+    }
+
+    #[test]
+    fn parser_follows_running_status_and_realtime_bytes()
+    {
+        let parsed = parse(&[0x90, 60, 0xf8, 100, 64, 0]);
+        assert_eq!(parsed, vec![
+            MidiMessage::Clock,
+            MidiMessage::NoteOn{ channel: 0, key: 60, velocity: 100 },
+            MidiMessage::NoteOn{ channel: 0, key: 64, velocity: 0 },
+        ]);
+    }
+
+    #[test]
+    fn instrument_events_become_channel_messages()
+    {
+        let mut instrument = MidiInstrument::new(MidiOut::new(Vec::new()), 2);
+        instrument.note_on(60, 0).unwrap();
+        instrument.note_off(60);
+        instrument.all_notes_off();
+        assert!(instrument.set_parameter("cc.7", 100.0));
+        assert!(instrument.set_parameter("pitch_bend", 1.0));
+        assert!(!instrument.set_parameter("cc.200", 1.0));
+        assert_eq!(instrument.note_on(200, 100), Err(PlayError::OutOfRange(200)));
+
+        let output = instrument.output();
+        let mut port = output.lock().unwrap();
+        assert_eq!(parse(port.port()), vec![
+            MidiMessage::NoteOn{ channel: 2, key: 60, velocity: 1 },
+            MidiMessage::NoteOff{ channel: 2, key: 60, velocity: 0 },
+            MidiMessage::ControlChange{ channel: 2, controller: ALL_NOTES_OFF, value: 0 },
+            MidiMessage::ControlChange{ channel: 2, controller: 7, value: 100 },
+            MidiMessage::PitchBend{ channel: 2, value: 8191 },
+        ]);
+    }
+
+    #[test]
+    fn retriggered_notes_are_not_cut_off_by_the_earlier_note_off()
+    {
+        let mut instrument = MidiInstrument::new(MidiOut::new(Vec::new()), 0);
+        instrument.play(&RawNote::new(Semitone::C, Register::C4, 0.0, 100.0, 20)).unwrap();
+        instrument.play(&RawNote::new(Semitone::C, Register::C4, 0.0, 100.0, 10_000)).unwrap();
+        instrument.play(&RawNote::new(Semitone::E, Register::C4, 0.0, 100.0, 20)).unwrap();
+        thread::sleep(Duration::from_millis(300));
+
+        let output = instrument.output();
+        let mut port = output.lock().unwrap();
+        assert_eq!(parse(port.port()), vec![
+            MidiMessage::NoteOn{ channel: 0, key: 60, velocity: 100 },
+            MidiMessage::NoteOn{ channel: 0, key: 60, velocity: 100 },
+            MidiMessage::NoteOn{ channel: 0, key: 64, velocity: 100 },
+            MidiMessage::NoteOff{ channel: 0, key: 64, velocity: 0 },
+        ]);
+    }
+}
@@ -0,0 +1,2 @@
+pub mod midi;
+pub mod osc;
@@ -0,0 +1,121 @@
+///OscInstrument: External instrument that sends notes as OSC messages, for
+///synths and visuals that listen to OSC instead of MIDI. With the default
+///prefix of "/synth" it sends:
+///
+///```text
+///    /synth/note <key> <velocity>    note on, velocity 0 being a note off
+///    /synth/off                      every note off
+///    /synth/param <name> <value>     set_parameter
+///```
+#[allow(non_snake_case)]
+pub mod OscInstrument
+{
+    use control::osc::OscPrimitives::OscMessage;
+    use control::osc::OscSocket::OscSender;
+    use instrumentation::interface::instrument::Instrument;
+    use instrumentation::interface::instrument::Instrument::PlayError;
+    use sequencing::tonation::note::NoteCollections::RawNote;
+    use std::io;
+    use std::net::ToSocketAddrs;
+    use std::sync::Arc;
+    use std::thread;
+    use std::time::Duration;
+
+    pub struct OscInstrument
+    {
+        sender: Arc<OscSender>,
+        prefix: String,
+    }
+
+    impl OscInstrument
+    {
+        ///OscInstrument::connect<A: ToSocketAddrs>(target: A) -> io::Result<OscInstrument>
+        pub fn connect<A: ToSocketAddrs>(target: A) -> io::Result<OscInstrument>
+        {
+            Ok(OscInstrument::new(OscSender::connect(target)?))
+        }
+
+        pub fn new(sender: OscSender) -> OscInstrument
+        {
+            OscInstrument{
+                sender: Arc::new(sender),
+                prefix: "/synth".to_string(),
+            }
+        }
+
+        ///OscInstrument.prefix(prefix: &str) -> OscInstrument: Builder style
+        ///address prefix.
+        pub fn prefix(mut self, prefix: &str) -> OscInstrument
+        {
+            self.prefix = prefix.trim_end_matches('/').to_string();
+            self
+        }
+
+        ///OscInstrument.send(message: &OscMessage) -> Result<(), PlayError>
+        pub fn send(&self, message: &OscMessage) -> Result<(), PlayError>
+        {
+            self.sender.send(message).map_err(|_| PlayError::DeviceUnavailable)
+        }
+
+        fn note(&self, key: u8, velocity: u8) -> OscMessage
+        {
+            OscMessage::new(&format!("{}/note", self.prefix)).int(key as i32).int(velocity as i32)
+        }
+    }
+
+    impl Instrument::PlayableInstrument for OscInstrument
+    {
+        ///Sends a note on and schedules its note off note.length ms later.
+        fn play(&mut self, note: &RawNote) -> Result<(), PlayError>
+        {
+            let key = note.key();
+            if key > 127
+            {
+                return Err(PlayError::OutOfRange(key));
+            }
+            self.send(&self.note(key, note.velocity().max(1)))?;
+
+            let (sender, off) = (self.sender.clone(), self.note(key, 0));
+            let length = note.length as u64;
+            thread::spawn(move || {
+                thread::sleep(Duration::from_millis(length));
+                sender.send(&off).ok();
+            });
+            Ok(())
+        }
+
+        fn note_on(&mut self, key: u8, velocity: u8) -> Result<(), PlayError>
+        {
+            if key > 127
+            {
+                return Err(PlayError::OutOfRange(key));
+            }
+            self.send(&self.note(key, velocity.max(1)))
+        }
+
+        fn note_off(&mut self, key: u8)
+        {
+            self.send(&self.note(key, 0)).ok();
+        }
+
+        fn all_notes_off(&mut self)
+        {
+            self.send(&OscMessage::new(&format!("{}/off", self.prefix))).ok();
+        }
+
+        ///Voices in their release tail were already sent a note off.
+        fn stop(&mut self, key: u8, held: bool)
+        {
+            if held
+            {
+                self.note_off(key);
+            }
+        }
+
+        ///Every parameter is forwarded as it is named.
+        fn set_parameter(&mut self, name: &str, value: f32) -> bool
+        {
+            self.send(&OscMessage::new(&format!("{}/param", self.prefix)).string(name).float(value)).is_ok()
+        }
+    }
+}
@@ -1,57 +1,591 @@
+#[allow(non_snake_case)]
 pub mod Instrument
 {
+    use processing::buffer::buffer::AudioBuffer::StereoBuffer;
     use sequencing::tonation::note::NoteCollections::RawNote;
+    use std::fmt::Display;
+    use std::fmt::Error;
+    use std::fmt::Formatter;
+
+    ///PlayError: Why an instrument could not play a note. OutOfRange and
+    ///MissingSample hold the midi key of the note. DeviceUnavailable is
+    ///reported by external instruments whose port failed.
+    #[derive(Clone, Copy, Debug, Eq, PartialEq)]
+    pub enum PlayError
+    {
+        OutOfRange(u8),
+        MissingSample(u8),
+        VoicesExhausted,
+        DeviceUnavailable,
+    }
+
+    impl Display for PlayError
+    {
+        fn fmt(&self, f: &mut Formatter) -> Result<(), Error>
+        {
+            match *self
+            {
+                PlayError::OutOfRange(key) => write!(f, "key {} is out of the instrument's range", key),
+                PlayError::MissingSample(key) => write!(f, "no sample is mapped to key {}", key),
+                PlayError::VoicesExhausted => write!(f, "every voice is in use"),
+                PlayError::DeviceUnavailable => write!(f, "the output device is unavailable"),
+            }
+        }
+    }
 
     pub trait PlayableInstrument
     {
-        fn play(&mut self, &RawNote);
+        ///PlayableInstrument.play(note: &RawNote) -> Result<(), PlayError>:
+        ///Starts note, or reports why the instrument cannot play it.
+        fn play(&mut self, note: &RawNote) -> Result<(), PlayError>;
+
+        ///PlayableInstrument.note_on(key: u8, velocity: u8) -> Result<(), PlayError>:
+        ///Starts a note on a midi key that sounds until note_off.
+        fn note_on(&mut self, key: u8, velocity: u8) -> Result<(), PlayError>;
+
+        ///PlayableInstrument.note_off(key: u8): Releases notes started on key.
+        fn note_off(&mut self, key: u8);
+
+        ///PlayableInstrument.all_notes_off(): Releases every note.
+        fn all_notes_off(&mut self)
+        {
+            for key in 0..128
+            {
+                self.note_off(key);
+            }
+        }
+
+        ///PlayableInstrument.process(buffer: &mut StereoBuffer): Renders the
+        ///next buffer.len() frames of the instrument's output. Instruments
+        ///that play through their own device leave the buffer alone.
+        fn process(&mut self, _buffer: &mut StereoBuffer)
+        {
+        }
+
+        ///PlayableInstrument.set_parameter(name: &str, value: f32) -> bool:
+        ///Changes a named parameter of the instrument, used by automation.
+        ///Returns false if the instrument has no parameter called name.
+        fn set_parameter(&mut self, _name: &str, _value: f32) -> bool
+        {
+            false
+        }
+
+        ///PlayableInstrument.stop(key: u8, held: bool): Cuts off the oldest
+        ///voice sounding the midi key that is still held, or the oldest one
+        ///in its release tail if held is false. Used when the wrapper
+        ///steals that voice.
+        fn stop(&mut self, _key: u8, _held: bool)
+        {
+        }
+
+        ///PlayableInstrument.set_polyphony(voices: usize): Sizes the
+        ///instrument's own voice pool. InstrumentWrapper passes its limit
+        ///through and does the stealing, so instruments with a pool only
+        ///reuse voices that are idle or releasing.
+        fn set_polyphony(&mut self, _voices: usize)
+        {
+        }
+
+        ///PlayableInstrument.glide(key: u8, note: &RawNote, time: f32) -> bool:
+        ///Moves the held voice sounding key to note over time milliseconds
+        ///without retriggering it, taking on note's length. Returns false
+        ///if the instrument cannot glide, in which case note is played
+        ///normally.
+        fn glide(&mut self, _key: u8, _note: &RawNote, _time: f32) -> bool
+        {
+            false
+        }
+    }
+
+    ///Boxed instruments play like the instrument inside, so sequencers of
+    ///instruments only known at runtime (as in a loaded project) can share
+    ///one type.
+    impl<T: PlayableInstrument + ?Sized> PlayableInstrument for Box<T>
+    {
+        fn play(&mut self, note: &RawNote) -> Result<(), PlayError>
+        {
+            (**self).play(note)
+        }
+
+        fn note_on(&mut self, key: u8, velocity: u8) -> Result<(), PlayError>
+        {
+            (**self).note_on(key, velocity)
+        }
+
+        fn note_off(&mut self, key: u8)
+        {
+            (**self).note_off(key)
+        }
+
+        fn all_notes_off(&mut self)
+        {
+            (**self).all_notes_off()
+        }
+
+        fn process(&mut self, buffer: &mut StereoBuffer)
+        {
+            (**self).process(buffer)
+        }
+
+        fn set_parameter(&mut self, name: &str, value: f32) -> bool
+        {
+            (**self).set_parameter(name, value)
+        }
+
+        fn stop(&mut self, key: u8, held: bool)
+        {
+            (**self).stop(key, held)
+        }
+
+        fn set_polyphony(&mut self, voices: usize)
+        {
+            (**self).set_polyphony(voices)
+        }
+
+        fn glide(&mut self, key: u8, note: &RawNote, time: f32) -> bool
+        {
+            (**self).glide(key, note, time)
+        }
     }
 }
 
+///Voices: Bookkeeping of the notes an InstrumentWrapper has sounding, used
+///to enforce its polyphony limit and mono modes. Times are in ms.
+#[allow(non_snake_case)]
+pub mod Voices
+{
+    ///StealMode: Which voice makes way for a new note once every voice is
+    ///in use. Voices in their release tail are always taken first.
+    ///SameNote retriggers a voice already sounding the new note's key and
+    ///otherwise falls back to Oldest. Never refuses the new note instead.
+    #[derive(Clone, Copy, Debug, Eq, PartialEq)]
+    pub enum StealMode
+    {
+        Oldest,
+        Quietest,
+        SameNote,
+        Never,
+    }
 
+    ///VoiceMode: Mono cuts the previous note off and retriggers, Legato
+    ///glides the held note to the new pitch over the portamento time.
+    #[derive(Clone, Copy, Debug, Eq, PartialEq)]
+    pub enum VoiceMode
+    {
+        Poly,
+        Mono,
+        Legato,
+    }
+
+    ///VoiceSettings: release is how long (ms) a voice stays allocated
+    ///after its note ends, portamento the legato glide time in ms.
+    #[derive(Clone, Copy, Debug, PartialEq)]
+    pub struct VoiceSettings
+    {
+        pub polyphony: usize,
+        pub steal: StealMode,
+        pub mode: VoiceMode,
+        pub portamento: f32,
+        pub release: u64,
+    }
+
+    impl VoiceSettings
+    {
+        ///VoiceSettings::new() -> VoiceSettings: 16 voice polyphony that
+        ///steals the oldest voice, no portamento or release tail.
+        pub fn new() -> VoiceSettings
+        {
+            VoiceSettings{
+                polyphony: 16,
+                steal: StealMode::Oldest,
+                mode: VoiceMode::Poly,
+                portamento: 0.0,
+                release: 0,
+            }
+        }
+    }
+
+    impl Default for VoiceSettings
+    {
+        fn default() -> VoiceSettings
+        {
+            VoiceSettings::new()
+        }
+    }
+
+    ///VoiceSlot: One sounding note. id tells apart voices on the same key,
+    ///released is when the note ends and ends when its release tail has
+    ///finished.
+    #[derive(Clone, Copy, Debug, PartialEq)]
+    pub struct VoiceSlot
+    {
+        pub id: u64,
+        pub key: u8,
+        pub amplitude: f32,
+        pub started: u64,
+        pub released: u64,
+        pub ends: u64,
+    }
+
+    impl VoiceSlot
+    {
+        pub fn is_held(&self, now: u64) -> bool
+        {
+            now < self.released
+        }
+
+        ///VoiceSlot.level(now: u64) -> f32: Estimated loudness, fading out
+        ///linearly over the release tail.
+        pub fn level(&self, now: u64) -> f32
+        {
+            if self.is_held(now)
+            {
+                self.amplitude
+            }
+            else if now >= self.ends
+            {
+                0.0
+            }
+            else
+            {
+                self.amplitude * (self.ends - now) as f32 / (self.ends - self.released) as f32
+            }
+        }
+    }
+
+    #[derive(Clone, Debug)]
+    pub struct VoiceAllocator
+    {
+        pub settings: VoiceSettings,
+        slots: Vec<VoiceSlot>,
+        next_id: u64,
+    }
+
+    impl VoiceAllocator
+    {
+        pub fn new(settings: VoiceSettings) -> VoiceAllocator
+        {
+            VoiceAllocator{
+                settings,
+                slots: Vec::new(),
+                next_id: 0,
+            }
+        }
+
+        pub fn slots(&self) -> &Vec<VoiceSlot>
+        {
+            &self.slots
+        }
+
+        ///VoiceAllocator.expire(now: u64): Frees voices whose release tail
+        ///has finished.
+        pub fn expire(&mut self, now: u64)
+        {
+            self.slots.retain(|x| x.ends > now);
+        }
+
+        ///VoiceAllocator.held(now: u64) -> Option<VoiceSlot>: Most recently
+        ///started voice whose note has not ended yet.
+        pub fn held(&self, now: u64) -> Option<VoiceSlot>
+        {
+            self.slots.iter().filter(|x| x.is_held(now)).max_by_key(|x| x.started).cloned()
+        }
+
+        ///VoiceAllocator.is_full() -> bool: Whether a new voice would need
+        ///to steal one. Call expire first.
+        pub fn is_full(&self) -> bool
+        {
+            self.slots.len() >= self.settings.polyphony.max(1)
+        }
+
+        ///VoiceAllocator.victim(now: u64, key: u8) -> Option<VoiceSlot>: The
+        ///voice to steal for a note on key, if one has to be stolen. Only
+        ///the oldest held and the oldest releasing voice of a key can be
+        ///chosen, as those are the voices PlayableInstrument.stop cuts.
+        pub fn victim(&self, now: u64, key: u8) -> Option<VoiceSlot>
+        {
+            if self.settings.steal == StealMode::SameNote
+            {
+                if let Some(x) = self.slots.iter().find(|x| x.key == key)
+                {
+                    return Some(*x);
+                }
+            }
+            if !self.is_full() || self.settings.steal == StealMode::Never
+            {
+                return None;
+            }
+
+            let first = |x: &&VoiceSlot| !self.slots.iter().any(|y| y.key == x.key && y.is_held(now) == x.is_held(now) && y.started < x.started);
+            let releasing: Vec<&VoiceSlot> = self.slots.iter().filter(|x| !x.is_held(now)).filter(&first).collect();
+            let pool: Vec<&VoiceSlot> = if releasing.is_empty() { self.slots.iter().filter(&first).collect() } else { releasing };
+            let choice = match self.settings.steal
+            {
+                StealMode::Quietest => pool.iter().min_by(|a, b| a.level(now).partial_cmp(&b.level(now)).unwrap_or(::std::cmp::Ordering::Equal)),
+                _ => pool.iter().min_by_key(|x| x.started),
+            };
+            choice.map(|x| **x)
+        }
+
+        ///VoiceAllocator.remove(id: u64): Frees the voice with id.
+        pub fn remove(&mut self, id: u64)
+        {
+            self.slots.retain(|x| x.id != id);
+        }
+
+        pub fn clear(&mut self)
+        {
+            self.slots.clear();
+        }
+
+        ///VoiceAllocator.push(key, amplitude, now, length) -> u64: Allocates a
+        ///voice for a note of length ms starting at now and returns its id.
+        pub fn push(&mut self, key: u8, amplitude: f32, now: u64, length: u64) -> u64
+        {
+            let released = now.saturating_add(length);
+            self.next_id += 1;
+            self.slots.push(VoiceSlot{
+                id: self.next_id,
+                key,
+                amplitude,
+                started: now,
+                released,
+                ends: released.saturating_add(self.settings.release),
+            });
+            self.next_id
+        }
+
+        ///VoiceAllocator.release_key(key: u8, now: u64): Ends the held notes
+        ///on key at now, starting their release tails.
+        pub fn release_key(&mut self, key: u8, now: u64)
+        {
+            let release = self.settings.release;
+            for slot in self.slots.iter_mut().filter(|x| x.key == key && x.is_held(now))
+            {
+                slot.released = now;
+                slot.ends = now.saturating_add(release);
+            }
+        }
+
+        ///VoiceAllocator.release_all(now: u64): Ends every held note at now.
+        pub fn release_all(&mut self, now: u64)
+        {
+            let release = self.settings.release;
+            for slot in self.slots.iter_mut().filter(|x| x.is_held(now))
+            {
+                slot.released = now;
+                slot.ends = now.saturating_add(release);
+            }
+        }
+    }
+}
+
+
+#[allow(non_snake_case)]
 pub mod InstrumentWrapper
 {
     use sequencing::tonation::note::NoteCollections::RawNote;
+    use sequencing::tonation::note::NotePrimitives;
     use instrumentation::interface::instrument::Instrument;
-    use std::thread;
+    use instrumentation::interface::instrument::Instrument::PlayError;
+    use instrumentation::interface::instrument::Voices::StealMode;
+    use instrumentation::interface::instrument::Voices::VoiceAllocator;
+    use instrumentation::interface::instrument::Voices::VoiceMode;
+    use instrumentation::interface::instrument::Voices::VoiceSettings;
+    use processing::buffer::buffer::AudioBuffer::SAMPLE_RATE;
+    use processing::buffer::buffer::AudioBuffer::StereoBuffer;
     
     ///T represents an implemented instrument that has the play
     ///function: The play function returns an Option<???> which
     ///at a later point will be able to unwrap to modify portions
     ///of metadata in the Sequence or PartialSequence.
-    #[derive(Copy, Clone)]
+    ///The wrapper keeps track of the voices it has started so it
+    ///can limit polyphony and play mono or legato. It is the only
+    ///allocator: its polyphony is passed through to the instrument.
+    ///Its clock counts the frames rendered through process.
+    #[derive(Clone)]
     pub struct InstrumentWrapper<T: Instrument::PlayableInstrument>
     {
         internal_instrument: T,
-        //To implement: voice count.
+        voices: VoiceAllocator,
+        frames: u64,
     }
 
     impl <T: Instrument::PlayableInstrument>InstrumentWrapper<T>{
         pub fn new(instrument: T) -> InstrumentWrapper<T>
         {
+            InstrumentWrapper::with_voices(instrument, VoiceSettings::new())
+        }
+
+        pub fn with_voices(mut instrument: T, settings: VoiceSettings) -> InstrumentWrapper<T>
+        {
+            instrument.set_polyphony(settings.polyphony.max(1));
             InstrumentWrapper
             {
                 internal_instrument: instrument,
+                voices: VoiceAllocator::new(settings),
+                frames: 0,
             }
         }
 
-        ///Safe play returns no value and panics if a note has
+        pub fn voice_settings(&self) -> VoiceSettings
+        {
+            self.voices.settings
+        }
+
+        ///Changes polyphony, stealing and mono settings. Voices already
+        ///sounding are left alone.
+        pub fn set_voice_settings(&mut self, settings: VoiceSettings)
+        {
+            self.internal_instrument.set_polyphony(settings.polyphony.max(1));
+            self.voices.settings = settings;
+        }
+
+        ///Amount of voices sounding at time (ms on the wrapper's clock),
+        ///release tails included.
+        pub fn active_voices(&mut self, time: u64) -> usize
+        {
+            self.voices.expire(time);
+            self.voices.slots().len()
+        }
+
+        ///Milliseconds of audio rendered through process, the clock play,
+        ///note_on and note_off use for voice allocation.
+        pub fn now(&self) -> u64
+        {
+            self.frames * 1000 / SAMPLE_RATE as u64
+        }
+
+        ///Play returns no value and panics if a note has
         ///not been implemented for this particular instrument.
-        ///Reimplement as similar result to playable instrument at
-        ///later point.
         pub fn play(&mut self, note: &RawNote) 
         {
-            self.internal_instrument.play(note);
-            //if blah blah returned a none value, panic.
+            match self.try_play(note)
+            {
+                Ok(()) => {},
+                Err(e) => panic!("could not play note: {}", e),
+            }
         }
 
-        ///Safe play returns no value and handles an improper play
-        ///argument by doing nothing.
+        ///Safe play handles an improper play argument by doing
+        ///nothing and returning false.
         pub fn safe_play(&mut self, note: &RawNote) -> bool
         {
-            self.internal_instrument.play(note);
-            //if blah blah returns none value, false, else...
-            true
+            self.try_play(note).is_ok()
+        }
+
+        ///Try play reports why a note could not be played.
+        pub fn try_play(&mut self, note: &RawNote) -> Result<(), PlayError>
+        {
+            let time = self.now();
+            self.play_at(note, time)
+        }
+
+        ///Plays note as if it started at time ms, for callers that keep
+        ///their own clock instead of rendering through process.
+        pub fn play_at(&mut self, note: &RawNote, time: u64) -> Result<(), PlayError>
+        {
+            if self.allocate(note.key(), time, Some(note), note.amplitude, note.length as u64)?
+            {
+                return Ok(());
+            }
+            self.internal_instrument.play(note)?;
+            self.voices.push(note.key(), note.amplitude, time, note.length as u64);
+            Ok(())
+        }
+
+        ///Starts a note on key that is held until note_off.
+        pub fn note_on(&mut self, key: u8, velocity: u8) -> Result<(), PlayError>
+        {
+            let time = self.now();
+            self.note_on_at(key, velocity, time)
+        }
+
+        pub fn note_on_at(&mut self, key: u8, velocity: u8, time: u64) -> Result<(), PlayError>
+        {
+            //Legato needs a note to glide to, which only exists for keys
+            //inside our registers.
+            let glide_to = NotePrimitives::midi_to_primitives(key).map(|(p, r)| RawNote::new(p, r, 0.0, velocity as f32, u32::MAX));
+            if self.allocate(key, time, glide_to.as_ref(), velocity as f32, u64::MAX)?
+            {
+                return Ok(());
+            }
+            self.internal_instrument.note_on(key, velocity)?;
+            self.voices.push(key, velocity as f32, time, u64::MAX);
+            Ok(())
+        }
+
+        pub fn note_off(&mut self, key: u8)
+        {
+            let time = self.now();
+            self.note_off_at(key, time);
+        }
+
+        ///Releases key, whose voice stays allocated for the release tail.
+        pub fn note_off_at(&mut self, key: u8, time: u64)
+        {
+            self.voices.release_key(key, time);
+            self.internal_instrument.note_off(key);
+        }
+
+        pub fn all_notes_off(&mut self)
+        {
+            let time = self.now();
+            self.voices.release_all(time);
+            self.internal_instrument.all_notes_off();
+        }
+
+        ///Renders the next block of the instrument's output, advancing
+        ///the wrapper's clock.
+        pub fn process(&mut self, buffer: &mut StereoBuffer)
+        {
+            self.internal_instrument.process(buffer);
+            self.frames += buffer.len() as u64;
+        }
+
+        ///Makes room for a note on key starting at time: cuts off or
+        ///glides the held note in mono modes and steals a voice once the
+        ///polyphony limit is reached. Returns true if the held note was
+        ///glided to glide_to, leaving nothing else to play.
+        fn allocate(&mut self, key: u8, time: u64, glide_to: Option<&RawNote>, amplitude: f32, length: u64) -> Result<bool, PlayError>
+        {
+            self.voices.expire(time);
+
+            if self.voices.settings.mode != VoiceMode::Poly
+            {
+                if let (Some(held), Some(note)) = (self.voices.held(time), glide_to)
+                {
+                    let portamento = self.voices.settings.portamento;
+                    if self.voices.settings.mode == VoiceMode::Legato &&
+                        self.internal_instrument.glide(held.key, note, portamento)
+                    {
+                        self.voices.remove(held.id);
+                        self.voices.push(key, amplitude, held.started, time.saturating_sub(held.started).saturating_add(length));
+                        return Ok(true);
+                    }
+                }
+                for slot in self.voices.slots().clone()
+                {
+                    self.internal_instrument.stop(slot.key, slot.is_held(time));
+                }
+                self.voices.clear();
+            }
+            else if let Some(victim) = self.voices.victim(time, key)
+            {
+                self.internal_instrument.stop(victim.key, victim.is_held(time));
+                self.voices.remove(victim.id);
+            }
+            else if self.voices.settings.steal == StealMode::Never && self.voices.is_full()
+            {
+                return Err(PlayError::VoicesExhausted);
+            }
+            Ok(false)
+        }
+
+        ///Forwards a parameter change to the wrapped instrument.
+        pub fn set_parameter(&mut self, name: &str, value: f32) -> bool
+        {
+            self.internal_instrument.set_parameter(name, value)
         }
 
         //For debug uses only
@@ -62,9 +596,14 @@ pub mod InstrumentWrapper
     }
 }
 
+///TestPlugin: Plays a single sound through OpenAL. Only built with the
+///openal feature.
+#[cfg(feature = "openal")]
+#[allow(non_snake_case)]
 pub mod TestPlugin
 {
     use sequencing::tonation::note::NoteCollections::RawNote;
+    use sequencing::tonation::note::NotePrimitives;
     use instrumentation::interface::instrument::Instrument;
     use ears::{Sound, AudioController};
     use schedule_recv::oneshot_ms;
@@ -79,7 +618,7 @@ pub mod TestPlugin
 
     impl Instrument::PlayableInstrument for TestSampler
     {
-        fn play(&mut self, note: &RawNote)
+        fn play(&mut self, note: &RawNote) -> Result<(), Instrument::PlayError>
         {
             if self.internal_audio.is_playing()
             {
@@ -91,9 +630,48 @@ pub mod TestPlugin
                 self.internal_audio.set_pitch(note.pitch_hz * 0.0015 * 8.0 * self.audio_scaler);
             }
             self.play_core(&note);
+            Ok(())
+        }
+
+        ///Starts the sound without blocking; it plays until note_off.
+        fn note_on(&mut self, key: u8, _velocity: u8) -> Result<(), Instrument::PlayError>
+        {
+            let pitch = NotePrimitives::midi_to_herz(key as f32) * 0.0015 * 8.0 * self.audio_scaler;
+            if self.cache != pitch
+            {
+                self.internal_audio.set_pitch(pitch);
+                self.cache = pitch;
+            }
+            self.internal_audio.play();
+            Ok(())
+        }
+
+        fn note_off(&mut self, _key: u8)
+        {
+            self.internal_audio.stop();
+        }
+
+        ///There is only one sound, so any key stops it.
+        fn stop(&mut self, _key: u8, _held: bool)
+        {
+            self.internal_audio.stop();
         }
 
-        
+        fn set_parameter(&mut self, name: &str, value: f32) -> bool
+        {
+            match name
+            {
+                "pitch" =>
+                {
+                    self.change_pitch(value);
+                    true
+                },
+                _ =>
+                {
+                    false
+                }
+            }
+        }
     }
 
     impl TestSampler
@@ -131,6 +709,381 @@ pub mod TestPlugin
             self.internal_audio.set_pitch(value);
         }
     }
+}
 
+///Probe: Instrument for tests that records what it is asked to do
+///instead of making any sound.
+#[cfg(test)]
+#[allow(non_snake_case)]
+pub mod Probe
+{
+    use instrumentation::interface::instrument::Instrument;
+    use processing::buffer::buffer::AudioBuffer::StereoBuffer;
+    use sequencing::tonation::note::NoteCollections::RawNote;
+
+    ///Probe: played holds every note started or glided to, held the keys
+    ///of notes started with note_on and not yet released or stopped,
+    ///stopped the keys cut off, parameters the accepted parameter changes
+    ///and polyphony the last pool size passed in. Notes outside range are
+    ///refused and glides decides whether legato glides are accepted. The
+    ///probe's output is the amount of held notes.
+    pub struct Probe
+    {
+        pub played: Vec<RawNote>,
+        pub held: Vec<u8>,
+        pub range: (u8, u8),
+        pub stopped: Vec<u8>,
+        pub parameters: Vec<(String, f32)>,
+        pub glides: bool,
+        pub polyphony: usize,
+    }
+
+    impl Probe
+    {
+        pub fn new() -> Probe
+        {
+            Probe{
+                played: Vec::new(),
+                held: Vec::new(),
+                range: (0, 127),
+                stopped: Vec::new(),
+                parameters: Vec::new(),
+                glides: false,
+                polyphony: 0,
+            }
+        }
+
+        ///Probe::gliding() -> Probe: Probe that accepts legato glides.
+        pub fn gliding() -> Probe
+        {
+            Probe{ glides: true, ..Probe::new() }
+        }
 
+        ///Probe::with_range(low: u8, high: u8) -> Probe: Probe that refuses
+        ///keys outside low..=high.
+        pub fn with_range(low: u8, high: u8) -> Probe
+        {
+            Probe{ range: (low, high), ..Probe::new() }
+        }
+
+        ///Probe.keys() -> Vec<u8>: Keys of the played notes, in order.
+        pub fn keys(&self) -> Vec<u8>
+        {
+            self.played.iter().map(|x| x.key()).collect()
+        }
+    }
+
+    impl Default for Probe
+    {
+        fn default() -> Probe
+        {
+            Probe::new()
+        }
+    }
+
+    impl Instrument::PlayableInstrument for Probe
+    {
+        fn play(&mut self, note: &RawNote) -> Result<(), Instrument::PlayError>
+        {
+            let key = note.key();
+            if key < self.range.0 || key > self.range.1
+            {
+                return Err(Instrument::PlayError::OutOfRange(key));
+            }
+            self.played.push(*note);
+            Ok(())
+        }
+
+        fn note_on(&mut self, key: u8, _velocity: u8) -> Result<(), Instrument::PlayError>
+        {
+            if key < self.range.0 || key > self.range.1
+            {
+                return Err(Instrument::PlayError::OutOfRange(key));
+            }
+            self.held.push(key);
+            Ok(())
+        }
+
+        fn note_off(&mut self, key: u8)
+        {
+            self.held.retain(|x| *x != key);
+        }
+
+        fn process(&mut self, buffer: &mut StereoBuffer)
+        {
+            let level = self.held.len() as f32;
+            for i in 0..buffer.len()
+            {
+                buffer.left[i] = level;
+                buffer.right[i] = level;
+            }
+        }
+
+        ///Accepts any parameter whose name is not empty.
+        fn set_parameter(&mut self, name: &str, value: f32) -> bool
+        {
+            if name.is_empty()
+            {
+                return false;
+            }
+            self.parameters.push((name.to_string(), value));
+            true
+        }
+
+        fn stop(&mut self, key: u8, held: bool)
+        {
+            self.stopped.push(key);
+            if held
+            {
+                if let Some(index) = self.held.iter().position(|x| *x == key)
+                {
+                    self.held.remove(index);
+                }
+            }
+        }
+
+        fn set_polyphony(&mut self, voices: usize)
+        {
+            self.polyphony = voices;
+        }
+
+        fn glide(&mut self, _key: u8, note: &RawNote, _time: f32) -> bool
+        {
+            if self.glides
+            {
+                self.played.push(*note);
+            }
+            self.glides
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use instrumentation::interface::instrument::Instrument::PlayError;
+    use instrumentation::interface::instrument::InstrumentWrapper::InstrumentWrapper;
+    use instrumentation::interface::instrument::Probe::Probe;
+    use instrumentation::interface::instrument::Voices::StealMode;
+    use instrumentation::interface::instrument::Voices::VoiceMode;
+    use instrumentation::interface::instrument::Voices::VoiceSettings;
+    use processing::buffer::buffer::AudioBuffer::SAMPLE_RATE;
+    use processing::buffer::buffer::AudioBuffer::StereoBuffer;
+    use sequencing::tonation::note::NoteCollections::RawNote;
+    use sequencing::tonation::note::NotePrimitives::Register;
+    use sequencing::tonation::note::NotePrimitives::Semitone;
+
+    fn note(semitone: Semitone, amplitude: f32, length: u32) -> RawNote
+    {
+        RawNote::new(semitone, Register::C4, 0.0, amplitude, length)
+    }
+
+    fn wrapper(instrument: Probe, settings: VoiceSettings) -> InstrumentWrapper<Probe>
+    {
+        InstrumentWrapper::with_voices(instrument, settings)
+    }
+
+    #[test]
+    fn oldest_voice_is_stolen_past_the_polyphony()
+    {
+        let mut settings = VoiceSettings::new();
+        settings.polyphony = 2;
+        let mut w = wrapper(Probe::new(), settings);
+        w.play_at(&note(Semitone::C, 100.0, 1000), 0).unwrap();
+        w.play_at(&note(Semitone::D, 100.0, 1000), 10).unwrap();
+        w.play_at(&note(Semitone::E, 100.0, 1000), 20).unwrap();
+        assert_eq!(w.active_voices(20), 2);
+        assert_eq!(w.reveal_internal_instrument_DEBUG().stopped, vec![60]);
+        assert_eq!(w.reveal_internal_instrument_DEBUG().keys(), vec![60, 62, 64]);
+    }
+
+    #[test]
+    fn stealing_frees_only_the_stolen_voice()
+    {
+        let mut settings = VoiceSettings::new();
+        settings.polyphony = 2;
+        let mut w = wrapper(Probe::new(), settings);
+        w.note_on_at(60, 100, 0).unwrap();
+        w.note_on_at(60, 100, 10).unwrap();
+        w.note_on_at(64, 100, 20).unwrap();
+        assert_eq!(w.active_voices(20), 2);
+        assert_eq!(w.reveal_internal_instrument_DEBUG().held, vec![60, 64]);
+    }
+
+    #[test]
+    fn polyphony_is_passed_to_the_instrument()
+    {
+        let mut settings = VoiceSettings::new();
+        settings.polyphony = 3;
+        let mut w = wrapper(Probe::new(), settings);
+        assert_eq!(w.reveal_internal_instrument_DEBUG().polyphony, 3);
+        settings.polyphony = 0;
+        w.set_voice_settings(settings);
+        assert_eq!(w.reveal_internal_instrument_DEBUG().polyphony, 1);
+    }
+
+    #[test]
+    fn the_clock_follows_rendered_frames()
+    {
+        let mut w = wrapper(Probe::new(), VoiceSettings::new());
+        w.play(&note(Semitone::C, 100.0, 100));
+        assert_eq!(w.active_voices(w.now()), 1);
+        let mut buffer = StereoBuffer::new(SAMPLE_RATE as usize / 2);
+        w.process(&mut buffer);
+        assert_eq!(w.now(), 500);
+        assert_eq!(w.active_voices(w.now()), 0);
+    }
+
+    #[test]
+    fn releasing_and_quiet_voices_are_stolen_first()
+    {
+        let mut settings = VoiceSettings::new();
+        settings.polyphony = 2;
+        settings.steal = StealMode::Quietest;
+        settings.release = 1000;
+        let mut w = wrapper(Probe::new(), settings);
+        w.play_at(&note(Semitone::C, 50.0, 1000), 0).unwrap();
+        w.play_at(&note(Semitone::D, 100.0, 1000), 10).unwrap();
+        w.play_at(&note(Semitone::E, 100.0, 1000), 20).unwrap();
+        assert_eq!(w.reveal_internal_instrument_DEBUG().stopped, vec![60]);
+
+        let mut w = wrapper(Probe::new(), settings);
+        w.play_at(&note(Semitone::C, 50.0, 1000), 0).unwrap();
+        w.play_at(&note(Semitone::D, 100.0, 100), 10).unwrap();
+        w.play_at(&note(Semitone::E, 100.0, 1000), 500).unwrap();
+        assert_eq!(w.reveal_internal_instrument_DEBUG().stopped, vec![62]);
+    }
+
+    #[test]
+    fn same_note_stealing_retriggers_the_key()
+    {
+        let mut settings = VoiceSettings::new();
+        settings.steal = StealMode::SameNote;
+        let mut w = wrapper(Probe::new(), settings);
+        w.play_at(&note(Semitone::C, 100.0, 1000), 0).unwrap();
+        w.play_at(&note(Semitone::D, 100.0, 1000), 0).unwrap();
+        w.play_at(&note(Semitone::C, 100.0, 1000), 10).unwrap();
+        assert_eq!(w.active_voices(10), 2);
+        assert_eq!(w.reveal_internal_instrument_DEBUG().stopped, vec![60]);
+    }
+
+    #[test]
+    fn release_tails_keep_voices_allocated()
+    {
+        let mut settings = VoiceSettings::new();
+        settings.release = 200;
+        let mut w = wrapper(Probe::new(), settings);
+        w.play_at(&note(Semitone::C, 100.0, 100), 0).unwrap();
+        assert_eq!(w.active_voices(250), 1);
+        assert_eq!(w.active_voices(300), 0);
+    }
+
+    #[test]
+    fn mono_cuts_off_the_held_note()
+    {
+        let mut settings = VoiceSettings::new();
+        settings.mode = VoiceMode::Mono;
+        let mut w = wrapper(Probe::gliding(), settings);
+        w.play_at(&note(Semitone::C, 100.0, 1000), 0).unwrap();
+        w.play_at(&note(Semitone::E, 100.0, 1000), 100).unwrap();
+        assert_eq!(w.active_voices(100), 1);
+        assert_eq!(w.reveal_internal_instrument_DEBUG().stopped, vec![60]);
+    }
+
+    #[test]
+    fn legato_glides_from_the_held_note()
+    {
+        let mut settings = VoiceSettings::new();
+        settings.mode = VoiceMode::Legato;
+        let mut w = wrapper(Probe::gliding(), settings);
+        w.play_at(&note(Semitone::C, 100.0, 500), 0).unwrap();
+        w.play_at(&note(Semitone::E, 100.0, 500), 300).unwrap();
+        assert!(w.reveal_internal_instrument_DEBUG().stopped.is_empty());
+        assert_eq!(w.active_voices(700), 1);
+        assert_eq!(w.active_voices(800), 0);
+
+        //Instruments that cannot glide are retriggered instead.
+        let mut w = wrapper(Probe::new(), settings);
+        w.play_at(&note(Semitone::C, 100.0, 500), 0).unwrap();
+        w.play_at(&note(Semitone::E, 100.0, 500), 300).unwrap();
+        assert_eq!(w.reveal_internal_instrument_DEBUG().stopped, vec![60]);
+    }
+
+    #[test]
+    fn legato_glides_to_a_note_before_the_held_one_started()
+    {
+        let mut settings = VoiceSettings::new();
+        settings.mode = VoiceMode::Legato;
+        let mut w = wrapper(Probe::gliding(), settings);
+        w.play_at(&note(Semitone::C, 100.0, 500), 1000).unwrap();
+        w.play_at(&note(Semitone::E, 100.0, 500), 400).unwrap();
+        assert_eq!(w.active_voices(400), 1);
+    }
+
+    #[test]
+    fn refused_notes_are_reported_and_take_no_voice()
+    {
+        let mut w = wrapper(Probe::with_range(60, 64), VoiceSettings::new());
+        assert_eq!(w.play_at(&note(Semitone::G, 100.0, 500), 0), Err(PlayError::OutOfRange(67)));
+        assert_eq!(w.active_voices(0), 0);
+        assert!(!w.safe_play(&note(Semitone::G, 100.0, 500)));
+        assert!(w.safe_play(&note(Semitone::C, 100.0, 500)));
+    }
+
+    #[test]
+    fn full_wrappers_that_never_steal_refuse_notes()
+    {
+        let mut settings = VoiceSettings::new();
+        settings.polyphony = 1;
+        settings.steal = StealMode::Never;
+        let mut w = wrapper(Probe::new(), settings);
+        w.play_at(&note(Semitone::C, 100.0, 500), 0).unwrap();
+        assert_eq!(w.play_at(&note(Semitone::D, 100.0, 500), 10), Err(PlayError::VoicesExhausted));
+        assert!(w.play_at(&note(Semitone::D, 100.0, 500), 500).is_ok());
+    }
+
+    #[test]
+    fn note_on_holds_a_voice_until_note_off()
+    {
+        let mut settings = VoiceSettings::new();
+        settings.release = 100;
+        let mut w = wrapper(Probe::new(), settings);
+        w.note_on_at(60, 100, 0).unwrap();
+        w.note_on_at(64, 100, 0).unwrap();
+        assert_eq!(w.active_voices(100_000), 2);
+        w.note_off_at(60, 100_000);
+        assert_eq!(w.reveal_internal_instrument_DEBUG().held, vec![64]);
+        assert_eq!(w.active_voices(100_050), 2);
+        assert_eq!(w.active_voices(100_100), 1);
+    }
+
+    #[test]
+    fn all_notes_off_releases_every_key()
+    {
+        let mut w = wrapper(Probe::new(), VoiceSettings::new());
+        w.note_on(60, 100).unwrap();
+        w.note_on(127, 100).unwrap();
+        w.all_notes_off();
+        assert!(w.reveal_internal_instrument_DEBUG().held.is_empty());
+    }
+
+    #[test]
+    fn process_renders_the_wrapped_instrument()
+    {
+        let mut w = wrapper(Probe::new(), VoiceSettings::new());
+        w.note_on_at(60, 100, 0).unwrap();
+        let mut buffer = StereoBuffer::new(16);
+        w.process(&mut buffer);
+        assert_eq!(buffer.left[15], 1.0);
+        assert_eq!(w.note_on_at(200, 100, 0), Err(PlayError::OutOfRange(200)));
+    }
+
+    #[test]
+    fn parameters_reach_the_wrapped_instrument()
+    {
+        let mut w = InstrumentWrapper::new(Probe::new());
+        assert!(w.set_parameter("cutoff", 0.5));
+        assert!(!w.set_parameter("", 0.5));
+        assert_eq!(w.reveal_internal_instrument_DEBUG().parameters, vec![("cutoff".to_string(), 0.5)]);
+    }
 }
@@ -0,0 +1,477 @@
+///DrumPad: A one-shot sample assigned to a key of a drum kit.
+#[allow(non_snake_case)]
+pub mod DrumPad
+{
+    use processing::sample::sample::Loader;
+    use processing::sample::sample::SampleData::SampleData;
+    use processing::sample::sample::SampleData::SampleError;
+    use std::sync::Arc;
+
+    ///Pad: tune is in cents. Pads sharing a choke group silence each other,
+    ///so an open hat is cut off by the closed hat that follows it.
+    #[derive(Clone, Debug)]
+    pub struct Pad
+    {
+        pub sample: Arc<SampleData>,
+        pub gain: f32,
+        pub pan: f32,
+        pub tune: f32,
+        pub choke: Option<u8>,
+    }
+
+    impl Pad
+    {
+        ///Pad::new(sample: Arc<SampleData>) -> Pad: Centered pad at unity
+        ///gain with no tuning or choke group.
+        pub fn new(sample: Arc<SampleData>) -> Pad
+        {
+            Pad{
+                sample,
+                gain: 1.0,
+                pan: 0.0,
+                tune: 0.0,
+                choke: None,
+            }
+        }
+
+        ///Pad::from_file(path: &str) -> Result<Pad, SampleError>: Loads a
+        ///WAV or AIFF file into a new pad.
+        pub fn from_file(path: &str) -> Result<Pad, SampleError>
+        {
+            Ok(Pad::new(Arc::new(Loader::load(path)?)))
+        }
+
+        ///Pad.gain(gain: f32) -> Pad: Builder style gain.
+        pub fn gain(mut self, gain: f32) -> Pad
+        {
+            self.gain = gain;
+            self
+        }
+
+        ///Pad.pan(pan: f32) -> Pad: Builder style pan (-1.0 to 1.0).
+        pub fn pan(mut self, pan: f32) -> Pad
+        {
+            self.pan = pan;
+            self
+        }
+
+        ///Pad.tune(cents: f32) -> Pad: Builder style tuning.
+        pub fn tune(mut self, cents: f32) -> Pad
+        {
+            self.tune = cents;
+            self
+        }
+
+        ///Pad.choke(group: u8) -> Pad: Builder style choke group.
+        pub fn choke(mut self, group: u8) -> Pad
+        {
+            self.choke = Some(group);
+            self
+        }
+    }
+}
+
+///DrumKit: Plays one-shot samples mapped to midi keys. Notes always play
+///their pad's sample to the end regardless of length; only the key and
+///velocity of a note are used.
+#[allow(non_snake_case)]
+pub mod DrumKit
+{
+    use instrumentation::interface::instrument::Instrument;
+    use instrumentation::internal::drumkit::DrumPad::Pad;
+    use processing::buffer::buffer::AudioBuffer::SAMPLE_RATE;
+    use processing::buffer::buffer::AudioBuffer::StereoBuffer;
+    use processing::mixing::mixer::MixerPrimitives;
+    use sequencing::tonation::note::NoteCollections::MAX_AMPLITUDE;
+    use sequencing::tonation::note::NoteCollections::RawNote;
+    use sequencing::tonation::note::NotePrimitives;
+    use sequencing::tonation::note::NotePrimitives::Register;
+    use sequencing::tonation::note::NotePrimitives::Semitone;
+    use std::collections::HashMap;
+
+    ///Default amount of hits that can sound at once.
+    pub const DEFAULT_POLYPHONY: usize = 32;
+    ///Length of the fade applied to a choked hit, in ms.
+    pub const CHOKE_FADE_MS: f32 = 5.0;
+
+    #[derive(Clone, Debug)]
+    struct Voice
+    {
+        key: u8,
+        position: f64,
+        increment: f64,
+        gain: f32,
+        fade: f32,
+        fade_step: f32,
+        age: u64,
+    }
+
+    pub struct DrumKit
+    {
+        pads: HashMap<u8, Pad>,
+        voices: Vec<Voice>,
+        polyphony: usize,
+        clock: u64,
+    }
+
+    impl DrumKit
+    {
+        ///DrumKit::new() -> DrumKit: Empty kit with DEFAULT_POLYPHONY voices.
+        pub fn new() -> DrumKit
+        {
+            DrumKit::with_polyphony(DEFAULT_POLYPHONY)
+        }
+
+        ///DrumKit::with_polyphony(voices: usize) -> DrumKit
+        pub fn with_polyphony(voices: usize) -> DrumKit
+        {
+            DrumKit{
+                pads: HashMap::new(),
+                voices: Vec::new(),
+                polyphony: if voices < 1 { 1 } else { voices },
+                clock: 0,
+            }
+        }
+
+        ///DrumKit.map(key: u8, pad: Pad) -> Option<Pad>: Assigns pad to a
+        ///midi key, returning the pad it replaced.
+        pub fn map(&mut self, key: u8, pad: Pad) -> Option<Pad>
+        {
+            self.pads.insert(key, pad)
+        }
+
+        ///DrumKit.map_note(p: Semitone, r: Register, pad: Pad) -> Option<Pad>:
+        ///Like map, keyed by semitone and register.
+        pub fn map_note(&mut self, p: Semitone, r: Register, pad: Pad) -> Option<Pad>
+        {
+            self.map(NotePrimitives::primitives_to_midi(p, r), pad)
+        }
+
+        ///DrumKit.unmap(key: u8) -> Option<Pad>
+        pub fn unmap(&mut self, key: u8) -> Option<Pad>
+        {
+            self.pads.remove(&key)
+        }
+
+        ///DrumKit.pad(key: u8) -> Option<&mut Pad>
+        pub fn pad(&mut self, key: u8) -> Option<&mut Pad>
+        {
+            self.pads.get_mut(&key)
+        }
+
+        ///DrumKit.keys() -> Vec<u8>: Every mapped key in ascending order.
+        pub fn keys(&self) -> Vec<u8>
+        {
+            let mut ret: Vec<u8> = self.pads.keys().cloned().collect();
+            ret.sort();
+            ret
+        }
+
+        ///DrumKit.active_voices() -> usize: Hits that are still sounding.
+        pub fn active_voices(&self) -> usize
+        {
+            self.voices.len()
+        }
+
+        ///DrumKit.choke(group: u8): Fades out every hit of the group.
+        pub fn choke(&mut self, group: u8)
+        {
+            let step = 1000.0 / (CHOKE_FADE_MS * SAMPLE_RATE as f32);
+            let pads = &self.pads;
+            for voice in self.voices.iter_mut()
+            {
+                if pads.get(&voice.key).and_then(|x| x.choke) == Some(group)
+                {
+                    voice.fade_step = step;
+                }
+            }
+        }
+
+        ///DrumKit.hit(key: u8, velocity: f32) -> bool: Plays the pad mapped
+        ///to key at a velocity from 0.0 to 1.0. False if nothing is mapped.
+        pub fn hit(&mut self, key: u8, velocity: f32) -> bool
+        {
+            let (choke, voice) = match self.pads.get(&key)
+            {
+                Some(pad) => (pad.choke, Voice{
+                    key,
+                    position: 0.0,
+                    increment: 2f64.powf(pad.tune as f64 / 1200.0) * pad.sample.sample_rate as f64 / SAMPLE_RATE as f64,
+                    gain: pad.gain * velocity.clamp(0.0, 1.0),
+                    fade: 1.0,
+                    fade_step: 0.0,
+                    age: self.clock + 1,
+                }),
+                None => return false,
+            };
+            if let Some(group) = choke
+            {
+                self.choke(group);
+            }
+
+            self.clock += 1;
+            if self.voices.len() >= self.polyphony
+            {
+                let oldest = (0..self.voices.len()).min_by_key(|x| (self.voices[*x].fade_step == 0.0, self.voices[*x].age)).unwrap();
+                self.voices.remove(oldest);
+            }
+            self.voices.push(voice);
+            true
+        }
+
+        ///DrumKit.render(buffer: &mut StereoBuffer): Overwrites buffer with
+        ///the next block of audio from every sounding hit.
+        pub fn render(&mut self, buffer: &mut StereoBuffer)
+        {
+            buffer.clear();
+            let pads = &self.pads;
+            for voice in self.voices.iter_mut()
+            {
+                let pad = match pads.get(&voice.key)
+                {
+                    Some(x) => x,
+                    None =>
+                    {
+                        voice.fade = 0.0;
+                        continue;
+                    },
+                };
+                let frames = pad.sample.frames();
+                let (pan_left, pan_right) = MixerPrimitives::pan_to_gains(pad.pan);
+                for i in 0..buffer.len()
+                {
+                    if voice.position >= frames as f64 || voice.fade <= 0.0
+                    {
+                        voice.fade = 0.0;
+                        break;
+                    }
+                    let index = voice.position as usize;
+                    let t = (voice.position - index as f64) as f32;
+                    let (a, b) = (pad.sample.frame(index), pad.sample.frame(index + 1));
+                    let gain = voice.gain * voice.fade;
+                    buffer.left[i] += (a.0 + (b.0 - a.0) * t) * gain * pan_left;
+                    buffer.right[i] += (a.1 + (b.1 - a.1) * t) * gain * pan_right;
+                    voice.position += voice.increment;
+                    voice.fade -= voice.fade_step;
+                }
+            }
+            self.voices.retain(|x| x.fade > 0.0);
+        }
+    }
+
+    impl Default for DrumKit
+    {
+        fn default() -> DrumKit
+        {
+            DrumKit::new()
+        }
+    }
+
+    impl Instrument::PlayableInstrument for DrumKit
+    {
+        ///Hits the pad mapped to the note's key.
+        fn play(&mut self, note: &RawNote) -> Result<(), Instrument::PlayError>
+        {
+            if self.hit(note.key(), note.amplitude / MAX_AMPLITUDE)
+            {
+                Ok(())
+            }
+            else
+            {
+                Err(Instrument::PlayError::MissingSample(note.key()))
+            }
+        }
+
+        fn note_on(&mut self, key: u8, velocity: u8) -> Result<(), Instrument::PlayError>
+        {
+            if self.hit(key, velocity as f32 / MAX_AMPLITUDE)
+            {
+                Ok(())
+            }
+            else
+            {
+                Err(Instrument::PlayError::MissingSample(key))
+            }
+        }
+
+        ///Hits always play out, so releasing a key does nothing.
+        fn note_off(&mut self, _key: u8)
+        {
+        }
+
+        fn all_notes_off(&mut self)
+        {
+        }
+
+        fn process(&mut self, buffer: &mut StereoBuffer)
+        {
+            self.render(buffer);
+        }
+
+        ///Fades out the oldest hit of key like a choke. Hits have no
+        ///release, so held is ignored.
+        fn stop(&mut self, key: u8, _held: bool)
+        {
+            let step = 1000.0 / (CHOKE_FADE_MS * SAMPLE_RATE as f32);
+            if let Some(voice) = self.voices.iter_mut().filter(|x| x.key == key && x.fade_step == 0.0).min_by_key(|x| x.age)
+            {
+                voice.fade_step = step;
+            }
+        }
+
+        ///Limits the hits that can sound at once, cutting the newest hits
+        ///past the new limit.
+        fn set_polyphony(&mut self, voices: usize)
+        {
+            self.polyphony = voices.max(1);
+            self.voices.truncate(self.polyphony);
+        }
+
+        ///Pads are addressed by key: "gain.36", "pan.42", "tune.38".
+        fn set_parameter(&mut self, name: &str, value: f32) -> bool
+        {
+            let mut parts = name.splitn(2, '.');
+            let (field, key) = match (parts.next(), parts.next().and_then(|x| x.parse::<u8>().ok()))
+            {
+                (Some(field), Some(key)) => (field, key),
+                _ => return false,
+            };
+            match (self.pads.get_mut(&key), field)
+            {
+                (Some(pad), "gain") => pad.gain = value,
+                (Some(pad), "pan") => pad.pan = value,
+                (Some(pad), "tune") => pad.tune = value,
+                _ => return false,
+            }
+            true
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use instrumentation::interface::instrument::Instrument::PlayError;
+    use instrumentation::interface::instrument::Instrument::PlayableInstrument;
+    use instrumentation::internal::drumkit::DrumKit::DrumKit;
+    use instrumentation::internal::drumkit::DrumPad::Pad;
+    use processing::buffer::buffer::AudioBuffer::SAMPLE_RATE;
+    use processing::buffer::buffer::AudioBuffer::StereoBuffer;
+    use processing::sample::sample::SampleData::SampleData;
+    use sequencing::tonation::note::NoteCollections::RawNote;
+    use sequencing::tonation::note::NotePrimitives::Register;
+    use sequencing::tonation::note::NotePrimitives::Semitone;
+    use std::sync::Arc;
+
+    fn pad(level: f32, frames: usize) -> Pad
+    {
+        Pad::new(Arc::new(SampleData::new(vec![vec![level; frames]], SAMPLE_RATE)))
+    }
+
+    fn block(kit: &mut DrumKit) -> StereoBuffer
+    {
+        let mut buffer = StereoBuffer::new(256);
+        kit.render(&mut buffer);
+        buffer
+    }
+
+    #[test]
+    fn notes_hit_the_pad_mapped_to_their_key()
+    {
+        let mut kit = DrumKit::new();
+        kit.map_note(Semitone::C, Register::C2, pad(0.5, 1000));
+        assert_eq!(kit.keys(), vec![36]);
+
+        let unmapped = kit.play(&RawNote::new(Semitone::D, Register::C2, 0.0, 127.0, 100));
+        assert_eq!(unmapped, Err(PlayError::MissingSample(38)));
+        assert_eq!(kit.active_voices(), 0);
+        kit.play(&RawNote::new(Semitone::C, Register::C2, 0.0, 127.0, 100)).unwrap();
+        assert_eq!(kit.active_voices(), 1);
+        let buffer = block(&mut kit);
+        assert!(buffer.left[10] > 0.0);
+        assert!((buffer.left[10] - buffer.right[10]).abs() < 1e-6);
+    }
+
+    #[test]
+    fn hits_play_to_the_end_of_their_sample()
+    {
+        let mut kit = DrumKit::new();
+        kit.map(36, pad(0.5, 300));
+        kit.hit(36, 1.0);
+        block(&mut kit);
+        assert_eq!(kit.active_voices(), 1);
+        let buffer = block(&mut kit);
+        assert!(buffer.left[10] > 0.0);
+        assert_eq!(buffer.left[100], 0.0);
+        assert_eq!(kit.active_voices(), 0);
+    }
+
+    #[test]
+    fn gain_pan_and_velocity_scale_the_hit()
+    {
+        let level = |pad: Pad, velocity: f32| {
+            let mut kit = DrumKit::new();
+            kit.map(36, pad);
+            kit.hit(36, velocity);
+            let buffer = block(&mut kit);
+            (buffer.left[10], buffer.right[10])
+        };
+        let full = level(pad(0.5, 1000), 1.0);
+        let half = level(pad(0.5, 1000), 0.5);
+        let quiet = level(pad(0.5, 1000).gain(0.25), 1.0);
+        assert!((half.0 / full.0 - 0.5).abs() < 1e-4);
+        assert!((quiet.0 / full.0 - 0.25).abs() < 1e-4);
+
+        let right = level(pad(0.5, 1000).pan(1.0), 1.0);
+        assert!(right.0.abs() < 1e-4 && right.1 > full.1);
+    }
+
+    #[test]
+    fn choke_groups_cut_off_earlier_hits()
+    {
+        let mut kit = DrumKit::new();
+        kit.map(46, pad(0.5, 44100).choke(1));
+        kit.map(42, pad(0.25, 44100).choke(1));
+        kit.map(38, pad(0.1, 44100));
+        kit.hit(46, 1.0);
+        kit.hit(38, 1.0);
+        block(&mut kit);
+        assert_eq!(kit.active_voices(), 2);
+        kit.hit(42, 1.0);
+        assert_eq!(kit.active_voices(), 3);
+        let buffer = block(&mut kit);
+        assert_eq!(kit.active_voices(), 2);
+        assert!((buffer.left[255] / buffer.left[0] - 0.35 / 0.85).abs() < 1e-4);
+    }
+
+    #[test]
+    fn oldest_hit_is_stolen_past_the_polyphony()
+    {
+        let mut kit = DrumKit::with_polyphony(2);
+        kit.map(36, pad(0.5, 1000));
+        kit.map(38, pad(0.25, 1000));
+        kit.hit(36, 1.0);
+        kit.hit(38, 1.0);
+        kit.hit(38, 1.0);
+        assert_eq!(kit.active_voices(), 2);
+        let buffer = block(&mut kit);
+        let mut single = DrumKit::new();
+        single.map(38, pad(0.25, 1000));
+        single.hit(38, 1.0);
+        assert!((buffer.left[10] - 2.0 * block(&mut single).left[10]).abs() < 1e-6);
+    }
+
+    #[test]
+    fn pad_parameters_are_addressed_by_key()
+    {
+        let mut kit = DrumKit::new();
+        kit.map(36, pad(0.5, 1000));
+        assert!(kit.set_parameter("gain.36", 0.5));
+        assert!(kit.set_parameter("tune.36", 1200.0));
+        assert!(!kit.set_parameter("gain.37", 0.5));
+        assert!(!kit.set_parameter("gain", 0.5));
+        assert!(!kit.set_parameter("decay.36", 0.5));
+        assert_eq!(kit.pad(36).unwrap().gain, 0.5);
+        assert_eq!(kit.pad(36).unwrap().tune, 1200.0);
+    }
+}
@@ -0,0 +1,675 @@
+///FmPatch: Settings for the FM synthesizer: the operators, the algorithm
+///that connects them, and saving/loading of presets.
+#[allow(non_snake_case)]
+pub mod FmPatch
+{
+    use processing::synthesis::synthesis::Envelope::Adsr;
+    use serde::Deserialize;
+    use serde::Serialize;
+    use serde_json;
+    use std::fmt::Display;
+    use std::fmt::Error;
+    use std::fmt::Formatter;
+    use std::str::FromStr;
+
+    ///Amount of operators in every voice.
+    pub const OPERATORS: usize = 4;
+
+    ///OperatorSettings: ratio is the frequency multiplier from the note
+    ///pitch, detune is in cents, level is the output (carrier) or
+    ///modulation depth (modulator) and feedback is how much the operator
+    ///modulates itself.
+    #[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+    pub struct OperatorSettings
+    {
+        pub ratio: f32,
+        pub detune: f32,
+        pub level: f32,
+        pub feedback: f32,
+        pub envelope: Adsr,
+    }
+
+    impl OperatorSettings
+    {
+        ///OperatorSettings::new(ratio: f32, level: f32) -> OperatorSettings:
+        ///Operator with an organ style envelope and no feedback.
+        pub fn new(ratio: f32, level: f32) -> OperatorSettings
+        {
+            OperatorSettings{
+                ratio,
+                detune: 0.0,
+                level,
+                feedback: 0.0,
+                envelope: Adsr::new(2.0, 100.0, 1.0, 200.0),
+            }
+        }
+
+        ///OperatorSettings.envelope(envelope: Adsr) -> OperatorSettings
+        pub fn envelope(mut self, envelope: Adsr) -> OperatorSettings
+        {
+            self.envelope = envelope;
+            self
+        }
+
+        ///OperatorSettings.feedback(feedback: f32) -> OperatorSettings
+        pub fn feedback(mut self, feedback: f32) -> OperatorSettings
+        {
+            self.feedback = feedback;
+            self
+        }
+
+        ///OperatorSettings.detune(detune: f32) -> OperatorSettings
+        pub fn detune(mut self, detune: f32) -> OperatorSettings
+        {
+            self.detune = detune;
+            self
+        }
+    }
+
+    ///Algorithm: How operators are connected. routes are (modulator,
+    ///modulated) pairs and carriers are the operators that are heard.
+    ///Operators are numbered from 0 and a modulator must always have a
+    ///higher number than the operator it modulates, so voices can be
+    ///computed from the last operator down to the first.
+    #[derive(Clone, Debug, Eq, PartialEq, Serialize, Deserialize)]
+    pub struct Algorithm
+    {
+        routes: Vec<(usize, usize)>,
+        carriers: Vec<usize>,
+    }
+
+    impl Algorithm
+    {
+        ///Algorithm::new(routes: Vec<(usize, usize)>, carriers: Vec<usize>) -> Option<Algorithm>:
+        ///Custom algorithm. None if an operator is out of range, a route
+        ///points upwards or there are no carriers.
+        pub fn new(routes: Vec<(usize, usize)>, carriers: Vec<usize>) -> Option<Algorithm>
+        {
+            let algorithm = Algorithm{ routes, carriers };
+            if algorithm.is_valid()
+            {
+                Some(algorithm)
+            }
+            else
+            {
+                None
+            }
+        }
+
+        ///Algorithm.is_valid() -> bool: True if every operator is in range,
+        ///every route points downwards and there is at least one carrier.
+        pub fn is_valid(&self) -> bool
+        {
+            let routes_valid = self.routes.iter().all(|&(from, to)| from < OPERATORS && to < from);
+            let carriers_valid = !self.carriers.is_empty() && self.carriers.iter().all(|x| *x < OPERATORS);
+            routes_valid && carriers_valid
+        }
+
+        ///Algorithm::from_number(n: u32) -> Option<Algorithm>: One of the
+        ///eight built in four operator algorithms. Written with operators
+        ///numbered from 1 as on a front panel:
+        ///1: 4>3>2>1, 2: (4+3)>2>1, 3: (4>3)+2 > 1, 4: 4>1 and 3>2>1,
+        ///5: 4>3 and 2>1 with 1 and 3 heard, 6: 4 modulating 1, 2 and 3,
+        ///7: 4>3 with 1, 2 and 3 heard, 8: all four heard.
+        pub fn from_number(n: u32) -> Option<Algorithm>
+        {
+            let (routes, carriers) = match n
+            {
+                1 => (vec![(3, 2), (2, 1), (1, 0)], vec![0]),
+                2 => (vec![(3, 1), (2, 1), (1, 0)], vec![0]),
+                3 => (vec![(3, 2), (2, 0), (1, 0)], vec![0]),
+                4 => (vec![(3, 0), (2, 1), (1, 0)], vec![0]),
+                5 => (vec![(3, 2), (1, 0)], vec![0, 2]),
+                6 => (vec![(3, 0), (3, 1), (3, 2)], vec![0, 1, 2]),
+                7 => (vec![(3, 2)], vec![0, 1, 2]),
+                8 => (vec![], vec![0, 1, 2, 3]),
+                _ => return None,
+            };
+            Algorithm::new(routes, carriers)
+        }
+
+        pub fn routes(&self) -> &Vec<(usize, usize)>
+        {
+            &self.routes
+        }
+
+        pub fn carriers(&self) -> &Vec<usize>
+        {
+            &self.carriers
+        }
+    }
+
+    ///Patch: A complete FM preset. Presets are saved and loaded as JSON
+    ///through FromStr and Display.
+    #[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+    pub struct Patch
+    {
+        pub name: String,
+        pub algorithm: Algorithm,
+        pub operators: [OperatorSettings; OPERATORS],
+        pub gain: f32,
+    }
+
+    ///PresetError: Reason a preset could not be read.
+    #[derive(Debug)]
+    pub enum PresetError
+    {
+        Json(serde_json::Error),
+        InvalidAlgorithm,
+    }
+
+    impl Display for PresetError
+    {
+        fn fmt(&self, f: &mut Formatter) -> Result<(), Error>
+        {
+            match *self
+            {
+                PresetError::Json(ref e) => write!(f, "json error: {}", e),
+                PresetError::InvalidAlgorithm => write!(f, "invalid algorithm"),
+            }
+        }
+    }
+
+    impl From<serde_json::Error> for PresetError
+    {
+        fn from(e: serde_json::Error) -> PresetError
+        {
+            PresetError::Json(e)
+        }
+    }
+
+    impl Patch
+    {
+        ///Patch::electric_piano() -> Patch: Tine style electric piano.
+        pub fn electric_piano() -> Patch
+        {
+            Patch{
+                name: "Electric Piano".to_string(),
+                algorithm: Algorithm::from_number(5).unwrap(),
+                operators: [
+                    OperatorSettings::new(1.0, 1.0).envelope(Adsr::new(1.0, 1500.0, 0.2, 400.0)),
+                    OperatorSettings::new(1.0, 0.35).envelope(Adsr::new(1.0, 600.0, 0.1, 300.0)),
+                    OperatorSettings::new(1.0, 0.5).envelope(Adsr::new(1.0, 800.0, 0.0, 300.0)).detune(3.0),
+                    OperatorSettings::new(14.0, 0.15).envelope(Adsr::new(1.0, 120.0, 0.0, 100.0)),
+                ],
+                gain: 0.4,
+            }
+        }
+
+        ///Patch::bell() -> Patch: Inharmonic bell with a long decay.
+        pub fn bell() -> Patch
+        {
+            Patch{
+                name: "Bell".to_string(),
+                algorithm: Algorithm::from_number(5).unwrap(),
+                operators: [
+                    OperatorSettings::new(1.0, 1.0).envelope(Adsr::new(1.0, 4000.0, 0.0, 2000.0)),
+                    OperatorSettings::new(3.5, 0.6).envelope(Adsr::new(1.0, 2500.0, 0.0, 1500.0)),
+                    OperatorSettings::new(2.0, 0.5).envelope(Adsr::new(1.0, 3000.0, 0.0, 2000.0)),
+                    OperatorSettings::new(5.19, 0.4).envelope(Adsr::new(1.0, 1500.0, 0.0, 1000.0)).feedback(0.2),
+                ],
+                gain: 0.4,
+            }
+        }
+    }
+
+    ///Patch::from_str(text: &str) -> Result<Patch, PresetError>: Reads a
+    ///JSON preset. The algorithm is checked the same way Algorithm::new
+    ///checks it.
+    impl FromStr for Patch
+    {
+        type Err = PresetError;
+
+        fn from_str(text: &str) -> Result<Patch, PresetError>
+        {
+            let patch: Patch = serde_json::from_str(text)?;
+            if !patch.algorithm.is_valid()
+            {
+                return Err(PresetError::InvalidAlgorithm);
+            }
+            Ok(patch)
+        }
+    }
+
+    ///Patch::fmt()...: Writes the preset as JSON.
+    impl Display for Patch
+    {
+        fn fmt(&self, f: &mut Formatter) -> Result<(), Error>
+        {
+            let text = serde_json::to_string_pretty(self).map_err(|_| Error)?;
+            write!(f, "{}", text)
+        }
+    }
+}
+
+///FmSynth: Polyphonic phase modulation synthesizer built from the
+///operators and algorithm of an FmPatch.
+#[allow(non_snake_case)]
+pub mod FmSynth
+{
+    use instrumentation::interface::instrument::Instrument;
+    use instrumentation::internal::fm::FmPatch::OPERATORS;
+    use instrumentation::internal::fm::FmPatch::Patch;
+    use processing::buffer::buffer::AudioBuffer::SAMPLE_RATE;
+    use processing::buffer::buffer::AudioBuffer::StereoBuffer;
+    use processing::synthesis::synthesis::Envelope::EnvelopeGenerator;
+    use processing::synthesis::synthesis::Portamento::Glide;
+    use sequencing::tonation::note::NoteCollections::MAX_AMPLITUDE;
+    use sequencing::tonation::note::NoteCollections::RawNote;
+    use sequencing::tonation::note::NotePrimitives;
+    use std::f32::consts::PI;
+
+    ///Default amount of voices that can sound at once.
+    pub const DEFAULT_POLYPHONY: usize = 8;
+
+    ///Phase modulation depth, in cycles, of a modulator at full level.
+    const MODULATION_SCALE: f32 = 1.0;
+
+    #[derive(Clone, Debug)]
+    struct Voice
+    {
+        key: u8,
+        pitch: Glide,
+        pitch_hz: f32,
+        velocity: f32,
+        phases: [f32; OPERATORS],
+        envelopes: Vec<EnvelopeGenerator>,
+        previous: [[f32; 2]; OPERATORS],
+        gate_samples: u64,
+        age: u64,
+    }
+
+    impl Voice
+    {
+        fn new(patch: &Patch) -> Voice
+        {
+            Voice{
+                key: 0,
+                pitch: Glide::new(0.0),
+                pitch_hz: 0.0,
+                velocity: 0.0,
+                phases: [0.0; OPERATORS],
+                envelopes: patch.operators.iter().map(|x| EnvelopeGenerator::new(x.envelope)).collect(),
+                previous: [[0.0; 2]; OPERATORS],
+                gate_samples: 0,
+                age: 0,
+            }
+        }
+
+        ///A voice lasts as long as any of its carriers is still sounding.
+        fn is_active(&self, patch: &Patch) -> bool
+        {
+            patch.algorithm.carriers().iter().any(|x| self.envelopes[*x].is_active())
+        }
+
+        fn tick(&mut self, patch: &Patch) -> f32
+        {
+            if self.gate_samples == 0
+            {
+                for i in self.envelopes.iter_mut()
+                {
+                    i.gate_off();
+                }
+            }
+            else
+            {
+                self.gate_samples -= 1;
+            }
+
+            self.pitch_hz = self.pitch.tick();
+            let mut outputs = [0.0; OPERATORS];
+            for op in (0..OPERATORS).rev()
+            {
+                let settings = &patch.operators[op];
+                let mut modulation = 0.0;
+                for &(from, to) in patch.algorithm.routes().iter()
+                {
+                    if to == op
+                    {
+                        modulation += outputs[from] * MODULATION_SCALE;
+                    }
+                }
+                let feedback = (self.previous[op][0] + self.previous[op][1]) * 0.5 * settings.feedback;
+
+                let value = (2.0 * PI * (self.phases[op] + modulation + feedback)).sin();
+                outputs[op] = value * self.envelopes[op].tick() * settings.level;
+                self.previous[op] = [outputs[op], self.previous[op][0]];
+
+                let frequency = self.pitch_hz * settings.ratio * 2f32.powf(settings.detune / 1200.0);
+                self.phases[op] += frequency / SAMPLE_RATE as f32;
+                self.phases[op] -= self.phases[op].floor();
+            }
+
+            let carriers = patch.algorithm.carriers();
+            let sum: f32 = carriers.iter().map(|x| outputs[*x]).sum();
+            sum / carriers.len() as f32 * self.velocity
+        }
+    }
+
+    pub struct FmSynth
+    {
+        patch: Patch,
+        voices: Vec<Voice>,
+        clock: u64,
+    }
+
+    impl FmSynth
+    {
+        ///FmSynth::new(patch: Patch) -> FmSynth: Generates a synth with
+        ///DEFAULT_POLYPHONY voices.
+        pub fn new(patch: Patch) -> FmSynth
+        {
+            FmSynth::with_polyphony(patch, DEFAULT_POLYPHONY)
+        }
+
+        ///FmSynth::with_polyphony(patch: Patch, voices: usize) -> FmSynth
+        pub fn with_polyphony(patch: Patch, voices: usize) -> FmSynth
+        {
+            let voices = if voices < 1 { 1 } else { voices };
+            FmSynth{
+                voices: (0..voices).map(|_| Voice::new(&patch)).collect(),
+                patch,
+                clock: 0,
+            }
+        }
+
+        pub fn patch(&self) -> &Patch
+        {
+            &self.patch
+        }
+
+        ///FmSynth.set_patch(patch: Patch): Loads a preset. Playing voices
+        ///are silenced.
+        pub fn set_patch(&mut self, patch: Patch)
+        {
+            let count = self.voices.len();
+            self.voices = (0..count).map(|_| Voice::new(&patch)).collect();
+            self.patch = patch;
+        }
+
+        ///FmSynth.active_voices() -> usize: Voices that are still sounding.
+        pub fn active_voices(&self) -> usize
+        {
+            let patch = &self.patch;
+            self.voices.iter().filter(|x| x.is_active(patch)).count()
+        }
+
+        ///FmSynth.free_voice() -> usize: Index of an idle voice, or the
+        ///oldest releasing voice, or the oldest voice if every voice is
+        ///held.
+        fn free_voice(&self) -> usize
+        {
+            if let Some(index) = self.voices.iter().position(|x| !x.is_active(&self.patch))
+            {
+                return index;
+            }
+            (0..self.voices.len()).min_by_key(|x| (self.voices[*x].gate_samples > 0, self.voices[*x].age)).unwrap_or(0)
+        }
+
+        ///FmSynth.start(key, pitch_hz, velocity, gate_samples) -> Result<(), PlayError>:
+        ///Starts a voice at pitch_hz with a velocity from 0.0 to 1.0 that is
+        ///released after gate_samples samples.
+        fn start(&mut self, key: u8, pitch_hz: f32, velocity: f32, gate_samples: u64) -> Result<(), Instrument::PlayError>
+        {
+            if pitch_hz <= 0.0 || pitch_hz >= SAMPLE_RATE as f32 / 2.0
+            {
+                return Err(Instrument::PlayError::OutOfRange(key));
+            }
+
+            let index = self.free_voice();
+            self.clock += 1;
+
+            let voice = &mut self.voices[index];
+            voice.key = key;
+            voice.pitch = Glide::new(pitch_hz);
+            voice.pitch_hz = pitch_hz;
+            voice.velocity = velocity.clamp(0.0, 1.0);
+            voice.gate_samples = gate_samples;
+            voice.age = self.clock;
+            voice.phases = [0.0; OPERATORS];
+            voice.previous = [[0.0; 2]; OPERATORS];
+            for i in voice.envelopes.iter_mut()
+            {
+                i.gate_on();
+            }
+            Ok(())
+        }
+
+        ///FmSynth.render(buffer: &mut StereoBuffer): Overwrites buffer with
+        ///the next block of audio from every active voice.
+        pub fn render(&mut self, buffer: &mut StereoBuffer)
+        {
+            buffer.clear();
+            let patch = &self.patch;
+            for i in 0..buffer.len()
+            {
+                let mut sample = 0.0;
+                for voice in self.voices.iter_mut()
+                {
+                    if voice.is_active(patch)
+                    {
+                        sample += voice.tick(patch);
+                    }
+                }
+                sample *= patch.gain;
+                buffer.left[i] = sample;
+                buffer.right[i] = sample;
+            }
+        }
+    }
+
+    impl Instrument::PlayableInstrument for FmSynth
+    {
+        ///Pitches at or above nyquist are refused.
+        fn play(&mut self, note: &RawNote) -> Result<(), Instrument::PlayError>
+        {
+            let gate = note.length as u64 * SAMPLE_RATE as u64 / 1000;
+            self.start(note.key(), note.pitch_hz, note.amplitude / MAX_AMPLITUDE, gate)
+        }
+
+        ///Starts a voice that is held until note_off.
+        fn note_on(&mut self, key: u8, velocity: u8) -> Result<(), Instrument::PlayError>
+        {
+            self.start(key, NotePrimitives::midi_to_herz(key as f32), velocity as f32 / MAX_AMPLITUDE, u64::MAX)
+        }
+
+        fn note_off(&mut self, key: u8)
+        {
+            for voice in self.voices.iter_mut().filter(|x| x.key == key)
+            {
+                voice.gate_samples = 0;
+            }
+        }
+
+        fn all_notes_off(&mut self)
+        {
+            for voice in self.voices.iter_mut()
+            {
+                voice.gate_samples = 0;
+            }
+        }
+
+        fn process(&mut self, buffer: &mut StereoBuffer)
+        {
+            self.render(buffer);
+        }
+
+        fn stop(&mut self, key: u8, held: bool)
+        {
+            let patch = &self.patch;
+            if let Some(voice) = self.voices.iter_mut().filter(|x| x.key == key && x.is_active(patch) && (x.gate_samples > 0) == held).min_by_key(|x| x.age)
+            {
+                for i in voice.envelopes.iter_mut()
+                {
+                    i.kill();
+                }
+            }
+        }
+
+        ///Grows or shrinks the voice pool. Voices cut from the end of the
+        ///pool are silenced.
+        fn set_polyphony(&mut self, voices: usize)
+        {
+            let voice = Voice::new(&self.patch);
+            self.voices.resize(voices.max(1), voice);
+        }
+
+        ///Slides the held voice playing key to the new note without
+        ///retriggering its envelopes.
+        fn glide(&mut self, key: u8, note: &RawNote, time: f32) -> bool
+        {
+            let patch = &self.patch;
+            match self.voices.iter_mut().find(|x| x.key == key && x.gate_samples > 0 && x.is_active(patch))
+            {
+                Some(voice) =>
+                {
+                    voice.key = note.key();
+                    voice.pitch.glide_to(note.pitch_hz, time);
+                    voice.gate_samples = note.length as u64 * SAMPLE_RATE as u64 / 1000;
+                    true
+                },
+                None => false,
+            }
+        }
+
+        fn set_parameter(&mut self, name: &str, value: f32) -> bool
+        {
+            if name == "gain"
+            {
+                self.patch.gain = value;
+                return true;
+            }
+
+            //Operator parameters are addressed as "level.1" through "level.4".
+            let parts: Vec<&str> = name.split('.').collect();
+            let op = match (parts.len(), parts.get(1).and_then(|x| x.parse::<usize>().ok()))
+            {
+                (2, Some(x)) if (1..=OPERATORS).contains(&x) => x - 1,
+                _ => return false,
+            };
+            match parts[0]
+            {
+                "level" => self.patch.operators[op].level = value,
+                "ratio" => self.patch.operators[op].ratio = value,
+                "feedback" => self.patch.operators[op].feedback = value,
+                "detune" => self.patch.operators[op].detune = value,
+                _ => return false,
+            }
+            true
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use instrumentation::interface::instrument::Instrument::PlayableInstrument;
+    use instrumentation::internal::fm::FmPatch::Algorithm;
+    use instrumentation::internal::fm::FmPatch::OperatorSettings;
+    use instrumentation::internal::fm::FmPatch::Patch;
+    use instrumentation::internal::fm::FmPatch::PresetError;
+    use instrumentation::internal::fm::FmSynth::FmSynth;
+    use processing::buffer::buffer::AudioBuffer::SAMPLE_RATE;
+    use processing::buffer::buffer::AudioBuffer::StereoBuffer;
+    use processing::synthesis::synthesis::Envelope::Adsr;
+    use sequencing::tonation::note::NoteCollections::RawNote;
+    use sequencing::tonation::note::NotePrimitives::Register;
+    use sequencing::tonation::note::NotePrimitives::Semitone;
+
+    //Four operators with a flat envelope; only operator 1 is heard and
+    //modulator sets the level of operator 2 modulating it.
+    fn two_operator_patch(modulator: f32) -> Patch
+    {
+        let flat = Adsr::new(0.0, 1.0, 1.0, 5.0);
+        Patch{
+            name: "test".to_string(),
+            algorithm: Algorithm::new(vec![(1, 0)], vec![0]).unwrap(),
+            operators: [
+                OperatorSettings::new(1.0, 1.0).envelope(flat),
+                OperatorSettings::new(3.0, modulator).envelope(flat),
+                OperatorSettings::new(1.0, 0.0).envelope(flat),
+                OperatorSettings::new(1.0, 0.0).envelope(flat),
+            ],
+            gain: 1.0,
+        }
+    }
+
+    fn render_note(patch: Patch, frames: usize) -> StereoBuffer
+    {
+        let mut synth = FmSynth::new(patch);
+        synth.play(&RawNote::new(Semitone::A, Register::C4, 0.0, 127.0, 1000)).unwrap();
+        let mut buffer = StereoBuffer::new(frames);
+        synth.render(&mut buffer);
+        buffer
+    }
+
+    fn zero_crossings(buffer: &StereoBuffer) -> usize
+    {
+        buffer.left.windows(2).filter(|x| x[0] < 0.0 && x[1] >= 0.0).count()
+    }
+
+    #[test]
+    fn unmodulated_carrier_is_a_sine_at_the_note_pitch()
+    {
+        let buffer = render_note(two_operator_patch(0.0), SAMPLE_RATE as usize / 10);
+        let note = RawNote::new(Semitone::A, Register::C4, 0.0, 127.0, 1000);
+        let expected = (note.pitch_hz / 10.0) as i64;
+        assert!((zero_crossings(&buffer) as i64 - expected).abs() <= 1);
+        assert!(buffer.peak() > 0.9 && buffer.peak() <= 1.0);
+    }
+
+    #[test]
+    fn modulator_adds_sidebands()
+    {
+        let plain = render_note(two_operator_patch(0.0), 4410);
+        let modulated = render_note(two_operator_patch(1.0), 4410);
+        assert!(zero_crossings(&modulated) > zero_crossings(&plain));
+    }
+
+    #[test]
+    fn feedback_changes_the_carrier()
+    {
+        let plain = render_note(two_operator_patch(0.0), 512);
+        let mut patch = two_operator_patch(0.0);
+        patch.operators[0] = patch.operators[0].feedback(0.8);
+        let fed_back = render_note(patch, 512);
+        assert!(plain.left.iter().zip(fed_back.left.iter()).any(|(a, b)| (a - b).abs() > 0.1));
+    }
+
+    #[test]
+    fn algorithms_reject_upward_routes()
+    {
+        for i in 1..9
+        {
+            assert!(Algorithm::from_number(i).is_some());
+        }
+        assert!(Algorithm::from_number(9).is_none());
+        assert!(Algorithm::new(vec![(0, 1)], vec![0]).is_none());
+        assert!(Algorithm::new(vec![(1, 0)], vec![]).is_none());
+        assert!(Algorithm::new(vec![], vec![4]).is_none());
+    }
+
+    #[test]
+    fn presets_round_trip_through_text()
+    {
+        for patch in [Patch::electric_piano(), Patch::bell()].iter()
+        {
+            let text = patch.to_string();
+            assert_eq!(&text.parse::<Patch>().unwrap(), patch);
+        }
+    }
+
+    #[test]
+    fn preset_with_invalid_algorithm_is_rejected()
+    {
+        let text = Patch::bell().to_string().replace("\"carriers\": [\n      0,\n      2\n    ]", "\"carriers\": []");
+        match text.parse::<Patch>()
+        {
+            Err(PresetError::InvalidAlgorithm) =>
+            {
+            },
+            x => panic!("expected an invalid algorithm, got {:?}", x),
+        }
+        assert!("{".parse::<Patch>().is_err());
+    }
+}
@@ -0,0 +1,6 @@
+pub mod drumkit;
+pub mod fm;
+pub mod sampler;
+pub mod sfz;
+pub mod soundfont;
+pub mod subtractive;
@@ -0,0 +1,562 @@
+///SamplerZone: A sample mapped onto a range of keys and velocities, along
+///with how it is tuned, looped and enveloped.
+#[allow(non_snake_case)]
+pub mod SamplerZone
+{
+    use processing::sample::sample::Loader;
+    use processing::sample::sample::SampleData::SampleData;
+    use processing::sample::sample::SampleData::SampleError;
+    use processing::synthesis::synthesis::Envelope::Adsr;
+    use std::sync::Arc;
+
+    ///LoopMode: NoLoop plays the sample once and stops early on release,
+    ///OneShot always plays the whole sample and ignores the release,
+    ///Continuous loops for as long as the voice sounds and Sustain loops
+    ///only while the note is held, then plays out the rest of the sample.
+    #[derive(Clone, Copy, Debug, Eq, PartialEq)]
+    pub enum LoopMode
+    {
+        NoLoop,
+        OneShot,
+        Continuous,
+        Sustain,
+    }
+
+    ///ZoneFilter: Resonant low pass applied to each voice of a zone. cutoff
+    ///is in hz and resonance runs from 0 to 1. envelope_amount is how many
+    ///octaves the filter envelope opens the cutoff, velocity_amount how
+    ///many octaves it moves at velocity 0, fading out towards velocity 127.
+    #[derive(Clone, Copy, Debug)]
+    pub struct ZoneFilter
+    {
+        pub cutoff: f32,
+        pub resonance: f32,
+        pub envelope: Adsr,
+        pub envelope_amount: f32,
+        pub velocity_amount: f32,
+    }
+
+    impl ZoneFilter
+    {
+        ///ZoneFilter::new(cutoff: f32, resonance: f32) -> ZoneFilter: Static
+        ///filter with no envelope or velocity tracking.
+        pub fn new(cutoff: f32, resonance: f32) -> ZoneFilter
+        {
+            ZoneFilter{
+                cutoff,
+                resonance,
+                envelope: Adsr::new(0.0, 0.0, 1.0, 0.0),
+                envelope_amount: 0.0,
+                velocity_amount: 0.0,
+            }
+        }
+    }
+
+    ///Zone: lo/hi key and velocity ranges are inclusive. root_key is the
+    ///midi note the sample was recorded at and tune is in cents. Loop
+    ///points are in frames of the sample.
+    #[derive(Clone, Debug)]
+    pub struct Zone
+    {
+        pub sample: Arc<SampleData>,
+        pub lokey: u8,
+        pub hikey: u8,
+        pub lovel: u8,
+        pub hivel: u8,
+        pub root_key: u8,
+        pub tune: f32,
+        pub gain: f32,
+        pub pan: f32,
+        pub loop_mode: LoopMode,
+        pub loop_start: usize,
+        pub loop_end: usize,
+        pub envelope: Adsr,
+        pub filter: Option<ZoneFilter>,
+    }
+
+    impl Zone
+    {
+        ///Zone::new(sample: Arc<SampleData>, root_key: u8) -> Zone: Zone that
+        ///covers every key and velocity. Loop points stored in the file
+        ///are picked up and turn on a sustain loop.
+        pub fn new(sample: Arc<SampleData>, root_key: u8) -> Zone
+        {
+            let (loop_mode, loop_start, loop_end) = match sample.loop_points
+            {
+                Some((start, end)) => (LoopMode::Sustain, start, end),
+                None => (LoopMode::NoLoop, 0, sample.frames()),
+            };
+            Zone{
+                sample,
+                lokey: 0,
+                hikey: 127,
+                lovel: 0,
+                hivel: 127,
+                root_key,
+                tune: 0.0,
+                gain: 1.0,
+                pan: 0.0,
+                loop_mode,
+                loop_start,
+                loop_end,
+                envelope: Adsr::new(0.0, 0.0, 1.0, 50.0),
+                filter: None,
+            }
+        }
+
+        ///Zone::from_file(path: &str, root_key: u8) -> Result<Zone, SampleError>:
+        ///Loads a WAV or AIFF file into a new zone.
+        pub fn from_file(path: &str, root_key: u8) -> Result<Zone, SampleError>
+        {
+            Ok(Zone::new(Arc::new(Loader::load(path)?), root_key))
+        }
+
+        ///Zone.keys(lokey: u8, hikey: u8) -> Zone: Builder style key range.
+        pub fn keys(mut self, lokey: u8, hikey: u8) -> Zone
+        {
+            self.lokey = lokey;
+            self.hikey = hikey;
+            self
+        }
+
+        ///Zone.velocities(lovel: u8, hivel: u8) -> Zone: Builder style
+        ///velocity range.
+        pub fn velocities(mut self, lovel: u8, hivel: u8) -> Zone
+        {
+            self.lovel = lovel;
+            self.hivel = hivel;
+            self
+        }
+
+        ///Zone.looped(mode: LoopMode, start: usize, end: usize) -> Zone:
+        ///Builder style loop settings.
+        pub fn looped(mut self, mode: LoopMode, start: usize, end: usize) -> Zone
+        {
+            self.loop_mode = mode;
+            self.loop_start = start;
+            self.loop_end = end;
+            self
+        }
+
+        ///Zone.envelope(envelope: Adsr) -> Zone: Builder style envelope.
+        pub fn envelope(mut self, envelope: Adsr) -> Zone
+        {
+            self.envelope = envelope;
+            self
+        }
+
+        ///Zone.filtered(filter: ZoneFilter) -> Zone: Builder style filter.
+        pub fn filtered(mut self, filter: ZoneFilter) -> Zone
+        {
+            self.filter = Some(filter);
+            self
+        }
+
+        ///Zone.contains(key: u8, velocity: u8) -> bool
+        pub fn contains(&self, key: u8, velocity: u8) -> bool
+        {
+            key >= self.lokey && key <= self.hikey && velocity >= self.lovel && velocity <= self.hivel
+        }
+
+        ///Zone.has_loop() -> bool: Whether the loop points describe a usable
+        ///loop for the current mode.
+        pub fn has_loop(&self) -> bool
+        {
+            (self.loop_mode == LoopMode::Continuous || self.loop_mode == LoopMode::Sustain) &&
+                self.loop_end > self.loop_start + 1 &&
+                self.loop_end <= self.sample.frames()
+        }
+    }
+}
+
+///Sampler: Multi-sample instrument. A note plays every zone that covers
+///its key and velocity, resampled with cubic interpolation so any
+///pitch_hz can be reached from the zone's root key. Zones with a filter
+///run each voice through its own low pass.
+#[allow(non_snake_case)]
+pub mod Sampler
+{
+    use instrumentation::interface::instrument::Instrument;
+    use instrumentation::internal::sampler::SamplerZone::LoopMode;
+    use instrumentation::internal::sampler::SamplerZone::Zone;
+    use instrumentation::internal::sampler::SamplerZone::ZoneFilter;
+    use processing::buffer::buffer::AudioBuffer::SAMPLE_RATE;
+    use processing::buffer::buffer::AudioBuffer::StereoBuffer;
+    use processing::mixing::mixer::MixerPrimitives;
+    use processing::synthesis::synthesis::Envelope::EnvelopeGenerator;
+    use processing::synthesis::synthesis::VoiceFilter::FilterMode;
+    use processing::synthesis::synthesis::VoiceFilter::StateVariable;
+    use sequencing::tonation::note::NoteCollections::MAX_AMPLITUDE;
+    use sequencing::tonation::note::NoteCollections::RawNote;
+    use sequencing::tonation::note::NotePrimitives;
+
+    ///Default amount of voices that can sound at once.
+    pub const DEFAULT_POLYPHONY: usize = 16;
+
+    #[derive(Clone, Debug)]
+    struct Voice
+    {
+        key: u8,
+        zone: usize,
+        position: f64,
+        increment: f64,
+        envelope: EnvelopeGenerator,
+        filter_envelope: EnvelopeGenerator,
+        filter_left: StateVariable,
+        filter_right: StateVariable,
+        cutoff: f32,
+        gain: f32,
+        gate_samples: u64,
+        held: bool,
+        active: bool,
+        age: u64,
+    }
+
+    ///is_held(voice: &Voice) -> bool: Whether the voice's note has not
+    ///been released yet.
+    fn is_held(voice: &Voice) -> bool
+    {
+        voice.held && voice.gate_samples > 0
+    }
+
+    ///hermite(x0, x1, x2, x3, t) -> f32: 4 point, 3rd order Hermite
+    ///interpolation between x1 and x2.
+    fn hermite(x0: f32, x1: f32, x2: f32, x3: f32, t: f32) -> f32
+    {
+        let c1 = 0.5 * (x2 - x0);
+        let c2 = x0 - 2.5 * x1 + 2.0 * x2 - 0.5 * x3;
+        let c3 = 0.5 * (x3 - x0) + 1.5 * (x1 - x2);
+        ((c3 * t + c2) * t + c1) * t + x1
+    }
+
+    pub struct Sampler
+    {
+        zones: Vec<Zone>,
+        voices: Vec<Voice>,
+        polyphony: usize,
+        clock: u64,
+    }
+
+    impl Sampler
+    {
+        ///Sampler::new() -> Sampler: Empty sampler with DEFAULT_POLYPHONY
+        ///voices.
+        pub fn new() -> Sampler
+        {
+            Sampler::with_polyphony(DEFAULT_POLYPHONY)
+        }
+
+        ///Sampler::with_polyphony(voices: usize) -> Sampler
+        pub fn with_polyphony(voices: usize) -> Sampler
+        {
+            Sampler{
+                zones: Vec::new(),
+                voices: Vec::new(),
+                polyphony: if voices < 1 { 1 } else { voices },
+                clock: 0,
+            }
+        }
+
+        ///Sampler.add_zone(zone: Zone) -> usize: Adds a zone and returns its
+        ///index.
+        pub fn add_zone(&mut self, zone: Zone) -> usize
+        {
+            self.zones.push(zone);
+            self.zones.len() - 1
+        }
+
+        ///Sampler.zone(index: usize) -> Option<&mut Zone>
+        pub fn zone(&mut self, index: usize) -> Option<&mut Zone>
+        {
+            self.zones.get_mut(index)
+        }
+
+        pub fn zones(&self) -> &Vec<Zone>
+        {
+            &self.zones
+        }
+
+        ///Sampler.active_voices() -> usize: Voices that are still sounding.
+        pub fn active_voices(&self) -> usize
+        {
+            self.voices.iter().filter(|x| x.active).count()
+        }
+
+        ///Sampler.start_voice(zone, key, pitch_hz, velocity, gate_samples): Starts
+        ///zone playing at pitch_hz with a velocity from 0.0 to 1.0, held for
+        ///gate_samples samples. Steals the oldest releasing voice, or the
+        ///oldest voice if every voice is held.
+        fn start_voice(&mut self, zone: usize, key: u8, pitch_hz: f32, velocity: f32, gate_samples: u64)
+        {
+            let settings = &self.zones[zone];
+            let velocity = velocity.clamp(0.0, 1.0);
+            let filter = settings.filter.unwrap_or(ZoneFilter::new(0.0, 0.0));
+            let root_hz = NotePrimitives::midi_to_herz(settings.root_key as f32 + settings.tune / 100.0);
+            let mut voice = Voice{
+                key,
+                zone,
+                position: 0.0,
+                increment: (pitch_hz / root_hz) as f64 * settings.sample.sample_rate as f64 / SAMPLE_RATE as f64,
+                envelope: EnvelopeGenerator::new(settings.envelope),
+                filter_envelope: EnvelopeGenerator::new(filter.envelope),
+                filter_left: StateVariable::new(FilterMode::LowPass),
+                filter_right: StateVariable::new(FilterMode::LowPass),
+                cutoff: filter.cutoff * 2f32.powf(filter.velocity_amount * (1.0 - velocity)),
+                gain: settings.gain * velocity,
+                gate_samples,
+                held: true,
+                active: true,
+                age: self.clock,
+            };
+            voice.envelope.gate_on();
+            voice.filter_envelope.gate_on();
+
+            let free = self.voices.iter().position(|x| !x.active);
+            match free
+            {
+                Some(index) => self.voices[index] = voice,
+                None if self.voices.len() < self.polyphony => self.voices.push(voice),
+                None =>
+                {
+                    let oldest = (0..self.voices.len()).min_by_key(|x| (is_held(&self.voices[*x]), self.voices[*x].age)).unwrap();
+                    self.voices[oldest] = voice;
+                },
+            }
+        }
+
+        ///Sampler.trigger(key, velocity, pitch_hz, gate_samples) -> Result<(), PlayError>:
+        ///Starts every zone covering key and velocity. The voices of one
+        ///note share their age.
+        fn trigger(&mut self, key: u8, velocity: u8, pitch_hz: f32, gate_samples: u64) -> Result<(), Instrument::PlayError>
+        {
+            let matching: Vec<usize> = (0..self.zones.len())
+                .filter(|x| self.zones[*x].contains(key, velocity) && self.zones[*x].sample.frames() > 0)
+                .collect();
+            if matching.is_empty()
+            {
+                return Err(Instrument::PlayError::MissingSample(key));
+            }
+            self.clock += 1;
+            for zone in matching
+            {
+                self.start_voice(zone, key, pitch_hz, velocity as f32 / MAX_AMPLITUDE, gate_samples);
+            }
+            Ok(())
+        }
+
+        ///Sampler.render(buffer: &mut StereoBuffer): Overwrites buffer with
+        ///the next block of audio from every active voice.
+        pub fn render(&mut self, buffer: &mut StereoBuffer)
+        {
+            buffer.clear();
+            let zones = &self.zones;
+            for voice in self.voices.iter_mut().filter(|x| x.active)
+            {
+                let zone = &zones[voice.zone];
+                let frames = zone.sample.frames();
+                let (pan_left, pan_right) = MixerPrimitives::pan_to_gains(zone.pan);
+
+                for i in 0..buffer.len()
+                {
+                    if voice.gate_samples == 0 && voice.held
+                    {
+                        voice.held = false;
+                        if zone.loop_mode != LoopMode::OneShot
+                        {
+                            voice.envelope.gate_off();
+                            voice.filter_envelope.gate_off();
+                        }
+                    }
+                    else if voice.held
+                    {
+                        voice.gate_samples -= 1;
+                    }
+
+                    let looping = zone.has_loop() && (zone.loop_mode == LoopMode::Continuous || voice.held);
+                    if looping && voice.position >= zone.loop_end as f64
+                    {
+                        voice.position -= (zone.loop_end - zone.loop_start) as f64;
+                    }
+                    if voice.position >= frames as f64 || !voice.envelope.is_active()
+                    {
+                        voice.active = false;
+                        break;
+                    }
+
+                    let index = voice.position as usize;
+                    let t = (voice.position - index as f64) as f32;
+                    //Neighbouring frames wrap around the loop while looping.
+                    let at = |offset: isize| -> (f32, f32) {
+                        let mut position = index as isize + offset;
+                        if looping && position >= zone.loop_end as isize
+                        {
+                            position -= (zone.loop_end - zone.loop_start) as isize;
+                        }
+                        if position < 0 { (0.0, 0.0) } else { zone.sample.frame(position as usize) }
+                    };
+                    let (a, b, c, d) = (at(-1), at(0), at(1), at(2));
+                    let mut left = hermite(a.0, b.0, c.0, d.0, t);
+                    let mut right = hermite(a.1, b.1, c.1, d.1, t);
+                    if let Some(ref filter) = zone.filter
+                    {
+                        let cutoff = voice.cutoff * 2f32.powf(voice.filter_envelope.tick() * filter.envelope_amount);
+                        left = voice.filter_left.tick(left, cutoff, filter.resonance);
+                        right = voice.filter_right.tick(right, cutoff, filter.resonance);
+                    }
+
+                    let gain = voice.envelope.tick() * voice.gain;
+                    buffer.left[i] += left * gain * pan_left;
+                    buffer.right[i] += right * gain * pan_right;
+                    voice.position += voice.increment;
+                }
+            }
+        }
+    }
+
+    impl Default for Sampler
+    {
+        fn default() -> Sampler
+        {
+            Sampler::new()
+        }
+    }
+
+    impl Instrument::PlayableInstrument for Sampler
+    {
+        ///Triggers every zone mapped to the key and velocity of note.
+        ///Fails if no zone with sample data covers them.
+        fn play(&mut self, note: &RawNote) -> Result<(), Instrument::PlayError>
+        {
+            let gate = note.length as u64 * SAMPLE_RATE as u64 / 1000;
+            self.trigger(note.key(), note.velocity(), note.pitch_hz, gate)
+        }
+
+        fn note_on(&mut self, key: u8, velocity: u8) -> Result<(), Instrument::PlayError>
+        {
+            self.trigger(key, velocity, NotePrimitives::midi_to_herz(key as f32), u64::MAX)
+        }
+
+        fn note_off(&mut self, key: u8)
+        {
+            for voice in self.voices.iter_mut().filter(|x| x.key == key)
+            {
+                voice.gate_samples = 0;
+            }
+        }
+
+        fn all_notes_off(&mut self)
+        {
+            for voice in self.voices.iter_mut()
+            {
+                voice.gate_samples = 0;
+            }
+        }
+
+        fn process(&mut self, buffer: &mut StereoBuffer)
+        {
+            self.render(buffer);
+        }
+
+        ///Cuts off every layer of the oldest note on key.
+        fn stop(&mut self, key: u8, held: bool)
+        {
+            let age = self.voices.iter().filter(|x| x.key == key && x.active && is_held(x) == held).map(|x| x.age).min();
+            for voice in self.voices.iter_mut().filter(|x| x.key == key && Some(x.age) == age)
+            {
+                voice.active = false;
+            }
+        }
+
+        ///Limits the voices that can sound at once, silencing the voices
+        ///past the new limit.
+        fn set_polyphony(&mut self, voices: usize)
+        {
+            self.polyphony = voices.max(1);
+            self.voices.truncate(self.polyphony);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use instrumentation::interface::instrument::Instrument::PlayError;
+    use instrumentation::interface::instrument::Instrument::PlayableInstrument;
+    use instrumentation::internal::sampler::Sampler::Sampler;
+    use instrumentation::internal::sampler::SamplerZone::LoopMode;
+    use instrumentation::internal::sampler::SamplerZone::Zone;
+    use processing::buffer::buffer::AudioBuffer::SAMPLE_RATE;
+    use processing::buffer::buffer::AudioBuffer::StereoBuffer;
+    use processing::sample::sample::SampleData::SampleData;
+    use sequencing::tonation::note::NoteCollections::RawNote;
+    use sequencing::tonation::note::NotePrimitives::Register;
+    use sequencing::tonation::note::NotePrimitives::Semitone;
+    use std::sync::Arc;
+
+    //One second of a constant level, so the output level tells which zone
+    //was played.
+    fn constant(level: f32) -> Arc<SampleData>
+    {
+        Arc::new(SampleData::new(vec![vec![level; SAMPLE_RATE as usize]], SAMPLE_RATE))
+    }
+
+    fn level_of(sampler: &mut Sampler, semitone: Semitone, amplitude: f32) -> f32
+    {
+        sampler.play(&RawNote::new(semitone, Register::C4, 0.0, amplitude, 500)).unwrap();
+        let mut buffer = StereoBuffer::new(256);
+        sampler.render(&mut buffer);
+        buffer.left[128] / (amplitude / 127.0)
+    }
+
+    #[test]
+    fn notes_pick_the_zone_covering_their_key()
+    {
+        let split = || {
+            let mut sampler = Sampler::new();
+            sampler.add_zone(Zone::new(constant(0.25), 60).keys(0, 63));
+            sampler.add_zone(Zone::new(constant(0.75), 67).keys(64, 127));
+            sampler
+        };
+        let empty = Sampler::new().play(&RawNote::new(Semitone::C, Register::C4, 0.0, 127.0, 500));
+        assert_eq!(empty, Err(PlayError::MissingSample(60)));
+        let low = level_of(&mut split(), Semitone::C, 127.0);
+        let high = level_of(&mut split(), Semitone::G, 127.0);
+        assert!((high / low - 3.0).abs() < 0.01, "{} {}", low, high);
+    }
+
+    #[test]
+    fn velocity_layers_are_selected_by_amplitude()
+    {
+        let layered = || {
+            let mut sampler = Sampler::new();
+            sampler.add_zone(Zone::new(constant(0.2), 60).velocities(0, 63));
+            sampler.add_zone(Zone::new(constant(0.6), 60).velocities(64, 127));
+            sampler
+        };
+        let soft = level_of(&mut layered(), Semitone::C, 40.0);
+        let hard = level_of(&mut layered(), Semitone::C, 100.0);
+        assert!((hard / soft - 3.0).abs() < 0.01, "{} {}", soft, hard);
+    }
+
+    #[test]
+    fn overlapping_zones_all_sound()
+    {
+        let mut sampler = Sampler::new();
+        sampler.add_zone(Zone::new(constant(0.25), 60));
+        sampler.add_zone(Zone::new(constant(0.25), 60).keys(60, 60));
+        sampler.add_zone(Zone::new(constant(0.25), 60).keys(61, 127));
+        sampler.play(&RawNote::new(Semitone::C, Register::C4, 0.0, 127.0, 500)).unwrap();
+        assert_eq!(sampler.active_voices(), 2);
+    }
+
+    #[test]
+    fn one_shot_zones_ignore_the_note_length()
+    {
+        let mut sampler = Sampler::new();
+        sampler.add_zone(Zone::new(constant(0.5), 60).looped(LoopMode::OneShot, 0, 0));
+        sampler.play(&RawNote::new(Semitone::C, Register::C4, 0.0, 127.0, 1)).unwrap();
+        let mut buffer = StereoBuffer::new(SAMPLE_RATE as usize / 2);
+        sampler.render(&mut buffer);
+        assert!(buffer.left[buffer.len() - 1] > 0.1);
+    }
+}
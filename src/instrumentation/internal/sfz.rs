@@ -0,0 +1,545 @@
+///SfzParser: Reads the text of an SFZ file into a flat list of regions.
+///Opcodes set under <control>, <global>, <master> and <group> headers are
+///inherited by the regions that follow them, so every region returned
+///holds its full set of opcodes.
+#[allow(non_snake_case)]
+pub mod SfzParser
+{
+    use processing::sample::sample::SampleData::SampleError;
+    use std::collections::HashMap;
+    use std::fmt::Display;
+    use std::fmt::Error;
+    use std::fmt::Formatter;
+
+    ///SfzError: Syntax errors carry the line (starting at 1) they were
+    ///found on, sample errors the path of the sample that failed to load.
+    #[derive(Debug)]
+    pub enum SfzError
+    {
+        Syntax(usize, String),
+        Sample(String, SampleError),
+    }
+
+    impl Display for SfzError
+    {
+        fn fmt(&self, f: &mut Formatter) -> Result<(), Error>
+        {
+            match *self
+            {
+                SfzError::Syntax(line, ref message) => write!(f, "line {}: {}", line, message),
+                SfzError::Sample(ref path, ref error) => write!(f, "{}: {}", path, error),
+            }
+        }
+    }
+
+    ///SfzRegion: Every opcode that applies to a single region.
+    #[derive(Clone, Debug, Default)]
+    pub struct SfzRegion
+    {
+        pub opcodes: HashMap<String, String>,
+    }
+
+    impl SfzRegion
+    {
+        pub fn get(&self, opcode: &str) -> Option<&str>
+        {
+            self.opcodes.get(opcode).map(|x| x.as_str())
+        }
+    }
+
+    ///note_from_str(s: &str) -> Option<u8>: Reads a key given either as a
+    ///midi number or a note name such as c4, c#4 or db4 (c4 being 60).
+    pub fn note_from_str(s: &str) -> Option<u8>
+    {
+        if let Ok(n) = s.parse::<u8>()
+        {
+            return if n < 128 { Some(n) } else { None };
+        }
+
+        let lower = s.to_lowercase();
+        let mut chars = lower.chars();
+        let base: i32 = match chars.next()
+        {
+            Some('c') => 0,
+            Some('d') => 2,
+            Some('e') => 4,
+            Some('f') => 5,
+            Some('g') => 7,
+            Some('a') => 9,
+            Some('b') => 11,
+            _ => return None,
+        };
+        let rest: String = chars.collect();
+        let (accidental, octave) = if let Some(x) = rest.strip_prefix('#')
+        {
+            (1, x)
+        }
+        else if let Some(x) = rest.strip_prefix('b')
+        {
+            (-1, x)
+        }
+        else
+        {
+            (0, &rest[..])
+        };
+        let octave = octave.parse::<i32>().ok()?;
+        let n = (octave + 1) * 12 + base + accidental;
+        if (0..128).contains(&n) { Some(n as u8) } else { None }
+    }
+
+    ///strip_comments(text: &str) -> String: Removes // and /* */ comments,
+    ///keeping newlines so line numbers stay correct.
+    fn strip_comments(text: &str) -> String
+    {
+        let mut ret = String::with_capacity(text.len());
+        let mut chars = text.chars().peekable();
+        let mut block = false;
+        while let Some(c) = chars.next()
+        {
+            if block
+            {
+                if c == '*' && chars.peek() == Some(&'/')
+                {
+                    chars.next();
+                    block = false;
+                }
+                else if c == '\n'
+                {
+                    ret.push('\n');
+                }
+                continue;
+            }
+            if c == '/' && chars.peek() == Some(&'/')
+            {
+                while let Some(&x) = chars.peek()
+                {
+                    if x == '\n'
+                    {
+                        break;
+                    }
+                    chars.next();
+                }
+                continue;
+            }
+            if c == '/' && chars.peek() == Some(&'*')
+            {
+                chars.next();
+                block = true;
+                continue;
+            }
+            ret.push(c);
+        }
+        ret
+    }
+
+    ///value_end(s: &str) -> usize: Where the value at the start of s ends.
+    ///Values run until the next header or "opcode=" so sample paths with
+    ///spaces in them survive.
+    fn value_end(s: &str) -> usize
+    {
+        for (i, c) in s.char_indices()
+        {
+            if c == '<'
+            {
+                return i;
+            }
+            if !c.is_whitespace()
+            {
+                continue;
+            }
+            let next = s[i..].trim_start();
+            let ident = next.bytes().take_while(|x| x.is_ascii_alphanumeric() || *x == b'_').count();
+            if next.starts_with('<') || (ident > 0 && next[ident..].starts_with('='))
+            {
+                return i;
+            }
+        }
+        s.len()
+    }
+
+    ///expand(line: &str, defines: &[(String, String)]) -> String: Replaces
+    ///every defined $name in line. Names are matched whole, so $KEY
+    ///leaves $KEYS alone, and the latest definition wins.
+    fn expand(line: &str, defines: &[(String, String)]) -> String
+    {
+        let mut ret = String::with_capacity(line.len());
+        let mut rest = line;
+        while let Some(start) = rest.find('$')
+        {
+            ret.push_str(&rest[..start]);
+            let length = 1 + rest[start + 1..].bytes().take_while(|x| x.is_ascii_alphanumeric() || *x == b'_').count();
+            let name = &rest[start..start + length];
+            match defines.iter().rev().find(|x| x.0 == name)
+            {
+                Some(x) => ret.push_str(&x.1),
+                None => ret.push_str(name),
+            }
+            rest = &rest[start + length..];
+        }
+        ret.push_str(rest);
+        ret
+    }
+
+    ///Scope: Which header opcodes are currently being written to.
+    #[derive(Clone, Copy, Debug, Eq, PartialEq)]
+    enum Scope
+    {
+        None,
+        Control,
+        Global,
+        Master,
+        Group,
+        Region,
+    }
+
+    ///SfzParser::parse(text: &str) -> Result<Vec<SfzRegion>, SfzError>
+    pub fn parse(text: &str) -> Result<Vec<SfzRegion>, SfzError>
+    {
+        let text = strip_comments(text);
+        let mut defines: Vec<(String, String)> = Vec::new();
+        let mut control = SfzRegion::default();
+        let mut global = SfzRegion::default();
+        let mut master = SfzRegion::default();
+        let mut group = SfzRegion::default();
+        let mut regions: Vec<SfzRegion> = Vec::new();
+        let mut scope = Scope::None;
+
+        for (index, raw) in text.lines().enumerate()
+        {
+            let line = index + 1;
+            let trimmed = raw.trim();
+            if let Some(definition) = trimmed.strip_prefix("#define")
+            {
+                let parts: Vec<&str> = definition.split_whitespace().collect();
+                if parts.len() != 2 || !parts[0].starts_with('$')
+                {
+                    return Err(SfzError::Syntax(line, "malformed #define".to_string()));
+                }
+                defines.push((parts[0].to_string(), parts[1].to_string()));
+                continue;
+            }
+            if trimmed.starts_with('#')
+            {
+                //#include and other directives are not supported.
+                continue;
+            }
+            let expanded = expand(trimmed, &defines);
+
+            let mut rest = expanded.trim();
+            while !rest.is_empty()
+            {
+                if rest.starts_with('<')
+                {
+                    let close = rest.find('>').ok_or_else(|| SfzError::Syntax(line, "unterminated header".to_string()))?;
+                    scope = match &rest[1..close]
+                    {
+                        "control" => Scope::Control,
+                        "global" =>
+                        {
+                            global = SfzRegion::default();
+                            master = SfzRegion::default();
+                            group = SfzRegion::default();
+                            Scope::Global
+                        },
+                        "master" =>
+                        {
+                            master = SfzRegion::default();
+                            group = SfzRegion::default();
+                            Scope::Master
+                        },
+                        "group" =>
+                        {
+                            group = SfzRegion::default();
+                            Scope::Group
+                        },
+                        "region" =>
+                        {
+                            let mut region = SfzRegion::default();
+                            for inherited in [&control, &global, &master, &group].iter()
+                            {
+                                for (key, value) in inherited.opcodes.iter()
+                                {
+                                    region.opcodes.insert(key.clone(), value.clone());
+                                }
+                            }
+                            regions.push(region);
+                            Scope::Region
+                        },
+                        //Unknown headers (<curve>, <effect>, <midi>...) are skipped.
+                        _ => Scope::None,
+                    };
+                    rest = rest[close + 1..].trim_start();
+                    continue;
+                }
+
+                let equals = rest.find('=').ok_or_else(|| SfzError::Syntax(line, format!("expected opcode=value, found '{}'", rest)))?;
+                let opcode = rest[..equals].trim();
+                if opcode.is_empty() || opcode.contains(char::is_whitespace)
+                {
+                    return Err(SfzError::Syntax(line, format!("invalid opcode '{}'", opcode)));
+                }
+                let after = &rest[equals + 1..];
+                let end = value_end(after);
+                let value = after[..end].trim().to_string();
+
+                let target = match scope
+                {
+                    Scope::Control => Some(&mut control),
+                    Scope::Global => Some(&mut global),
+                    Scope::Master => Some(&mut master),
+                    Scope::Group => Some(&mut group),
+                    Scope::Region => regions.last_mut(),
+                    Scope::None => None,
+                };
+                if let Some(x) = target
+                {
+                    x.opcodes.insert(opcode.to_string(), value);
+                }
+                rest = after[end..].trim_start();
+            }
+        }
+        Ok(regions)
+    }
+}
+
+///SfzLoader: Turns parsed SFZ regions into a Sampler. Supported opcodes:
+///sample, default_path, key, lokey, hikey, lovel, hivel, pitch_keycenter,
+///transpose, tune, volume, pan, loop_mode, loop_start/loop_end (and the
+///loopstart/loopend aliases) and the ampeg_attack/decay/sustain/release
+///amp envelope. Regions with trigger=release are skipped.
+#[allow(non_snake_case)]
+pub mod SfzLoader
+{
+    use instrumentation::internal::sampler::Sampler::Sampler;
+    use instrumentation::internal::sampler::SamplerZone::LoopMode;
+    use instrumentation::internal::sampler::SamplerZone::Zone;
+    use instrumentation::internal::sfz::SfzParser;
+    use instrumentation::internal::sfz::SfzParser::SfzError;
+    use instrumentation::internal::sfz::SfzParser::SfzRegion;
+    use processing::mixing::mixer::MixerPrimitives;
+    use processing::sample::sample::Loader;
+    use processing::sample::sample::SampleData::SampleData;
+    use processing::sample::sample::SampleData::SampleError;
+    use processing::synthesis::synthesis::Envelope::Adsr;
+    use std::collections::HashMap;
+    use std::fs::File;
+    use std::io::Read;
+    use std::path::Path;
+    use std::sync::Arc;
+
+    fn number(region: &SfzRegion, opcode: &str) -> Option<f32>
+    {
+        region.get(opcode).and_then(|x| x.parse::<f32>().ok())
+    }
+
+    fn key(region: &SfzRegion, opcode: &str) -> Option<u8>
+    {
+        region.get(opcode).and_then(SfzParser::note_from_str)
+    }
+
+    ///SfzLoader::region_to_zone(region: &SfzRegion, sample: Arc<SampleData>) -> Zone
+    pub fn region_to_zone(region: &SfzRegion, sample: Arc<SampleData>) -> Zone
+    {
+        let single = key(region, "key");
+        let root = key(region, "pitch_keycenter").or(single).unwrap_or(60);
+        let mut zone = Zone::new(sample.clone(), root)
+            .keys(key(region, "lokey").or(single).unwrap_or(0), key(region, "hikey").or(single).unwrap_or(127))
+            .velocities(number(region, "lovel").unwrap_or(1.0) as u8, number(region, "hivel").unwrap_or(127.0) as u8)
+            .envelope(Adsr::new(
+                number(region, "ampeg_attack").unwrap_or(0.0) * 1000.0,
+                number(region, "ampeg_decay").unwrap_or(0.0) * 1000.0,
+                number(region, "ampeg_sustain").unwrap_or(100.0) / 100.0,
+                number(region, "ampeg_release").unwrap_or(0.001) * 1000.0));
+
+        zone.tune = number(region, "transpose").unwrap_or(0.0) * 100.0 + number(region, "tune").unwrap_or(0.0);
+        zone.gain = MixerPrimitives::db_to_gain(number(region, "volume").unwrap_or(0.0));
+        zone.pan = number(region, "pan").unwrap_or(0.0) / 100.0;
+
+        //SFZ loops are inclusive of loop_end, zones are not.
+        let (file_start, file_end) = sample.loop_points.unwrap_or((0, sample.frames()));
+        let loop_start = number(region, "loop_start").or(number(region, "loopstart")).map(|x| x as usize).unwrap_or(file_start);
+        let loop_end = number(region, "loop_end").or(number(region, "loopend")).map(|x| x as usize + 1).unwrap_or(file_end);
+        let mode = match region.get("loop_mode").or(region.get("loopmode"))
+        {
+            Some("one_shot") => LoopMode::OneShot,
+            Some("loop_continuous") => LoopMode::Continuous,
+            Some("loop_sustain") => LoopMode::Sustain,
+            Some(_) => LoopMode::NoLoop,
+            None if sample.loop_points.is_some() => LoopMode::Continuous,
+            None => LoopMode::NoLoop,
+        };
+        zone.looped(mode, loop_start, loop_end)
+    }
+
+    ///SfzLoader::from_str(text: &str, base: &Path) -> Result<Sampler, SfzError>:
+    ///Builds a sampler from SFZ text, resolving sample paths relative to
+    ///base. Samples used by several regions are only loaded once.
+    pub fn from_str(text: &str, base: &Path) -> Result<Sampler, SfzError>
+    {
+        let regions = SfzParser::parse(text)?;
+        let mut cache: HashMap<String, Arc<SampleData>> = HashMap::new();
+        let mut sampler = Sampler::new();
+
+        for region in regions.iter()
+        {
+            if region.get("trigger").map(|x| x != "attack").unwrap_or(false)
+            {
+                continue;
+            }
+            let name = match region.get("sample")
+            {
+                Some(x) => x,
+                None => continue,
+            };
+            //SFZ files written on windows use backslashes.
+            let relative = format!("{}{}", region.get("default_path").unwrap_or(""), name).replace('\\', "/");
+            let path = base.join(&relative).to_string_lossy().into_owned();
+
+            let sample = match cache.get(&path).cloned()
+            {
+                Some(x) => x,
+                None =>
+                {
+                    let loaded = Arc::new(Loader::load(&path).map_err(|e| SfzError::Sample(path.clone(), e))?);
+                    cache.insert(path.clone(), loaded.clone());
+                    loaded
+                },
+            };
+            sampler.add_zone(region_to_zone(region, sample));
+        }
+        Ok(sampler)
+    }
+
+    ///SfzLoader::load(path: &str) -> Result<Sampler, SfzError>: Reads an
+    ///SFZ file from disk. Samples are looked up next to the file.
+    pub fn load(path: &str) -> Result<Sampler, SfzError>
+    {
+        let mut text = String::new();
+        File::open(path)
+            .and_then(|mut x| x.read_to_string(&mut text))
+            .map_err(|e| SfzError::Sample(path.to_string(), SampleError::Io(e)))?;
+        let base = Path::new(path).parent().unwrap_or(Path::new("."));
+        from_str(&text, base)
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use instrumentation::internal::sampler::SamplerZone::LoopMode;
+    use instrumentation::internal::sfz::SfzLoader;
+    use instrumentation::internal::sfz::SfzParser;
+    use instrumentation::internal::sfz::SfzParser::SfzError;
+    use processing::sample::sample::SampleData::SampleData;
+    use std::path::Path;
+    use std::sync::Arc;
+
+    #[test]
+    fn note_names_and_numbers()
+    {
+        assert_eq!(SfzParser::note_from_str("60"), Some(60));
+        assert_eq!(SfzParser::note_from_str("c4"), Some(60));
+        assert_eq!(SfzParser::note_from_str("C#4"), Some(61));
+        assert_eq!(SfzParser::note_from_str("db4"), Some(61));
+        assert_eq!(SfzParser::note_from_str("c-1"), Some(0));
+        assert_eq!(SfzParser::note_from_str("128"), None);
+        assert_eq!(SfzParser::note_from_str("h4"), None);
+    }
+
+    #[test]
+    fn regions_inherit_header_opcodes()
+    {
+        let text = "<global> volume=-6 // comment\n\
+                    <group> lokey=c4 hikey=b4\n\
+                    <region> sample=a.wav\n\
+                    /* block\n comment */<region> sample=b.wav lokey=c5\n\
+                    <group> <region> sample=c.wav";
+        let regions = SfzParser::parse(text).unwrap();
+        assert_eq!(regions.len(), 3);
+        assert_eq!(regions[0].get("volume"), Some("-6"));
+        assert_eq!(regions[0].get("lokey"), Some("c4"));
+        assert_eq!(regions[1].get("lokey"), Some("c5"));
+        assert_eq!(regions[1].get("hikey"), Some("b4"));
+        assert_eq!(regions[2].get("lokey"), None);
+        assert_eq!(regions[2].get("volume"), Some("-6"));
+    }
+
+    #[test]
+    fn sample_paths_keep_their_spaces()
+    {
+        let regions = SfzParser::parse("<region> sample=Grand Piano/C 4.wav key=60 <region> sample=x.wav").unwrap();
+        assert_eq!(regions[0].get("sample"), Some("Grand Piano/C 4.wav"));
+        assert_eq!(regions[0].get("key"), Some("60"));
+        assert_eq!(regions[1].get("sample"), Some("x.wav"));
+    }
+
+    #[test]
+    fn defines_are_expanded()
+    {
+        let regions = SfzParser::parse("#define $ROOT 48\n<region> sample=a.wav pitch_keycenter=$ROOT").unwrap();
+        assert_eq!(regions[0].get("pitch_keycenter"), Some("48"));
+    }
+
+    #[test]
+    fn defines_match_whole_names()
+    {
+        let text = "#define $KEY 60\n#define $KEYS 72\n<region> sample=a.wav key=$KEYS lokey=$KEY hikey=$KEY_HI";
+        let regions = SfzParser::parse(text).unwrap();
+        assert_eq!(regions[0].get("key"), Some("72"));
+        assert_eq!(regions[0].get("lokey"), Some("60"));
+        assert_eq!(regions[0].get("hikey"), Some("$KEY_HI"));
+    }
+
+    #[test]
+    fn non_ascii_sample_paths_survive()
+    {
+        let regions = SfzParser::parse("<region> sample=Piano é/à C4.wav key=60 <region> sample=ü ö.wav").unwrap();
+        assert_eq!(regions[0].get("sample"), Some("Piano é/à C4.wav"));
+        assert_eq!(regions[0].get("key"), Some("60"));
+        assert_eq!(regions[1].get("sample"), Some("ü ö.wav"));
+    }
+
+    #[test]
+    fn syntax_errors_report_their_line()
+    {
+        match SfzParser::parse("<region> sample=a.wav\n<region\n")
+        {
+            Err(SfzError::Syntax(2, _)) =>
+            {
+            },
+            x => panic!("expected a syntax error on line 2, got {:?}", x),
+        }
+        match SfzParser::parse("\n\n<region> key")
+        {
+            Err(SfzError::Syntax(3, _)) =>
+            {
+            },
+            x => panic!("expected a syntax error on line 3, got {:?}", x),
+        }
+    }
+
+    #[test]
+    fn regions_map_onto_zones()
+    {
+        let regions = SfzParser::parse("<region> sample=a.wav key=d4 transpose=1 tune=-50 pan=-100 \
+                                        loop_mode=loop_continuous loop_start=10 loop_end=19 lovel=64").unwrap();
+        let sample = Arc::new(SampleData::new(vec![vec![0.0; 100]], 44100));
+        let zone = SfzLoader::region_to_zone(&regions[0], sample);
+        assert_eq!((zone.lokey, zone.hikey, zone.root_key), (62, 62, 62));
+        assert_eq!((zone.lovel, zone.hivel), (64, 127));
+        assert_eq!(zone.tune, 50.0);
+        assert_eq!(zone.pan, -1.0);
+        assert_eq!(zone.loop_mode, LoopMode::Continuous);
+        assert_eq!((zone.loop_start, zone.loop_end), (10, 20));
+    }
+
+    #[test]
+    fn missing_samples_name_the_path()
+    {
+        match SfzLoader::from_str("<region> sample=missing.wav", Path::new("/nonexistent"))
+        {
+            Err(SfzError::Sample(path, _)) => assert!(path.ends_with("missing.wav")),
+            x => panic!("expected a sample error, got {:?}", x.map(|_| ())),
+        }
+    }
+}
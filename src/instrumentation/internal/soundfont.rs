@@ -0,0 +1,972 @@
+///SoundFontFile: Reader for SoundFont 2 files. Keeps the preset,
+///instrument and sample tables as they are stored in the file along with
+///the sample pool decoded to floats.
+#[allow(non_snake_case)]
+pub mod SoundFontFile
+{
+    use processing::sample::sample::Decoding;
+    use processing::sample::sample::Decoding::Endian;
+    use std::fmt::Display;
+    use std::fmt::Error;
+    use std::fmt::Formatter;
+    use std::fs::File;
+    use std::io;
+    use std::io::Read;
+
+    ///SoundFontError: Reason a soundfont or one of its presets could not
+    ///be loaded. MissingPreset holds the bank and preset asked for.
+    #[derive(Debug)]
+    pub enum SoundFontError
+    {
+        Io(io::Error),
+        Format(String),
+        MissingPreset(u16, u16),
+    }
+
+    impl Display for SoundFontError
+    {
+        fn fmt(&self, f: &mut Formatter) -> Result<(), Error>
+        {
+            match *self
+            {
+                SoundFontError::Io(ref e) => write!(f, "io error: {}", e),
+                SoundFontError::Format(ref s) => write!(f, "format error: {}", s),
+                SoundFontError::MissingPreset(bank, preset) => write!(f, "no preset {} in bank {}", preset, bank),
+            }
+        }
+    }
+
+    impl From<io::Error> for SoundFontError
+    {
+        fn from(e: io::Error) -> SoundFontError
+        {
+            SoundFontError::Io(e)
+        }
+    }
+
+    ///PresetHeader: bag is the index of the preset's first zone in
+    ///preset_bags.
+    #[derive(Clone, Debug)]
+    pub struct PresetHeader
+    {
+        pub name: String,
+        pub preset: u16,
+        pub bank: u16,
+        pub bag: usize,
+    }
+
+    ///InstrumentHeader: bag is the index of the instrument's first zone in
+    ///instrument_bags.
+    #[derive(Clone, Debug)]
+    pub struct InstrumentHeader
+    {
+        pub name: String,
+        pub bag: usize,
+    }
+
+    ///Bag: A zone, given as the index of its first generator and modulator.
+    #[derive(Clone, Copy, Debug)]
+    pub struct Bag
+    {
+        pub generator: usize,
+        pub modulator: usize,
+    }
+
+    ///Generator: amount is kept raw since its meaning depends on operator.
+    #[derive(Clone, Copy, Debug)]
+    pub struct Generator
+    {
+        pub operator: u16,
+        pub amount: u16,
+    }
+
+    impl Generator
+    {
+        pub fn signed(&self) -> i16
+        {
+            self.amount as i16
+        }
+
+        ///Generator.range() -> (u8, u8): Low and high end of a key or
+        ///velocity range.
+        pub fn range(&self) -> (u8, u8)
+        {
+            ((self.amount & 0xff) as u8, (self.amount >> 8) as u8)
+        }
+    }
+
+    #[derive(Clone, Copy, Debug, Eq, PartialEq)]
+    pub struct Modulator
+    {
+        pub source: u16,
+        pub destination: u16,
+        pub amount: i16,
+        pub amount_source: u16,
+        pub transform: u16,
+    }
+
+    impl Modulator
+    {
+        ///Modulator.same_as(other: &Modulator) -> bool: Whether two
+        ///modulators are identical in the sense of the spec, in which case
+        ///the later one replaces or adds to the earlier one.
+        pub fn same_as(&self, other: &Modulator) -> bool
+        {
+            self.source == other.source &&
+                self.destination == other.destination &&
+                self.amount_source == other.amount_source &&
+                self.transform == other.transform
+        }
+    }
+
+    ///SampleHeader: Positions are in frames of the sample pool. loop_end
+    ///is the first frame after the loop.
+    #[derive(Clone, Debug)]
+    pub struct SampleHeader
+    {
+        pub name: String,
+        pub start: usize,
+        pub end: usize,
+        pub loop_start: usize,
+        pub loop_end: usize,
+        pub sample_rate: u32,
+        pub original_pitch: u8,
+        pub pitch_correction: i8,
+        pub link: u16,
+        pub sample_type: u16,
+    }
+
+    ///SoundFont: Every table ends with the terminal record written by the
+    ///file (EOP, EOI, EOS), so the zones of entry i run up to the bag of
+    ///entry i + 1.
+    #[derive(Clone, Debug)]
+    pub struct SoundFont
+    {
+        pub name: String,
+        pub presets: Vec<PresetHeader>,
+        pub preset_bags: Vec<Bag>,
+        pub preset_modulators: Vec<Modulator>,
+        pub preset_generators: Vec<Generator>,
+        pub instruments: Vec<InstrumentHeader>,
+        pub instrument_bags: Vec<Bag>,
+        pub instrument_modulators: Vec<Modulator>,
+        pub instrument_generators: Vec<Generator>,
+        pub samples: Vec<SampleHeader>,
+        pub data: Vec<f32>,
+    }
+
+    fn u16_at(data: &[u8], at: usize) -> u16
+    {
+        Decoding::u16_at(data, at, Endian::Little).unwrap_or(0)
+    }
+
+    fn u32_at(data: &[u8], at: usize) -> u32
+    {
+        Decoding::u32_at(data, at, Endian::Little).unwrap_or(0)
+    }
+
+    fn name_at(data: &[u8], at: usize) -> String
+    {
+        let raw = &data[at..at + 20];
+        let end = raw.iter().position(|x| *x == 0).unwrap_or(20);
+        String::from_utf8_lossy(&raw[..end]).trim().to_string()
+    }
+
+    ///records(body: &[u8], size: usize, id: &str) -> Result<Vec<&[u8]>, SoundFontError>:
+    ///Splits a pdta chunk into its fixed size records.
+    fn records<'a>(body: &'a [u8], size: usize, id: &str) -> Result<Vec<&'a [u8]>, SoundFontError>
+    {
+        if !body.len().is_multiple_of(size) || body.len() < size
+        {
+            return Err(SoundFontError::Format(format!("{} chunk has a bad size", id)));
+        }
+        Ok(body.chunks(size).collect())
+    }
+
+    ///chunks(data: &[u8]) -> Vec<(&[u8], &[u8])>: Id and body of every
+    ///RIFF chunk in data.
+    fn chunks(data: &[u8]) -> Vec<(&[u8], &[u8])>
+    {
+        let mut ret = Vec::new();
+        let mut at = 0;
+        while at + 8 <= data.len()
+        {
+            let size = u32_at(data, at + 4) as usize;
+            let end = (at + 8 + size).min(data.len());
+            ret.push((&data[at..at + 4], &data[at + 8..end]));
+            at += 8 + size + (size & 1);
+        }
+        ret
+    }
+
+    impl SoundFont
+    {
+        ///SoundFont::load(path: &str) -> Result<SoundFont, SoundFontError>
+        pub fn load(path: &str) -> Result<SoundFont, SoundFontError>
+        {
+            let mut data = Vec::new();
+            File::open(path)?.read_to_end(&mut data)?;
+            SoundFont::from_bytes(&data)
+        }
+
+        ///SoundFont::from_bytes(data: &[u8]) -> Result<SoundFont, SoundFontError>
+        pub fn from_bytes(data: &[u8]) -> Result<SoundFont, SoundFontError>
+        {
+            let bad = |s: &str| SoundFontError::Format(s.to_string());
+            if data.len() < 12 || &data[0..4] != b"RIFF" || &data[8..12] != b"sfbk"
+            {
+                return Err(bad("not a soundfont file"));
+            }
+
+            let mut font = SoundFont{
+                name: String::new(),
+                presets: Vec::new(),
+                preset_bags: Vec::new(),
+                preset_modulators: Vec::new(),
+                preset_generators: Vec::new(),
+                instruments: Vec::new(),
+                instrument_bags: Vec::new(),
+                instrument_modulators: Vec::new(),
+                instrument_generators: Vec::new(),
+                samples: Vec::new(),
+                data: Vec::new(),
+            };
+            let mut smpl: Option<&[u8]> = None;
+            let mut sm24: Option<&[u8]> = None;
+
+            for (id, body) in chunks(&data[12..])
+            {
+                if id != b"LIST" || body.len() < 4
+                {
+                    continue;
+                }
+                for (sub, chunk) in chunks(&body[4..])
+                {
+                    match (&body[0..4], sub)
+                    {
+                        (b"INFO", b"INAM") =>
+                        {
+                            let end = chunk.iter().position(|x| *x == 0).unwrap_or(chunk.len());
+                            font.name = String::from_utf8_lossy(&chunk[..end]).to_string();
+                        },
+                        (b"sdta", b"smpl") => smpl = Some(chunk),
+                        (b"sdta", b"sm24") => sm24 = Some(chunk),
+                        (b"pdta", b"phdr") =>
+                        {
+                            for r in records(chunk, 38, "phdr")?
+                            {
+                                font.presets.push(PresetHeader{ name: name_at(r, 0), preset: u16_at(r, 20), bank: u16_at(r, 22), bag: u16_at(r, 24) as usize });
+                            }
+                        },
+                        (b"pdta", b"inst") =>
+                        {
+                            for r in records(chunk, 22, "inst")?
+                            {
+                                font.instruments.push(InstrumentHeader{ name: name_at(r, 0), bag: u16_at(r, 20) as usize });
+                            }
+                        },
+                        (b"pdta", b"pbag") | (b"pdta", b"ibag") =>
+                        {
+                            let bags: Vec<Bag> = records(chunk, 4, "bag")?.iter().map(|r| Bag{ generator: u16_at(r, 0) as usize, modulator: u16_at(r, 2) as usize }).collect();
+                            if sub == b"pbag" { font.preset_bags = bags; } else { font.instrument_bags = bags; }
+                        },
+                        (b"pdta", b"pmod") | (b"pdta", b"imod") =>
+                        {
+                            let modulators: Vec<Modulator> = records(chunk, 10, "mod")?.iter().map(|r| Modulator{
+                                source: u16_at(r, 0),
+                                destination: u16_at(r, 2),
+                                amount: u16_at(r, 4) as i16,
+                                amount_source: u16_at(r, 6),
+                                transform: u16_at(r, 8),
+                            }).collect();
+                            if sub == b"pmod" { font.preset_modulators = modulators; } else { font.instrument_modulators = modulators; }
+                        },
+                        (b"pdta", b"pgen") | (b"pdta", b"igen") =>
+                        {
+                            let generators: Vec<Generator> = records(chunk, 4, "gen")?.iter().map(|r| Generator{ operator: u16_at(r, 0), amount: u16_at(r, 2) }).collect();
+                            if sub == b"pgen" { font.preset_generators = generators; } else { font.instrument_generators = generators; }
+                        },
+                        (b"pdta", b"shdr") =>
+                        {
+                            for r in records(chunk, 46, "shdr")?
+                            {
+                                font.samples.push(SampleHeader{
+                                    name: name_at(r, 0),
+                                    start: u32_at(r, 20) as usize,
+                                    end: u32_at(r, 24) as usize,
+                                    loop_start: u32_at(r, 28) as usize,
+                                    loop_end: u32_at(r, 32) as usize,
+                                    sample_rate: u32_at(r, 36),
+                                    original_pitch: r[40],
+                                    pitch_correction: r[41] as i8,
+                                    link: u16_at(r, 42),
+                                    sample_type: u16_at(r, 44),
+                                });
+                            }
+                        },
+                        _ =>
+                        {
+                        },
+                    }
+                }
+            }
+
+            if font.presets.is_empty() || font.preset_bags.is_empty() || font.instruments.is_empty() || font.instrument_bags.is_empty() || font.samples.is_empty()
+            {
+                return Err(bad("missing preset, instrument or sample tables"));
+            }
+
+            //16 bit samples, with the optional sm24 chunk holding the low
+            //byte of 24 bit samples.
+            let smpl = smpl.ok_or_else(|| bad("missing smpl chunk"))?;
+            let frames = smpl.len() / 2;
+            let sm24 = sm24.filter(|x| x.len() >= frames);
+            font.data = (0..frames).map(|i| {
+                let high = u16_at(smpl, i * 2) as i16 as i32;
+                match sm24
+                {
+                    Some(low) => ((high << 8) | low[i] as i32) as f32 / 8388608.0,
+                    None => high as f32 / 32768.0,
+                }
+            }).collect();
+            Ok(font)
+        }
+
+        ///SoundFont.preset(bank: u16, preset: u16) -> Option<usize>: Index of
+        ///the preset header for bank and preset.
+        pub fn preset(&self, bank: u16, preset: u16) -> Option<usize>
+        {
+            let count = self.presets.len() - 1;
+            self.presets[..count].iter().position(|x| x.bank == bank && x.preset == preset)
+        }
+
+        ///SoundFont.preset_list() -> Vec<(u16, u16, String)>: Bank, preset
+        ///number and name of every preset in the file.
+        pub fn preset_list(&self) -> Vec<(u16, u16, String)>
+        {
+            let count = self.presets.len() - 1;
+            self.presets[..count].iter().map(|x| (x.bank, x.preset, x.name.clone())).collect()
+        }
+    }
+}
+
+///SoundFontInstrument: Turns a preset of a soundfont into a Sampler. Each
+///pair of preset and instrument zones becomes a sampler zone with the
+///generators of both levels combined. Supported are the key and velocity
+///splits, sample offsets, loop modes, tuning, pan, attenuation, the
+///volume envelope (as an ADSR, delay and hold are ignored), the low pass
+///filter and the modulation envelope's effect on it. Of the modulators,
+///those driving the filter cutoff from note velocity are applied.
+#[allow(non_snake_case)]
+pub mod SoundFontInstrument
+{
+    use instrumentation::interface::instrument::Instrument::PlayableInstrument;
+    use instrumentation::internal::sampler::Sampler::Sampler;
+    use instrumentation::internal::sampler::SamplerZone::LoopMode;
+    use instrumentation::internal::sampler::SamplerZone::Zone;
+    use instrumentation::internal::sampler::SamplerZone::ZoneFilter;
+    use instrumentation::internal::soundfont::SoundFontFile::Bag;
+    use instrumentation::internal::soundfont::SoundFontFile::Generator;
+    use instrumentation::internal::soundfont::SoundFontFile::Modulator;
+    use instrumentation::internal::soundfont::SoundFontFile::SoundFont;
+    use instrumentation::internal::soundfont::SoundFontFile::SoundFontError;
+    use processing::buffer::buffer::AudioBuffer::BLOCK_SIZE;
+    use processing::buffer::buffer::AudioBuffer::SAMPLE_RATE;
+    use processing::buffer::buffer::AudioBuffer::StereoBuffer;
+    use processing::sample::sample::SampleData::SampleData;
+    use processing::synthesis::synthesis::Envelope::Adsr;
+    use sequencing::tonation::note::NoteCollections::RawNote;
+    use std::collections::HashMap;
+    use std::f32::consts::FRAC_1_SQRT_2;
+    use std::sync::Arc;
+
+    const START_OFFSET: usize = 0;
+    const END_OFFSET: usize = 1;
+    const LOOP_START_OFFSET: usize = 2;
+    const LOOP_END_OFFSET: usize = 3;
+    const START_COARSE_OFFSET: usize = 4;
+    const FILTER_CUTOFF: usize = 8;
+    const FILTER_Q: usize = 9;
+    const MOD_ENV_TO_FILTER: usize = 11;
+    const END_COARSE_OFFSET: usize = 12;
+    const PAN: usize = 17;
+    const MOD_ENV_ATTACK: usize = 26;
+    const MOD_ENV_DECAY: usize = 28;
+    const MOD_ENV_SUSTAIN: usize = 29;
+    const MOD_ENV_RELEASE: usize = 30;
+    const VOL_ENV_ATTACK: usize = 34;
+    const VOL_ENV_DECAY: usize = 36;
+    const VOL_ENV_SUSTAIN: usize = 37;
+    const VOL_ENV_RELEASE: usize = 38;
+    const INSTRUMENT: usize = 41;
+    const KEY_RANGE: usize = 43;
+    const VELOCITY_RANGE: usize = 44;
+    const LOOP_START_COARSE_OFFSET: usize = 45;
+    const ATTENUATION: usize = 48;
+    const LOOP_END_COARSE_OFFSET: usize = 50;
+    const COARSE_TUNE: usize = 51;
+    const FINE_TUNE: usize = 52;
+    const SAMPLE_ID: usize = 53;
+    const SAMPLE_MODES: usize = 54;
+    const OVERRIDING_ROOT_KEY: usize = 58;
+    const GENERATORS: usize = 61;
+
+    ///Modulator source meaning note-on velocity.
+    const SOURCE_VELOCITY: u16 = 2;
+    ///Top of the filter cutoff range, where the filter is left open.
+    const OPEN_FILTER_CENTS: i32 = 13500;
+    ///Sample type flag of samples stored in ROM, which are not in the file.
+    const ROM_SAMPLE: u16 = 0x8000;
+
+    ///Generators: Generator amounts indexed by operator, with key and
+    ///velocity ranges kept separately.
+    #[derive(Clone, Copy)]
+    struct Generators
+    {
+        values: [i32; GENERATORS],
+        keys: (u8, u8),
+        velocities: (u8, u8),
+    }
+
+    impl Generators
+    {
+        ///Generators::defaults() -> Generators: Default values of the
+        ///instrument level.
+        fn defaults() -> Generators
+        {
+            let mut values = [0; GENERATORS];
+            values[FILTER_CUTOFF] = OPEN_FILTER_CENTS;
+            for i in [25, 26, 27, 28, 30, 33, 34, 35, 36, 38].iter()
+            {
+                values[*i] = -12000;
+            }
+            values[46] = -1;
+            values[47] = -1;
+            values[56] = 100;
+            values[OVERRIDING_ROOT_KEY] = -1;
+            Generators{ values, keys: (0, 127), velocities: (0, 127) }
+        }
+
+        ///Generators::zero() -> Generators: Starting point of the preset
+        ///level, whose amounts are added to the instrument's.
+        fn zero() -> Generators
+        {
+            Generators{ values: [0; GENERATORS], keys: (0, 127), velocities: (0, 127) }
+        }
+
+        fn set(&mut self, generators: &[Generator])
+        {
+            for g in generators.iter()
+            {
+                match g.operator as usize
+                {
+                    KEY_RANGE => self.keys = g.range(),
+                    VELOCITY_RANGE => self.velocities = g.range(),
+                    //Offsets and sample modes are unsigned.
+                    START_OFFSET | END_OFFSET | LOOP_START_OFFSET | LOOP_END_OFFSET | INSTRUMENT | SAMPLE_ID | SAMPLE_MODES => self.values[g.operator as usize] = g.amount as i32,
+                    x if x < GENERATORS => self.values[x] = g.signed() as i32,
+                    _ =>
+                    {
+                    },
+                }
+            }
+        }
+
+        ///Generators.add(preset: &Generators): Applies preset level amounts
+        ///on top of instrument level ones. Ranges are intersected and
+        ///generators that are only valid for instruments are skipped.
+        fn add(&mut self, preset: &Generators)
+        {
+            for i in 0..GENERATORS
+            {
+                match i
+                {
+                    0..=4 | 12 | 45 | 46 | 47 | 50 | INSTRUMENT | SAMPLE_ID | SAMPLE_MODES | 57 | OVERRIDING_ROOT_KEY => {},
+                    _ => self.values[i] += preset.values[i],
+                }
+            }
+            self.keys = (self.keys.0.max(preset.keys.0), self.keys.1.min(preset.keys.1));
+            self.velocities = (self.velocities.0.max(preset.velocities.0), self.velocities.1.min(preset.velocities.1));
+        }
+    }
+
+    ///default_modulators() -> Vec<Modulator>: The spec's default modulators
+    ///that affect offline rendering: velocity to attenuation and velocity
+    ///to filter cutoff.
+    fn default_modulators() -> Vec<Modulator>
+    {
+        vec![
+            Modulator{ source: 0x0502, destination: ATTENUATION as u16, amount: 960, amount_source: 0, transform: 0 },
+            Modulator{ source: 0x0102, destination: FILTER_CUTOFF as u16, amount: -2400, amount_source: 0, transform: 0 },
+        ]
+    }
+
+    ///replace(list: &mut Vec<Modulator>, modulators: &[Modulator]): Merges
+    ///modulators into list, replacing identical ones.
+    fn replace(list: &mut Vec<Modulator>, modulators: &[Modulator])
+    {
+        for m in modulators.iter()
+        {
+            match list.iter().position(|x| x.same_as(m))
+            {
+                Some(i) => list[i] = *m,
+                None => list.push(*m),
+            }
+        }
+    }
+
+    ///add(list: &mut Vec<Modulator>, modulators: &[Modulator]): Merges
+    ///preset level modulators into list, adding to identical ones.
+    fn add(list: &mut Vec<Modulator>, modulators: &[Modulator])
+    {
+        for m in modulators.iter()
+        {
+            match list.iter().position(|x| x.same_as(m))
+            {
+                Some(i) => list[i].amount = list[i].amount.saturating_add(m.amount),
+                None => list.push(*m),
+            }
+        }
+    }
+
+    ///ZoneData: Generators and modulators of one zone.
+    struct ZoneData<'a>
+    {
+        generators: &'a [Generator],
+        modulators: &'a [Modulator],
+    }
+
+    ///Zones: The global zone (if any) and the other zones of an entry.
+    type Zones<'a> = (Option<ZoneData<'a>>, Vec<ZoneData<'a>>);
+
+    ///zones(...) -> Result<Zones, SoundFontError>: Global zone (if any) and
+    ///the other zones of bags first..last. A zone is global if it is the
+    ///first one and does not end in the terminal generator. Index ranges
+    ///that go backwards, as in a malformed file, are a format error.
+    fn zones<'a>(bags: &[Bag], generators: &'a [Generator], modulators: &'a [Modulator], first: usize, last: usize, terminal: usize) -> Result<Zones<'a>, SoundFontError>
+    {
+        let backwards = |what: &str, i: usize| SoundFontError::Format(format!("{} of bag {} go backwards", what, i));
+        if first > last
+        {
+            return Err(SoundFontError::Format(format!("zones {}..{} go backwards", first, last)));
+        }
+        let mut global = None;
+        let mut ret = Vec::new();
+        for i in first..last.min(bags.len() - 1)
+        {
+            let (g_start, g_end) = (bags[i].generator.min(generators.len()), bags[i + 1].generator.min(generators.len()));
+            let (m_start, m_end) = (bags[i].modulator.min(modulators.len()), bags[i + 1].modulator.min(modulators.len()));
+            if g_start > g_end
+            {
+                return Err(backwards("generators", i));
+            }
+            if m_start > m_end
+            {
+                return Err(backwards("modulators", i));
+            }
+            let zone = ZoneData{ generators: &generators[g_start..g_end], modulators: &modulators[m_start..m_end] };
+            let g = zone.generators;
+            match g.last()
+            {
+                Some(x) if x.operator as usize == terminal => ret.push(zone),
+                _ if i == first => global = Some(zone),
+                _ =>
+                {
+                },
+            }
+        }
+        Ok((global, ret))
+    }
+
+    ///timecents_to_ms(tc: i32) -> f32
+    fn timecents_to_ms(tc: i32) -> f32
+    {
+        2f32.powf(tc as f32 / 1200.0) * 1000.0
+    }
+
+    ///cents_to_herz(cents: i32) -> f32: Absolute cents, 0 being 8.176 hz.
+    fn cents_to_herz(cents: i32) -> f32
+    {
+        8.176 * 2f32.powf(cents as f32 / 1200.0)
+    }
+
+    ///make_zone(...) -> Option<Zone>: Sampler zone for the combined
+    ///generators and modulators of a preset and instrument zone.
+    fn make_zone(font: &SoundFont, g: &Generators, modulators: &[Modulator], cache: &mut HashMap<(usize, usize), Arc<SampleData>>) -> Option<Zone>
+    {
+        let v = &g.values;
+        let header = font.samples.get(v[SAMPLE_ID] as usize)?;
+        if header.sample_type & ROM_SAMPLE != 0 || g.keys.0 > g.keys.1 || g.velocities.0 > g.velocities.1
+        {
+            return None;
+        }
+
+        let clamp = |x: i64| -> usize { if x < 0 { 0 } else if x as usize > font.data.len() { font.data.len() } else { x as usize } };
+        let start = clamp(header.start as i64 + v[START_OFFSET] as i64 + v[START_COARSE_OFFSET] as i64 * 32768);
+        let end = clamp(header.end as i64 + v[END_OFFSET] as i64 + v[END_COARSE_OFFSET] as i64 * 32768);
+        let loop_start = clamp(header.loop_start as i64 + v[LOOP_START_OFFSET] as i64 + v[LOOP_START_COARSE_OFFSET] as i64 * 32768);
+        let loop_end = clamp(header.loop_end as i64 + v[LOOP_END_OFFSET] as i64 + v[LOOP_END_COARSE_OFFSET] as i64 * 32768);
+        if end <= start
+        {
+            return None;
+        }
+        let sample = cache.entry((start, end)).or_insert_with(|| Arc::new(SampleData::new(vec![font.data[start..end].to_vec()], header.sample_rate))).clone();
+
+        let root = if v[OVERRIDING_ROOT_KEY] >= 0 { v[OVERRIDING_ROOT_KEY] as u8 } else if header.original_pitch <= 127 { header.original_pitch } else { 60 };
+        let mode = match v[SAMPLE_MODES] & 3
+        {
+            1 => LoopMode::Continuous,
+            3 => LoopMode::Sustain,
+            _ => LoopMode::NoLoop,
+        };
+        let sustain_cb = v[VOL_ENV_SUSTAIN].clamp(0, 1440) as f32;
+        let mut zone = Zone::new(sample, root)
+            .keys(g.keys.0, g.keys.1)
+            .velocities(g.velocities.0, g.velocities.1)
+            .looped(mode, loop_start.saturating_sub(start), loop_end.saturating_sub(start))
+            .envelope(Adsr::new(
+                timecents_to_ms(v[VOL_ENV_ATTACK]),
+                timecents_to_ms(v[VOL_ENV_DECAY]),
+                10f32.powf(-sustain_cb / 200.0),
+                timecents_to_ms(v[VOL_ENV_RELEASE])));
+        zone.tune = (v[COARSE_TUNE] * 100 + v[FINE_TUNE] + header.pitch_correction as i32) as f32;
+        zone.gain = 10f32.powf(-(v[ATTENUATION].max(0) as f32) / 200.0);
+        zone.pan = (v[PAN] as f32 / 500.0).clamp(-1.0, 1.0);
+
+        //Velocity to cutoff modulators, with their curve treated as linear.
+        let mut cutoff = v[FILTER_CUTOFF];
+        let mut velocity_amount = 0.0;
+        for m in modulators.iter().filter(|x| x.destination as usize == FILTER_CUTOFF && x.amount_source == 0 && x.source & 0xff == SOURCE_VELOCITY)
+        {
+            let octaves = m.amount as f32 / 1200.0;
+            if m.source & 0x0100 != 0
+            {
+                velocity_amount += octaves;
+            }
+            else
+            {
+                cutoff += m.amount as i32;
+                velocity_amount -= octaves;
+            }
+        }
+
+        let envelope_amount = v[MOD_ENV_TO_FILTER] as f32 / 1200.0;
+        if cutoff < OPEN_FILTER_CENTS || envelope_amount != 0.0 || velocity_amount != 0.0
+        {
+            //Q is the resonance peak in centibels above a flat (0.707 Q)
+            //response. The voice filter has a Q of 1 / (2 - 1.96 * resonance).
+            let q = 10f32.powf(v[FILTER_Q].clamp(0, 960) as f32 / 200.0) * FRAC_1_SQRT_2;
+            let filter = ZoneFilter{
+                cutoff: cents_to_herz(cutoff.clamp(1500, OPEN_FILTER_CENTS)),
+                resonance: ((2.0 - 1.0 / q) / 1.96).clamp(0.0, 1.0),
+                envelope: Adsr::new(
+                    timecents_to_ms(v[MOD_ENV_ATTACK]),
+                    timecents_to_ms(v[MOD_ENV_DECAY]),
+                    1.0 - (v[MOD_ENV_SUSTAIN].clamp(0, 1000) as f32 / 1000.0),
+                    timecents_to_ms(v[MOD_ENV_RELEASE])),
+                envelope_amount,
+                velocity_amount,
+            };
+            zone = zone.filtered(filter);
+        }
+        Some(zone)
+    }
+
+    ///SoundFontInstrument::build(font: &SoundFont, bank: u16, preset: u16) -> Result<Sampler, SoundFontError>
+    pub fn build(font: &SoundFont, bank: u16, preset: u16) -> Result<Sampler, SoundFontError>
+    {
+        let index = font.preset(bank, preset).ok_or(SoundFontError::MissingPreset(bank, preset))?;
+        let mut sampler = Sampler::new();
+        let mut cache = HashMap::new();
+
+        let (preset_global, preset_zones) = zones(&font.preset_bags, &font.preset_generators, &font.preset_modulators,
+                                                  font.presets[index].bag, font.presets[index + 1].bag, INSTRUMENT)?;
+        for preset_zone in preset_zones.iter()
+        {
+            let mut preset_generators = Generators::zero();
+            let mut preset_modulators = Vec::new();
+            if let Some(ref global) = preset_global
+            {
+                preset_generators.set(global.generators);
+                replace(&mut preset_modulators, global.modulators);
+            }
+            preset_generators.set(preset_zone.generators);
+            replace(&mut preset_modulators, preset_zone.modulators);
+
+            let instrument = preset_zone.generators.last().unwrap().amount as usize;
+            if instrument + 1 >= font.instruments.len()
+            {
+                return Err(SoundFontError::Format(format!("preset zone refers to missing instrument {}", instrument)));
+            }
+            let (instrument_global, instrument_zones) = zones(&font.instrument_bags, &font.instrument_generators, &font.instrument_modulators,
+                                                              font.instruments[instrument].bag, font.instruments[instrument + 1].bag, SAMPLE_ID)?;
+            for instrument_zone in instrument_zones.iter()
+            {
+                let mut generators = Generators::defaults();
+                let mut local = Vec::new();
+                if let Some(ref global) = instrument_global
+                {
+                    generators.set(global.generators);
+                    replace(&mut local, global.modulators);
+                }
+                generators.set(instrument_zone.generators);
+                replace(&mut local, instrument_zone.modulators);
+                generators.add(&preset_generators);
+
+                let mut modulators = default_modulators();
+                replace(&mut modulators, &local);
+                add(&mut modulators, &preset_modulators);
+
+                if let Some(zone) = make_zone(font, &generators, &modulators, &mut cache)
+                {
+                    sampler.add_zone(zone);
+                }
+            }
+        }
+        Ok(sampler)
+    }
+
+    ///SoundFontInstrument::load(path: &str, bank: u16, preset: u16) -> Result<Sampler, SoundFontError>
+    pub fn load(path: &str, bank: u16, preset: u16) -> Result<Sampler, SoundFontError>
+    {
+        build(&SoundFont::load(path)?, bank, preset)
+    }
+
+    ///SoundFontInstrument::render_offline(instrument, notes, tail) -> StereoBuffer:
+    ///Plays notes (start time in ms, note) through instrument as fast as
+    ///possible and returns the audio, running tail ms past the end of the
+    ///last note so releases can ring out. Used for quick demos.
+    pub fn render_offline(instrument: &mut Sampler, notes: &[(u64, RawNote)], tail: u64) -> StereoBuffer
+    {
+        let mut notes: Vec<&(u64, RawNote)> = notes.iter().collect();
+        notes.sort_by_key(|x| x.0);
+        let length = notes.iter().map(|x| x.0 + x.1.length as u64).max().unwrap_or(0) + tail;
+        let frames = (length * SAMPLE_RATE as u64 / 1000) as usize;
+
+        let mut output = StereoBuffer::new(frames);
+        let mut block = StereoBuffer::new(BLOCK_SIZE);
+        let mut next = 0;
+        let mut at = 0;
+        while at < frames
+        {
+            let time = at as u64 * 1000 / SAMPLE_RATE as u64;
+            while next < notes.len() && notes[next].0 <= time
+            {
+                //Notes outside the preset's zones are left out of the demo.
+                instrument.play(&notes[next].1).ok();
+                next += 1;
+            }
+            let count = BLOCK_SIZE.min(frames - at);
+            block.resize(count);
+            instrument.render(&mut block);
+            output.left[at..at + count].copy_from_slice(&block.left);
+            output.right[at..at + count].copy_from_slice(&block.right);
+            at += count;
+        }
+        output
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use instrumentation::interface::instrument::Instrument::PlayError;
+    use instrumentation::interface::instrument::Instrument::PlayableInstrument;
+    use instrumentation::internal::soundfont::SoundFontFile::SoundFont;
+    use instrumentation::internal::soundfont::SoundFontFile::SoundFontError;
+    use instrumentation::internal::soundfont::SoundFontInstrument;
+    use processing::buffer::buffer::AudioBuffer::StereoBuffer;
+    use sequencing::tonation::note::NoteCollections::RawNote;
+    use sequencing::tonation::note::NotePrimitives::Register;
+    use sequencing::tonation::note::NotePrimitives::Semitone;
+
+    fn chunk(id: &[u8], body: &[u8]) -> Vec<u8>
+    {
+        let mut ret = id.to_vec();
+        ret.extend_from_slice(&(body.len() as u32).to_le_bytes());
+        ret.extend_from_slice(body);
+        if body.len() & 1 == 1
+        {
+            ret.push(0);
+        }
+        ret
+    }
+
+    fn list(kind: &[u8], chunks: &[Vec<u8>]) -> Vec<u8>
+    {
+        let mut body = kind.to_vec();
+        for c in chunks.iter()
+        {
+            body.extend_from_slice(c);
+        }
+        chunk(b"LIST", &body)
+    }
+
+    fn name(s: &str) -> Vec<u8>
+    {
+        let mut ret = s.as_bytes().to_vec();
+        ret.resize(20, 0);
+        ret
+    }
+
+    fn words(values: &[u16]) -> Vec<u8>
+    {
+        values.iter().flat_map(|x| x.to_le_bytes().to_vec()).collect()
+    }
+
+    fn preset(title: &str, number: u16, bag: u16) -> Vec<u8>
+    {
+        let mut ret = name(title);
+        ret.extend(words(&[number, 0, bag]));
+        ret.resize(38, 0);
+        ret
+    }
+
+    fn instrument(title: &str, bag: u16) -> Vec<u8>
+    {
+        let mut ret = name(title);
+        ret.extend(words(&[bag]));
+        ret
+    }
+
+    fn sample(title: &str, end: u32) -> Vec<u8>
+    {
+        let mut ret = name(title);
+        for x in [0, end, 0, 0, 44100].iter()
+        {
+            ret.extend_from_slice(&x.to_le_bytes());
+        }
+        ret.extend_from_slice(&[60, 0]);
+        ret.extend(words(&[0, if end > 0 { 1 } else { 0 }]));
+        ret
+    }
+
+    //One preset playing one instrument, whose single zone covers keys
+    //0 to 63 with a sample at half of full scale. phdr is passed in so
+    //tests can break it.
+    fn font(phdr: Vec<u8>) -> Vec<u8>
+    {
+        let frames = 4096;
+        let smpl = words(&vec![16384; frames]);
+        let mut inst = instrument("Inst", 0);
+        inst.extend(instrument("EOI", 1));
+        let mut shdr = sample("Half", frames as u32);
+        shdr.extend(sample("EOS", 0));
+
+        let mut body = b"sfbk".to_vec();
+        body.extend(list(b"INFO", &[chunk(b"INAM", b"Test font\0")]));
+        body.extend(list(b"sdta", &[chunk(b"smpl", &smpl)]));
+        body.extend(list(b"pdta", &[
+            chunk(b"phdr", &phdr),
+            chunk(b"pbag", &words(&[0, 0, 1, 0])),
+            chunk(b"pmod", &[0; 10]),
+            chunk(b"pgen", &words(&[41, 0, 0, 0])),
+            chunk(b"inst", &inst),
+            chunk(b"ibag", &words(&[0, 0, 2, 0])),
+            chunk(b"imod", &[0; 10]),
+            chunk(b"igen", &words(&[43, 63 << 8, 53, 0, 0, 0])),
+            chunk(b"shdr", &shdr),
+        ]));
+        chunk(b"RIFF", &body)
+    }
+
+    fn presets() -> Vec<u8>
+    {
+        let mut ret = preset("Piano", 3, 0);
+        ret.extend(preset("EOP", 0, 1));
+        ret
+    }
+
+    #[test]
+    fn tables_and_samples_are_read()
+    {
+        let font = SoundFont::from_bytes(&font(presets())).unwrap();
+        assert_eq!(font.name, "Test font");
+        assert_eq!(font.preset_list(), vec![(0, 3, "Piano".to_string())]);
+        assert_eq!(font.preset(0, 3), Some(0));
+        assert_eq!(font.preset(0, 0), None);
+        assert_eq!(font.samples[0].end, 4096);
+        assert_eq!(font.data.len(), 4096);
+        assert!((font.data[0] - 0.5).abs() < 1e-6);
+    }
+
+    #[test]
+    fn other_riff_files_are_rejected()
+    {
+        let mut data = font(presets());
+        data[8..12].copy_from_slice(b"WAVE");
+        match SoundFont::from_bytes(&data)
+        {
+            Err(SoundFontError::Format(..)) =>
+            {
+            },
+            x => panic!("expected a format error, got {:?}", x.map(|f| f.name)),
+        }
+    }
+
+    #[test]
+    fn tables_with_partial_records_are_rejected()
+    {
+        let mut phdr = presets();
+        phdr.pop();
+        match SoundFont::from_bytes(&font(phdr))
+        {
+            Err(SoundFontError::Format(ref s)) if s.contains("phdr") =>
+            {
+            },
+            x => panic!("expected a format error, got {:?}", x.map(|f| f.name)),
+        }
+    }
+
+    #[test]
+    fn missing_presets_are_reported()
+    {
+        let font = SoundFont::from_bytes(&font(presets())).unwrap();
+        match SoundFontInstrument::build(&font, 1, 3)
+        {
+            Err(SoundFontError::MissingPreset(1, 3)) =>
+            {
+            },
+            Err(e) => panic!("unexpected error {}", e),
+            Ok(_) => panic!("expected a missing preset"),
+        }
+    }
+
+    #[test]
+    fn built_sampler_plays_the_zone_key_range()
+    {
+        let font = SoundFont::from_bytes(&font(presets())).unwrap();
+        let mut sampler = SoundFontInstrument::build(&font, 0, 3).unwrap();
+        let outside = sampler.play(&RawNote::new(Semitone::E, Register::C4, 0.0, 127.0, 500));
+        assert_eq!(outside, Err(PlayError::MissingSample(64)));
+        sampler.play(&RawNote::new(Semitone::C, Register::C4, 0.0, 127.0, 500)).unwrap();
+        let mut buffer = StereoBuffer::new(256);
+        sampler.render(&mut buffer);
+        assert!(buffer.left[200].abs() > 0.01);
+    }
+
+    #[test]
+    fn backwards_index_ranges_are_rejected()
+    {
+        let fine = SoundFont::from_bytes(&font(presets())).unwrap();
+        let mut generators = fine.clone();
+        generators.instrument_bags[0].generator = 2;
+        generators.instrument_bags[1].generator = 0;
+        let mut modulators = fine.clone();
+        modulators.preset_bags[0].modulator = 1;
+        let mut presets = fine.clone();
+        presets.presets[1].bag = 0;
+        presets.presets[0].bag = 1;
+        for font in [generators, modulators, presets].iter()
+        {
+            match SoundFontInstrument::build(font, 0, 3)
+            {
+                Err(SoundFontError::Format(..)) =>
+                {
+                },
+                Err(e) => panic!("unexpected error {}", e),
+                Ok(_) => panic!("expected a format error"),
+            }
+        }
+    }
+}
@@ -0,0 +1,540 @@
+///SubtractivePatch: Settings that describe the sound of the subtractive
+///synthesizer. A patch is shared by every voice of the synth.
+#[allow(non_snake_case)]
+pub mod SubtractivePatch
+{
+    use processing::synthesis::synthesis::Envelope::Adsr;
+    use processing::synthesis::synthesis::Oscillator::Waveform;
+    use processing::synthesis::synthesis::VoiceFilter::FilterMode;
+    use serde::Deserialize;
+    use serde::Serialize;
+
+    ///OscillatorSettings: One oscillator of a voice. detune is in cents,
+    ///octave shifts the note pitch by whole octaves and level is the mix
+    ///gain of the oscillator.
+    #[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+    pub struct OscillatorSettings
+    {
+        pub waveform: Waveform,
+        pub octave: i32,
+        pub detune: f32,
+        pub level: f32,
+    }
+
+    impl OscillatorSettings
+    {
+        pub fn new(waveform: Waveform) -> OscillatorSettings
+        {
+            OscillatorSettings{
+                waveform,
+                octave: 0,
+                detune: 0.0,
+                level: 1.0,
+            }
+        }
+    }
+
+    ///FilterSettings: cutoff is in herz, envelope_amount is how many
+    ///octaves the filter envelope opens the cutoff and key_tracking is how
+    ///much the cutoff follows the note pitch (1.0 follows it exactly).
+    #[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+    pub struct FilterSettings
+    {
+        pub mode: FilterMode,
+        pub cutoff: f32,
+        pub resonance: f32,
+        pub envelope_amount: f32,
+        pub key_tracking: f32,
+    }
+
+    ///LfoDestination: What an LFO modulates. Pitch depth is in semitones,
+    ///Cutoff depth in octaves and Amplitude depth is a 0.0 to 1.0 tremolo.
+    #[derive(Clone, Copy, Debug, Eq, PartialEq, Serialize, Deserialize)]
+    pub enum LfoDestination
+    {
+        Pitch,
+        Cutoff,
+        Amplitude,
+    }
+
+    #[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+    pub struct LfoSettings
+    {
+        pub waveform: Waveform,
+        pub rate: f32,
+        pub depth: f32,
+        pub destination: LfoDestination,
+    }
+
+    #[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+    pub struct Patch
+    {
+        pub oscillators: Vec<OscillatorSettings>,
+        pub amp_envelope: Adsr,
+        pub filter_envelope: Adsr,
+        pub filter: FilterSettings,
+        pub lfos: Vec<LfoSettings>,
+        pub gain: f32,
+    }
+
+    impl Patch
+    {
+        ///Patch::new() -> Patch: Default patch of two slightly detuned saws
+        ///through a low pass filter.
+        pub fn new() -> Patch
+        {
+            let mut detuned = OscillatorSettings::new(Waveform::Saw);
+            detuned.detune = 7.0;
+            Patch{
+                oscillators: vec![OscillatorSettings::new(Waveform::Saw), detuned],
+                amp_envelope: Adsr::new(5.0, 200.0, 0.7, 300.0),
+                filter_envelope: Adsr::new(5.0, 400.0, 0.2, 300.0),
+                filter: FilterSettings{
+                    mode: FilterMode::LowPass,
+                    cutoff: 800.0,
+                    resonance: 0.3,
+                    envelope_amount: 3.0,
+                    key_tracking: 0.5,
+                },
+                lfos: Vec::new(),
+                gain: 0.5,
+            }
+        }
+    }
+
+    impl Default for Patch
+    {
+        fn default() -> Patch
+        {
+            Patch::new()
+        }
+    }
+}
+
+///SubtractiveSynth: Native polyphonic synthesizer. Every note played
+///grabs a voice, holds it for the length of the note and then releases
+///it; render pulls the mixed voices into a buffer.
+#[allow(non_snake_case)]
+pub mod SubtractiveSynth
+{
+    use instrumentation::interface::instrument::Instrument;
+    use instrumentation::internal::subtractive::SubtractivePatch::LfoDestination;
+    use instrumentation::internal::subtractive::SubtractivePatch::Patch;
+    use processing::buffer::buffer::AudioBuffer::SAMPLE_RATE;
+    use processing::buffer::buffer::AudioBuffer::StereoBuffer;
+    use processing::synthesis::synthesis::Envelope::EnvelopeGenerator;
+    use processing::synthesis::synthesis::Oscillator::Oscillator;
+    use processing::synthesis::synthesis::Portamento::Glide;
+    use processing::synthesis::synthesis::VoiceFilter::StateVariable;
+    use sequencing::tonation::note::NoteCollections::MAX_AMPLITUDE;
+    use sequencing::tonation::note::NoteCollections::RawNote;
+    use sequencing::tonation::note::NotePrimitives;
+
+    ///Default amount of voices that can sound at once.
+    pub const DEFAULT_POLYPHONY: usize = 8;
+
+    ///Voice: One sounding note.
+    #[derive(Clone, Debug)]
+    struct Voice
+    {
+        key: u8,
+        pitch: Glide,
+        pitch_hz: f32,
+        velocity: f32,
+        oscillators: Vec<Oscillator>,
+        amp_envelope: EnvelopeGenerator,
+        filter_envelope: EnvelopeGenerator,
+        filter: StateVariable,
+        gate_samples: u64,
+        age: u64,
+    }
+
+    impl Voice
+    {
+        fn new(patch: &Patch) -> Voice
+        {
+            Voice{
+                key: 0,
+                pitch: Glide::new(0.0),
+                pitch_hz: 0.0,
+                velocity: 0.0,
+                oscillators: patch.oscillators.iter().map(|x| Oscillator::new(x.waveform)).collect(),
+                amp_envelope: EnvelopeGenerator::new(patch.amp_envelope),
+                filter_envelope: EnvelopeGenerator::new(patch.filter_envelope),
+                filter: StateVariable::new(patch.filter.mode),
+                gate_samples: 0,
+                age: 0,
+            }
+        }
+
+        fn is_active(&self) -> bool
+        {
+            self.amp_envelope.is_active()
+        }
+
+        fn is_held(&self) -> bool
+        {
+            self.is_active() && self.gate_samples > 0
+        }
+    }
+
+    pub struct SubtractiveSynth
+    {
+        patch: Patch,
+        voices: Vec<Voice>,
+        lfos: Vec<Oscillator>,
+        clock: u64,
+    }
+
+    impl SubtractiveSynth
+    {
+        ///SubtractiveSynth::new(patch: Patch) -> SubtractiveSynth: Generates
+        ///a synth with DEFAULT_POLYPHONY voices.
+        pub fn new(patch: Patch) -> SubtractiveSynth
+        {
+            SubtractiveSynth::with_polyphony(patch, DEFAULT_POLYPHONY)
+        }
+
+        ///SubtractiveSynth::with_polyphony(patch: Patch, voices: usize) -> SubtractiveSynth
+        pub fn with_polyphony(patch: Patch, voices: usize) -> SubtractiveSynth
+        {
+            let voices = if voices < 1 { 1 } else { voices };
+            SubtractiveSynth{
+                voices: (0..voices).map(|_| Voice::new(&patch)).collect(),
+                lfos: patch.lfos.iter().map(|x| Oscillator::new(x.waveform)).collect(),
+                patch,
+                clock: 0,
+            }
+        }
+
+        pub fn patch(&self) -> &Patch
+        {
+            &self.patch
+        }
+
+        ///SubtractiveSynth.set_patch(patch: Patch): Swaps the patch. Playing
+        ///voices are silenced.
+        pub fn set_patch(&mut self, patch: Patch)
+        {
+            let count = self.voices.len();
+            self.voices = (0..count).map(|_| Voice::new(&patch)).collect();
+            self.lfos = patch.lfos.iter().map(|x| Oscillator::new(x.waveform)).collect();
+            self.patch = patch;
+        }
+
+        ///SubtractiveSynth.active_voices() -> usize: Voices that are still
+        ///sounding, including release tails.
+        pub fn active_voices(&self) -> usize
+        {
+            self.voices.iter().filter(|x| x.is_active()).count()
+        }
+
+        ///SubtractiveSynth.free_voice() -> usize: Index of an idle voice, or
+        ///the oldest releasing voice, or the oldest voice if every voice is
+        ///held.
+        fn free_voice(&self) -> usize
+        {
+            if let Some(index) = self.voices.iter().position(|x| !x.is_active())
+            {
+                return index;
+            }
+            (0..self.voices.len()).min_by_key(|x| (self.voices[*x].is_held(), self.voices[*x].age)).unwrap_or(0)
+        }
+
+        ///SubtractiveSynth.start(key, pitch_hz, velocity, gate_samples) -> Result<(), PlayError>:
+        ///Starts a voice at pitch_hz with a velocity from 0.0 to 1.0 that is
+        ///released after gate_samples samples.
+        fn start(&mut self, key: u8, pitch_hz: f32, velocity: f32, gate_samples: u64) -> Result<(), Instrument::PlayError>
+        {
+            if pitch_hz <= 0.0 || pitch_hz >= SAMPLE_RATE as f32 / 2.0
+            {
+                return Err(Instrument::PlayError::OutOfRange(key));
+            }
+
+            let index = self.free_voice();
+            self.clock += 1;
+
+            let voice = &mut self.voices[index];
+            voice.key = key;
+            voice.pitch = Glide::new(pitch_hz);
+            voice.pitch_hz = pitch_hz;
+            voice.velocity = velocity.clamp(0.0, 1.0);
+            voice.gate_samples = gate_samples;
+            voice.age = self.clock;
+            voice.filter.reset();
+            for oscillator in voice.oscillators.iter_mut()
+            {
+                oscillator.reset(0.0);
+            }
+            voice.amp_envelope.gate_on();
+            voice.filter_envelope.gate_on();
+            Ok(())
+        }
+
+        ///SubtractiveSynth.render(buffer: &mut StereoBuffer): Overwrites buffer
+        ///with the next block of audio from every active voice.
+        pub fn render(&mut self, buffer: &mut StereoBuffer)
+        {
+            buffer.clear();
+            let patch = &self.patch;
+
+            for i in 0..buffer.len()
+            {
+                let mut pitch_mod = 0.0;
+                let mut cutoff_mod = 0.0;
+                let mut amp_mod = 1.0;
+                for (lfo, settings) in self.lfos.iter_mut().zip(patch.lfos.iter())
+                {
+                    let value = lfo.tick(settings.rate);
+                    match settings.destination
+                    {
+                        LfoDestination::Pitch => pitch_mod += value * settings.depth,
+                        LfoDestination::Cutoff => cutoff_mod += value * settings.depth,
+                        LfoDestination::Amplitude => amp_mod *= 1.0 - settings.depth * (value + 1.0) / 2.0,
+                    }
+                }
+
+                let mut sample = 0.0;
+                for voice in self.voices.iter_mut().filter(|x| x.is_active())
+                {
+                    if voice.gate_samples == 0
+                    {
+                        voice.amp_envelope.gate_off();
+                        voice.filter_envelope.gate_off();
+                    }
+                    else
+                    {
+                        voice.gate_samples -= 1;
+                    }
+
+                    voice.pitch_hz = voice.pitch.tick();
+                    let base = voice.pitch_hz * 2f32.powf(pitch_mod / 12.0);
+                    let mut raw = 0.0;
+                    for (oscillator, settings) in voice.oscillators.iter_mut().zip(patch.oscillators.iter())
+                    {
+                        let frequency = base * 2f32.powf(settings.octave as f32 + settings.detune / 1200.0);
+                        raw += oscillator.tick(frequency) * settings.level;
+                    }
+
+                    let tracking = (voice.pitch_hz / 261.63).powf(patch.filter.key_tracking);
+                    let octaves = voice.filter_envelope.tick() * patch.filter.envelope_amount + cutoff_mod;
+                    let cutoff = patch.filter.cutoff * tracking * 2f32.powf(octaves);
+                    let filtered = voice.filter.tick(raw, cutoff, patch.filter.resonance);
+
+                    sample += filtered * voice.amp_envelope.tick() * voice.velocity;
+                }
+
+                sample *= patch.gain * amp_mod;
+                buffer.left[i] = sample;
+                buffer.right[i] = sample;
+            }
+        }
+    }
+
+    impl Instrument::PlayableInstrument for SubtractiveSynth
+    {
+        ///Starts a voice at note.pitch_hz whose loudness follows
+        ///note.amplitude. The voice is released after note.length
+        ///milliseconds of rendered audio. Pitches at or above nyquist are
+        ///refused.
+        fn play(&mut self, note: &RawNote) -> Result<(), Instrument::PlayError>
+        {
+            let gate = note.length as u64 * SAMPLE_RATE as u64 / 1000;
+            self.start(note.key(), note.pitch_hz, note.amplitude / MAX_AMPLITUDE, gate)
+        }
+
+        ///Starts a voice that is held until note_off.
+        fn note_on(&mut self, key: u8, velocity: u8) -> Result<(), Instrument::PlayError>
+        {
+            self.start(key, NotePrimitives::midi_to_herz(key as f32), velocity as f32 / MAX_AMPLITUDE, u64::MAX)
+        }
+
+        fn note_off(&mut self, key: u8)
+        {
+            for voice in self.voices.iter_mut().filter(|x| x.key == key)
+            {
+                voice.gate_samples = 0;
+            }
+        }
+
+        fn all_notes_off(&mut self)
+        {
+            for voice in self.voices.iter_mut()
+            {
+                voice.gate_samples = 0;
+            }
+        }
+
+        fn process(&mut self, buffer: &mut StereoBuffer)
+        {
+            self.render(buffer);
+        }
+
+        fn stop(&mut self, key: u8, held: bool)
+        {
+            if let Some(voice) = self.voices.iter_mut().filter(|x| x.key == key && x.is_active() && x.is_held() == held).min_by_key(|x| x.age)
+            {
+                voice.amp_envelope.kill();
+                voice.filter_envelope.kill();
+            }
+        }
+
+        ///Grows or shrinks the voice pool. Voices cut from the end of the
+        ///pool are silenced.
+        fn set_polyphony(&mut self, voices: usize)
+        {
+            let voice = Voice::new(&self.patch);
+            self.voices.resize(voices.max(1), voice);
+        }
+
+        ///Slides the held voice playing key to the new note without
+        ///retriggering its envelopes.
+        fn glide(&mut self, key: u8, note: &RawNote, time: f32) -> bool
+        {
+            match self.voices.iter_mut().find(|x| x.key == key && x.is_held())
+            {
+                Some(voice) =>
+                {
+                    voice.key = note.key();
+                    voice.pitch.glide_to(note.pitch_hz, time);
+                    voice.gate_samples = note.length as u64 * SAMPLE_RATE as u64 / 1000;
+                    true
+                },
+                None => false,
+            }
+        }
+
+        fn set_parameter(&mut self, name: &str, value: f32) -> bool
+        {
+            match name
+            {
+                "cutoff" => self.patch.filter.cutoff = value,
+                "resonance" => self.patch.filter.resonance = value,
+                "envelope_amount" => self.patch.filter.envelope_amount = value,
+                "gain" => self.patch.gain = value,
+                _ => return false,
+            }
+            true
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use instrumentation::interface::instrument::Instrument::PlayableInstrument;
+    use instrumentation::internal::subtractive::SubtractivePatch::Patch;
+    use instrumentation::internal::subtractive::SubtractiveSynth::SubtractiveSynth;
+    use processing::buffer::buffer::AudioBuffer::StereoBuffer;
+    use processing::synthesis::synthesis::Envelope::Adsr;
+    use sequencing::tonation::note::NoteCollections::RawNote;
+    use sequencing::tonation::note::NotePrimitives::Register;
+    use sequencing::tonation::note::NotePrimitives::Semitone;
+
+    fn note(semitone: Semitone, length: u32) -> RawNote
+    {
+        RawNote::new(semitone, Register::C4, 0.0, 127.0, length)
+    }
+
+    //Fixed cutoff and a fast, full sustain envelope so only the setting
+    //under test changes the output.
+    fn flat_patch(cutoff: f32) -> Patch
+    {
+        let mut patch = Patch::new();
+        patch.oscillators.truncate(1);
+        patch.amp_envelope = Adsr::new(1.0, 1.0, 1.0, 10.0);
+        patch.filter.cutoff = cutoff;
+        patch.filter.envelope_amount = 0.0;
+        patch.filter.key_tracking = 0.0;
+        patch.filter.resonance = 0.0;
+        patch
+    }
+
+    //Sum of the sample to sample movement, a rough measure of how much
+    //high frequency content is in the buffer.
+    fn roughness(buffer: &StereoBuffer) -> f32
+    {
+        buffer.left.windows(2).map(|x| (x[1] - x[0]).abs()).sum()
+    }
+
+    fn render(synth: &mut SubtractiveSynth, frames: usize) -> StereoBuffer
+    {
+        let mut buffer = StereoBuffer::new(frames);
+        synth.render(&mut buffer);
+        buffer
+    }
+
+    #[test]
+    fn low_pass_cutoff_removes_high_frequencies()
+    {
+        let mut open = SubtractiveSynth::new(flat_patch(15000.0));
+        let mut closed = SubtractiveSynth::new(flat_patch(200.0));
+        open.play(&note(Semitone::A, 1000)).unwrap();
+        closed.play(&note(Semitone::A, 1000)).unwrap();
+        let open = render(&mut open, 4410);
+        let closed = render(&mut closed, 4410);
+        assert!(open.peak() > 0.1);
+        assert!(roughness(&closed) < roughness(&open) * 0.5);
+    }
+
+    #[test]
+    fn envelope_releases_after_note_length()
+    {
+        let mut synth = SubtractiveSynth::new(flat_patch(5000.0));
+        synth.play(&note(Semitone::A, 50)).unwrap();
+        let held = render(&mut synth, 2205);
+        assert!(held.peak() > 0.1);
+        assert_eq!(synth.active_voices(), 1);
+
+        //50 ms gate plus a 10 ms release is long over after 100 ms.
+        render(&mut synth, 4410);
+        assert_eq!(synth.active_voices(), 0);
+        assert_eq!(render(&mut synth, 64).peak(), 0.0);
+    }
+
+    #[test]
+    fn polyphony_steals_the_oldest_voice()
+    {
+        let mut synth = SubtractiveSynth::with_polyphony(flat_patch(5000.0), 2);
+        synth.play(&note(Semitone::C, 1000)).unwrap();
+        synth.play(&note(Semitone::E, 1000)).unwrap();
+        synth.play(&note(Semitone::G, 1000)).unwrap();
+        render(&mut synth, 64);
+        assert_eq!(synth.active_voices(), 2);
+    }
+
+    #[test]
+    fn stop_cuts_one_voice_and_stealing_spares_held_voices()
+    {
+        let mut synth = SubtractiveSynth::with_polyphony(flat_patch(5000.0), 2);
+        synth.set_polyphony(3);
+        synth.note_on(60, 100).unwrap();
+        synth.note_on(60, 100).unwrap();
+        synth.note_on(64, 100).unwrap();
+        synth.stop(60, true);
+        assert_eq!(synth.active_voices(), 2);
+
+        //The pool is full, so 67 takes the releasing 60 even though the
+        //held 64 is older.
+        let mut synth = SubtractiveSynth::with_polyphony(flat_patch(5000.0), 2);
+        synth.note_on(64, 100).unwrap();
+        synth.note_on(60, 100).unwrap();
+        synth.note_off(60);
+        synth.note_on(67, 100).unwrap();
+        synth.stop(64, true);
+        assert_eq!(synth.active_voices(), 1);
+    }
+
+    #[test]
+    fn chord_is_louder_than_a_single_note()
+    {
+        let mut single = SubtractiveSynth::new(flat_patch(5000.0));
+        single.play(&note(Semitone::C, 1000)).unwrap();
+        let mut chord = SubtractiveSynth::new(flat_patch(5000.0));
+        chord.play(&note(Semitone::C, 1000)).unwrap();
+        chord.play(&note(Semitone::C, 1000)).unwrap();
+        let single = render(&mut single, 2205);
+        let chord = render(&mut chord, 2205);
+        assert!(chord.peak() > single.peak() * 1.5);
+    }
+}
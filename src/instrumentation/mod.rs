@@ -1,2 +1,3 @@
 pub mod external;
 pub mod interface;
+pub mod internal;
@@ -0,0 +1,15 @@
+#[cfg(feature = "openal")]
+extern crate ears;
+#[cfg(feature = "alsa")]
+extern crate alsa;
+#[cfg(feature = "jack")]
+extern crate jack;
+extern crate schedule_recv;
+extern crate serde;
+extern crate serde_json;
+
+pub mod sequencing;
+pub mod instrumentation;
+pub mod processing;
+pub mod control;
+pub mod persistence;
@@ -1,158 +1,149 @@
-extern crate ears;
-extern crate schedule_recv;
-
-mod sequencing;
-mod instrumentation;
-
-use sequencing::tonation::note::NoteGeneration::NoteBuilder;
-
-use sequencing::tonation::note::NoteCollections::IncompleteNote;
-use sequencing::tonation::note::NoteCollections::NoteResult;
-use sequencing::tonation::note::NoteCollections::RawNote;
-use sequencing::tonation::note::NotePrimitives::Register;
-use sequencing::tonation::note::NotePrimitives::Semitone;
-use sequencing::timing::timing::Beat::BeatValue;
-
-use instrumentation::interface::instrument::InstrumentWrapper::InstrumentWrapper;
-use instrumentation::interface::instrument::TestPlugin;
-
-use sequencing::sequencing::partialsequencer::PartialSequencer::PartialSequencer;
-
-use std::thread;
-
+extern crate jaru3;
+
+use jaru3::sequencing::tonation::note::NotePrimitives;
+use jaru3::sequencing::tonation::note::NotePrimitives::Register;
+use jaru3::sequencing::tonation::note::NotePrimitives::Semitone;
+
+use jaru3::persistence::project::Project::InstrumentSettings;
+use jaru3::persistence::project::Project::PadSettings;
+use jaru3::persistence::project::Project::Project;
+use jaru3::persistence::project::Project::TrackSettings;
+use jaru3::persistence::project::Project::ZoneSettings;
+
+use jaru3::processing::backend::backend::Selection;
+use jaru3::processing::buffer::buffer::AudioBuffer::BLOCK_SIZE;
+use jaru3::processing::buffer::buffer::AudioBuffer::SAMPLE_RATE;
+use jaru3::processing::buffer::buffer::AudioBuffer::StereoBuffer;
+
+use jaru3::sequencing::sequencing::driver::Driver::Driver;
+use jaru3::sequencing::sequencing::pattern::Pattern::Pattern;
+use jaru3::sequencing::timing::transport::Transport::Transport;
+
+use std::env;
+use std::path::Path;
+use std::time::Instant;
+
+
+///demo() -> Project: The session played when no project is given.
+fn demo() -> Project
+{
+    let kick = NotePrimitives::primitives_to_midi(Semitone::A, Register::C2);
+    let drums = TrackSettings::new("Drums", InstrumentSettings::DrumKit(vec![PadSettings::new(kick, "../debug/bassdrum.aiff")]))
+        .pattern(Pattern::parse("A2*4").unwrap());
+    let root = NotePrimitives::primitives_to_midi(Semitone::C, Register::C2);
+    let piano = TrackSettings::new("Piano", InstrumentSettings::Sampler(vec![ZoneSettings::new("../release/pianoc.aiff", root)]))
+        .pattern(Pattern::parse("C2 B C# A").unwrap());
+    Project::new("Demo", 130.0).track(drums).track(piano)
+}
 
+///Usage: jaru3 [backend] [project], see processing::backend::backend::Selection
+///for the backends that can be given. Sample paths of a project are taken
+///from the directory it is in.
 fn main() {
-    let mut children = vec![];
-    children.push(thread::spawn(move || {
-        let sampler = TestPlugin::TestSampler::from_audio("../debug/bassdrum.aiff").unwrap();
-        //
-        let mut nb = NoteBuilder::new();
-        let mut x = PartialSequencer::<TestPlugin::TestSampler>::new(130.0, sampler);
-        nb = nb.semitone(Semitone::A).register(Register::C2).offset(0.0).amplitude(15.0).length(x.local_beat_builder.u64_from_beats(1, 1).unwrap() as u32);
-        for i in 0..4
+    let spec = env::args().nth(1).unwrap_or(Selection::default_spec().to_string());
+    let (project, directory) = match env::args().nth(2)
+    {
+        Some(path) =>
         {
-            match nb.build()
+            match Project::load(&path)
             {
-                NoteResult::Incomplete(x) =>
-                {
-                },
-                NoteResult::Complete(note) =>
+                Ok(x) => (x, Path::new(&path).parent().unwrap_or(Path::new(".")).to_path_buf()),
+                Err(e) =>
                 {
-                    nb = nb.amplitude(i as f32);
-                    x.push_time_to_note(i, 4, note);
+                    println!("Could not load {}: {}", path, e);
+                    return;
                 },
             }
-        }
-        loop
+        },
+        None => (demo(), Path::new(".").to_path_buf()),
+    };
+    let mut sequencers = match project.sequencers(&directory)
+    {
+        Ok(x) => x,
+        Err(e) =>
         {
-            x.play_sequence_DEBUG();
-        }
-    }));
-
-    children.push(thread::spawn(move || {
-        let sampler = TestPlugin::TestSampler::from_audio("../release/pianoc.aiff").unwrap();
-        let mut nb = NoteBuilder::new();
-        let mut x = PartialSequencer::<TestPlugin::TestSampler>::new(130.0, sampler);
-        nb = nb.semitone(Semitone::C).register(Register::C2).offset(0.0).amplitude(15.0).length(x.local_beat_builder.u64_from_beats(1, 1).unwrap() as u32);
-        for i in 0..4
+            println!("Could not build {}: {}", project.name, e);
+            return;
+        },
+    };
+    let mut mixer = project.mixer();
+    let automation = project.automation();
+
+    let mut backend = match Selection::open(&spec)
+    {
+        Ok(x) => x,
+        Err(e) =>
         {
-            match nb.build()
-            {
-                NoteResult::Incomplete(_) =>
-                {
-                },
-                NoteResult::Complete(note) =>
-                {
-                    if i == 0
-                    {
-                        nb = nb.semitone(Semitone::B).register(Register::C2);
-                    }
-                    if i == 1
-                    {
-                        nb = nb.semitone(Semitone::CSharp).register(Register::C2);
-                    }
-                    if i == 2
-                    {
-                        nb = nb.semitone(Semitone::A).register(Register::C2);
-                    }
-                    if i == 3
-                    {
-                        nb = nb.semitone(Semitone::A).register(Register::C2);
-                    }
-                    if i == 4
-                    {
-                        nb = nb.semitone(Semitone::A).register(Register::C2);
-                    }
-                    nb = nb.amplitude(i as f32);
-                    match nb.build()
-                    {
-                        NoteResult::Complete(note) =>
-                        {
-                            x.push_time_to_note(i, 4, note);
-                        }
-                        _ => {}
-                    }
-                },
-            }
+            println!("Could not open {}: {} (available: {:?})", spec, e, Selection::available());
+            return;
+        },
+    };
+
+    let mut tracks: Vec<StereoBuffer> = sequencers.iter().map(|_| StereoBuffer::new(BLOCK_SIZE)).collect();
+    let mut master = StereoBuffer::new(BLOCK_SIZE);
+    let mut transport = Transport::new();
+    let mut driver = Driver::new();
+    transport.start();
+    loop
+    {
+        if let Some(position) = driver.render(&transport, Instant::now(), &mut sequencers, &mut tracks)
+        {
+            automation.apply(position as f64 * 1000.0 / SAMPLE_RATE as f64, &mut mixer);
         }
-        loop
+        mixer.process(&tracks, &mut master);
+        match backend.write(&master)
         {
-            x.play_sequence_DEBUG();
+            Ok(()) =>
+            {
+            },
+            Err(e) =>
+            {
+                println!("Playback stopped: {}", e);
+                break;
+            },
         }
-    }));
-
-    for i in children
-    {
-        i.join();
     }
+    backend.close().ok();
 
 
     /*
     let internal_beat = BeatValue::from_bpm(126.0);
     let mut children = vec![];
     children.push(thread::spawn(move || {
-    let mut nb_seq1 = NoteBuilder::new();
-    nb_seq1 = nb_seq1.length(internal_beat.u64_from_beats(16, 16).unwrap() as u32);
-    let sequence = nb_seq1.build_sequence_DEBUG("A:B:A:B:A:A:A:A:A:A:A:A:A:A:A:A");
+    let sequence = Pattern::parse("A3/1 B A B A*12").unwrap().to_notes(&internal_beat);
     let mut sampler = InstrumentWrapper::InstrumentWrapper::new(TestPlugin::TestSampler::from_audio("../debug/sampe4.wav").unwrap());
     sampler.reveal_internal_instrument_DEBUG().change_pitch(0.19);
-        for i in sequence
+        for (_, i) in sequence
         {
             sampler.play(&i);
         }
     }));
 
     children.push(thread::spawn(move || {
-    let mut nb_seq1 = NoteBuilder::new();
-    nb_seq1 = nb_seq1.length(internal_beat.u64_from_beats(4, 8).unwrap() as u32);
-    let sequence = nb_seq1.build_sequence_DEBUG("E:E:E:E:E:E:E:E:E:E:E:E:E:E:E:E");
+    let sequence = Pattern::parse("E3:2*16").unwrap().to_notes(&internal_beat);
     let mut sampler = InstrumentWrapper::InstrumentWrapper::new(TestPlugin::TestSampler::from_audio("../debug/clap.wav").unwrap());
     sampler.reveal_internal_instrument_DEBUG().change_pitch(0.3);
-        for i in sequence
+        for (_, i) in sequence
         {
             sampler.play(&i);
         }
     }));
 
     children.push(thread::spawn(move || {
-    let mut nb_seq1 = NoteBuilder::new();
-    nb_seq1 = nb_seq1.length(internal_beat.u64_from_beats(4, 4).unwrap() as u32);
-    let sequence = nb_seq1.build_sequence_DEBUG("A:A:A:A:A:A:A:A:A:A:A:A:A:A:A:A:A:A:A:A:A:A:A:A:A:A:A:A:A:A:A:A");
+    let sequence = Pattern::parse("A3/1*32").unwrap().to_notes(&internal_beat);
     let mut sampler = InstrumentWrapper::InstrumentWrapper::new(TestPlugin::TestSampler::from_audio("../debug/bass.wav").unwrap());
     sampler.reveal_internal_instrument_DEBUG().change_pitch(0.3);
-        for i in sequence
+        for (_, i) in sequence
         {
             sampler.play(&i);
         }
     }));
 
     children.push(thread::spawn(move || {
-    let mut nb_seq1 = NoteBuilder::new();
-    nb_seq1 = nb_seq1.length(internal_beat.u64_from_beats(8, 2).unwrap() as u32);
-    let sequence = nb_seq1.build_sequence_DEBUG("A:A:A:A:A:A:A:A:A:A:A:A:A:A:A:A:A:A:A:A:A:A:A:A:A:A:A:A:A:A:A:A:A:A:A:A:A:A:A:A:A:A:A:A:A:A:A:A:A:A:A:A:A:A:A:A:A:A:A:A:A:A:A:A");
+    let sequence = Pattern::parse("A3/4*64").unwrap().to_notes(&internal_beat);
     let mut sampler = InstrumentWrapper::InstrumentWrapper::new(TestPlugin::TestSampler::from_audio("../debug/hats.wav").unwrap());
     sampler.reveal_internal_instrument_DEBUG().change_pitch(0.35);
-        for i in sequence
+        for (_, i) in sequence
         {
             sampler.play(&i);
         }
@@ -0,0 +1,1201 @@
+///Abc: Reading and writing tunes in ABC notation. The parser reads the
+///first tune of a file: the header fields for title (T:), meter (M:), unit
+///note length (L:), tempo (Q:) and key (K:), then the body with its notes,
+///accidentals, octaves, durations, broken rhythm, triplets, ties, chords,
+///rests, bar lines and repeats with first and second endings. Decorations,
+///chord symbols, grace notes and slurs are skipped, except for dynamics
+///(!p!, !f!, ...) which set the velocity.
+///
+///Tunes are read into a Pattern, which keeps lengths in whole notes.
+///AbcTune.apply loads a tune into a sequencer with one bar per measure, and
+///export writes a sequencer back in a given meter and key.
+#[allow(non_snake_case)]
+pub mod Abc
+{
+    use instrumentation::interface::instrument::Instrument;
+    use sequencing::sequencing::partialsequencer::PartialSequencer::PartialSequencer;
+    use sequencing::sequencing::pattern::Pattern::DEFAULT_VELOCITY;
+    use sequencing::sequencing::pattern::Pattern::Pattern;
+    use sequencing::sequencing::pattern::Pattern::PatternEvent;
+    use sequencing::timing::timing::Beat::BeatValue;
+    use sequencing::tonation::note::NoteCollections::RawNote;
+    use std::collections::HashMap;
+    use std::fmt::Display;
+    use std::fmt::Error;
+    use std::fmt::Formatter;
+
+    ///Tempo, in quarter notes per minute, of tunes without a Q: field.
+    pub const DEFAULT_TEMPO: f32 = 120.0;
+    ///Steps per whole note that export rounds note times to.
+    pub const EXPORT_GRID: u64 = 64;
+    ///Bars per line of exported tunes.
+    pub const EXPORT_BARS_PER_LINE: u64 = 4;
+
+    const EPSILON: f64 = 1e-9;
+
+    ///AbcError: What is wrong with a tune and where, counted in characters
+    ///from 1.
+    #[derive(Clone, Debug, PartialEq)]
+    pub struct AbcError
+    {
+        pub line: usize,
+        pub column: usize,
+        pub message: String,
+    }
+
+    impl Display for AbcError
+    {
+        fn fmt(&self, f: &mut Formatter) -> Result<(), Error>
+        {
+            write!(f, "line {}, column {}: {}", self.line, self.column, self.message)
+        }
+    }
+
+    ///AbcTune: A tune as read from ABC. unit is the L: length in whole
+    ///notes, tempo the Q: tempo in quarter notes per minute.
+    #[derive(Clone, Debug, PartialEq)]
+    pub struct AbcTune
+    {
+        pub title: String,
+        pub meter: (u32, u32),
+        pub unit: f64,
+        pub tempo: f32,
+        pub key: String,
+        pub pattern: Pattern,
+    }
+
+    impl AbcTune
+    {
+        ///AbcTune::parse(text: &str) -> Result<AbcTune, AbcError>: Reads the
+        ///first tune in text.
+        pub fn parse(text: &str) -> Result<AbcTune, AbcError>
+        {
+            let mut parser = Parser{
+                tune: AbcTune{
+                    title: String::new(),
+                    meter: (4, 4),
+                    unit: 0.0,
+                    tempo: DEFAULT_TEMPO,
+                    key: "C".to_string(),
+                    pattern: Pattern{
+                        events: Vec::new(),
+                        length: 0.0,
+                    },
+                },
+                signature: [0; 7],
+                accidentals: HashMap::new(),
+                velocity: DEFAULT_VELOCITY,
+                events: Vec::new(),
+                time: 0.0,
+                last: None,
+                broken: 1.0,
+                tuplet: None,
+                repeat: (0, 0.0),
+                ending: None,
+                line: 0,
+            };
+            parser.run(text)?;
+            Ok(parser.tune)
+        }
+
+        ///AbcTune.beat_value() -> BeatValue: The tempo for a sequencer, whose
+        ///bars are whole notes.
+        pub fn beat_value(&self) -> BeatValue
+        {
+            BeatValue::from_bpm(self.tempo / 4.0)
+        }
+
+        ///AbcTune.to_notes() -> Vec<(u64, RawNote)>: The notes with their
+        ///start, both in ms at the tune's tempo.
+        pub fn to_notes(&self) -> Vec<(u64, RawNote)>
+        {
+            self.pattern.to_notes(&self.beat_value())
+        }
+
+        ///AbcTune.measure() -> f64: Length of a measure in whole notes.
+        pub fn measure(&self) -> f64
+        {
+            self.meter.0 as f64 / self.meter.1 as f64
+        }
+
+        ///AbcTune.apply<T>(sequencer: &mut PartialSequencer<T>): Sets
+        ///sequencer to the tune's tempo with one sequencer bar per measure
+        ///and loads the tune into it, so the loop lasts as many measures as
+        ///the tune instead of being padded to whole notes.
+        pub fn apply<T: Instrument::PlayableInstrument>(&self, sequencer: &mut PartialSequencer<T>)
+        {
+            let measure = self.measure();
+            let events = self.pattern.events.iter().map(|x| PatternEvent{
+                start: x.start / measure,
+                length: x.length / measure,
+                ..*x
+            }).collect();
+            sequencer.set_tempo((self.tempo as f64 / 4.0 / measure) as f32);
+            sequencer.load_pattern(&Pattern{
+                events,
+                length: self.pattern.length / measure,
+            });
+        }
+    }
+
+    ///Abc::export<T>(sequencer, title: &str, meter: (u32, u32), key: &str) -> Result<String, AbcError>:
+    ///Writes the loop of sequencer as a tune in meter and key, a sequencer
+    ///bar being one measure as AbcTune.apply loads it. Times are rounded
+    ///to EXPORT_GRID and the notes flattened to one voice, see
+    ///Pattern.voice. A meter or key that cannot be written is reported at
+    ///its line of the header.
+    pub fn export<T: Instrument::PlayableInstrument>(sequencer: &PartialSequencer<T>, title: &str, meter: (u32, u32), key: &str) -> Result<String, AbcError>
+    {
+        if meter.0 == 0 || meter.1 == 0
+        {
+            return Err(AbcError{ line: 3, column: 3, message: "meter must not be zero".to_string() });
+        }
+        let signature = signature(key).map_err(|e| AbcError{ line: 6, column: 3, message: e.to_string() })?;
+        let measure = (EXPORT_GRID * meter.0 as u64 / meter.1 as u64).max(1);
+        let pattern = sequencer.to_pattern();
+        let total = pattern.bars() * measure;
+        let mut writer = Writer{
+            body: String::new(),
+            position: 0,
+            measure,
+            signature,
+            flats: signature.iter().any(|x| *x < 0),
+            accidentals: HashMap::new(),
+        };
+        for (start, length, notes) in pattern.voice(measure)
+        {
+            if start > writer.position
+            {
+                let rest = start - writer.position;
+                writer.element(None, rest);
+            }
+            let keys: Vec<u8> = notes.iter().map(|x| x.0).collect();
+            writer.element(Some(&keys), length);
+        }
+        if writer.position < total
+        {
+            let rest = total - writer.position;
+            writer.element(None, rest);
+        }
+        let body = writer.body.trim_end().trim_end_matches('|').trim_end().to_string();
+        let tempo = sequencer.local_beat_builder.bpm() as f64 * 4.0 * meter.0 as f64 / meter.1 as f64;
+        let key = if key.trim().is_empty() { "C" } else { key.trim() };
+
+        Ok(format!("X:1\nT:{}\nM:{}/{}\nL:1/16\nQ:1/4={}\nK:{}\n{} |]\n", title, meter.0, meter.1, tempo.round(), key, body))
+    }
+
+    struct Writer
+    {
+        body: String,
+        position: u64,
+        //Steps per measure.
+        measure: u64,
+        signature: [i32; 7],
+        //Whether black keys are spelled as flats.
+        flats: bool,
+        //Alteration last written for each letter and octave in this bar.
+        accidentals: HashMap<(usize, i32), i32>,
+    }
+
+    impl Writer
+    {
+        //Writes a chord, note or rest (no keys) of length steps, split and
+        //tied over bar lines.
+        fn element(&mut self, keys: Option<&Vec<u8>>, mut length: u64)
+        {
+            while length > 0
+            {
+                let room = self.measure - self.position % self.measure;
+                let part = length.min(room);
+                let tie = part < length;
+                match keys
+                {
+                    Some(keys) if keys.len() > 1 =>
+                    {
+                        self.body.push('[');
+                        for key in keys.iter()
+                        {
+                            let note = self.note(*key);
+                            self.body.push_str(&note);
+                            if tie
+                            {
+                                self.body.push('-');
+                            }
+                        }
+                        self.body.push(']');
+                        self.body.push_str(&length_to_abc(part));
+                    },
+                    Some(keys) =>
+                    {
+                        let note = self.note(keys[0]);
+                        self.body.push_str(&note);
+                        self.body.push_str(&length_to_abc(part));
+                        if tie
+                        {
+                            self.body.push('-');
+                        }
+                    },
+                    None =>
+                    {
+                        self.body.push('z');
+                        self.body.push_str(&length_to_abc(part));
+                    },
+                }
+                self.body.push(' ');
+                self.position += part;
+                length -= part;
+                if self.position.is_multiple_of(self.measure)
+                {
+                    self.accidentals.clear();
+                    self.body.push('|');
+                    if self.position.is_multiple_of(self.measure * EXPORT_BARS_PER_LINE)
+                    {
+                        self.body.push('\n');
+                    }
+                    else
+                    {
+                        self.body.push(' ');
+                    }
+                }
+            }
+        }
+
+        //Spells key as a letter and alteration, writing an accidental only
+        //where the key signature and the bar so far do not give it.
+        fn note(&mut self, key: u8) -> String
+        {
+            const SHARPS: [(usize, i32); 12] = [(0, 0), (0, 1), (1, 0), (1, 1), (2, 0), (3, 0), (3, 1), (4, 0), (4, 1), (5, 0), (5, 1), (6, 0)];
+            const FLATS: [(usize, i32); 12] = [(0, 0), (1, -1), (1, 0), (2, -1), (2, 0), (3, 0), (4, -1), (4, 0), (5, -1), (5, 0), (6, -1), (6, 0)];
+            let (letter, alteration) = if self.flats { FLATS } else { SHARPS }[(key % 12) as usize];
+            let octave = key as i32 / 12 - 1;
+            let mut ret = String::new();
+            let current = self.accidentals.get(&(letter, octave)).cloned().unwrap_or(self.signature[letter]);
+            if alteration != current
+            {
+                ret.push(match alteration { 1 => '^', -1 => '_', _ => '=' });
+                self.accidentals.insert((letter, octave), alteration);
+            }
+            let letter = ['C', 'D', 'E', 'F', 'G', 'A', 'B'][letter];
+            if octave >= 5
+            {
+                ret.push(letter.to_ascii_lowercase());
+                for _ in 5..octave
+                {
+                    ret.push('\'');
+                }
+            }
+            else
+            {
+                ret.push(letter);
+                for _ in octave..4
+                {
+                    ret.push(',');
+                }
+            }
+            ret
+        }
+    }
+
+    //Length of steps in L:1/16 units, as written after a note.
+    fn length_to_abc(steps: u64) -> String
+    {
+        let per_unit = EXPORT_GRID / 16;
+        let mut numerator = steps;
+        let mut denominator = per_unit;
+        let divisor = gcd(numerator, denominator);
+        numerator /= divisor;
+        denominator /= divisor;
+        match (numerator, denominator)
+        {
+            (1, 1) => String::new(),
+            (n, 1) => format!("{}", n),
+            (1, d) => format!("/{}", d),
+            (n, d) => format!("{}/{}", n, d),
+        }
+    }
+
+    fn gcd(a: u64, b: u64) -> u64
+    {
+        if b == 0 { a } else { gcd(b, a % b) }
+    }
+
+    struct Event
+    {
+        start: f64,
+        length: f64,
+        key: u8,
+        velocity: u8,
+        tied: bool,
+    }
+
+    struct Parser
+    {
+        tune: AbcTune,
+        //Alteration of each letter (C to B) by the key signature.
+        signature: [i32; 7],
+        //Alterations written in the current bar, by letter and octave.
+        accidentals: HashMap<(usize, i32), i32>,
+        velocity: u8,
+        events: Vec<Event>,
+        time: f64,
+        //First event and length of the last note, chord or rest, for
+        //broken rhythm.
+        last: Option<(usize, f64)>,
+        broken: f64,
+        //Notes left in a tuplet and the factor applied to them.
+        tuplet: Option<(u32, f64)>,
+        //Event and time repeats go back to, and where a first ending
+        //started.
+        repeat: (usize, f64),
+        ending: Option<(usize, f64)>,
+        line: usize,
+    }
+
+    fn letter_index(c: char) -> Option<usize>
+    {
+        match c.to_ascii_uppercase()
+        {
+            'C' => Some(0),
+            'D' => Some(1),
+            'E' => Some(2),
+            'F' => Some(3),
+            'G' => Some(4),
+            'A' => Some(5),
+            'B' => Some(6),
+            _ => None,
+        }
+    }
+
+    const LETTER_SEMITONES: [i32; 7] = [0, 2, 4, 5, 7, 9, 11];
+
+    //Alteration of each letter (C to B) in the key written in a K: field,
+    //like G, F#m or Bbdor.
+    fn signature(value: &str) -> Result<[i32; 7], &'static str>
+    {
+        let mut ret = [0; 7];
+        let value = value.split_whitespace().next().unwrap_or("");
+        if value.is_empty() || value == "none"
+        {
+            return Ok(ret);
+        }
+        let chars: Vec<char> = value.chars().collect();
+        let tonic = match letter_index(chars[0])
+        {
+            Some(x) if chars[0].is_ascii_uppercase() => x,
+            _ => return Err("key should start with its tonic, like G or F#m"),
+        };
+        let mut rest: String = chars[1..].iter().collect();
+        //Sharps of the major keys on each tonic.
+        let mut sharps: i32 = [0, 2, 4, -1, 1, 3, 5][tonic];
+        if rest.starts_with('#')
+        {
+            sharps += 7;
+            rest.remove(0);
+        }
+        else if rest.starts_with('b')
+        {
+            sharps -= 7;
+            rest.remove(0);
+        }
+        let mode = rest.to_lowercase();
+        sharps += match mode.get(..3).unwrap_or(mode.as_str())
+        {
+            "" | "maj" | "ion" => 0,
+            "m" | "min" | "aeo" => -3,
+            "mix" => -1,
+            "dor" => -2,
+            "phr" => -4,
+            "lyd" => 1,
+            "loc" => -5,
+            _ => return Err("unknown mode"),
+        };
+        if !(-7..=7).contains(&sharps)
+        {
+            return Err("key has more than 7 accidentals");
+        }
+        //F C G D A E B for sharps, the other way around for flats.
+        let order = [3, 0, 4, 1, 5, 2, 6];
+        for i in 0..sharps.unsigned_abs() as usize
+        {
+            if sharps > 0
+            {
+                ret[order[i]] = 1;
+            }
+            else
+            {
+                ret[order[6 - i]] = -1;
+            }
+        }
+        Ok(ret)
+    }
+
+    impl Parser
+    {
+        fn error(&self, column: usize, message: &str) -> AbcError
+        {
+            AbcError{
+                line: self.line,
+                column: column + 1,
+                message: message.to_string(),
+            }
+        }
+
+        fn run(&mut self, text: &str) -> Result<(), AbcError>
+        {
+            let mut in_body = false;
+            let mut started = false;
+            for (number, line) in text.lines().enumerate()
+            {
+                self.line = number + 1;
+                let line = strip_comment(line);
+                let line = line.as_str();
+                let chars: Vec<char> = line.chars().collect();
+                if chars.len() >= 2 && chars[1] == ':' && chars[0].is_ascii_alphabetic()
+                {
+                    if chars[0] == 'X' && started
+                    {
+                        //The next tune starts here.
+                        break;
+                    }
+                    started = true;
+                    let value = chars[2..].iter().collect::<String>().replace("\\%", "%");
+                    self.field(chars[0], value.trim(), 2)?;
+                    if chars[0] == 'K'
+                    {
+                        in_body = true;
+                    }
+                    continue;
+                }
+                if !in_body
+                {
+                    if line.trim().is_empty()
+                    {
+                        continue;
+                    }
+                    //Tunes without a K: field start straight away.
+                    in_body = true;
+                }
+                if line.trim().is_empty() && started && !self.events.is_empty()
+                {
+                    //A blank line ends the tune.
+                    break;
+                }
+                started = true;
+                self.body(&chars)?;
+            }
+            self.finish()
+        }
+
+        fn field(&mut self, name: char, value: &str, column: usize) -> Result<(), AbcError>
+        {
+            match name
+            {
+                'T' if self.tune.title.is_empty() =>
+                {
+                    self.tune.title = value.to_string();
+                },
+                'M' =>
+                {
+                    self.tune.meter = match value
+                    {
+                        "C" => (4, 4),
+                        "C|" => (2, 2),
+                        "none" | "" => (4, 4),
+                        _ => match fraction(value)
+                        {
+                            Some((n, d)) if n > 0 && d > 0 => (n as u32, d as u32),
+                            _ => return Err(self.error(column, "meter should look like 6/8")),
+                        },
+                    };
+                },
+                'L' =>
+                {
+                    self.tune.unit = match fraction(value)
+                    {
+                        Some((n, d)) if n > 0 && d > 0 => n as f64 / d as f64,
+                        _ => return Err(self.error(column, "unit length should look like 1/8")),
+                    };
+                },
+                'Q' =>
+                {
+                    self.tune.tempo = self.tempo(value, column)?;
+                },
+                'K' =>
+                {
+                    self.signature = self.key(value, column)?;
+                    self.tune.key = if value.is_empty() { "C".to_string() } else { value.to_string() };
+                },
+                _ =>
+                {
+                },
+            }
+            Ok(())
+        }
+
+        fn unit(&self) -> f64
+        {
+            if self.tune.unit > 0.0
+            {
+                return self.tune.unit;
+            }
+            let (n, d) = self.tune.meter;
+            if (n as f64 / d as f64) < 0.75 { 1.0 / 16.0 } else { 1.0 / 8.0 }
+        }
+
+        //Q:1/4=120, Q:3/8=40, Q:"Allegro" 1/4=120 or Q:120 (in units).
+        fn tempo(&self, value: &str, column: usize) -> Result<f32, AbcError>
+        {
+            let value = match value.rfind('"')
+            {
+                Some(x) => &value[x + 1..],
+                None => value,
+            };
+            let (beat, bpm) = match value.find('=')
+            {
+                Some(x) =>
+                {
+                    let mut beat = 0.0;
+                    for part in value[..x].split_whitespace()
+                    {
+                        match fraction(part)
+                        {
+                            Some((n, d)) if d > 0 => beat += n as f64 / d as f64,
+                            _ => return Err(self.error(column, "tempo should look like 1/4=120")),
+                        }
+                    }
+                    (beat, value[x + 1..].trim().parse::<f64>().ok())
+                },
+                None => (self.unit(), value.trim().parse::<f64>().ok()),
+            };
+            match bpm
+            {
+                Some(bpm) if bpm > 0.0 && beat > 0.0 => Ok((bpm * beat * 4.0) as f32),
+                _ => Err(self.error(column, "tempo should look like 1/4=120")),
+            }
+        }
+
+        fn key(&self, value: &str, column: usize) -> Result<[i32; 7], AbcError>
+        {
+            signature(value).map_err(|e| self.error(column, e))
+        }
+
+        fn body(&mut self, chars: &[char]) -> Result<(), AbcError>
+        {
+            let mut i = 0;
+            while i < chars.len()
+            {
+                let c = chars[i];
+                match c
+                {
+                    ' ' | '\t' | '\\' | '`' | 'y' | ')' | '.' | '~' | 'H' | 'L' | 'M' | 'O' | 'P' | 'S' | 'T' | 'u' | 'v' =>
+                    {
+                        i += 1;
+                    },
+                    '"' =>
+                    {
+                        //Chord symbols and annotations.
+                        i = skip_to(chars, i, '"').ok_or_else(|| self.error(i, "this is never closed"))?;
+                    },
+                    '!' | '+' =>
+                    {
+                        let end = skip_to(chars, i, c).ok_or_else(|| self.error(i, "this is never closed"))?;
+                        let name: String = chars[i + 1..end - 1].iter().collect();
+                        self.decoration(&name);
+                        i = end;
+                    },
+                    '{' =>
+                    {
+                        //Grace notes.
+                        i = skip_to(chars, i, '}').ok_or_else(|| self.error(i, "this is never closed"))?;
+                    },
+                    '(' =>
+                    {
+                        match chars.get(i + 1).and_then(|x| x.to_digit(10))
+                        {
+                            Some(p) if p >= 2 =>
+                            {
+                                let q = match p
+                                {
+                                    3 | 6 => 2,
+                                    2 | 4 | 8 => 3,
+                                    _ => if self.tune.meter.0.is_multiple_of(3) && self.tune.meter.0 > 3 { 3 } else { 2 },
+                                };
+                                self.tuplet = Some((p, q as f64 / p as f64));
+                                i += 2;
+                            },
+                            _ =>
+                            {
+                                //A slur.
+                                i += 1;
+                            },
+                        }
+                    },
+                    '>' | '<' =>
+                    {
+                        let mut dots = 0;
+                        while chars.get(i) == Some(&c)
+                        {
+                            dots += 1;
+                            i += 1;
+                        }
+                        let shorter = 0.5f64.powi(dots);
+                        let (first, second) = if c == '>' { (2.0 - shorter, shorter) } else { (shorter, 2.0 - shorter) };
+                        match self.last
+                        {
+                            Some((index, length)) =>
+                            {
+                                let changed = length * first;
+                                for event in self.events[index..].iter_mut()
+                                {
+                                    event.length *= first;
+                                }
+                                self.time += changed - length;
+                                self.broken = second;
+                            },
+                            None =>
+                            {
+                                return Err(self.error(i - dots as usize, "broken rhythm needs a note before it"));
+                            },
+                        }
+                    },
+                    '|' | ':' =>
+                    {
+                        i = self.bar_line(chars, i)?;
+                    },
+                    '[' =>
+                    {
+                        match (chars.get(i + 1), chars.get(i + 2))
+                        {
+                            (Some(&x), Some(&':')) if x.is_ascii_alphabetic() =>
+                            {
+                                let end = skip_to(chars, i, ']').ok_or_else(|| self.error(i, "this is never closed"))?;
+                                let value: String = chars[i + 3..end - 1].iter().collect();
+                                self.field(x, value.trim(), i)?;
+                                i = end;
+                            },
+                            (Some(x), _) if x.is_ascii_digit() =>
+                            {
+                                self.start_ending(chars[i + 1]);
+                                i += 2;
+                            },
+                            (Some(&'|'), _) =>
+                            {
+                                i = self.bar_line(chars, i + 1)?;
+                            },
+                            _ =>
+                            {
+                                i = self.chord(chars, i)?;
+                            },
+                        }
+                    },
+                    'z' | 'x' | 'Z' | 'X' =>
+                    {
+                        let start = i;
+                        i += 1;
+                        let (multiplier, end) = self.length(chars, i)?;
+                        i = end;
+                        let length = if c.is_ascii_uppercase()
+                        {
+                            //Whole bars.
+                            let (n, d) = self.tune.meter;
+                            let bars = if chars[start + 1..end].is_empty() { 1.0 } else { multiplier };
+                            bars * n as f64 / d as f64
+                        }
+                        else
+                        {
+                            self.adjust(multiplier * self.unit())
+                        };
+                        self.last = Some((self.events.len(), length));
+                        self.time += length;
+                    },
+                    _ =>
+                    {
+                        if letter_index(c).is_some() || c == '^' || c == '_' || c == '='
+                        {
+                            let first = self.events.len();
+                            let (key, end) = self.pitch(chars, i)?;
+                            let (multiplier, end) = self.length(chars, end)?;
+                            let length = self.adjust(multiplier * self.unit());
+                            let tied = chars.get(end) == Some(&'-');
+                            self.events.push(Event{
+                                start: self.time,
+                                length,
+                                key,
+                                velocity: self.velocity,
+                                tied,
+                            });
+                            self.last = Some((first, length));
+                            self.time += length;
+                            i = if tied { end + 1 } else { end };
+                        }
+                        else
+                        {
+                            return Err(self.error(i, &format!("unexpected '{}'", c)));
+                        }
+                    },
+                }
+            }
+            Ok(())
+        }
+
+        //Applies broken rhythm and tuplets to the length of the next note.
+        fn adjust(&mut self, length: f64) -> f64
+        {
+            let mut ret = length * self.broken;
+            self.broken = 1.0;
+            if let Some((left, factor)) = self.tuplet
+            {
+                ret *= factor;
+                self.tuplet = if left > 1 { Some((left - 1, factor)) } else { None };
+            }
+            ret
+        }
+
+        fn decoration(&mut self, name: &str)
+        {
+            self.velocity = match name
+            {
+                "pppp" => 15,
+                "ppp" => 30,
+                "pp" => 45,
+                "p" => 60,
+                "mp" => 75,
+                "mf" => 90,
+                "f" => 105,
+                "ff" => 120,
+                "fff" | "ffff" => 127,
+                _ => return,
+            };
+        }
+
+        fn pitch(&mut self, chars: &[char], mut i: usize) -> Result<(u8, usize), AbcError>
+        {
+            let start = i;
+            let mut accidental = None;
+            while let Some(&c) = chars.get(i)
+            {
+                let step = match c
+                {
+                    '^' => 1,
+                    '_' => -1,
+                    '=' => 0,
+                    _ => break,
+                };
+                accidental = Some(if step == 0 { 0 } else { accidental.unwrap_or(0) + step });
+                i += 1;
+            }
+            let letter = match chars.get(i).and_then(|x| letter_index(*x))
+            {
+                Some(x) => x,
+                None => return Err(self.error(start, "accidental without a note")),
+            };
+            let mut octave = if chars[i].is_ascii_lowercase() { 5 } else { 4 };
+            i += 1;
+            while let Some(&c) = chars.get(i)
+            {
+                match c
+                {
+                    '\'' => octave += 1,
+                    ',' => octave -= 1,
+                    _ => break,
+                }
+                i += 1;
+            }
+            let alteration = match accidental
+            {
+                Some(x) =>
+                {
+                    self.accidentals.insert((letter, octave), x);
+                    x
+                },
+                None => self.accidentals.get(&(letter, octave)).cloned().unwrap_or(self.signature[letter]),
+            };
+            let key = 12 * (octave + 1) + LETTER_SEMITONES[letter] + alteration;
+            if !(12..=127).contains(&key)
+            {
+                return Err(self.error(start, "note is out of range"));
+            }
+            Ok((key as u8, i))
+        }
+
+        //Length multiplier written at i: 2, 3/2, /2, / (half), // (quarter).
+        fn length(&self, chars: &[char], mut i: usize) -> Result<(f64, usize), AbcError>
+        {
+            let start = i;
+            let number = |i: &mut usize| -> Option<u32>
+            {
+                let mut ret = None;
+                while let Some(digit) = chars.get(*i).and_then(|x| x.to_digit(10))
+                {
+                    ret = Some(ret.unwrap_or(0u32).saturating_mul(10).saturating_add(digit));
+                    *i += 1;
+                }
+                ret
+            };
+            let numerator = number(&mut i).unwrap_or(1);
+            let mut denominator = 1u32;
+            while chars.get(i) == Some(&'/')
+            {
+                i += 1;
+                denominator = denominator.saturating_mul(number(&mut i).unwrap_or(2));
+            }
+            if numerator == 0 || denominator == 0
+            {
+                return Err(self.error(start, "length must not be zero"));
+            }
+            Ok((numerator as f64 / denominator as f64, i))
+        }
+
+        fn chord(&mut self, chars: &[char], start: usize) -> Result<usize, AbcError>
+        {
+            let first = self.events.len();
+            let mut i = start + 1;
+            let mut notes = Vec::new();
+            loop
+            {
+                match chars.get(i)
+                {
+                    Some(&']') =>
+                    {
+                        i += 1;
+                        break;
+                    },
+                    Some(&c) if letter_index(c).is_some() || c == '^' || c == '_' || c == '=' =>
+                    {
+                        let (key, end) = self.pitch(chars, i)?;
+                        let (multiplier, end) = self.length(chars, end)?;
+                        let tied = chars.get(end) == Some(&'-');
+                        notes.push((key, multiplier, tied));
+                        i = if tied { end + 1 } else { end };
+                    },
+                    Some(&' ') =>
+                    {
+                        i += 1;
+                    },
+                    Some(&c) =>
+                    {
+                        return Err(self.error(i, &format!("expected a note in the chord, not '{}'", c)));
+                    },
+                    None =>
+                    {
+                        return Err(self.error(start, "this is never closed"));
+                    },
+                }
+            }
+            if notes.is_empty()
+            {
+                return Err(self.error(start, "empty chord"));
+            }
+            let (multiplier, end) = self.length(chars, i)?;
+            let tied = chars.get(end) == Some(&'-');
+            let unit = self.adjust(multiplier * self.unit());
+            for &(key, own, tie) in notes.iter()
+            {
+                self.events.push(Event{
+                    start: self.time,
+                    length: unit * own,
+                    key,
+                    velocity: self.velocity,
+                    tied: tie || tied,
+                });
+            }
+            //The chord lasts as long as its first note.
+            let length = unit * notes[0].1;
+            self.last = Some((first, length));
+            self.time += length;
+            Ok(if tied { end + 1 } else { end })
+        }
+
+        fn start_ending(&mut self, number: char)
+        {
+            if number == '1'
+            {
+                self.ending = Some((self.events.len(), self.time));
+            }
+        }
+
+        fn bar_line(&mut self, chars: &[char], start: usize) -> Result<usize, AbcError>
+        {
+            let mut i = start;
+            while let Some(&c) = chars.get(i)
+            {
+                if c != '|' && c != ':' && c != ']' && !(c == '[' && chars.get(i + 1) == Some(&'|'))
+                {
+                    break;
+                }
+                i += 1;
+            }
+            let bar: String = chars[start..i].iter().collect();
+            self.accidentals.clear();
+            let close = bar.starts_with(':');
+            let open = bar.ends_with(':');
+            if close
+            {
+                self.repeat_section();
+            }
+            if open
+            {
+                self.repeat = (self.events.len(), self.time);
+                self.ending = None;
+            }
+            if bar == ":"
+            {
+                return Err(self.error(start, "unexpected ':'"));
+            }
+            if let Some(&number) = chars.get(i)
+            {
+                if number.is_ascii_digit() && bar.ends_with('|')
+                {
+                    self.start_ending(number);
+                    i += 1;
+                }
+            }
+            Ok(i)
+        }
+
+        //Plays the section since the last |: (or the start) again, leaving
+        //out a first ending.
+        fn repeat_section(&mut self)
+        {
+            let (index, time) = self.repeat;
+            let (end, end_time) = self.ending.unwrap_or((self.events.len(), self.time));
+            let shift = self.time - time;
+            let copies: Vec<Event> = self.events[index..end].iter().filter(|x| x.start < end_time - EPSILON).map(|x| Event{
+                start: x.start + shift,
+                length: x.length,
+                key: x.key,
+                velocity: x.velocity,
+                tied: x.tied,
+            }).collect();
+            self.events.extend(copies);
+            self.time += end_time - time;
+            self.repeat = (self.events.len(), self.time);
+            self.ending = None;
+            self.last = None;
+        }
+
+        fn finish(&mut self) -> Result<(), AbcError>
+        {
+            //A tie into nothing, as at the end of a tune, just holds the
+            //note.
+            let (events, _) = Pattern::join_ties(self.events.drain(..).map(|x| (PatternEvent{
+                start: x.start,
+                length: x.length,
+                key: x.key,
+                velocity: x.velocity,
+            }, x.tied)).collect());
+            self.tune.pattern = Pattern{
+                events,
+                length: self.time,
+            };
+            if self.tune.unit == 0.0
+            {
+                self.tune.unit = self.unit();
+            }
+            Ok(())
+        }
+    }
+
+    fn fraction(value: &str) -> Option<(u64, u64)>
+    {
+        let mut parts = value.trim().splitn(2, '/');
+        let numerator = parts.next()?.trim().parse().ok()?;
+        let denominator = match parts.next()
+        {
+            Some(x) => x.trim().parse().ok()?,
+            None => 1,
+        };
+        Some((numerator, denominator))
+    }
+
+    //line up to its comment, which starts at the first % not written as \%.
+    fn strip_comment(line: &str) -> String
+    {
+        let mut ret = String::new();
+        let mut escaped = false;
+        for c in line.chars()
+        {
+            if c == '%' && !escaped
+            {
+                break;
+            }
+            escaped = c == '\\';
+            ret.push(c);
+        }
+        ret
+    }
+
+    //Index just past the next c after i.
+    fn skip_to(chars: &[char], i: usize, c: char) -> Option<usize>
+    {
+        chars[i + 1..].iter().position(|x| *x == c).map(|x| i + x + 2)
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use instrumentation::interface::instrument::Probe::Probe;
+    use persistence::abc::Abc;
+    use persistence::abc::Abc::AbcTune;
+    use sequencing::sequencing::partialsequencer::PartialSequencer::PartialSequencer;
+    use sequencing::sequencing::pattern::Pattern::Pattern;
+
+    fn keys(tune: &AbcTune) -> Vec<u8>
+    {
+        tune.pattern.events.iter().map(|x| x.key).collect()
+    }
+
+    fn starts(tune: &AbcTune) -> Vec<f64>
+    {
+        tune.pattern.events.iter().map(|x| x.start).collect()
+    }
+
+    #[test]
+    fn header_and_notes_are_read()
+    {
+        let tune = AbcTune::parse("X:1\nT:Scale\nM:3/4\nL:1/8\nQ:1/4=90\nK:C\nC D E F | G A B c | c' C, z2 |]\n").unwrap();
+        assert_eq!(tune.title, "Scale");
+        assert_eq!(tune.meter, (3, 4));
+        assert_eq!(tune.unit, 0.125);
+        assert_eq!(tune.tempo, 90.0);
+        assert_eq!(keys(&tune), vec![60, 62, 64, 65, 67, 69, 71, 72, 84, 48]);
+        assert_eq!(tune.pattern.events[9].start, 1.125);
+        assert_eq!(tune.pattern.length, 1.5);
+    }
+
+    #[test]
+    fn key_signature_and_accidentals_last_a_bar()
+    {
+        let tune = AbcTune::parse("X:1\nL:1/4\nK:G\nF ^C C =F | F C |]\n").unwrap();
+        assert_eq!(keys(&tune), vec![66, 61, 61, 65, 66, 60]);
+    }
+
+    #[test]
+    fn durations_broken_rhythm_and_triplets()
+    {
+        let tune = AbcTune::parse("X:1\nL:1/8\nK:C\nC2 D/ E> F (3GAB |]\n").unwrap();
+        let lengths: Vec<f64> = tune.pattern.events.iter().map(|x| x.length).collect();
+        assert_eq!(&lengths[..4], &[0.25, 0.0625, 0.1875, 0.0625]);
+        assert!((lengths[4] - 1.0 / 12.0).abs() < 1e-9);
+        assert!((tune.pattern.length - (0.5625 + 0.25)).abs() < 1e-9);
+    }
+
+    #[test]
+    fn chords_ties_and_repeats()
+    {
+        let tune = AbcTune::parse("X:1\nL:1/4\nK:C\n|: [CEG] C- | C :|\n").unwrap();
+        assert_eq!(keys(&tune), vec![60, 64, 67, 60, 60, 64, 67, 60]);
+        assert_eq!(starts(&tune), vec![0.0, 0.0, 0.0, 0.25, 0.75, 0.75, 0.75, 1.0]);
+        assert_eq!(tune.pattern.events[3].length, 0.5);
+    }
+
+    #[test]
+    fn first_and_second_endings()
+    {
+        let tune = AbcTune::parse("X:1\nL:1/4\nK:C\n|: C |1 D :|2 E |]\n").unwrap();
+        assert_eq!(keys(&tune), vec![60, 62, 60, 64]);
+    }
+
+    #[test]
+    fn errors_point_at_the_problem()
+    {
+        let error = AbcTune::parse("X:1\nK:C\nC D &\n").unwrap_err();
+        assert_eq!((error.line, error.column), (3, 5));
+        assert!(AbcTune::parse("X:1\nK:C\n[CE\n").is_err());
+    }
+
+    #[test]
+    fn sequencer_loops_are_exported()
+    {
+        let mut sequencer = PartialSequencer::new(120.0, Probe::new());
+        sequencer.load_pattern(&Pattern::parse("C4/4 [E G]/8 r/8 C#5/2").unwrap());
+        let abc = Abc::export(&sequencer, "Loop", (4, 4), "C").unwrap();
+        assert_eq!(abc, "X:1\nT:Loop\nM:4/4\nL:1/16\nQ:1/4=480\nK:C\nC4 [EG]2 z2 ^c8 |]\n");
+        let tune = AbcTune::parse(&abc).unwrap();
+        assert_eq!(keys(&tune), vec![60, 64, 67, 73]);
+    }
+
+    //Parses text, loads it into a sequencer, exports it with the tune's own
+    //meter and key and parses the result again.
+    fn round_trip(text: &str) -> (AbcTune, AbcTune, String)
+    {
+        let tune = AbcTune::parse(text).unwrap();
+        let mut sequencer = PartialSequencer::new(120.0, Probe::new());
+        tune.apply(&mut sequencer);
+        let abc = Abc::export(&sequencer, &tune.title, tune.meter, &tune.key).unwrap();
+        let again = AbcTune::parse(&abc).unwrap();
+        (tune, again, abc)
+    }
+
+    fn assert_same_notes(a: &AbcTune, b: &AbcTune)
+    {
+        assert_eq!(a.pattern.events.len(), b.pattern.events.len());
+        for (x, y) in a.pattern.events.iter().zip(b.pattern.events.iter())
+        {
+            assert_eq!(x.key, y.key);
+            assert!((x.start - y.start).abs() < 1e-6, "{:?} {:?}", x, y);
+            assert!((x.length - y.length).abs() < 1e-6, "{:?} {:?}", x, y);
+        }
+        assert!((a.pattern.length - b.pattern.length).abs() < 1e-6);
+    }
+
+    #[test]
+    fn notes_lengths_ties_and_chords_survive_a_round_trip()
+    {
+        let (tune, again, abc) = round_trip("X:1\nT:Trip\nM:4/4\nL:1/8\nQ:1/4=100\nK:D\nD2 F/ A/ =c3 B2- | B4 [DFA]4 | _B,8 |]\n");
+        assert_same_notes(&tune, &again);
+        assert_eq!(again.tempo, 100.0);
+        assert_eq!(again.key, "D");
+        assert!(abc.contains("\nM:4/4\n"));
+        assert!(abc.contains("\nK:D\n"));
+        //The tie over the bar line is written back as one.
+        assert!(abc.contains("=c6 B4- | B8"));
+    }
+
+    #[test]
+    fn meters_survive_a_round_trip()
+    {
+        let (tune, again, abc) = round_trip("X:1\nT:Waltz\nM:3/4\nL:1/4\nQ:1/4=90\nK:F\nF A c | B2 A | G3 |]\n");
+        assert_same_notes(&tune, &again);
+        assert_eq!(again.meter, (3, 4));
+        assert_eq!(again.tempo, 90.0);
+        assert!(abc.contains("\nM:3/4\n"));
+        assert!(abc.contains("\nK:F\n"));
+        assert!(abc.contains("B8 A4 |"));
+        let (tune, again, abc) = round_trip("X:1\nM:6/8\nL:1/8\nK:Am\nA2 B c2 d | e3 ^G3 |]\n");
+        assert_same_notes(&tune, &again);
+        assert_eq!(again.meter, (6, 8));
+        assert!(abc.contains("\nM:6/8\n"));
+        assert!(abc.contains("^G6 |]"));
+    }
+
+    #[test]
+    fn loops_last_as_long_as_the_tune()
+    {
+        let tune = AbcTune::parse("X:1\nM:3/4\nL:1/4\nQ:1/4=120\nK:C\nC D E | F G A |]\n").unwrap();
+        let mut sequencer = PartialSequencer::new(120.0, Probe::new());
+        tune.apply(&mut sequencer);
+        assert_eq!(sequencer.bars, 2);
+        //Six quarter notes at 120 quarter notes per minute.
+        assert_eq!(sequencer.loop_length(), 3000);
+        let last = tune.to_notes().last().unwrap().0;
+        assert!(sequencer.note_hash.contains_key(&last));
+    }
+
+    #[test]
+    fn bad_meters_and_keys_are_not_exported()
+    {
+        let sequencer = PartialSequencer::new(120.0, Probe::new());
+        assert_eq!(Abc::export(&sequencer, "", (0, 4), "C").unwrap_err().line, 3);
+        assert_eq!(Abc::export(&sequencer, "", (4, 4), "Q#").unwrap_err().line, 6);
+    }
+
+    #[test]
+    fn escaped_percent_signs_are_not_comments()
+    {
+        let tune = AbcTune::parse("X:1\nT:50\\% off % the rest\nK:C\nC D % E F\n").unwrap();
+        assert_eq!(tune.title, "50% off");
+        assert_eq!(keys(&tune), vec![60, 62]);
+    }
+}
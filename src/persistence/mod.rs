@@ -0,0 +1,6 @@
+pub mod abc;
+pub mod musicxml;
+pub mod project;
+#[cfg(feature = "serde")]
+pub mod serialization;
+pub mod xml;
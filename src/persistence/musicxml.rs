@@ -0,0 +1,920 @@
+///MusicXml: Scores in MusicXML (partwise), for opening our parts in
+///notation software. A score is a list of named parts, each a Pattern, so
+///lengths are in whole notes like sequencer bars.
+///
+///Export writes every part as a single voice (see Pattern.voice) on a grid
+///of GRID steps per whole note, splitting notes and rests into measures and
+///into lengths that can be written as one (dotted or triplet) note, tied
+///together where a note is split.
+///
+///Import reads every part, with its notes, rests, chords, ties, tuplets,
+///backups and forwards (so several voices work), into a Pattern. Meter,
+///key and tempo are taken from the first place they are given; later
+///changes are not followed. Grace notes are left out.
+#[allow(non_snake_case)]
+pub mod MusicXml
+{
+    use instrumentation::interface::instrument::Instrument;
+    use persistence::xml::Xml::Element;
+    use persistence::xml::Xml::XmlError;
+    use sequencing::sequencing::partialsequencer::PartialSequencer::PartialSequencer;
+    use sequencing::sequencing::pattern::Pattern::Pattern;
+    use sequencing::sequencing::pattern::Pattern::PatternEvent;
+    use sequencing::sequencing::pattern::Pattern::VoiceElement;
+    use sequencing::timing::timing::Beat::BeatValue;
+    use sequencing::tonation::note::NotePrimitives;
+    use sequencing::tonation::note::NotePrimitives::Semitone;
+    use std::fmt::Display;
+    use std::fmt::Error;
+    use std::fmt::Formatter;
+    use std::fs;
+    use std::io;
+
+    ///Steps per whole note that export rounds note times to, fine enough
+    ///for 64th notes and triplets down to 128ths.
+    pub const GRID: u64 = 192;
+    ///Velocity written as a dynamics of 100 percent.
+    pub const FORTE_VELOCITY: f32 = 90.0;
+
+    //Lengths in steps that one note can have, with their type and dots.
+    const NOTE_VALUES: [(u64, &str, u32); 16] = [
+        (192, "whole", 0),
+        (168, "half", 2),
+        (144, "half", 1),
+        (96, "half", 0),
+        (84, "quarter", 2),
+        (72, "quarter", 1),
+        (48, "quarter", 0),
+        (42, "eighth", 2),
+        (36, "eighth", 1),
+        (24, "eighth", 0),
+        (21, "16th", 2),
+        (18, "16th", 1),
+        (12, "16th", 0),
+        (9, "32nd", 1),
+        (6, "32nd", 0),
+        (3, "64th", 0),
+    ];
+
+    //Lengths in steps of notes in a triplet, for what NOTE_VALUES can not
+    //write.
+    const TRIPLET_VALUES: [(u64, &str); 8] = [
+        (128, "whole"),
+        (64, "half"),
+        (32, "quarter"),
+        (16, "eighth"),
+        (8, "16th"),
+        (4, "32nd"),
+        (2, "64th"),
+        (1, "128th"),
+    ];
+
+    ///MusicXmlError: Reason a score could not be read.
+    #[derive(Debug)]
+    pub enum MusicXmlError
+    {
+        Io(io::Error),
+        Xml(XmlError),
+        Format(String),
+    }
+
+    impl Display for MusicXmlError
+    {
+        fn fmt(&self, f: &mut Formatter) -> Result<(), Error>
+        {
+            match *self
+            {
+                MusicXmlError::Io(ref e) => write!(f, "io error: {}", e),
+                MusicXmlError::Xml(ref e) => write!(f, "xml error: {}", e),
+                MusicXmlError::Format(ref e) => write!(f, "{}", e),
+            }
+        }
+    }
+
+    impl From<io::Error> for MusicXmlError
+    {
+        fn from(e: io::Error) -> MusicXmlError
+        {
+            MusicXmlError::Io(e)
+        }
+    }
+
+    impl From<XmlError> for MusicXmlError
+    {
+        fn from(e: XmlError) -> MusicXmlError
+        {
+            MusicXmlError::Xml(e)
+        }
+    }
+
+    ///Score: The parts of a score and what they share. tempo is in quarter
+    ///notes per minute and key in fifths, negative for flats.
+    #[derive(Clone, Debug, PartialEq)]
+    pub struct Score
+    {
+        pub title: String,
+        pub meter: (u32, u32),
+        pub key: i32,
+        pub tempo: f32,
+        pub parts: Vec<(String, Pattern)>,
+    }
+
+    impl Score
+    {
+        ///Score::new(title: &str) -> Score: An empty score in 4/4 and C major
+        ///at 120 quarter notes per minute.
+        pub fn new(title: &str) -> Score
+        {
+            Score{
+                title: title.to_string(),
+                meter: (4, 4),
+                key: 0,
+                tempo: 120.0,
+                parts: Vec::new(),
+            }
+        }
+
+        ///Score.meter(beats: u32, beat_type: u32) -> Score
+        pub fn meter(mut self, beats: u32, beat_type: u32) -> Score
+        {
+            self.meter = (beats.max(1), beat_type.max(1));
+            self
+        }
+
+        ///Score.key(fifths: i32) -> Score: Key signature, from -7 (7 flats)
+        ///to 7 (7 sharps). Black keys are spelled as flats in flat keys.
+        pub fn key(mut self, fifths: i32) -> Score
+        {
+            self.key = fifths.clamp(-7, 7);
+            self
+        }
+
+        ///Score.tempo(bpm: f32) -> Score: Tempo in quarter notes per minute.
+        pub fn tempo(mut self, bpm: f32) -> Score
+        {
+            self.tempo = bpm;
+            self
+        }
+
+        ///Score.part(name: &str, pattern: Pattern) -> Score
+        pub fn part(mut self, name: &str, pattern: Pattern) -> Score
+        {
+            self.parts.push((name.to_string(), pattern));
+            self
+        }
+
+        ///Score.sequencer<T>(name: &str, sequencer: &PartialSequencer<T>) -> Score:
+        ///Adds the loop of sequencer as a part. The first sequencer added
+        ///also sets the tempo, a sequencer bar being a whole note.
+        pub fn sequencer<T: Instrument::PlayableInstrument>(mut self, name: &str, sequencer: &PartialSequencer<T>) -> Score
+        {
+            if self.parts.is_empty()
+            {
+                self.tempo = sequencer.local_beat_builder.bpm() * 4.0;
+            }
+            self.part(name, sequencer.to_pattern())
+        }
+
+        ///Score::load(path: &str) -> Result<Score, MusicXmlError>
+        pub fn load(path: &str) -> Result<Score, MusicXmlError>
+        {
+            Score::parse(&fs::read_to_string(path)?)
+        }
+
+        ///Score::parse(text: &str) -> Result<Score, MusicXmlError>: Reads a
+        ///partwise score. Parts without a name are named by their id.
+        pub fn parse(text: &str) -> Result<Score, MusicXmlError>
+        {
+            let root = Element::parse(text)?;
+            if root.name != "score-partwise"
+            {
+                return Err(MusicXmlError::Format(format!("only partwise scores can be read, not <{}>", root.name)));
+            }
+            let mut reader = PartReader{
+                score: Score::new(""),
+                meter: None,
+                key: None,
+                tempo: None,
+            };
+            reader.score.title = root.child("work").and_then(|x| x.child_text("work-title"))
+                .or_else(|| root.child_text("movement-title"))
+                .unwrap_or_default();
+            let mut names = Vec::new();
+            if let Some(list) = root.child("part-list")
+            {
+                for part in list.children_named("score-part")
+                {
+                    let id = part.attribute("id").unwrap_or("").to_string();
+                    let name = part.child_text("part-name").unwrap_or_default();
+                    names.push((id, name));
+                }
+            }
+            for part in root.children_named("part")
+            {
+                let id = part.attribute("id").unwrap_or("");
+                let name = names.iter().find(|x| x.0 == id).map(|x| x.1.clone()).unwrap_or_default();
+                let pattern = reader.part(part, id)?;
+                reader.score.parts.push((if name.is_empty() { id.to_string() } else { name }, pattern));
+            }
+            let mut score = reader.score;
+            score.meter = reader.meter.unwrap_or((4, 4));
+            score.key = reader.key.unwrap_or(0);
+            score.tempo = reader.tempo.unwrap_or(120.0);
+            Ok(score)
+        }
+
+        ///Score.beat_value() -> BeatValue: The tempo for a sequencer, whose
+        ///bars are whole notes.
+        pub fn beat_value(&self) -> BeatValue
+        {
+            BeatValue::from_bpm(self.tempo / 4.0)
+        }
+
+        ///Score.apply<T>(part: usize, sequencer: &mut PartialSequencer<T>) -> bool:
+        ///Sets sequencer to the score's tempo and loads a part into it, so
+        ///that part n can be played on track n. False if there is no such
+        ///part.
+        pub fn apply<T: Instrument::PlayableInstrument>(&self, part: usize, sequencer: &mut PartialSequencer<T>) -> bool
+        {
+            match self.parts.get(part)
+            {
+                Some(x) =>
+                {
+                    sequencer.set_tempo(self.tempo / 4.0);
+                    sequencer.load_pattern(&x.1);
+                    true
+                },
+                None =>
+                {
+                    false
+                },
+            }
+        }
+
+        ///Score.save(path: &str) -> io::Result<()>
+        pub fn save(&self, path: &str) -> io::Result<()>
+        {
+            fs::write(path, self.to_xml())
+        }
+
+        ///Score.to_xml() -> String
+        pub fn to_xml(&self) -> String
+        {
+            let mut ret = String::new();
+            ret.push_str("<?xml version=\"1.0\" encoding=\"UTF-8\" standalone=\"no\"?>\n");
+            ret.push_str("<!DOCTYPE score-partwise PUBLIC \"-//Recordare//DTD MusicXML 3.1 Partwise//EN\" \"http://www.musicxml.org/dtds/partwise.dtd\">\n");
+            ret.push_str("<score-partwise version=\"3.1\">\n");
+            ret.push_str(&format!("  <work>\n    <work-title>{}</work-title>\n  </work>\n", escape(&self.title)));
+            ret.push_str("  <part-list>\n");
+            for (i, part) in self.parts.iter().enumerate()
+            {
+                ret.push_str(&format!("    <score-part id=\"P{}\">\n      <part-name>{}</part-name>\n    </score-part>\n", i + 1, escape(&part.0)));
+            }
+            ret.push_str("  </part-list>\n");
+            let measure = (GRID * self.meter.0 as u64 / self.meter.1 as u64).max(1);
+            let measures = self.parts.iter().map(|x| (x.1.bars() * GRID).div_ceil(measure)).max().unwrap_or(0).max(1);
+            for (i, part) in self.parts.iter().enumerate()
+            {
+                ret.push_str(&format!("  <part id=\"P{}\">\n", i + 1));
+                self.write_part(&mut ret, &part.1, measure, measures);
+                ret.push_str("  </part>\n");
+            }
+            ret.push_str("</score-partwise>\n");
+            ret
+        }
+
+        fn write_part(&self, out: &mut String, pattern: &Pattern, measure: u64, measures: u64)
+        {
+            //Notes and rests (no keys) with their start and length in steps.
+            let mut elements: Vec<VoiceElement> = Vec::new();
+            let mut position = 0;
+            for (start, length, notes) in pattern.voice(GRID)
+            {
+                if start > position
+                {
+                    elements.push((position, start - position, Vec::new()));
+                }
+                elements.push((start, length, notes));
+                position = start + length;
+            }
+            let total = measure * measures;
+            if position < total
+            {
+                elements.push((position, total - position, Vec::new()));
+            }
+
+            let keys: Vec<u8> = pattern.events.iter().map(|x| x.key).collect();
+            let bass = !keys.is_empty() && keys.iter().map(|x| *x as u64).sum::<u64>() / (keys.len() as u64) < 60;
+
+            let mut index = 0;
+            for number in 0..measures
+            {
+                out.push_str(&format!("    <measure number=\"{}\">\n", number + 1));
+                if number == 0
+                {
+                    out.push_str("      <attributes>\n");
+                    out.push_str(&format!("        <divisions>{}</divisions>\n", GRID / 4));
+                    out.push_str(&format!("        <key>\n          <fifths>{}</fifths>\n        </key>\n", self.key));
+                    out.push_str(&format!("        <time>\n          <beats>{}</beats>\n          <beat-type>{}</beat-type>\n        </time>\n", self.meter.0, self.meter.1));
+                    if bass
+                    {
+                        out.push_str("        <clef>\n          <sign>F</sign>\n          <line>4</line>\n        </clef>\n");
+                    }
+                    else
+                    {
+                        out.push_str("        <clef>\n          <sign>G</sign>\n          <line>2</line>\n        </clef>\n");
+                    }
+                    out.push_str("      </attributes>\n");
+                    out.push_str("      <direction placement=\"above\">\n        <direction-type>\n          <metronome>\n");
+                    out.push_str(&format!("            <beat-unit>quarter</beat-unit>\n            <per-minute>{}</per-minute>\n", self.tempo.round()));
+                    out.push_str(&format!("          </metronome>\n        </direction-type>\n        <sound tempo=\"{}\"/>\n      </direction>\n", self.tempo));
+                }
+
+                let (begin, end) = (number * measure, (number + 1) * measure);
+                while index < elements.len() && elements[index].0 < end
+                {
+                    let (start, length, ref notes) = elements[index];
+                    let from = start.max(begin);
+                    let to = (start + length).min(end);
+                    if notes.is_empty() && from == begin && to == end
+                    {
+                        out.push_str(&format!("      <note>\n        <rest measure=\"yes\"/>\n        <duration>{}</duration>\n        <voice>1</voice>\n      </note>\n", measure));
+                    }
+                    else
+                    {
+                        let mut at = from;
+                        while at < to
+                        {
+                            let value = note_value(to - at);
+                            let tie_stop = at > start;
+                            let tie_start = at + value.0 < start + length;
+                            self.write_note(out, notes, value, tie_stop, tie_start);
+                            at += value.0;
+                        }
+                    }
+                    if start + length <= end
+                    {
+                        index += 1;
+                    }
+                    else
+                    {
+                        break;
+                    }
+                }
+                out.push_str("    </measure>\n");
+            }
+        }
+
+        fn write_note(&self, out: &mut String, notes: &[(u8, u8)], value: (u64, &str, u32, bool), tie_stop: bool, tie_start: bool)
+        {
+            let (length, kind, dots, triplet) = value;
+            let mut value = format!("        <type>{}</type>\n", kind);
+            for _ in 0..dots
+            {
+                value.push_str("        <dot/>\n");
+            }
+            if triplet
+            {
+                value.push_str("        <time-modification>\n          <actual-notes>3</actual-notes>\n          <normal-notes>2</normal-notes>\n        </time-modification>\n");
+            }
+            if notes.is_empty()
+            {
+                out.push_str(&format!("      <note>\n        <rest/>\n        <duration>{}</duration>\n        <voice>1</voice>\n{}      </note>\n", length, value));
+                return;
+            }
+            for (i, &(key, velocity)) in notes.iter().enumerate()
+            {
+                let (step, alter, octave) = self.spell(key);
+                out.push_str(&format!("      <note dynamics=\"{:.2}\">\n", velocity as f32 / FORTE_VELOCITY * 100.0));
+                if i > 0
+                {
+                    out.push_str("        <chord/>\n");
+                }
+                out.push_str(&format!("        <pitch>\n          <step>{}</step>\n", step));
+                if alter != 0
+                {
+                    out.push_str(&format!("          <alter>{}</alter>\n", alter));
+                }
+                out.push_str(&format!("          <octave>{}</octave>\n        </pitch>\n", octave));
+                out.push_str(&format!("        <duration>{}</duration>\n", length));
+                if tie_stop
+                {
+                    out.push_str("        <tie type=\"stop\"/>\n");
+                }
+                if tie_start
+                {
+                    out.push_str("        <tie type=\"start\"/>\n");
+                }
+                out.push_str("        <voice>1</voice>\n");
+                out.push_str(&value);
+                if tie_stop || tie_start
+                {
+                    out.push_str("        <notations>\n");
+                    if tie_stop
+                    {
+                        out.push_str("          <tied type=\"stop\"/>\n");
+                    }
+                    if tie_start
+                    {
+                        out.push_str("          <tied type=\"start\"/>\n");
+                    }
+                    out.push_str("        </notations>\n");
+                }
+                out.push_str("      </note>\n");
+            }
+        }
+
+        //Step, alteration and octave of key, spelled from its Semitone.
+        fn spell(&self, key: u8) -> (char, i32, u8)
+        {
+            let (semitone, register) = match NotePrimitives::midi_to_primitives(key)
+            {
+                Some(x) => x,
+                None => return ('C', 0, 0),
+            };
+            let octave = NotePrimitives::register_to_index(register);
+            let flats = self.key < 0;
+            let (step, alter) = match semitone
+            {
+                Semitone::C => ('C', 0),
+                Semitone::CSharp => if flats { ('D', -1) } else { ('C', 1) },
+                Semitone::D => ('D', 0),
+                Semitone::DSharp => if flats { ('E', -1) } else { ('D', 1) },
+                Semitone::E => ('E', 0),
+                Semitone::F => ('F', 0),
+                Semitone::FSharp => if flats { ('G', -1) } else { ('F', 1) },
+                Semitone::G => ('G', 0),
+                Semitone::GSharp => if flats { ('A', -1) } else { ('G', 1) },
+                Semitone::A => ('A', 0),
+                Semitone::ASharp => if flats { ('B', -1) } else { ('A', 1) },
+                Semitone::B => ('B', 0),
+            };
+            (step, alter, octave)
+        }
+    }
+
+    struct PartReader
+    {
+        score: Score,
+        meter: Option<(u32, u32)>,
+        key: Option<i32>,
+        tempo: Option<f32>,
+    }
+
+    impl PartReader
+    {
+        fn part(&mut self, part: &Element, id: &str) -> Result<Pattern, MusicXmlError>
+        {
+            let mut divisions = 1.0;
+            let mut velocity = FORTE_VELOCITY;
+            let mut events = Vec::new();
+            let mut measure_start = 0.0;
+
+            for (number, measure) in part.children_named("measure").into_iter().enumerate()
+            {
+                let fail = |message: &str| MusicXmlError::Format(format!("part {}, measure {}: {}", id, measure.attribute("number").map_or((number + 1).to_string(), |x| x.to_string()), message));
+                let mut position: f64 = 0.0;
+                let mut longest: f64 = 0.0;
+                let mut last_start = 0.0;
+                for element in measure.elements()
+                {
+                    match element.name.as_str()
+                    {
+                        "attributes" =>
+                        {
+                            if let Some(x) = element.child_text("divisions")
+                            {
+                                divisions = match x.parse::<f64>()
+                                {
+                                    Ok(x) if x > 0.0 && x.is_finite() => x,
+                                    _ => return Err(fail("divisions must be a positive number")),
+                                };
+                            }
+                            if let Some(time) = element.child("time")
+                            {
+                                let beats: Option<u32> = time.child_text("beats").and_then(|x| x.split('+').map(|x| x.trim().parse::<u32>().ok()).sum());
+                                let beat_type = time.child_text("beat-type").and_then(|x| x.parse::<u32>().ok());
+                                match (beats, beat_type)
+                                {
+                                    (Some(b), Some(t)) if b > 0 && t > 0 =>
+                                    {
+                                        self.meter = self.meter.or(Some((b, t)));
+                                    },
+                                    _ =>
+                                    {
+                                        return Err(fail("time signature should have beats and a beat-type"));
+                                    },
+                                }
+                            }
+                            if let Some(fifths) = element.child("key").and_then(|x| x.child_text("fifths"))
+                            {
+                                match fifths.parse::<i32>()
+                                {
+                                    Ok(x) => self.key = self.key.or(Some(x)),
+                                    Err(_) => return Err(fail("fifths must be a number")),
+                                }
+                            }
+                        },
+                        "direction" | "sound" =>
+                        {
+                            let sound = if element.name == "sound" { Some(element) } else { element.child("sound") };
+                            if let Some(sound) = sound
+                            {
+                                if let Some(tempo) = sound.attribute("tempo").and_then(|x| x.parse::<f32>().ok())
+                                {
+                                    if tempo > 0.0
+                                    {
+                                        self.tempo = self.tempo.or(Some(tempo));
+                                    }
+                                }
+                                if let Some(dynamics) = sound.attribute("dynamics").and_then(|x| x.parse::<f32>().ok())
+                                {
+                                    velocity = dynamics * FORTE_VELOCITY / 100.0;
+                                }
+                            }
+                        },
+                        "backup" | "forward" =>
+                        {
+                            let length = match duration(element).map_err(fail)?
+                            {
+                                Some(x) => x / divisions / 4.0,
+                                None => return Err(fail(&format!("<{}> without a duration", element.name))),
+                            };
+                            if element.name == "backup"
+                            {
+                                position = (position - length).max(0.0);
+                            }
+                            else
+                            {
+                                position += length;
+                                longest = longest.max(position);
+                            }
+                        },
+                        "note" =>
+                        {
+                            if element.has("grace")
+                            {
+                                continue;
+                            }
+                            let length = match duration(element).map_err(fail)?
+                            {
+                                Some(x) => x / divisions / 4.0,
+                                None => match written_length(element)
+                                {
+                                    Some(x) => x,
+                                    None => return Err(fail("note without a duration")),
+                                },
+                            };
+                            let start = if element.has("chord")
+                            {
+                                last_start
+                            }
+                            else
+                            {
+                                last_start = position;
+                                position += length;
+                                longest = longest.max(position);
+                                last_start
+                            };
+                            if element.has("rest")
+                            {
+                                continue;
+                            }
+                            let pitch = match element.child("pitch")
+                            {
+                                Some(x) => x,
+                                //Unpitched percussion has no key to play.
+                                None => continue,
+                            };
+                            let step = match pitch.child_text("step").as_deref()
+                            {
+                                Some("C") => 0,
+                                Some("D") => 2,
+                                Some("E") => 4,
+                                Some("F") => 5,
+                                Some("G") => 7,
+                                Some("A") => 9,
+                                Some("B") => 11,
+                                _ => return Err(fail("pitch without a step")),
+                            };
+                            let alter = pitch.child_text("alter").and_then(|x| x.parse::<f32>().ok()).unwrap_or(0.0).round() as i32;
+                            let octave = match pitch.child_text("octave").and_then(|x| x.parse::<i32>().ok())
+                            {
+                                Some(x) => x,
+                                None => return Err(fail("pitch without an octave")),
+                            };
+                            let key = 12 * (octave + 1) + step + alter;
+                            if !(12..=127).contains(&key)
+                            {
+                                return Err(fail("note is out of range"));
+                            }
+                            let tied = element.children_named("tie").iter().any(|x| x.attribute("type") == Some("start"))
+                                || element.child("notations").is_some_and(|x| x.children_named("tied").iter().any(|x| x.attribute("type") == Some("start")));
+                            let velocity = element.attribute("dynamics").and_then(|x| x.parse::<f32>().ok()).map_or(velocity, |x| x * FORTE_VELOCITY / 100.0);
+                            events.push((PatternEvent{
+                                start: measure_start + start,
+                                length,
+                                key: key as u8,
+                                velocity: velocity.clamp(0.0, 127.0).round() as u8,
+                            }, tied));
+                        },
+                        _ =>
+                        {
+                        },
+                    }
+                }
+                if longest == 0.0
+                {
+                    //An empty measure still takes its time.
+                    let (beats, beat_type) = self.meter.unwrap_or((4, 4));
+                    longest = beats as f64 / beat_type as f64;
+                }
+                measure_start += longest;
+            }
+
+            let (events, _) = Pattern::join_ties(events);
+            Ok(Pattern{
+                events,
+                length: measure_start,
+            })
+        }
+    }
+
+    //The <duration> of element in divisions, if it has a number there.
+    fn duration(element: &Element) -> Result<Option<f64>, &'static str>
+    {
+        match element.child_text("duration").and_then(|x| x.parse::<f64>().ok())
+        {
+            Some(x) if !x.is_finite() || x < 0.0 => Err("duration must be a finite number that is not negative"),
+            x => Ok(x),
+        }
+    }
+
+    //Length of a note from its type, dots and time modification, for notes
+    //without a duration.
+    fn written_length(note: &Element) -> Option<f64>
+    {
+        let mut ret = match note.child_text("type").as_deref()
+        {
+            Some("maxima") => 8.0,
+            Some("long") => 4.0,
+            Some("breve") => 2.0,
+            Some("whole") => 1.0,
+            Some("half") => 0.5,
+            Some("quarter") => 0.25,
+            Some("eighth") => 0.125,
+            Some("16th") => 1.0 / 16.0,
+            Some("32nd") => 1.0 / 32.0,
+            Some("64th") => 1.0 / 64.0,
+            Some("128th") => 1.0 / 128.0,
+            Some("256th") => 1.0 / 256.0,
+            _ => return None,
+        };
+        let mut dot = ret;
+        for _ in note.children_named("dot")
+        {
+            dot /= 2.0;
+            ret += dot;
+        }
+        if let Some(modification) = note.child("time-modification")
+        {
+            let actual = modification.child_text("actual-notes").and_then(|x| x.parse::<f64>().ok());
+            let normal = modification.child_text("normal-notes").and_then(|x| x.parse::<f64>().ok());
+            if let (Some(actual), Some(normal)) = (actual, normal)
+            {
+                if actual > 0.0 && actual.is_finite() && normal > 0.0 && normal.is_finite()
+                {
+                    ret *= normal / actual;
+                }
+            }
+        }
+        Some(ret)
+    }
+
+    //Longest note that fits into steps: a plain or dotted one where the
+    //rest can still be written that way, a triplet otherwise.
+    fn note_value(steps: u64) -> (u64, &'static str, u32, bool)
+    {
+        if steps.is_multiple_of(3)
+        {
+            let value = NOTE_VALUES.iter().find(|x| x.0 <= steps).unwrap();
+            return (value.0, value.1, value.2, false);
+        }
+        let value = TRIPLET_VALUES.iter().find(|x| x.0 <= steps && (steps - x.0).is_multiple_of(3)).unwrap_or(&TRIPLET_VALUES[7]);
+        (value.0, value.1, 0, true)
+    }
+
+    fn escape(text: &str) -> String
+    {
+        text.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;").replace('"', "&quot;").replace('\'', "&apos;")
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use instrumentation::interface::instrument::Probe::Probe;
+    use persistence::musicxml::MusicXml::MusicXmlError;
+    use persistence::musicxml::MusicXml::Score;
+    use sequencing::sequencing::partialsequencer::PartialSequencer::PartialSequencer;
+    use sequencing::sequencing::pattern::Pattern::Pattern;
+
+    fn count(xml: &str, needle: &str) -> usize
+    {
+        xml.matches(needle).count()
+    }
+
+    #[test]
+    fn parts_and_attributes_are_written()
+    {
+        let xml = Score::new("Tom & Jerry").meter(3, 4).key(-2).tempo(90.0)
+            .part("Lead", Pattern::parse("C5/4 D E").unwrap())
+            .part("Bass", Pattern::parse("C2/2.").unwrap())
+            .to_xml();
+        assert!(xml.contains("<work-title>Tom &amp; Jerry</work-title>"));
+        assert!(xml.contains("<score-part id=\"P1\">\n      <part-name>Lead</part-name>"));
+        assert!(xml.contains("<score-part id=\"P2\">\n      <part-name>Bass</part-name>"));
+        assert_eq!(count(&xml, "<divisions>48</divisions>"), 2);
+        assert_eq!(count(&xml, "<fifths>-2</fifths>"), 2);
+        assert_eq!(count(&xml, "<beats>3</beats>"), 2);
+        assert_eq!(count(&xml, "<sign>G</sign>"), 1);
+        assert_eq!(count(&xml, "<sign>F</sign>"), 1);
+        assert_eq!(count(&xml, "<per-minute>90</per-minute>"), 2);
+        //A pattern bar is a whole note, so it takes two 3/4 measures.
+        assert_eq!(count(&xml, "<measure number=\"2\">"), 2);
+        assert_eq!(count(&xml, "<measure number=\"3\">"), 0);
+    }
+
+    #[test]
+    fn notes_are_split_into_measures_and_tied()
+    {
+        //A dotted half from the last quarter of a 4/4 bar: a quarter tied
+        //into a half.
+        let xml = Score::new("").part("", Pattern::parse("r:3/4 C4:3/4").unwrap()).to_xml();
+        assert_eq!(count(&xml, "<measure number="), 2);
+        assert_eq!(count(&xml, "<tie type=\"start\"/>"), 1);
+        assert_eq!(count(&xml, "<tie type=\"stop\"/>"), 1);
+        assert!(xml.contains("<duration>48</duration>\n        <tie type=\"start\"/>"));
+        assert!(xml.contains("<duration>96</duration>\n        <tie type=\"stop\"/>"));
+        assert!(xml.contains("<rest/>\n        <duration>144</duration>\n        <voice>1</voice>\n        <type>half</type>\n        <dot/>"));
+        assert!(xml.contains("<rest/>\n        <duration>96</duration>"));
+    }
+
+    #[test]
+    fn chords_triplets_and_spelling()
+    {
+        let pattern = Pattern::parse("[C4 Eb G] {A#4 B C5}/4 r:1/2").unwrap();
+        let xml = Score::new("").part("", pattern.clone()).to_xml();
+        assert_eq!(count(&xml, "<chord/>"), 2);
+        assert_eq!(count(&xml, "<actual-notes>3</actual-notes>"), 3);
+        assert_eq!(count(&xml, "<alter>1</alter>"), 2);
+        assert!(xml.contains("<step>D</step>\n          <alter>1</alter>"));
+
+        let xml = Score::new("").key(-1).part("", pattern).to_xml();
+        assert_eq!(count(&xml, "<alter>-1</alter>"), 2);
+        assert!(xml.contains("<step>E</step>\n          <alter>-1</alter>"));
+    }
+
+    #[test]
+    fn empty_measures_are_whole_rests()
+    {
+        let xml = Score::new("").part("", Pattern::parse("C:1 r:1").unwrap()).to_xml();
+        assert_eq!(count(&xml, "<rest measure=\"yes\"/>"), 1);
+        assert!(xml.contains("<type>whole</type>"));
+    }
+
+    #[test]
+    fn sequencers_set_the_tempo()
+    {
+        let mut sequencer = PartialSequencer::new(30.0, Probe::new());
+        sequencer.load_pattern(&Pattern::parse("C4/2 E/2@50").unwrap());
+        let xml = Score::new("").sequencer("Keys", &sequencer).to_xml();
+        assert!(xml.contains("<per-minute>120</per-minute>"));
+        assert_eq!(count(&xml, "<type>half</type>"), 2);
+        assert!(xml.contains("<note dynamics=\"55.56\">"));
+    }
+
+    fn score(measures: &str) -> String
+    {
+        format!("<?xml version=\"1.0\"?>\n<score-partwise version=\"3.1\">\n<work><work-title>Imported</work-title></work>\n\
+                 <part-list><score-part id=\"P1\"><part-name>Piano</part-name></score-part></part-list>\n\
+                 <part id=\"P1\">{}</part>\n</score-partwise>\n", measures)
+    }
+
+    fn keys(pattern: &Pattern) -> Vec<(f64, f64, u8)>
+    {
+        pattern.events.iter().map(|x| (x.start, x.length, x.key)).collect()
+    }
+
+    #[test]
+    fn notes_chords_rests_and_ties_are_read()
+    {
+        let text = score("<measure number=\"1\">\
+            <attributes><divisions>2</divisions><key><fifths>-1</fifths></key><time><beats>3</beats><beat-type>4</beat-type></time></attributes>\
+            <direction><sound tempo=\"96\"/></direction>\
+            <note><pitch><step>C</step><octave>4</octave></pitch><duration>2</duration></note>\
+            <note><chord/><pitch><step>E</step><alter>-1</alter><octave>4</octave></pitch><duration>2</duration></note>\
+            <note><rest/><duration>1</duration></note>\
+            <note><pitch><step>G</step><octave>4</octave></pitch><duration>3</duration><tie type=\"start\"/></note>\
+            </measure><measure number=\"2\">\
+            <note><pitch><step>G</step><octave>4</octave></pitch><duration>2</duration><tie type=\"stop\"/></note>\
+            </measure>");
+        let score = Score::parse(&text).unwrap();
+        assert_eq!(score.title, "Imported");
+        assert_eq!((score.meter, score.key, score.tempo), ((3, 4), -1, 96.0));
+        assert_eq!(score.parts.len(), 1);
+        assert_eq!(score.parts[0].0, "Piano");
+        assert_eq!(keys(&score.parts[0].1), vec![(0.0, 0.25, 60), (0.0, 0.25, 63), (0.375, 0.625, 67)]);
+        //The second measure is only as long as what is in it.
+        assert_eq!(score.parts[0].1.length, 1.0);
+    }
+
+    #[test]
+    fn tuplets_backups_and_grace_notes()
+    {
+        let text = score("<measure number=\"1\"><attributes><divisions>3</divisions></attributes>\
+            <note><grace/><pitch><step>B</step><octave>3</octave></pitch><type>eighth</type></note>\
+            <note><pitch><step>C</step><octave>5</octave></pitch><duration>2</duration></note>\
+            <note><pitch><step>D</step><octave>5</octave></pitch><duration>2</duration></note>\
+            <note><pitch><step>E</step><octave>5</octave></pitch><duration>2</duration></note>\
+            <backup><duration>6</duration></backup>\
+            <note><pitch><step>C</step><octave>3</octave></pitch><duration>6</duration></note>\
+            </measure>");
+        let pattern = Score::parse(&text).unwrap().parts.remove(0).1;
+        let third = 1.0 / 6.0;
+        assert_eq!(pattern.events.len(), 4);
+        assert_eq!(pattern.events[0].key, 48);
+        assert_eq!(pattern.events[0].length, 0.5);
+        for (i, event) in pattern.events[1..].iter().enumerate()
+        {
+            assert!((event.start - third * i as f64).abs() < 1e-9);
+            assert!((event.length - third).abs() < 1e-9);
+        }
+        assert_eq!(pattern.length, 0.5);
+    }
+
+    #[test]
+    fn exported_scores_read_back()
+    {
+        let pattern = Pattern::parse("[C4 E]/4 G/8 r/8 {A B C5}/4 D5:1/4~ D5/2").unwrap();
+        let written = Score::new("Round").meter(4, 4).tempo(100.0).part("Lead", pattern.clone()).to_xml();
+        let score = Score::parse(&written).unwrap();
+        assert_eq!((score.title.as_str(), score.tempo), ("Round", 100.0));
+        let read = &score.parts[0].1;
+        assert_eq!(read.events.len(), pattern.events.len());
+        for (a, b) in read.events.iter().zip(pattern.events.iter())
+        {
+            assert_eq!(a.key, b.key);
+            assert!((a.start - b.start).abs() < 1e-9 && (a.length - b.length).abs() < 1e-9);
+        }
+    }
+
+    #[test]
+    fn parts_are_applied_to_sequencers()
+    {
+        let score = Score::new("").tempo(240.0).part("", Pattern::parse("C4 D").unwrap());
+        let mut sequencer = PartialSequencer::new(120.0, Probe::new());
+        assert!(score.apply(0, &mut sequencer));
+        assert!(!score.apply(1, &mut sequencer));
+        assert_eq!(sequencer.local_beat_builder.bpm(), 60.0);
+        assert_eq!(sequencer.note_hash.len(), 2);
+    }
+
+    #[test]
+    fn other_documents_are_rejected()
+    {
+        match Score::parse("<score-timewise/>")
+        {
+            Err(MusicXmlError::Format(_)) => {},
+            x => panic!("{:?}", x),
+        }
+        match Score::parse("<score-partwise>")
+        {
+            Err(MusicXmlError::Xml(_)) => {},
+            x => panic!("{:?}", x),
+        }
+        let text = score("<measure><note><pitch><octave>4</octave></pitch><duration>1</duration></note></measure>");
+        assert!(Score::parse(&text).is_err());
+    }
+
+    #[test]
+    fn durations_must_be_finite_and_not_negative()
+    {
+        for duration in ["NaN", "inf", "-inf", "-1"].iter()
+        {
+            let note = format!("<measure><note><pitch><step>C</step><octave>4</octave></pitch><duration>{}</duration></note></measure>", duration);
+            let backup = format!("<measure><backup><duration>{}</duration></backup></measure>", duration);
+            let forward = format!("<measure><forward><duration>{}</duration></forward></measure>", duration);
+            for text in [note, backup, forward].iter()
+            {
+                match Score::parse(&score(text))
+                {
+                    Err(MusicXmlError::Format(x)) => assert!(x.contains("duration must be"), "{}", x),
+                    x => panic!("{:?}", x),
+                }
+            }
+        }
+        let text = score("<measure><attributes><divisions>inf</divisions></attributes></measure>");
+        assert!(Score::parse(&text).is_err());
+    }
+}
@@ -0,0 +1,995 @@
+///Project: A whole session on disk, so what main.rs builds in code can be
+///saved and opened again. A project holds the tempo, every track (its
+///instrument, notes and channel strip) and the rest of the mixer. It is
+///written as JSON:
+///
+///```text
+///{
+///  "version": 2,
+///  "name": "Demo",
+///  "bpm": 130.0,
+///  "master_gain": 1.0,
+///  "tracks": [
+///    {
+///      "name": "Drums",
+///      "instrument": { "DrumKit": [ { "key": 45, "path": "samples/kick.wav", ... } ] },
+///      "pattern": { "events": [ { "start": 0.0, "length": 0.125, "key": 45, "velocity": 100 } ], "length": 1.0 },
+///      "strip": { "gain": 1.0, "pan": 0.0, "mute": false, "solo": false, "sends": [], "output": { "Group": 0 },
+///                 "inserts": [ { "Compressor": { "threshold_db": -18.0, "ratio": 4.0, ... } } ], "bypass": false },
+///      "automation": [ { "target": { "Instrument": "cutoff" }, "points": [ { "time": 0, "value": 400.0, "curve": "Exponential" } ] } ]
+///    }
+///  ],
+///  "groups": [ ... ],
+///  "returns": [ ... ],
+///  "master_inserts": [ ... ],
+///  "master_bypass": false,
+///  "automation": [ ... ]
+///}
+///```
+///
+///Gains are linear factors. Notes are a Pattern, with start and length in
+///bars. Sample paths are kept as written; relative ones are taken from the
+///directory the sequencers are built in, usually the one the project file
+///is in.
+///
+///Instruments are stored as the settings to build them from, not as the
+///live instruments: samplers as zones, drum kits as pads, SFZ and
+///SoundFont instruments as the file (and preset) to load and FM and
+///subtractive synths as their patch.
+///
+///The automation of a track drives its instrument; the project's
+///automation is the mixer's. Insert effects are saved as their
+///EffectSettings, so read_mixer refuses a mixer holding an effect that has
+///none.
+///
+///Files from older versions are migrated to VERSION when read, one version
+///at a time. Version 1 named the bpm tempo and kept the notes of a track
+///as notes, with its loop length in bars, instead of a pattern.
+#[allow(non_snake_case)]
+pub mod Project
+{
+    use instrumentation::interface::instrument::Instrument::PlayableInstrument;
+    use instrumentation::internal::drumkit::DrumKit::DrumKit;
+    use instrumentation::internal::drumkit::DrumPad::Pad;
+    use instrumentation::internal::fm::FmPatch::Patch;
+    use instrumentation::internal::fm::FmSynth::FmSynth;
+    use instrumentation::internal::sampler::Sampler::Sampler;
+    use instrumentation::internal::sampler::SamplerZone::Zone;
+    use instrumentation::internal::sfz::SfzLoader;
+    use instrumentation::internal::soundfont::SoundFontInstrument;
+    use instrumentation::internal::subtractive::SubtractivePatch;
+    use instrumentation::internal::subtractive::SubtractiveSynth::SubtractiveSynth;
+    use processing::automation::automation::Automation::Automation;
+    use processing::automation::automation::AutomationPrimitives::AutomationTarget;
+    use processing::automation::automation::Lane::AutomationLane;
+    use processing::effects::effect::Effect::EffectChain;
+    use processing::effects::effect::Effect::EffectSettings;
+    use processing::mixing::mixer::Mixer::Mixer;
+    use processing::mixing::mixer::MixerPrimitives::AuxSend;
+    use processing::mixing::mixer::MixerPrimitives::Destination;
+    use sequencing::sequencing::partialsequencer::PartialSequencer::PartialSequencer;
+    use sequencing::sequencing::pattern::Pattern::Pattern;
+    use serde::Deserialize;
+    use serde::Serialize;
+    use serde_json;
+    use serde_json::Value;
+    use std::fmt::Display;
+    use std::fmt::Error;
+    use std::fmt::Formatter;
+    use std::fs;
+    use std::io;
+    use std::path::Path;
+    use std::str::FromStr;
+
+    ///VERSION: Version of the files written by this build.
+    pub const VERSION: u64 = 2;
+
+    ///ProjectError: Reason a project could not be read, built or taken
+    ///over. Format covers files that parse as JSON but not as a project,
+    ///Instrument an instrument whose files could not be loaded and
+    ///Unsupported settings a project has no way of storing.
+    #[derive(Debug)]
+    pub enum ProjectError
+    {
+        Io(io::Error),
+        Json(serde_json::Error),
+        Format(String),
+        Instrument(String),
+        Unsupported(String),
+    }
+
+    impl Display for ProjectError
+    {
+        fn fmt(&self, f: &mut Formatter) -> Result<(), Error>
+        {
+            match *self
+            {
+                ProjectError::Io(ref e) => write!(f, "io error: {}", e),
+                ProjectError::Json(ref e) => write!(f, "json error: {}", e),
+                ProjectError::Format(ref e) => write!(f, "{}", e),
+                ProjectError::Instrument(ref e) => write!(f, "instrument error: {}", e),
+                ProjectError::Unsupported(ref e) => write!(f, "cannot be saved: {}", e),
+            }
+        }
+    }
+
+    impl From<io::Error> for ProjectError
+    {
+        fn from(e: io::Error) -> ProjectError
+        {
+            ProjectError::Io(e)
+        }
+    }
+
+    impl From<serde_json::Error> for ProjectError
+    {
+        fn from(e: serde_json::Error) -> ProjectError
+        {
+            ProjectError::Json(e)
+        }
+    }
+
+    ///ZoneSettings: A sampler zone as it is saved. Loop points come from
+    ///the sample file, as with Zone::from_file.
+    #[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+    pub struct ZoneSettings
+    {
+        pub path: String,
+        pub root_key: u8,
+        pub keys: (u8, u8),
+        pub velocities: (u8, u8),
+        pub tune: f32,
+        pub gain: f32,
+        pub pan: f32,
+    }
+
+    impl ZoneSettings
+    {
+        ///ZoneSettings::new(path: &str, root_key: u8) -> ZoneSettings: Zone
+        ///over every key and velocity, like Zone::new.
+        pub fn new(path: &str, root_key: u8) -> ZoneSettings
+        {
+            ZoneSettings{
+                path: path.to_string(),
+                root_key,
+                keys: (0, 127),
+                velocities: (0, 127),
+                tune: 0.0,
+                gain: 1.0,
+                pan: 0.0,
+            }
+        }
+
+        ///ZoneSettings.keys(lokey: u8, hikey: u8) -> ZoneSettings
+        pub fn keys(mut self, lokey: u8, hikey: u8) -> ZoneSettings
+        {
+            self.keys = (lokey, hikey);
+            self
+        }
+
+        ///ZoneSettings.velocities(lovel: u8, hivel: u8) -> ZoneSettings
+        pub fn velocities(mut self, lovel: u8, hivel: u8) -> ZoneSettings
+        {
+            self.velocities = (lovel, hivel);
+            self
+        }
+    }
+
+    ///PadSettings: A drum kit pad as it is saved, with the key it is
+    ///mapped to.
+    #[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+    pub struct PadSettings
+    {
+        pub key: u8,
+        pub path: String,
+        pub gain: f32,
+        pub pan: f32,
+        pub tune: f32,
+        pub choke: Option<u8>,
+    }
+
+    impl PadSettings
+    {
+        ///PadSettings::new(key: u8, path: &str) -> PadSettings: Centered
+        ///pad at unity gain, like Pad::new.
+        pub fn new(key: u8, path: &str) -> PadSettings
+        {
+            PadSettings{
+                key,
+                path: path.to_string(),
+                gain: 1.0,
+                pan: 0.0,
+                tune: 0.0,
+                choke: None,
+            }
+        }
+    }
+
+    ///InstrumentSettings: What a track plays through, as it is saved.
+    ///SoundFont holds the path, bank and preset.
+    #[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+    pub enum InstrumentSettings
+    {
+        Sampler(Vec<ZoneSettings>),
+        DrumKit(Vec<PadSettings>),
+        Sfz(String),
+        SoundFont(String, u16, u16),
+        Fm(Patch),
+        Subtractive(SubtractivePatch::Patch),
+    }
+
+    ///resolve(directory: &Path, path: &str) -> String: path, taken from
+    ///directory if it is relative.
+    fn resolve(directory: &Path, path: &str) -> String
+    {
+        directory.join(path).to_string_lossy().into_owned()
+    }
+
+    impl InstrumentSettings
+    {
+        ///InstrumentSettings.build(directory: &Path) -> Result<Box<PlayableInstrument>, ProjectError>:
+        ///Loads the instrument, with relative paths taken from directory.
+        pub fn build(&self, directory: &Path) -> Result<Box<dyn PlayableInstrument>, ProjectError>
+        {
+            let failed = |path: &str, e: &dyn Display| ProjectError::Instrument(format!("{}: {}", path, e));
+            match *self
+            {
+                InstrumentSettings::Sampler(ref zones) =>
+                {
+                    let mut sampler = Sampler::new();
+                    for i in zones.iter()
+                    {
+                        let mut zone = Zone::from_file(&resolve(directory, &i.path), i.root_key)
+                            .map_err(|e| failed(&i.path, &e))?
+                            .keys(i.keys.0, i.keys.1)
+                            .velocities(i.velocities.0, i.velocities.1);
+                        zone.tune = i.tune;
+                        zone.gain = i.gain;
+                        zone.pan = i.pan;
+                        sampler.add_zone(zone);
+                    }
+                    Ok(Box::new(sampler))
+                },
+                InstrumentSettings::DrumKit(ref pads) =>
+                {
+                    let mut kit = DrumKit::new();
+                    for i in pads.iter()
+                    {
+                        let mut pad = Pad::from_file(&resolve(directory, &i.path))
+                            .map_err(|e| failed(&i.path, &e))?
+                            .gain(i.gain)
+                            .pan(i.pan)
+                            .tune(i.tune);
+                        pad.choke = i.choke;
+                        kit.map(i.key, pad);
+                    }
+                    Ok(Box::new(kit))
+                },
+                InstrumentSettings::Sfz(ref path) =>
+                {
+                    let sampler = SfzLoader::load(&resolve(directory, path)).map_err(|e| failed(path, &e))?;
+                    Ok(Box::new(sampler))
+                },
+                InstrumentSettings::SoundFont(ref path, bank, preset) =>
+                {
+                    let sampler = SoundFontInstrument::load(&resolve(directory, path), bank, preset).map_err(|e| failed(path, &e))?;
+                    Ok(Box::new(sampler))
+                },
+                InstrumentSettings::Fm(ref patch) =>
+                {
+                    Ok(Box::new(FmSynth::new(patch.clone())))
+                },
+                InstrumentSettings::Subtractive(ref patch) =>
+                {
+                    Ok(Box::new(SubtractiveSynth::new(patch.clone())))
+                },
+            }
+        }
+    }
+
+    ///StripSettings: The fader, sends, routing and insert effects of a
+    ///track, group or return. Returns have no sends or output and ignore
+    ///solo.
+    #[derive(Clone, Debug, Serialize, Deserialize)]
+    pub struct StripSettings
+    {
+        pub gain: f32,
+        pub pan: f32,
+        pub mute: bool,
+        pub solo: bool,
+        pub sends: Vec<AuxSend>,
+        pub output: Destination,
+        #[serde(default)]
+        pub inserts: Vec<EffectSettings>,
+        #[serde(default)]
+        pub bypass: bool,
+    }
+
+    impl StripSettings
+    {
+        ///StripSettings::new() -> StripSettings: Unity gain, centered strip
+        ///into the master bus.
+        pub fn new() -> StripSettings
+        {
+            StripSettings{
+                gain: 1.0,
+                pan: 0.0,
+                mute: false,
+                solo: false,
+                sends: Vec::new(),
+                output: Destination::Master,
+                inserts: Vec::new(),
+                bypass: false,
+            }
+        }
+    }
+
+    impl Default for StripSettings
+    {
+        fn default() -> StripSettings
+        {
+            StripSettings::new()
+        }
+    }
+
+    ///TrackSettings: A sequencer and the channel strip it plays into.
+    ///pattern holds its notes and, in its length, the loop length, and
+    ///automation the lanes of its instrument.
+    #[derive(Clone, Debug, Serialize, Deserialize)]
+    pub struct TrackSettings
+    {
+        pub name: String,
+        pub instrument: InstrumentSettings,
+        pub pattern: Pattern,
+        #[serde(default)]
+        pub strip: StripSettings,
+        #[serde(default)]
+        pub automation: Vec<AutomationLane>,
+    }
+
+    impl TrackSettings
+    {
+        ///TrackSettings::new(name: &str, instrument: InstrumentSettings) -> TrackSettings:
+        ///A track with a one bar loop and no notes.
+        pub fn new(name: &str, instrument: InstrumentSettings) -> TrackSettings
+        {
+            TrackSettings{
+                name: name.to_string(),
+                instrument,
+                pattern: Pattern{
+                    events: Vec::new(),
+                    length: 1.0,
+                },
+                strip: StripSettings::new(),
+                automation: Vec::new(),
+            }
+        }
+
+        ///TrackSettings.pattern(pattern: Pattern) -> TrackSettings: Builder
+        ///style notes.
+        pub fn pattern(mut self, pattern: Pattern) -> TrackSettings
+        {
+            self.pattern = pattern;
+            self
+        }
+
+        ///TrackSettings.lane(lane: AutomationLane) -> TrackSettings: Builder
+        ///style instrument automation.
+        pub fn lane(mut self, lane: AutomationLane) -> TrackSettings
+        {
+            self.automation.push(lane);
+            self
+        }
+    }
+
+    ///Project: automation holds the lanes of the mixer, see automation().
+    #[derive(Clone, Debug, Serialize, Deserialize)]
+    pub struct Project
+    {
+        pub name: String,
+        pub bpm: f32,
+        pub master_gain: f32,
+        pub tracks: Vec<TrackSettings>,
+        #[serde(default)]
+        pub groups: Vec<StripSettings>,
+        #[serde(default)]
+        pub returns: Vec<StripSettings>,
+        #[serde(default)]
+        pub master_inserts: Vec<EffectSettings>,
+        #[serde(default)]
+        pub master_bypass: bool,
+        #[serde(default)]
+        pub automation: Vec<AutomationLane>,
+    }
+
+    ///read_inserts(chain: &mut EffectChain, what: &str) -> Result<Vec<EffectSettings>, ProjectError>:
+    ///The settings of the effects in chain, which can only be taken over
+    ///if every effect has them.
+    fn read_inserts(chain: &mut EffectChain, what: &str) -> Result<Vec<EffectSettings>, ProjectError>
+    {
+        chain.settings().map_err(|i|
+        {
+            let name = chain.get(i).map_or(String::new(), |x| x.name().to_string());
+            ProjectError::Unsupported(format!("insert {} of {} ({}) has no settings", i, what, name))
+        })
+    }
+
+    ///build_inserts(inserts: &[EffectSettings], bypass: bool) -> EffectChain
+    fn build_inserts(inserts: &[EffectSettings], bypass: bool) -> EffectChain
+    {
+        let mut ret = EffectChain::new();
+        for i in inserts.iter()
+        {
+            ret.push(i.build());
+        }
+        ret.bypass = bypass;
+        ret
+    }
+
+    ///migrate_1(project: &mut Value): Version 1 to 2, tempo to bpm and the
+    ///notes and bars of every track to a pattern.
+    fn migrate_1(project: &mut Value)
+    {
+        if let Some(x) = project.as_object_mut()
+        {
+            if let Some(tempo) = x.remove("tempo")
+            {
+                x.insert("bpm".to_string(), tempo);
+            }
+        }
+        if let Some(tracks) = project.get_mut("tracks").and_then(|x| x.as_array_mut())
+        {
+            for track in tracks.iter_mut().filter_map(|x| x.as_object_mut())
+            {
+                let events = track.remove("notes").unwrap_or_else(|| Value::Array(Vec::new()));
+                let length = track.remove("bars").unwrap_or_else(|| Value::from(1.0));
+                let mut pattern = serde_json::Map::new();
+                pattern.insert("events".to_string(), events);
+                pattern.insert("length".to_string(), length);
+                track.insert("pattern".to_string(), Value::Object(pattern));
+            }
+        }
+    }
+
+    ///migrate(project: &mut Value) -> Result<(), ProjectError>: Brings a
+    ///project of any version we know up to VERSION.
+    fn migrate(project: &mut Value) -> Result<(), ProjectError>
+    {
+        let mut version = match project.get("version")
+        {
+            Some(x) => x.as_u64().ok_or_else(|| ProjectError::Format(format!("invalid version {}", x)))?,
+            None => return Err(ProjectError::Format("not a project, there is no version".to_string())),
+        };
+        while version != VERSION
+        {
+            match version
+            {
+                1 => migrate_1(project),
+                x if x > VERSION => return Err(ProjectError::Format(format!("version {} is newer than this build reads ({})", x, VERSION))),
+                x => return Err(ProjectError::Format(format!("unknown version {}", x))),
+            }
+            version += 1;
+            project["version"] = Value::from(version);
+        }
+        Ok(())
+    }
+
+    impl Project
+    {
+        ///Project::new(name: &str, bpm: f32) -> Project: An empty project
+        ///with a unity gain master.
+        pub fn new(name: &str, bpm: f32) -> Project
+        {
+            Project{
+                name: name.to_string(),
+                bpm,
+                master_gain: 1.0,
+                tracks: Vec::new(),
+                groups: Vec::new(),
+                returns: Vec::new(),
+                master_inserts: Vec::new(),
+                master_bypass: false,
+                automation: Vec::new(),
+            }
+        }
+
+        ///Project.track(track: TrackSettings) -> Project: Builder style
+        ///version of adding a track.
+        pub fn track(mut self, track: TrackSettings) -> Project
+        {
+            self.tracks.push(track);
+            self
+        }
+
+        ///Project::load(path: &str) -> Result<Project, ProjectError>
+        pub fn load(path: &str) -> Result<Project, ProjectError>
+        {
+            fs::read_to_string(path)?.parse()
+        }
+
+        ///Project.save(path: &str) -> io::Result<()>
+        pub fn save(&self, path: &str) -> io::Result<()>
+        {
+            fs::write(path, self.to_string())
+        }
+
+        //The tempo has to be playable, every output and send has to point
+        //at a bus that exists, and every lane at something the project has.
+        fn check(&self) -> Result<(), ProjectError>
+        {
+            if !self.bpm.is_finite() || self.bpm <= 0.0
+            {
+                return Err(ProjectError::Format(format!("invalid bpm {}", self.bpm)));
+            }
+            let strips = self.tracks.iter().enumerate().map(|(i, x)| (format!("track {}", i), &x.strip))
+                .chain(self.groups.iter().enumerate().map(|(i, x)| (format!("group {}", i), x)));
+            for (what, strip) in strips
+            {
+                match strip.output
+                {
+                    Destination::Group(x) if x >= self.groups.len() =>
+                    {
+                        return Err(ProjectError::Format(format!("{} outputs to group {}, which does not exist", what, x)));
+                    },
+                    _ =>
+                    {
+                    },
+                }
+                if let Some(i) = strip.sends.iter().find(|x| x.bus >= self.returns.len())
+                {
+                    return Err(ProjectError::Format(format!("{} sends to return {}, which does not exist", what, i.bus)));
+                }
+            }
+            for (i, track) in self.tracks.iter().enumerate()
+            {
+                if let Some(lane) = track.automation.iter().find(|x| !matches!(x.target, AutomationTarget::Instrument(_)))
+                {
+                    return Err(ProjectError::Format(format!("automation of track {} targets {:?}, not its instrument", i, lane.target)));
+                }
+                if let InstrumentSettings::Fm(ref patch) = track.instrument
+                {
+                    if !patch.algorithm.is_valid()
+                    {
+                        return Err(ProjectError::Format(format!("track {} has an invalid FM algorithm", i)));
+                    }
+                }
+            }
+            for lane in self.automation.iter()
+            {
+                match lane.target
+                {
+                    AutomationTarget::Track(x, _) if x >= self.tracks.len() =>
+                    {
+                        return Err(ProjectError::Format(format!("automation of track {}, which does not exist", x)));
+                    },
+                    AutomationTarget::Instrument(_) =>
+                    {
+                        return Err(ProjectError::Format("instrument automation belongs to its track".to_string()));
+                    },
+                    _ =>
+                    {
+                    },
+                }
+            }
+            Ok(())
+        }
+
+        ///Project.sequencers(directory: &Path) -> Result<Vec<PartialSequencer<Box<PlayableInstrument>>>, ProjectError>:
+        ///Builds a sequencer for every track, in order, loading instruments
+        ///with relative paths taken from directory.
+        pub fn sequencers(&self, directory: &Path) -> Result<Vec<PartialSequencer<Box<dyn PlayableInstrument>>>, ProjectError>
+        {
+            let mut ret = Vec::new();
+            for track in self.tracks.iter()
+            {
+                let mut sequencer = PartialSequencer::new(self.bpm, track.instrument.build(directory)?);
+                sequencer.load_pattern(&track.pattern);
+                for lane in track.automation.iter()
+                {
+                    if !sequencer.push_automation(lane.clone())
+                    {
+                        return Err(ProjectError::Format(format!("track {} has automation for {:?}, not its instrument", track.name, lane.target)));
+                    }
+                }
+                ret.push(sequencer);
+            }
+            Ok(ret)
+        }
+
+        ///Project.mixer() -> Mixer: A mixer with a strip for every track,
+        ///in order, and the project's groups and returns.
+        pub fn mixer(&self) -> Mixer
+        {
+            let mut mixer = Mixer::new();
+            for i in self.groups.iter()
+            {
+                let index = mixer.add_group();
+                let group = mixer.group(index).unwrap();
+                group.gain = i.gain;
+                group.pan = i.pan;
+                group.mute = i.mute;
+                group.sends = i.sends.clone();
+                group.inserts = build_inserts(&i.inserts, i.bypass);
+            }
+            for i in self.returns.iter()
+            {
+                let index = mixer.add_return();
+                let bus = mixer.return_bus(index).unwrap();
+                bus.gain = i.gain;
+                bus.pan = i.pan;
+                bus.mute = i.mute;
+                bus.inserts = build_inserts(&i.inserts, i.bypass);
+            }
+            for i in self.tracks.iter()
+            {
+                let index = mixer.add_track();
+                let strip = mixer.track(index).unwrap();
+                strip.gain = i.strip.gain;
+                strip.pan = i.strip.pan;
+                strip.mute = i.strip.mute;
+                strip.solo = i.strip.solo;
+                strip.sends = i.strip.sends.clone();
+                strip.output = i.strip.output;
+                strip.inserts = build_inserts(&i.strip.inserts, i.strip.bypass);
+            }
+            mixer.master_gain = self.master_gain;
+            mixer.master_inserts = build_inserts(&self.master_inserts, self.master_bypass);
+            mixer
+        }
+
+        ///Project.read_mixer(mixer: &mut Mixer) -> Result<(), ProjectError>:
+        ///Takes over the settings of mixer: its groups, returns, master and
+        ///the strips of the tracks the project has. A mixer with an insert
+        ///effect that can't be saved is refused and the project left as it
+        ///was.
+        pub fn read_mixer(&mut self, mixer: &mut Mixer) -> Result<(), ProjectError>
+        {
+            let mut tracks = Vec::new();
+            for i in 0..self.tracks.len()
+            {
+                if let Some(x) = mixer.track(i)
+                {
+                    tracks.push(StripSettings{
+                        gain: x.gain,
+                        pan: x.pan,
+                        mute: x.mute,
+                        solo: x.solo,
+                        sends: x.sends.clone(),
+                        output: x.output,
+                        inserts: read_inserts(&mut x.inserts, &format!("track {}", i))?,
+                        bypass: x.inserts.bypass,
+                    });
+                }
+            }
+            let mut groups = Vec::new();
+            while let Some(x) = mixer.group(groups.len())
+            {
+                let what = format!("group {}", groups.len());
+                groups.push(StripSettings{
+                    gain: x.gain,
+                    pan: x.pan,
+                    mute: x.mute,
+                    sends: x.sends.clone(),
+                    inserts: read_inserts(&mut x.inserts, &what)?,
+                    bypass: x.inserts.bypass,
+                    ..StripSettings::new()
+                });
+            }
+            let mut returns = Vec::new();
+            while let Some(x) = mixer.return_bus(returns.len())
+            {
+                let what = format!("return {}", returns.len());
+                returns.push(StripSettings{
+                    gain: x.gain,
+                    pan: x.pan,
+                    mute: x.mute,
+                    inserts: read_inserts(&mut x.inserts, &what)?,
+                    bypass: x.inserts.bypass,
+                    ..StripSettings::new()
+                });
+            }
+            self.master_inserts = read_inserts(&mut mixer.master_inserts, "master")?;
+            self.master_bypass = mixer.master_inserts.bypass;
+            for (track, strip) in self.tracks.iter_mut().zip(tracks)
+            {
+                track.strip = strip;
+            }
+            self.groups = groups;
+            self.returns = returns;
+            self.master_gain = mixer.master_gain;
+            Ok(())
+        }
+
+        ///Project.read_sequencer<T>(track: usize, sequencer: &PartialSequencer<T>) -> bool:
+        ///Takes over the notes, loop length and instrument automation of
+        ///sequencer for a track. False if there is no such track.
+        pub fn read_sequencer<T: PlayableInstrument>(&mut self, track: usize, sequencer: &PartialSequencer<T>) -> bool
+        {
+            match self.tracks.get_mut(track)
+            {
+                Some(x) =>
+                {
+                    x.pattern = sequencer.to_pattern();
+                    x.automation = sequencer.automation().clone();
+                    true
+                },
+                None =>
+                {
+                    false
+                },
+            }
+        }
+
+        ///Project.automation() -> Automation: The mixer's automation, to
+        ///apply to the mixer built by mixer().
+        pub fn automation(&self) -> Automation
+        {
+            let mut ret = Automation::new();
+            for i in self.automation.iter()
+            {
+                ret.push(i.clone());
+            }
+            ret
+        }
+
+        ///Project.read_automation(automation: &Automation): Takes over the
+        ///lanes of the mixer's automation.
+        pub fn read_automation(&mut self, automation: &Automation)
+        {
+            self.automation = automation.lanes.clone();
+        }
+    }
+
+    ///Project::from_str(text: &str) -> Result<Project, ProjectError>: Reads
+    ///a project of this or an older version.
+    impl FromStr for Project
+    {
+        type Err = ProjectError;
+
+        fn from_str(text: &str) -> Result<Project, ProjectError>
+        {
+            let mut value: Value = serde_json::from_str(text)?;
+            migrate(&mut value)?;
+            let project: Project = serde_json::from_value(value)?;
+            project.check()?;
+            Ok(project)
+        }
+    }
+
+    ///Project::fmt()...: Writes the project as JSON at VERSION.
+    impl Display for Project
+    {
+        fn fmt(&self, f: &mut Formatter) -> Result<(), Error>
+        {
+            let mut value = serde_json::to_value(self).map_err(|_| Error)?;
+            value["version"] = Value::from(VERSION);
+            let text = serde_json::to_string_pretty(&value).map_err(|_| Error)?;
+            write!(f, "{}", text)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use instrumentation::internal::fm::FmPatch::Patch;
+    use instrumentation::internal::subtractive::SubtractivePatch;
+    use instrumentation::internal::subtractive::SubtractivePatch::LfoDestination;
+    use instrumentation::internal::subtractive::SubtractivePatch::LfoSettings;
+    use persistence::project::Project::InstrumentSettings;
+    use persistence::project::Project::Project;
+    use persistence::project::Project::ProjectError;
+    use persistence::project::Project::StripSettings;
+    use persistence::project::Project::TrackSettings;
+    use processing::automation::automation::AutomationPrimitives::AutomationTarget;
+    use processing::automation::automation::AutomationPrimitives::Curve;
+    use processing::automation::automation::AutomationPrimitives::EffectLocation;
+    use processing::automation::automation::AutomationPrimitives::TrackParameter;
+    use processing::automation::automation::Lane::AutomationLane;
+    use processing::buffer::buffer::AudioBuffer::StereoBuffer;
+    use processing::effects::effect::Delay::Delay;
+    use processing::effects::effect::Effect::Effect;
+    use processing::effects::effect::Effect::EffectSettings;
+    use processing::effects::effect::Filter::FilterType;
+    use processing::mixing::mixer::MixerPrimitives::AuxSend;
+    use processing::mixing::mixer::MixerPrimitives::Destination;
+    use processing::mixing::mixer::MixerPrimitives::SendPosition;
+    use processing::synthesis::synthesis::Oscillator::Waveform;
+    use sequencing::sequencing::pattern::Pattern::Pattern;
+    use std::env;
+    use std::fs;
+    use std::path::Path;
+    use std::process;
+
+    fn example() -> Project
+    {
+        let mut patch = SubtractivePatch::Patch::new();
+        patch.filter.cutoff = 1200.0;
+        patch.lfos.push(LfoSettings{
+            waveform: Waveform::Triangle,
+            rate: 5.0,
+            depth: 0.25,
+            destination: LfoDestination::Pitch,
+        });
+        let mut project = Project::new("round trip", 128.0)
+            .track(TrackSettings::new("keys", InstrumentSettings::Fm(Patch::electric_piano()))
+                .pattern(Pattern::parse("C4 E G r").unwrap()))
+            .track(TrackSettings::new("bass", InstrumentSettings::Subtractive(patch))
+                .pattern(Pattern::parse("C2/2 G").unwrap())
+                .lane(AutomationLane::new(AutomationTarget::Instrument("cutoff".to_string()))
+                    .point(0, 400.0, Curve::Exponential)
+                    .point(2000, 4000.0, Curve::Step)));
+        project.master_gain = 0.5;
+        project.groups.push(StripSettings{
+            gain: 0.8,
+            pan: -0.25,
+            ..StripSettings::new()
+        });
+        project.returns.push(StripSettings{
+            gain: 0.6,
+            mute: true,
+            ..StripSettings::new()
+        });
+        project.tracks[0].strip = StripSettings{
+            gain: 0.7,
+            pan: 0.5,
+            mute: false,
+            solo: true,
+            sends: vec![AuxSend{ bus: 0, level: 0.3, position: SendPosition::PreFader }],
+            output: Destination::Group(0),
+            inserts: vec![EffectSettings::Compressor{ threshold_db: -18.0, ratio: 4.0, attack_ms: 5.0, release_ms: 80.0, makeup_db: 3.0 }],
+            bypass: false,
+        };
+        project.groups[0].inserts.push(EffectSettings::Equalizer{ bands: vec![(FilterType::LowShelf(-3.0), 120.0, 0.7), (FilterType::Peak(2.0), 2500.0, 1.4)] });
+        project.returns[0].inserts.push(EffectSettings::Reverb{ room_size: 0.8, damping: 0.3, wet: 0.5, dry: 0.0, width: 1.0 });
+        project.returns[0].bypass = true;
+        project.master_inserts.push(EffectSettings::SoftClip{ drive: 2.0, mix: 0.5, output: 0.9 });
+        project.automation.push(AutomationLane::new(AutomationTarget::Track(1, TrackParameter::Send(0)))
+            .point(0, 0.0, Curve::Linear)
+            .point(1000, 0.5, Curve::Linear));
+        project.automation.push(AutomationLane::new(AutomationTarget::Effect(EffectLocation::Return(0), 0, "mix".to_string()))
+            .point(500, 0.2, Curve::Step));
+        project.automation.push(AutomationLane::new(AutomationTarget::MasterGain)
+            .point(0, 1.0, Curve::Linear));
+        project
+    }
+
+    #[test]
+    fn save_and_load_round_trip()
+    {
+        let project = example();
+        let path = env::temp_dir().join(format!("jaru3-project-{}.json", process::id()));
+        let path = path.to_str().unwrap();
+        project.save(path).unwrap();
+        let loaded = Project::load(path);
+        fs::remove_file(path).ok();
+        let loaded = loaded.unwrap();
+
+        assert_eq!(loaded.to_string(), project.to_string());
+        assert_eq!(loaded.tracks[1].instrument, project.tracks[1].instrument);
+        assert_eq!(loaded.tracks[0].pattern, project.tracks[0].pattern);
+        assert_eq!(loaded.tracks[0].strip.output, Destination::Group(0));
+        assert_eq!(loaded.tracks[0].strip.sends[0].position, SendPosition::PreFader);
+        assert_eq!(loaded.automation[1].target, AutomationTarget::Effect(EffectLocation::Return(0), 0, "mix".to_string()));
+
+        let sequencers = loaded.sequencers(Path::new(".")).unwrap();
+        assert_eq!(sequencers.len(), 2);
+        assert_eq!(sequencers[1].automation().len(), 1);
+        let mixer = loaded.mixer();
+        assert_eq!(mixer.track_count(), 2);
+        assert_eq!(mixer.master_gain, 0.5);
+    }
+
+    #[test]
+    fn lanes_are_sorted_when_read()
+    {
+        let text = r#"{"version": 2, "name": "", "bpm": 120.0, "master_gain": 1.0, "tracks": [],
+                       "automation": [{"target": "MasterGain", "points": [
+                           {"time": 1000, "value": 1.0, "curve": "Linear"},
+                           {"time": 0, "value": 0.0, "curve": "Linear"}]}]}"#;
+        let project: Project = text.parse().unwrap();
+        assert_eq!(project.automation[0].value_at(500.0), Some(0.5));
+    }
+
+    #[test]
+    fn migrates_version_1()
+    {
+        let text = r#"{"version": 1, "name": "old", "tempo": 90.0, "master_gain": 0.5,
+                       "tracks": [{"name": "strings", "instrument": {"Sfz": "strings.sfz"}, "bars": 2.0,
+                                   "notes": [{"start": 0.5, "length": 0.25, "key": 60, "velocity": 90}]}]}"#;
+        let project: Project = text.parse().unwrap();
+        assert_eq!(project.name, "old");
+        assert_eq!(project.bpm, 90.0);
+        assert_eq!(project.tracks[0].instrument, InstrumentSettings::Sfz("strings.sfz".to_string()));
+        assert_eq!(project.tracks[0].pattern.length, 2.0);
+        assert_eq!(project.tracks[0].pattern.events[0].key, 60);
+        assert_eq!(project.tracks[0].strip.gain, 1.0);
+        assert!(project.to_string().parse::<Project>().is_ok());
+    }
+
+    #[test]
+    fn refuses_unknown_versions_and_missing_buses()
+    {
+        let newer = r#"{"version": 3, "name": "", "bpm": 120.0, "master_gain": 1.0, "tracks": []}"#;
+        assert!(matches!(newer.parse::<Project>(), Err(ProjectError::Format(_))));
+        assert!(matches!("{}".parse::<Project>(), Err(ProjectError::Format(_))));
+        assert!(matches!("[".parse::<Project>(), Err(ProjectError::Json(_))));
+
+        let mut project = example();
+        project.groups.clear();
+        assert!(matches!(project.to_string().parse::<Project>(), Err(ProjectError::Format(_))));
+    }
+
+    #[test]
+    fn refuses_unplayable_tempos()
+    {
+        for bpm in ["0.0", "-120.0", "1e39"].iter()
+        {
+            let text = format!(r#"{{"version": 2, "name": "", "bpm": {}, "master_gain": 1.0, "tracks": []}}"#, bpm);
+            assert!(matches!(text.parse::<Project>(), Err(ProjectError::Format(_))), "{}", bpm);
+        }
+        let mut project = example();
+        project.bpm = f32::NAN;
+        assert!(project.to_string().parse::<Project>().is_err());
+    }
+
+    #[test]
+    fn inserts_are_built_and_read_back()
+    {
+        let mut project = example();
+        let mut mixer = project.mixer();
+        assert_eq!(mixer.track(0).unwrap().inserts.len(), 1);
+        assert_eq!(mixer.group(0).unwrap().inserts.len(), 1);
+        assert!(mixer.return_bus(0).unwrap().inserts.bypass);
+        assert_eq!(mixer.master_inserts.len(), 1);
+
+        let mut delay = Delay::new(375, 0.4, 0.3);
+        delay.ping_pong = true;
+        mixer.track(1).unwrap().inserts.push(Box::new(delay));
+        mixer.master_inserts.bypass = true;
+        let expected = project.to_string();
+        project.read_mixer(&mut mixer).unwrap();
+        assert_eq!(project.tracks[1].strip.inserts, vec![EffectSettings::Delay{ time_ms: 375, feedback: 0.4, mix: 0.3, ping_pong: true }]);
+        assert!(project.master_bypass);
+        project.tracks[1].strip.inserts.clear();
+        project.master_bypass = false;
+        assert_eq!(project.to_string(), expected);
+
+        let loaded: Project = project.to_string().parse().unwrap();
+        assert_eq!(loaded.groups[0].inserts, project.groups[0].inserts);
+        assert_eq!(loaded.master_inserts, project.master_inserts);
+    }
+
+    struct Custom;
+
+    impl Effect for Custom
+    {
+        fn process(&mut self, _buffer: &mut StereoBuffer)
+        {
+        }
+
+        fn name(&self) -> &str
+        {
+            "Custom"
+        }
+    }
+
+    #[test]
+    fn read_mixer_refuses_inserts_without_settings()
+    {
+        let mut project = example();
+        let mut mixer = project.mixer();
+        mixer.track(0).unwrap().gain = 0.1;
+        project.read_mixer(&mut mixer).unwrap();
+        assert_eq!(project.tracks[0].strip.gain, 0.1);
+
+        mixer.track(0).unwrap().gain = 0.2;
+        mixer.return_bus(0).unwrap().inserts.push(Box::new(Custom));
+        match project.read_mixer(&mut mixer)
+        {
+            Err(ProjectError::Unsupported(x)) => assert!(x.contains("return 0 (Custom)"), "{}", x),
+            x => panic!("expected Unsupported, got {:?}", x),
+        }
+        assert_eq!(project.tracks[0].strip.gain, 0.1);
+    }
+}
@@ -0,0 +1,247 @@
+///Serialization: serde support for the note and timing types, behind the
+///serde feature. Values are written the way they are usually spoken of:
+///semitones as "C#", registers as "C3", pitches as "C#3", divisions as a
+///fraction of a whole note ("1/8", "3/16", or "2" for two whole notes) and
+///a BeatValue as its bpm. Reading also takes flats ("Db3") and the names
+///semitone_from_str takes ("CS3").
+///
+///A RawNote is written as its pitch, offset, amplitude and length; pitch_hz
+///is worked out from the pitch again when it is read. An IncompleteNote
+///has the same fields, each optional, with semitone and register kept
+///apart since either can be missing.
+#[allow(non_snake_case)]
+pub mod Serialization
+{
+    use sequencing::timing::timing::Beat::BeatValue;
+    use sequencing::timing::timing::BeatPrimitives;
+    use sequencing::timing::timing::BeatPrimitives::Division;
+    use sequencing::tonation::note::NoteCollections::IncompleteNote;
+    use sequencing::tonation::note::NoteCollections::RawNote;
+    use sequencing::tonation::note::NotePrimitives;
+    use sequencing::tonation::note::NotePrimitives::Register;
+    use sequencing::tonation::note::NotePrimitives::Semitone;
+    use serde::de::Error;
+    use serde::Deserialize;
+    use serde::Deserializer;
+    use serde::Serialize;
+    use serde::Serializer;
+
+    ///from_string(...): Reads a string and parses it, failing with what
+    ///was expected if it does not parse.
+    fn from_string<'de, D, T, F>(deserializer: D, what: &str, parse: F) -> Result<T, D::Error>
+        where D: Deserializer<'de>, F: Fn(&str) -> Option<T>
+    {
+        let s = String::deserialize(deserializer)?;
+        parse(&s).ok_or_else(|| D::Error::custom(format!("invalid {} '{}'", what, s)))
+    }
+
+    impl Serialize for Semitone
+    {
+        fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error>
+        {
+            serializer.serialize_str(NotePrimitives::semitone_name(*self))
+        }
+    }
+
+    impl<'de> Deserialize<'de> for Semitone
+    {
+        fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Semitone, D::Error>
+        {
+            from_string(deserializer, "semitone", NotePrimitives::semitone_from_name)
+        }
+    }
+
+    impl Serialize for Register
+    {
+        fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error>
+        {
+            serializer.serialize_str(&format!("C{}", NotePrimitives::register_to_index(*self)))
+        }
+    }
+
+    impl<'de> Deserialize<'de> for Register
+    {
+        fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Register, D::Error>
+        {
+            from_string(deserializer, "register", NotePrimitives::register_from_str)
+        }
+    }
+
+    impl Serialize for Division
+    {
+        fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error>
+        {
+            serializer.serialize_str(&self.to_string())
+        }
+    }
+
+    impl<'de> Deserialize<'de> for Division
+    {
+        fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Division, D::Error>
+        {
+            from_string(deserializer, "division", BeatPrimitives::division_from_fraction)
+        }
+    }
+
+    impl Serialize for BeatValue
+    {
+        fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error>
+        {
+            serializer.serialize_f32(self.bpm())
+        }
+    }
+
+    impl<'de> Deserialize<'de> for BeatValue
+    {
+        fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<BeatValue, D::Error>
+        {
+            let bpm = f32::deserialize(deserializer)?;
+            if !bpm.is_finite() || bpm <= 0.0
+            {
+                return Err(D::Error::custom(format!("invalid bpm {}", bpm)));
+            }
+            Ok(BeatValue::from_bpm(bpm))
+        }
+    }
+
+    #[derive(Serialize, Deserialize)]
+    struct RawNoteFields
+    {
+        pitch: String,
+        offset: f32,
+        amplitude: f32,
+        length: u32,
+    }
+
+    impl Serialize for RawNote
+    {
+        fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error>
+        {
+            RawNoteFields{
+                pitch: NotePrimitives::pitch_name(self.semitone, self.register),
+                offset: self.offset,
+                amplitude: self.amplitude,
+                length: self.length,
+            }.serialize(serializer)
+        }
+    }
+
+    impl<'de> Deserialize<'de> for RawNote
+    {
+        fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<RawNote, D::Error>
+        {
+            let fields = RawNoteFields::deserialize(deserializer)?;
+            match NotePrimitives::pitch_from_str(&fields.pitch)
+            {
+                Some((p, r)) => Ok(RawNote::new(p, r, fields.offset, fields.amplitude, fields.length)),
+                None => Err(D::Error::custom(format!("invalid pitch '{}'", fields.pitch))),
+            }
+        }
+    }
+
+    #[derive(Serialize, Deserialize)]
+    struct IncompleteNoteFields
+    {
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        semitone: Option<Semitone>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        register: Option<Register>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        offset: Option<f32>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        amplitude: Option<f32>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        length: Option<u32>,
+    }
+
+    impl Serialize for IncompleteNote
+    {
+        fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error>
+        {
+            IncompleteNoteFields{
+                semitone: self.semitone,
+                register: self.register,
+                offset: self.offset,
+                amplitude: self.amplitude,
+                length: self.length,
+            }.serialize(serializer)
+        }
+    }
+
+    impl<'de> Deserialize<'de> for IncompleteNote
+    {
+        fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<IncompleteNote, D::Error>
+        {
+            let fields = IncompleteNoteFields::deserialize(deserializer)?;
+            let mut ret = IncompleteNote::from_options(fields.register, fields.amplitude, fields.length, fields.semitone);
+            ret.offset = fields.offset;
+            Ok(ret)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use sequencing::timing::timing::Beat::BeatValue;
+    use sequencing::timing::timing::BeatPrimitives::Division;
+    use sequencing::tonation::note::NoteCollections::IncompleteNote;
+    use sequencing::tonation::note::NoteCollections::RawNote;
+    use sequencing::tonation::note::NotePrimitives;
+    use sequencing::tonation::note::NotePrimitives::Register;
+    use sequencing::tonation::note::NotePrimitives::Semitone;
+    use serde_json;
+
+    #[test]
+    fn pitches_and_divisions_are_written_by_name()
+    {
+        assert_eq!(serde_json::to_string(&Semitone::CSharp).unwrap(), "\"C#\"");
+        assert_eq!(serde_json::to_string(&Register::C3).unwrap(), "\"C3\"");
+        assert_eq!(serde_json::to_string(&Division::Eighth(3)).unwrap(), "\"3/8\"");
+        assert_eq!(serde_json::to_string(&Division::Sixteenth(3)).unwrap(), "\"3/16\"");
+        assert_eq!(serde_json::to_string(&Division::Whole(2)).unwrap(), "\"2\"");
+        assert_eq!(serde_json::from_str::<Division>("\"1/16\"").unwrap(), Division::Sixteenth(1));
+        assert_eq!(serde_json::from_str::<Semitone>("\"Db\"").unwrap(), Semitone::CSharp);
+        assert!(serde_json::from_str::<Division>("\"1/3\"").is_err());
+        assert_eq!(serde_json::from_str::<Division>("\"16777215\"").unwrap().length(), 16777215 * 256);
+        assert!(serde_json::from_str::<Division>("\"16777216\"").is_err());
+        assert!(serde_json::from_str::<Division>("\"4294967295/256\"").is_err());
+        assert!(serde_json::from_str::<Semitone>("\"H\"").is_err());
+        assert_eq!(NotePrimitives::pitch_from_str("Cb4").map(|(p, r)| (p, NotePrimitives::register_to_index(r))), Some((Semitone::B, 3)));
+    }
+
+    #[test]
+    fn raw_notes_round_trip()
+    {
+        let note = RawNote::new(Semitone::CSharp, Register::C3, 0.5, 0.8, 250);
+        let text = serde_json::to_string(&note).unwrap();
+        assert!(text.contains("\"pitch\":\"C#3\""));
+        let read: RawNote = serde_json::from_str(&text).unwrap();
+        assert_eq!(read.semitone, Semitone::CSharp);
+        assert_eq!(NotePrimitives::register_to_index(read.register), 3);
+        assert_eq!((read.offset, read.amplitude, read.length), (0.5, 0.8, 250));
+        assert_eq!(read.pitch_hz, note.pitch_hz);
+        assert!(serde_json::from_str::<RawNote>(&text.replace("C#3", "C#x")).is_err());
+    }
+
+    #[test]
+    fn incomplete_notes_leave_out_missing_fields()
+    {
+        let note = IncompleteNote::from_options(None, Some(0.5), Some(100), Some(Semitone::A));
+        let text = serde_json::to_string(&note).unwrap();
+        assert!(!text.contains("register"));
+        let read: IncompleteNote = serde_json::from_str(&text).unwrap();
+        assert_eq!(read.semitone, Some(Semitone::A));
+        assert!(read.register.is_none());
+        assert_eq!((read.amplitude, read.length), (Some(0.5), Some(100)));
+    }
+
+    #[test]
+    fn beat_values_are_written_as_bpm()
+    {
+        let beat: BeatValue = serde_json::from_str("120").unwrap();
+        assert_eq!(beat.bpm(), 120.0);
+        assert_eq!(serde_json::to_string(&beat).unwrap(), "120.0");
+        assert!(serde_json::from_str::<BeatValue>("0").is_err());
+    }
+}
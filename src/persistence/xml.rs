@@ -0,0 +1,480 @@
+///Xml: A small XML reader, enough for the documents we import (MusicXML).
+///It reads elements, attributes, text and CDATA, resolves the predefined
+///and numeric entities, and skips the declaration, processing
+///instructions, comments and the DOCTYPE. Namespaces are left in names as
+///written and DTDs are not read, so entities they declare are errors.
+#[allow(non_snake_case)]
+pub mod Xml
+{
+    use std::fmt::Display;
+    use std::fmt::Error;
+    use std::fmt::Formatter;
+
+    ///Deepest nesting of elements that is read, the root being 1. Deeper
+    ///documents are rejected instead of running out of stack.
+    pub const MAX_DEPTH: usize = 256;
+
+    ///XmlError: What is wrong with a document and where, counted in
+    ///characters from 1.
+    #[derive(Clone, Debug, PartialEq)]
+    pub struct XmlError
+    {
+        pub line: usize,
+        pub column: usize,
+        pub message: String,
+    }
+
+    impl Display for XmlError
+    {
+        fn fmt(&self, f: &mut Formatter) -> Result<(), Error>
+        {
+            write!(f, "line {}, column {}: {}", self.line, self.column, self.message)
+        }
+    }
+
+    #[derive(Clone, Debug, PartialEq)]
+    pub enum Node
+    {
+        Element(Element),
+        Text(String),
+    }
+
+    #[derive(Clone, Debug, PartialEq)]
+    pub struct Element
+    {
+        pub name: String,
+        pub attributes: Vec<(String, String)>,
+        pub children: Vec<Node>,
+    }
+
+    impl Element
+    {
+        ///Element::parse(text: &str) -> Result<Element, XmlError>: Reads a
+        ///document and returns its root element.
+        pub fn parse(text: &str) -> Result<Element, XmlError>
+        {
+            let mut reader = Reader{
+                chars: text.chars().collect(),
+                position: 0,
+                depth: 1,
+            };
+            reader.prolog()?;
+            let root = reader.element()?;
+            reader.prolog()?;
+            if reader.position < reader.chars.len()
+            {
+                return Err(reader.error(reader.position, "content after the root element"));
+            }
+            Ok(root)
+        }
+
+        ///Element.attribute(name: &str) -> Option<&str>
+        pub fn attribute(&self, name: &str) -> Option<&str>
+        {
+            self.attributes.iter().find(|x| x.0 == name).map(|x| x.1.as_str())
+        }
+
+        ///Element.elements() -> Vec<&Element>: Child elements, in order.
+        pub fn elements(&self) -> Vec<&Element>
+        {
+            self.children.iter().filter_map(|x| match *x
+            {
+                Node::Element(ref e) => Some(e),
+                Node::Text(_) => None,
+            }).collect()
+        }
+
+        ///Element.child(name: &str) -> Option<&Element>: First child element
+        ///called name.
+        pub fn child(&self, name: &str) -> Option<&Element>
+        {
+            self.elements().into_iter().find(|x| x.name == name)
+        }
+
+        ///Element.children_named(name: &str) -> Vec<&Element>
+        pub fn children_named(&self, name: &str) -> Vec<&Element>
+        {
+            self.elements().into_iter().filter(|x| x.name == name).collect()
+        }
+
+        ///Element.has(name: &str) -> bool: True if there is a child element
+        ///called name, as for flags like <chord/>.
+        pub fn has(&self, name: &str) -> bool
+        {
+            self.child(name).is_some()
+        }
+
+        ///Element.text() -> String: All text inside the element, trimmed.
+        pub fn text(&self) -> String
+        {
+            let mut ret = String::new();
+            self.collect_text(&mut ret);
+            ret.trim().to_string()
+        }
+
+        ///Element.child_text(name: &str) -> Option<String>: Text of the first
+        ///child element called name.
+        pub fn child_text(&self, name: &str) -> Option<String>
+        {
+            self.child(name).map(|x| x.text())
+        }
+
+        fn collect_text(&self, out: &mut String)
+        {
+            for i in self.children.iter()
+            {
+                match *i
+                {
+                    Node::Element(ref e) => e.collect_text(out),
+                    Node::Text(ref t) => out.push_str(t),
+                }
+            }
+        }
+    }
+
+    struct Reader
+    {
+        chars: Vec<char>,
+        position: usize,
+        //Elements open around the one being read, including it.
+        depth: usize,
+    }
+
+    impl Reader
+    {
+        fn error(&self, position: usize, message: &str) -> XmlError
+        {
+            let mut line = 1;
+            let mut column = 1;
+            for c in self.chars.iter().take(position)
+            {
+                if *c == '\n'
+                {
+                    line += 1;
+                    column = 1;
+                }
+                else
+                {
+                    column += 1;
+                }
+            }
+            XmlError{
+                line,
+                column,
+                message: message.to_string(),
+            }
+        }
+
+        fn starts_with(&self, text: &str) -> bool
+        {
+            text.chars().enumerate().all(|(i, c)| self.chars.get(self.position + i) == Some(&c))
+        }
+
+        fn skip_space(&mut self)
+        {
+            while self.chars.get(self.position).is_some_and(|x| x.is_whitespace())
+            {
+                self.position += 1;
+            }
+        }
+
+        //Moves past the next end, or fails with what was left open.
+        fn skip_past(&mut self, end: &str, what: &str) -> Result<(), XmlError>
+        {
+            let start = self.position;
+            while self.position < self.chars.len()
+            {
+                if self.starts_with(end)
+                {
+                    self.position += end.chars().count();
+                    return Ok(());
+                }
+                self.position += 1;
+            }
+            Err(self.error(start, &format!("{} is never closed", what)))
+        }
+
+        //Skips whitespace, comments, processing instructions and the DOCTYPE.
+        fn prolog(&mut self) -> Result<(), XmlError>
+        {
+            loop
+            {
+                self.skip_space();
+                if self.starts_with("<?")
+                {
+                    self.skip_past("?>", "processing instruction")?;
+                }
+                else if self.starts_with("<!--")
+                {
+                    self.skip_past("-->", "comment")?;
+                }
+                else if self.starts_with("<!DOCTYPE")
+                {
+                    self.doctype()?;
+                }
+                else
+                {
+                    return Ok(());
+                }
+            }
+        }
+
+        fn doctype(&mut self) -> Result<(), XmlError>
+        {
+            let start = self.position;
+            let mut depth = 0;
+            while let Some(&c) = self.chars.get(self.position)
+            {
+                self.position += 1;
+                match c
+                {
+                    '[' => depth += 1,
+                    ']' => depth -= 1,
+                    '>' if depth == 0 => return Ok(()),
+                    _ =>
+                    {
+                    },
+                }
+            }
+            Err(self.error(start, "DOCTYPE is never closed"))
+        }
+
+        fn name(&mut self) -> Result<String, XmlError>
+        {
+            let start = self.position;
+            while let Some(&c) = self.chars.get(self.position)
+            {
+                if c.is_alphanumeric() || c == '_' || c == '-' || c == '.' || c == ':'
+                {
+                    self.position += 1;
+                }
+                else
+                {
+                    break;
+                }
+            }
+            if self.position == start
+            {
+                return Err(self.error(start, "expected a name"));
+            }
+            Ok(self.chars[start..self.position].iter().collect())
+        }
+
+        fn expect(&mut self, c: char) -> Result<(), XmlError>
+        {
+            if self.chars.get(self.position) == Some(&c)
+            {
+                self.position += 1;
+                Ok(())
+            }
+            else
+            {
+                Err(self.error(self.position, &format!("expected '{}'", c)))
+            }
+        }
+
+        fn element(&mut self) -> Result<Element, XmlError>
+        {
+            let start = self.position;
+            self.expect('<')?;
+            let name = self.name()?;
+            let mut element = Element{
+                name,
+                attributes: Vec::new(),
+                children: Vec::new(),
+            };
+            loop
+            {
+                self.skip_space();
+                match self.chars.get(self.position)
+                {
+                    Some(&'/') =>
+                    {
+                        self.position += 1;
+                        self.expect('>')?;
+                        return Ok(element);
+                    },
+                    Some(&'>') =>
+                    {
+                        self.position += 1;
+                        break;
+                    },
+                    Some(_) =>
+                    {
+                        let at = self.position;
+                        let key = self.name()?;
+                        self.skip_space();
+                        self.expect('=')?;
+                        self.skip_space();
+                        let quote = match self.chars.get(self.position)
+                        {
+                            Some(&'"') => '"',
+                            Some(&'\'') => '\'',
+                            _ => return Err(self.error(self.position, "expected a quoted value")),
+                        };
+                        self.position += 1;
+                        let value = self.text_until(quote)?;
+                        self.position += 1;
+                        if element.attribute(&key).is_some()
+                        {
+                            return Err(self.error(at, &format!("attribute {} is given twice", key)));
+                        }
+                        element.attributes.push((key, value));
+                    },
+                    None =>
+                    {
+                        return Err(self.error(start, "tag is never closed"));
+                    },
+                }
+            }
+
+            loop
+            {
+                if self.position >= self.chars.len()
+                {
+                    return Err(self.error(start, &format!("<{}> is never closed", element.name)));
+                }
+                if self.starts_with("</")
+                {
+                    let at = self.position;
+                    self.position += 2;
+                    let name = self.name()?;
+                    if name != element.name
+                    {
+                        return Err(self.error(at, &format!("expected </{}>, not </{}>", element.name, name)));
+                    }
+                    self.skip_space();
+                    self.expect('>')?;
+                    return Ok(element);
+                }
+                else if self.starts_with("<!--")
+                {
+                    self.skip_past("-->", "comment")?;
+                }
+                else if self.starts_with("<![CDATA[")
+                {
+                    self.position += 9;
+                    let from = self.position;
+                    self.skip_past("]]>", "CDATA section")?;
+                    let text: String = self.chars[from..self.position - 3].iter().collect();
+                    element.children.push(Node::Text(text));
+                }
+                else if self.starts_with("<?")
+                {
+                    self.skip_past("?>", "processing instruction")?;
+                }
+                else if self.starts_with("<")
+                {
+                    if self.depth >= MAX_DEPTH
+                    {
+                        return Err(self.error(self.position, &format!("elements are nested more than {} deep", MAX_DEPTH)));
+                    }
+                    self.depth += 1;
+                    let child = self.element()?;
+                    self.depth -= 1;
+                    element.children.push(Node::Element(child));
+                }
+                else
+                {
+                    let text = self.text_until('<')?;
+                    element.children.push(Node::Text(text));
+                }
+            }
+        }
+
+        //Reads text up to (not including) end, resolving entities.
+        fn text_until(&mut self, end: char) -> Result<String, XmlError>
+        {
+            let mut ret = String::new();
+            while let Some(&c) = self.chars.get(self.position)
+            {
+                if c == end
+                {
+                    return Ok(ret);
+                }
+                if c == '&'
+                {
+                    let start = self.position;
+                    let close = match self.chars[start..].iter().take(12).position(|x| *x == ';')
+                    {
+                        Some(x) => start + x,
+                        None => return Err(self.error(start, "entity is never closed")),
+                    };
+                    let entity: String = self.chars[start + 1..close].iter().collect();
+                    let value = match entity.as_str()
+                    {
+                        "amp" => Some('&'),
+                        "lt" => Some('<'),
+                        "gt" => Some('>'),
+                        "quot" => Some('"'),
+                        "apos" => Some('\''),
+                        x if x.starts_with("#x") => u32::from_str_radix(&x[2..], 16).ok().and_then(::std::char::from_u32),
+                        x if x.starts_with('#') => x[1..].parse().ok().and_then(::std::char::from_u32),
+                        _ => None,
+                    };
+                    match value
+                    {
+                        Some(x) => ret.push(x),
+                        None => return Err(self.error(start, &format!("unknown entity &{};", entity))),
+                    }
+                    self.position = close + 1;
+                    continue;
+                }
+                if c == '<' && end != '<'
+                {
+                    return Err(self.error(self.position, "'<' in an attribute value"));
+                }
+                ret.push(c);
+                self.position += 1;
+            }
+            if end == '<'
+            {
+                return Ok(ret);
+            }
+            Err(self.error(self.position, "value is never closed"))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use persistence::xml::Xml::Element;
+    use persistence::xml::Xml::MAX_DEPTH;
+
+    #[test]
+    fn elements_attributes_and_text_are_read()
+    {
+        let root = Element::parse("<?xml version=\"1.0\"?>\n<!DOCTYPE a>\n<!-- c -->\n<a x='1' y=\"&lt;2&gt;\">\n  <b>one &amp; <![CDATA[<two>]]></b>\n  <c/><b>&#65;&#x42;</b>\n</a>\n").unwrap();
+        assert_eq!(root.name, "a");
+        assert_eq!(root.attribute("x"), Some("1"));
+        assert_eq!(root.attribute("y"), Some("<2>"));
+        assert_eq!(root.attribute("z"), None);
+        assert_eq!(root.elements().len(), 3);
+        assert_eq!(root.child_text("b"), Some("one & <two>".to_string()));
+        assert_eq!(root.children_named("b")[1].text(), "AB");
+        assert!(root.has("c"));
+        assert!(!root.has("d"));
+    }
+
+    #[test]
+    fn malformed_documents_are_rejected_with_a_position()
+    {
+        let error = Element::parse("<a>\n  <b></c>\n</a>").unwrap_err();
+        assert_eq!(error.line, 2);
+        assert!(Element::parse("<a>").is_err());
+        assert!(Element::parse("<a/><b/>").is_err());
+        assert!(Element::parse("<a>&nbsp;</a>").is_err());
+        assert!(Element::parse("<a x=1/>").is_err());
+    }
+
+    #[test]
+    fn nesting_is_limited()
+    {
+        let nested = |depth: usize| "<a>".repeat(depth) + &"</a>".repeat(depth);
+        assert!(Element::parse(&nested(MAX_DEPTH)).is_ok());
+        let error = Element::parse(&nested(MAX_DEPTH + 1)).unwrap_err();
+        assert_eq!((error.line, error.column), (1, MAX_DEPTH * 3 + 1));
+        assert!(Element::parse(&nested(1_000_000)).is_err());
+    }
+}
@@ -0,0 +1,440 @@
+///AutomationPrimitives: Values that make up an automation lane: the
+///shape of each segment, the breakpoints themselves and the parameter a
+///lane is attached to.
+#[allow(non_snake_case)]
+pub mod AutomationPrimitives
+{
+    use serde::Deserialize;
+    use serde::Serialize;
+
+    ///Curve: Shape of the segment that starts at a breakpoint and ends at
+    ///the next one. Step holds the value until the next breakpoint.
+    #[derive(Clone, Copy, Debug, Eq, PartialEq, Serialize, Deserialize)]
+    pub enum Curve
+    {
+        Linear,
+        Exponential,
+        Step,
+    }
+
+    ///Breakpoint: A value at a point in time on the sequencer timeline. time
+    ///is in milliseconds, the same unit used for note placement.
+    #[derive(Clone, Copy, Debug, Serialize, Deserialize)]
+    pub struct Breakpoint
+    {
+        pub time: u64,
+        pub value: f32,
+        pub curve: Curve,
+    }
+
+    ///TrackParameter: Mixer controls on a channel strip that can be
+    ///automated. Send holds the index of the return bus.
+    #[derive(Clone, Copy, Debug, Eq, PartialEq, Serialize, Deserialize)]
+    pub enum TrackParameter
+    {
+        Gain,
+        Pan,
+        Send(usize),
+    }
+
+    ///EffectLocation: Which insert chain of the mixer an effect lives in.
+    #[derive(Clone, Copy, Debug, Eq, PartialEq, Serialize, Deserialize)]
+    pub enum EffectLocation
+    {
+        Track(usize),
+        Group(usize),
+        Return(usize),
+        Master,
+    }
+
+    ///AutomationTarget: What a lane controls. Effects are addressed by
+    ///their chain and slot, instruments by the name of the parameter on
+    ///the instrument of the sequencer that owns the lane.
+    #[derive(Clone, Debug, Eq, PartialEq, Serialize, Deserialize)]
+    pub enum AutomationTarget
+    {
+        Track(usize, TrackParameter),
+        Effect(EffectLocation, usize, String),
+        Instrument(String),
+        MasterGain,
+    }
+}
+
+///Lane: A breakpoint envelope attached to a single parameter.
+#[allow(non_snake_case)]
+pub mod Lane
+{
+    use processing::automation::automation::AutomationPrimitives::Breakpoint;
+    use processing::automation::automation::AutomationPrimitives::Curve;
+    use processing::automation::automation::AutomationPrimitives::AutomationTarget;
+    use serde::Deserialize;
+    use serde::Serialize;
+
+    #[derive(Clone, Debug, Serialize, Deserialize)]
+    #[serde(from = "LaneData")]
+    pub struct AutomationLane
+    {
+        pub target: AutomationTarget,
+        points: Vec<Breakpoint>,
+    }
+
+    ///LaneData: A lane as it is read, before its breakpoints are placed
+    ///with add_point so they end up sorted.
+    #[derive(Deserialize)]
+    struct LaneData
+    {
+        target: AutomationTarget,
+        points: Vec<Breakpoint>,
+    }
+
+    impl From<LaneData> for AutomationLane
+    {
+        fn from(data: LaneData) -> AutomationLane
+        {
+            let mut lane = AutomationLane::new(data.target);
+            for i in data.points
+            {
+                lane.add_point(i.time, i.value, i.curve);
+            }
+            lane
+        }
+    }
+
+    ///interpolate(from: &Breakpoint, to: &Breakpoint, time: f64) -> f32:
+    ///Value between two breakpoints using the curve of from. Exponential
+    ///segments fall back to linear when they cross or touch zero.
+    fn interpolate(from: &Breakpoint, to: &Breakpoint, time: f64) -> f32
+    {
+        let span = (to.time - from.time) as f64;
+        let x = if span <= 0.0 { 1.0 } else { ((time - from.time as f64) / span) as f32 };
+        match from.curve
+        {
+            Curve::Step =>
+            {
+                from.value
+            },
+            Curve::Exponential if from.value * to.value > 0.0 =>
+            {
+                from.value * (to.value / from.value).powf(x)
+            },
+            _ =>
+            {
+                from.value + (to.value - from.value) * x
+            },
+        }
+    }
+
+    impl AutomationLane
+    {
+        ///AutomationLane::new(target: AutomationTarget) -> AutomationLane:
+        ///Generates an empty lane for target.
+        pub fn new(target: AutomationTarget) -> AutomationLane
+        {
+            AutomationLane{
+                target,
+                points: Vec::new(),
+            }
+        }
+
+        ///AutomationLane.point(time: u64, value: f32, curve: Curve) -> AutomationLane:
+        ///Builder style version of add_point.
+        pub fn point(mut self, time: u64, value: f32, curve: Curve) -> AutomationLane
+        {
+            self.add_point(time, value, curve);
+            self
+        }
+
+        ///AutomationLane.add_point(time: u64, value: f32, curve: Curve): Places
+        ///a breakpoint, keeping the lane sorted. A breakpoint already at
+        ///time is replaced.
+        pub fn add_point(&mut self, time: u64, value: f32, curve: Curve)
+        {
+            let point = Breakpoint{ time, value, curve };
+            match self.points.binary_search_by(|x| x.time.cmp(&time))
+            {
+                Ok(index) => self.points[index] = point,
+                Err(index) => self.points.insert(index, point),
+            }
+        }
+
+        ///AutomationLane.remove_point(time: u64) -> Option<Breakpoint>: Removes
+        ///the breakpoint placed at time.
+        pub fn remove_point(&mut self, time: u64) -> Option<Breakpoint>
+        {
+            match self.points.binary_search_by(|x| x.time.cmp(&time))
+            {
+                Ok(index) => Some(self.points.remove(index)),
+                Err(_) => None,
+            }
+        }
+
+        pub fn points(&self) -> &Vec<Breakpoint>
+        {
+            &self.points
+        }
+
+        ///AutomationLane.value_at(time: f64) -> Option<f32>: Evaluates the
+        ///envelope at time milliseconds. Before the first breakpoint the
+        ///first value is held, after the last the last value is held. None
+        ///if the lane is empty or time is NaN.
+        pub fn value_at(&self, time: f64) -> Option<f32>
+        {
+            if self.points.is_empty() || time.is_nan()
+            {
+                return None;
+            }
+            let first = self.points[0];
+            let last = self.points[self.points.len() - 1];
+            if time <= first.time as f64
+            {
+                return Some(first.value);
+            }
+            if time >= last.time as f64
+            {
+                return Some(last.value);
+            }
+
+            let next = match self.points.binary_search_by(|x| (x.time as f64).total_cmp(&time))
+            {
+                Ok(index) => return Some(self.points[index].value),
+                Err(index) => index,
+            };
+            Some(interpolate(&self.points[next - 1], &self.points[next], time))
+        }
+    }
+}
+
+///Automation: A set of lanes for a session and the control rate loop that
+///applies them to the mixer, both during playback and offline renders.
+#[allow(non_snake_case)]
+pub mod Automation
+{
+    use processing::automation::automation::AutomationPrimitives::AutomationTarget;
+    use processing::automation::automation::AutomationPrimitives::EffectLocation;
+    use processing::automation::automation::AutomationPrimitives::TrackParameter;
+    use processing::automation::automation::Lane::AutomationLane;
+    use processing::buffer::buffer::AudioBuffer::SAMPLE_RATE;
+    use processing::buffer::buffer::AudioBuffer::StereoBuffer;
+    use processing::effects::effect::Effect::EffectChain;
+    use processing::mixing::mixer::Mixer::Mixer;
+
+    ///Amount of frames between automation updates (control rate).
+    pub const CONTROL_BLOCK: usize = 64;
+
+    pub struct Automation
+    {
+        pub lanes: Vec<AutomationLane>,
+    }
+
+    ///set_effect_parameter(...) -> bool: Sets a parameter on the effect in
+    ///slot of chain.
+    fn set_effect_parameter(chain: Option<&mut EffectChain>, slot: usize, name: &str, value: f32) -> bool
+    {
+        match chain.and_then(|x| x.get(slot))
+        {
+            Some(effect) => effect.set_parameter(name, value),
+            None => false,
+        }
+    }
+
+    impl Automation
+    {
+        ///Automation::new() -> Automation: Generates an empty set of lanes.
+        pub fn new() -> Automation
+        {
+            Automation{
+                lanes: Vec::new(),
+            }
+        }
+
+        ///Automation.push(lane: AutomationLane): Adds a lane to the set.
+        pub fn push(&mut self, lane: AutomationLane)
+        {
+            self.lanes.push(lane);
+        }
+
+        ///Automation.apply(time: f64, mixer: &mut Mixer): Evaluates every
+        ///mixer and effect lane at time milliseconds and writes the values
+        ///into the mixer. Instrument lanes are skipped, they are applied by
+        ///the sequencer that owns the instrument.
+        pub fn apply(&self, time: f64, mixer: &mut Mixer)
+        {
+            for lane in self.lanes.iter()
+            {
+                let value = match lane.value_at(time)
+                {
+                    Some(x) => x,
+                    None => continue,
+                };
+                match lane.target
+                {
+                    AutomationTarget::Track(track, parameter) =>
+                    {
+                        match (mixer.track(track), parameter)
+                        {
+                            (Some(strip), TrackParameter::Gain) => strip.gain = value,
+                            (Some(strip), TrackParameter::Pan) => strip.pan = value,
+                            (Some(strip), TrackParameter::Send(bus)) =>
+                            {
+                                for i in strip.sends.iter_mut().filter(|x| x.bus == bus)
+                                {
+                                    i.level = value;
+                                }
+                            },
+                            (None, _) =>
+                            {
+                            },
+                        }
+                    },
+                    AutomationTarget::Effect(location, slot, ref name) =>
+                    {
+                        let chain = match location
+                        {
+                            EffectLocation::Track(x) => mixer.track(x).map(|x| &mut x.inserts),
+                            EffectLocation::Group(x) => mixer.group(x).map(|x| &mut x.inserts),
+                            EffectLocation::Return(x) => mixer.return_bus(x).map(|x| &mut x.inserts),
+                            EffectLocation::Master => Some(&mut mixer.master_inserts),
+                        };
+                        set_effect_parameter(chain, slot, name, value);
+                    },
+                    AutomationTarget::MasterGain =>
+                    {
+                        mixer.master_gain = value;
+                    },
+                    AutomationTarget::Instrument(_) =>
+                    {
+                    },
+                }
+            }
+        }
+
+        ///Automation.render(mixer: &mut Mixer, inputs: &[StereoBuffer], output: &mut StereoBuffer):
+        ///Offline render of whole tracks through the mixer. inputs[n] holds
+        ///the full audio of track n starting at time zero, output is sized by
+        ///the caller. Automation is applied every CONTROL_BLOCK frames.
+        pub fn render(&self, mixer: &mut Mixer, inputs: &[StereoBuffer], output: &mut StereoBuffer)
+        {
+            let frames = output.len();
+            let mut block_inputs: Vec<StereoBuffer> = inputs.iter().map(|_| StereoBuffer::new(CONTROL_BLOCK)).collect();
+            let mut block_output = StereoBuffer::new(CONTROL_BLOCK);
+            let mut position = 0;
+
+            while position < frames
+            {
+                let length = if frames - position < CONTROL_BLOCK { frames - position } else { CONTROL_BLOCK };
+                let time = position as f64 * 1000.0 / SAMPLE_RATE as f64;
+                self.apply(time, mixer);
+
+                for (block, input) in block_inputs.iter_mut().zip(inputs.iter())
+                {
+                    block.resize(length);
+                    block.clear();
+                    if position < input.len()
+                    {
+                        let available = if input.len() - position < length { input.len() - position } else { length };
+                        block.left[..available].copy_from_slice(&input.left[position..position + available]);
+                        block.right[..available].copy_from_slice(&input.right[position..position + available]);
+                    }
+                }
+                block_output.resize(length);
+                mixer.process(&block_inputs, &mut block_output);
+
+                output.left[position..position + length].copy_from_slice(&block_output.left);
+                output.right[position..position + length].copy_from_slice(&block_output.right);
+                position += length;
+            }
+        }
+    }
+
+    impl Default for Automation
+    {
+        fn default() -> Automation
+        {
+            Automation::new()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use processing::automation::automation::Automation::Automation;
+    use processing::automation::automation::AutomationPrimitives::AutomationTarget;
+    use processing::automation::automation::AutomationPrimitives::Curve;
+    use processing::automation::automation::AutomationPrimitives::TrackParameter;
+    use processing::automation::automation::Lane::AutomationLane;
+    use processing::buffer::buffer::AudioBuffer::StereoBuffer;
+    use processing::mixing::mixer::Mixer::Mixer;
+
+    #[test]
+    fn value_at_follows_segment_curves()
+    {
+        let lane = AutomationLane::new(AutomationTarget::MasterGain)
+            .point(0, 0.0, Curve::Linear)
+            .point(100, 1.0, Curve::Step)
+            .point(200, 0.5, Curve::Exponential)
+            .point(300, 2.0, Curve::Linear);
+        assert_eq!(lane.value_at(50.0), Some(0.5));
+        assert_eq!(lane.value_at(150.0), Some(1.0));
+        assert!((lane.value_at(250.0).unwrap() - 1.0).abs() < 1e-6);
+    }
+
+    #[test]
+    fn value_at_holds_the_ends()
+    {
+        let empty = AutomationLane::new(AutomationTarget::MasterGain);
+        assert_eq!(empty.value_at(0.0), None);
+
+        let lane = AutomationLane::new(AutomationTarget::MasterGain)
+            .point(100, 0.25, Curve::Linear)
+            .point(200, 0.75, Curve::Linear);
+        assert_eq!(lane.value_at(0.0), Some(0.25));
+        assert_eq!(lane.value_at(1000.0), Some(0.75));
+    }
+
+    #[test]
+    fn value_at_ignores_nan()
+    {
+        let lane = AutomationLane::new(AutomationTarget::MasterGain)
+            .point(100, 0.25, Curve::Linear)
+            .point(200, 0.75, Curve::Linear);
+        assert_eq!(lane.value_at(f64::NAN), None);
+        assert_eq!(lane.value_at(150.0), Some(0.5));
+    }
+
+    #[test]
+    fn add_point_replaces_and_keeps_order()
+    {
+        let mut lane = AutomationLane::new(AutomationTarget::MasterGain);
+        lane.add_point(200, 1.0, Curve::Linear);
+        lane.add_point(0, 0.0, Curve::Linear);
+        lane.add_point(200, 0.5, Curve::Linear);
+        let times: Vec<u64> = lane.points().iter().map(|x| x.time).collect();
+        assert_eq!(times, vec![0, 200]);
+        assert_eq!(lane.points()[1].value, 0.5);
+        assert!(lane.remove_point(0).is_some());
+        assert!(lane.remove_point(0).is_none());
+    }
+
+    #[test]
+    fn render_applies_track_gain_lane()
+    {
+        let mut mixer = Mixer::new();
+        let track = mixer.add_track();
+        mixer.track(track).unwrap().pan = -1.0;
+        let mut automation = Automation::new();
+        automation.push(AutomationLane::new(AutomationTarget::Track(track, TrackParameter::Gain))
+            .point(0, 1.0, Curve::Step)
+            .point(10, 0.0, Curve::Step));
+
+        let mut input = StereoBuffer::new(2205);
+        for i in input.left.iter_mut()
+        {
+            *i = 1.0;
+        }
+        let mut output = StereoBuffer::new(2205);
+        automation.render(&mut mixer, &[input], &mut output);
+        assert!((output.left[0] - 1.0).abs() < 1e-6);
+        assert_eq!(output.left[2204], 0.0);
+    }
+}
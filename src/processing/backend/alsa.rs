@@ -0,0 +1,120 @@
+///AlsaSink: Plays the output on an ALSA PCM device. Writes block until the
+///device has room for the audio; underruns are recovered from silently.
+#[allow(non_snake_case)]
+pub mod AlsaSink
+{
+    use alsa;
+    use alsa::Direction;
+    use alsa::ValueOr;
+    use alsa::pcm::Access;
+    use alsa::pcm::Format;
+    use alsa::pcm::HwParams;
+    use alsa::pcm::PCM;
+    use processing::backend::backend::Backend::AudioBackend;
+    use processing::backend::backend::Backend::BackendError;
+    use processing::buffer::buffer::AudioBuffer::BLOCK_SIZE;
+    use processing::buffer::buffer::AudioBuffer::SAMPLE_RATE;
+    use processing::buffer::buffer::AudioBuffer::StereoBuffer;
+
+    ///Device buffer length in blocks. Longer is safer, shorter reacts
+    ///sooner.
+    pub const BUFFER_BLOCKS: i64 = 4;
+
+    fn device_error(e: alsa::Error) -> BackendError
+    {
+        BackendError::Device(e.to_string())
+    }
+
+    pub struct AlsaSink
+    {
+        pcm: PCM,
+        rate: u32,
+        interleaved: Vec<f32>,
+    }
+
+    impl AlsaSink
+    {
+        ///AlsaSink::open(device: &str) -> Result<AlsaSink, BackendError>:
+        ///Opens device ("default", "hw:0", ...) for stereo float playback
+        ///at SAMPLE_RATE, letting ALSA resample if the hardware runs at
+        ///another rate. Fails if the device still cannot play SAMPLE_RATE.
+        pub fn open(device: &str) -> Result<AlsaSink, BackendError>
+        {
+            let pcm = PCM::new(device, Direction::Playback, false).map_err(device_error)?;
+            let rate = {
+                let params = HwParams::any(&pcm).map_err(device_error)?;
+                params.set_channels(2).map_err(device_error)?;
+                params.set_rate_resample(true).map_err(device_error)?;
+                params.set_rate(SAMPLE_RATE, ValueOr::Nearest).map_err(device_error)?;
+                params.set_format(Format::float()).map_err(device_error)?;
+                params.set_access(Access::RWInterleaved).map_err(device_error)?;
+                params.set_period_size_near(BLOCK_SIZE as alsa::pcm::Frames, ValueOr::Nearest).map_err(device_error)?;
+                params.set_buffer_size_near(BLOCK_SIZE as alsa::pcm::Frames * BUFFER_BLOCKS).map_err(device_error)?;
+                pcm.hw_params(&params).map_err(device_error)?;
+                params.get_rate().map_err(device_error)?
+            };
+            if rate != SAMPLE_RATE
+            {
+                return Err(BackendError::Device(format!("{} plays at {} Hz instead of {} Hz", device, rate, SAMPLE_RATE)));
+            }
+            Ok(AlsaSink{
+                pcm,
+                rate,
+                interleaved: Vec::with_capacity(BLOCK_SIZE * 2),
+            })
+        }
+    }
+
+    impl AudioBackend for AlsaSink
+    {
+        fn sample_rate(&self) -> u32
+        {
+            self.rate
+        }
+
+        fn write(&mut self, buffer: &StereoBuffer) -> Result<(), BackendError>
+        {
+            self.interleaved.clear();
+            for i in 0..buffer.len()
+            {
+                self.interleaved.push(buffer.left[i]);
+                self.interleaved.push(buffer.right[i]);
+            }
+
+            let io = self.pcm.io_f32().map_err(device_error)?;
+            let mut done = 0;
+            while done < buffer.len()
+            {
+                match io.writei(&self.interleaved[done * 2..])
+                {
+                    Ok(frames) => done += frames,
+                    Err(e) => self.pcm.try_recover(e, true).map_err(device_error)?,
+                }
+            }
+            Ok(())
+        }
+
+        fn close(&mut self) -> Result<(), BackendError>
+        {
+            self.pcm.drain().map_err(device_error)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use processing::backend::alsa::AlsaSink::AlsaSink;
+    use processing::backend::backend::Backend::AudioBackend;
+    use processing::buffer::buffer::AudioBuffer::SAMPLE_RATE;
+    use processing::buffer::buffer::AudioBuffer::StereoBuffer;
+
+    //ALSA's null device takes any rate and needs no sound card.
+    #[test]
+    fn null_device_plays_at_the_sample_rate()
+    {
+        let mut sink = AlsaSink::open("null").unwrap();
+        assert_eq!(sink.sample_rate(), SAMPLE_RATE);
+        sink.write(&StereoBuffer::new(64)).unwrap();
+    }
+}
@@ -0,0 +1,439 @@
+///Backend: Where the engine's output goes. The engine renders blocks of
+///audio and writes them to an AudioBackend, which either plays them,
+///stores them or drops them. Device backends block in write until the
+///device has room, which is what paces playback.
+#[allow(non_snake_case)]
+pub mod Backend
+{
+    use processing::buffer::buffer::AudioBuffer::StereoBuffer;
+    use std::fmt::Display;
+    use std::fmt::Error;
+    use std::fmt::Formatter;
+    use std::io;
+
+    ///BackendError: Reason a backend could not be opened or written to.
+    #[derive(Debug)]
+    pub enum BackendError
+    {
+        Io(io::Error),
+        Device(String),
+        Unknown(String),
+    }
+
+    impl Display for BackendError
+    {
+        fn fmt(&self, f: &mut Formatter) -> Result<(), Error>
+        {
+            match *self
+            {
+                BackendError::Io(ref e) => write!(f, "io error: {}", e),
+                BackendError::Device(ref e) => write!(f, "device error: {}", e),
+                BackendError::Unknown(ref e) => write!(f, "unknown backend {:?}", e),
+            }
+        }
+    }
+
+    impl From<io::Error> for BackendError
+    {
+        fn from(e: io::Error) -> BackendError
+        {
+            BackendError::Io(e)
+        }
+    }
+
+    pub trait AudioBackend: Send
+    {
+        ///Rate the backend expects the written audio to be at.
+        fn sample_rate(&self) -> u32;
+
+        ///Plays or stores one block of audio.
+        fn write(&mut self, buffer: &StereoBuffer) -> Result<(), BackendError>;
+
+        ///Flushes anything still pending. Nothing may be written after.
+        fn close(&mut self) -> Result<(), BackendError>
+        {
+            Ok(())
+        }
+    }
+}
+
+///NullSink: Drops everything written to it, for tests and machines without
+///audio. A realtime sink sleeps in write so that playback still takes as
+///long as it would on a device.
+#[allow(non_snake_case)]
+pub mod NullSink
+{
+    use processing::backend::backend::Backend::AudioBackend;
+    use processing::backend::backend::Backend::BackendError;
+    use processing::buffer::buffer::AudioBuffer::SAMPLE_RATE;
+    use processing::buffer::buffer::AudioBuffer::StereoBuffer;
+    use std::thread;
+    use std::time::Duration;
+    use std::time::Instant;
+
+    pub struct NullSink
+    {
+        frames: u64,
+        realtime: bool,
+        started: Option<Instant>,
+    }
+
+    impl NullSink
+    {
+        pub fn new() -> NullSink
+        {
+            NullSink{
+                frames: 0,
+                realtime: false,
+                started: None,
+            }
+        }
+
+        ///NullSink::realtime() -> NullSink: Sink paced to SAMPLE_RATE.
+        pub fn realtime() -> NullSink
+        {
+            NullSink{
+                realtime: true,
+                ..NullSink::new()
+            }
+        }
+
+        ///NullSink.frames() -> u64: Frames written so far.
+        pub fn frames(&self) -> u64
+        {
+            self.frames
+        }
+    }
+
+    impl Default for NullSink
+    {
+        fn default() -> NullSink
+        {
+            NullSink::new()
+        }
+    }
+
+    impl AudioBackend for NullSink
+    {
+        fn sample_rate(&self) -> u32
+        {
+            SAMPLE_RATE
+        }
+
+        fn write(&mut self, buffer: &StereoBuffer) -> Result<(), BackendError>
+        {
+            let started = *self.started.get_or_insert_with(Instant::now);
+            self.frames += buffer.len() as u64;
+            if self.realtime
+            {
+                let due = started + Duration::from_micros(self.frames * 1_000_000 / SAMPLE_RATE as u64);
+                let now = Instant::now();
+                if due > now
+                {
+                    thread::sleep(due - now);
+                }
+            }
+            Ok(())
+        }
+    }
+}
+
+///FileSink: Writes the output to a stereo WAV file. The sizes in the header
+///are filled in on close, which also happens when the sink is dropped.
+#[allow(non_snake_case)]
+pub mod FileSink
+{
+    use processing::backend::backend::Backend::AudioBackend;
+    use processing::backend::backend::Backend::BackendError;
+    use processing::buffer::buffer::AudioBuffer::SAMPLE_RATE;
+    use processing::buffer::buffer::AudioBuffer::StereoBuffer;
+    use processing::sample::sample::Wav;
+    use std::fs::File;
+    use std::io;
+    use std::io::BufWriter;
+    use std::io::Seek;
+    use std::io::SeekFrom;
+    use std::io::Write;
+
+    pub struct FileSink<W: Write + Seek + Send>
+    {
+        output: W,
+        float: bool,
+        frames: u64,
+        closed: bool,
+    }
+
+    impl FileSink<BufWriter<File>>
+    {
+        ///FileSink::create(path: &str, float: bool) -> io::Result<FileSink<BufWriter<File>>>:
+        ///Creates or truncates path. Samples are 16 bit unless float is set.
+        pub fn create(path: &str, float: bool) -> io::Result<FileSink<BufWriter<File>>>
+        {
+            FileSink::new(BufWriter::new(File::create(path)?), float)
+        }
+    }
+
+    impl<W: Write + Seek + Send> FileSink<W>
+    {
+        ///FileSink::new(output: W, float: bool) -> io::Result<FileSink<W>>:
+        ///Writes a placeholder header to output.
+        pub fn new(mut output: W, float: bool) -> io::Result<FileSink<W>>
+        {
+            output.write_all(&Wav::header(2, SAMPLE_RATE, float, 0))?;
+            Ok(FileSink{
+                output,
+                float,
+                frames: 0,
+                closed: false,
+            })
+        }
+
+        ///FileSink.frames() -> u64: Frames written so far.
+        pub fn frames(&self) -> u64
+        {
+            self.frames
+        }
+    }
+
+    impl<W: Write + Seek + Send> AudioBackend for FileSink<W>
+    {
+        fn sample_rate(&self) -> u32
+        {
+            SAMPLE_RATE
+        }
+
+        fn write(&mut self, buffer: &StereoBuffer) -> Result<(), BackendError>
+        {
+            if self.closed
+            {
+                return Err(BackendError::Device("file sink is closed".to_string()));
+            }
+            let mut bytes = Vec::with_capacity(buffer.len() * if self.float { 8 } else { 4 });
+            for i in 0..buffer.len()
+            {
+                for sample in [buffer.left[i], buffer.right[i]].iter()
+                {
+                    if self.float
+                    {
+                        bytes.extend_from_slice(&sample.to_bits().to_le_bytes());
+                    }
+                    else
+                    {
+                        bytes.extend_from_slice(&((sample.clamp(-1.0, 1.0) * 32767.0) as i16).to_le_bytes());
+                    }
+                }
+            }
+            self.output.write_all(&bytes)?;
+            self.frames += buffer.len() as u64;
+            Ok(())
+        }
+
+        fn close(&mut self) -> Result<(), BackendError>
+        {
+            if self.closed
+            {
+                return Ok(());
+            }
+            self.closed = true;
+            let frames = self.frames.min(u32::MAX as u64) as u32;
+            self.output.seek(SeekFrom::Start(0))?;
+            self.output.write_all(&Wav::header(2, SAMPLE_RATE, self.float, frames))?;
+            self.output.seek(SeekFrom::End(0))?;
+            self.output.flush()?;
+            Ok(())
+        }
+    }
+
+    impl<W: Write + Seek + Send> Drop for FileSink<W>
+    {
+        fn drop(&mut self)
+        {
+            self.close().ok();
+        }
+    }
+}
+
+///Selection: Opening a backend by name at runtime, as given on the
+///command line or in a settings file:
+///
+///```text
+///    null              discard the output as fast as it is rendered
+///    null:realtime     discard the output at playback speed
+///    file:<path>       16 bit WAV file, file32:<path> for 32 bit float
+///    alsa[:<device>]   ALSA device, "default" if none is given
+///    jack[:<name>]     JACK client named <name>, "jaru3" if none is given
+///```
+///
+///alsa and jack are only available when built with the features of the
+///same name.
+#[allow(non_snake_case)]
+pub mod Selection
+{
+    #[cfg(feature = "alsa")]
+    use processing::backend::alsa::AlsaSink::AlsaSink;
+    use processing::backend::backend::Backend::AudioBackend;
+    use processing::backend::backend::Backend::BackendError;
+    use processing::backend::backend::FileSink::FileSink;
+    use processing::backend::backend::NullSink::NullSink;
+    #[cfg(feature = "jack")]
+    use processing::backend::jack::JackBackend::JackSettings;
+    #[cfg(feature = "jack")]
+    use processing::backend::jack::JackBackend::JackSink;
+
+    ///Selection::available() -> Vec<&'static str>: Backends this build
+    ///can open.
+    pub fn available() -> Vec<&'static str>
+    {
+        let mut ret = vec!["null", "file"];
+        if cfg!(feature = "alsa")
+        {
+            ret.push("alsa");
+        }
+        if cfg!(feature = "jack")
+        {
+            ret.push("jack");
+        }
+        ret
+    }
+
+    ///Selection::default_spec() -> &'static str: A device backend if this
+    ///build has one, the realtime null sink otherwise.
+    pub fn default_spec() -> &'static str
+    {
+        if cfg!(feature = "alsa")
+        {
+            "alsa"
+        }
+        else if cfg!(feature = "jack")
+        {
+            "jack"
+        }
+        else
+        {
+            "null:realtime"
+        }
+    }
+
+    ///Selection::open(spec: &str) -> Result<Box<dyn AudioBackend>, BackendError>
+    pub fn open(spec: &str) -> Result<Box<dyn AudioBackend>, BackendError>
+    {
+        let mut parts = spec.splitn(2, ':');
+        let (kind, argument) = (parts.next().unwrap_or(""), parts.next());
+        match (kind, argument)
+        {
+            ("null", None) => Ok(Box::new(NullSink::new())),
+            ("null", Some("realtime")) => Ok(Box::new(NullSink::realtime())),
+            ("file", Some(path)) => Ok(Box::new(FileSink::create(path, false)?)),
+            ("file32", Some(path)) => Ok(Box::new(FileSink::create(path, true)?)),
+            #[cfg(feature = "alsa")]
+            ("alsa", device) => Ok(Box::new(AlsaSink::open(device.unwrap_or("default"))?)),
+            #[cfg(feature = "jack")]
+            ("jack", name) => Ok(Box::new(JackSink::start(JackSettings::new(name.unwrap_or("jaru3")))?)),
+            _ => Err(BackendError::Unknown(spec.to_string())),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use processing::backend::backend::Backend::AudioBackend;
+    use processing::backend::backend::Backend::BackendError;
+    use processing::backend::backend::FileSink::FileSink;
+    use processing::backend::backend::NullSink::NullSink;
+    use processing::backend::backend::Selection;
+    use processing::buffer::buffer::AudioBuffer::SAMPLE_RATE;
+    use processing::buffer::buffer::AudioBuffer::StereoBuffer;
+    use processing::sample::sample::Wav;
+    use std::env;
+    use std::fs;
+    use std::process;
+
+    fn block(frames: usize) -> StereoBuffer
+    {
+        let mut buffer = StereoBuffer::new(frames);
+        for i in 0..frames
+        {
+            buffer.left[i] = 0.5;
+            buffer.right[i] = -0.25;
+        }
+        buffer
+    }
+
+    fn write_file(name: &str, float: bool) -> Vec<u8>
+    {
+        let path = env::temp_dir().join(format!("jaru3_{}_{}.wav", name, process::id()));
+        let path = path.to_string_lossy().into_owned();
+        {
+            let mut sink = FileSink::create(&path, float).unwrap();
+            sink.write(&block(100)).unwrap();
+            sink.write(&block(28)).unwrap();
+            assert_eq!(sink.frames(), 128);
+            //The header is filled in when the sink is dropped.
+        }
+        let bytes = fs::read(&path).unwrap();
+        fs::remove_file(&path).ok();
+        bytes
+    }
+
+    #[test]
+    fn file_sink_header_reads_back()
+    {
+        for &float in [false, true].iter()
+        {
+            let sample = Wav::from_bytes(&write_file(if float { "float" } else { "pcm" }, float)).unwrap();
+            assert_eq!(sample.frames(), 128);
+            assert_eq!(sample.channels.len(), 2);
+            assert_eq!(sample.sample_rate, SAMPLE_RATE);
+            assert!((sample.channels[0][127] - 0.5).abs() < 1e-3);
+            assert!((sample.channels[1][0] + 0.25).abs() < 1e-3);
+        }
+    }
+
+    #[test]
+    fn file_sink_refuses_writes_after_close()
+    {
+        let path = env::temp_dir().join(format!("jaru3_closed_{}.wav", process::id()));
+        let path = path.to_string_lossy().into_owned();
+        let mut sink = FileSink::create(&path, false).unwrap();
+        sink.close().unwrap();
+        assert!(sink.write(&block(1)).is_err());
+        fs::remove_file(&path).ok();
+    }
+
+    #[test]
+    fn null_sink_counts_frames()
+    {
+        let mut sink = NullSink::new();
+        assert_eq!(sink.frames(), 0);
+        sink.write(&block(64)).unwrap();
+        sink.write(&block(36)).unwrap();
+        assert_eq!(sink.frames(), 100);
+        assert_eq!(sink.sample_rate(), SAMPLE_RATE);
+    }
+
+    #[test]
+    fn selection_rejects_unknown_specs()
+    {
+        let mut unknown = vec!["", "bogus", "null:fast", "file", "file32", "wav:out.wav"];
+        if !cfg!(feature = "alsa")
+        {
+            unknown.push("alsa");
+        }
+        if !cfg!(feature = "jack")
+        {
+            unknown.push("jack:jaru3");
+        }
+        for spec in unknown
+        {
+            match Selection::open(spec)
+            {
+                Err(BackendError::Unknown(ref x)) if x == spec => {},
+                Err(e) => panic!("{:?} failed with {}", spec, e),
+                Ok(_) => panic!("{:?} opened", spec),
+            }
+        }
+        assert!(Selection::open("null").is_ok());
+        assert!(Selection::available().contains(&"null"));
+    }
+}
@@ -0,0 +1,547 @@
+///JackBackend: Plays the engine through a JACK client. Every cycle the
+///renderer is handed a JackCycle holding the master buffer, one buffer per
+///track output, the MIDI received this cycle and a list to queue outgoing
+///MIDI into. What it writes is copied to the client's ports afterwards.
+///
+///Instruments render at AudioBuffer::SAMPLE_RATE, so the server should run
+///at that rate too. Without audio hardware a server can be started with
+///the dummy driver, `jackd -d dummy -r 44100`.
+#[allow(non_snake_case)]
+pub mod JackBackend
+{
+    use instrumentation::external::midi::MidiMessage::MidiMessage;
+    use instrumentation::external::midi::MidiParser::MidiParser;
+    use jack;
+    use jack::AsyncClient;
+    use jack::AudioOut;
+    use jack::Client;
+    use jack::ClientOptions;
+    use jack::ClientStatus;
+    use jack::Control;
+    use jack::Frames;
+    use jack::MidiIn;
+    use jack::MidiOut;
+    use jack::NotificationHandler;
+    use jack::Port;
+    use jack::PortFlags;
+    use jack::ProcessHandler;
+    use jack::ProcessScope;
+    use jack::RawMidi;
+    use jack::RingBuffer;
+    use jack::TransportState;
+    use processing::backend::backend::Backend::AudioBackend;
+    use processing::backend::backend::Backend::BackendError;
+    use processing::buffer::buffer::AudioBuffer::SAMPLE_RATE;
+    use processing::buffer::buffer::AudioBuffer::StereoBuffer;
+    use sequencing::timing::transport::Transport::Transport;
+    use std::sync::Arc;
+    use std::sync::atomic::AtomicBool;
+    use std::sync::atomic::AtomicUsize;
+    use std::sync::atomic::Ordering;
+    use std::thread;
+    use std::time::Duration;
+
+    ///Largest difference in ms between JACK transport and our own before
+    ///sync relocates ours.
+    pub const SYNC_TOLERANCE_MS: u64 = 20;
+    ///Audio JackSink can hold ahead of the server, in seconds.
+    pub const SINK_SECONDS: f32 = 0.25;
+
+    ///JackCycle: One process cycle. position is the JACK transport frame at
+    ///the start of the cycle. Outgoing MIDI is (frame within the cycle,
+    ///message).
+    pub struct JackCycle<'a>
+    {
+        pub frames: usize,
+        pub rolling: bool,
+        pub position: u64,
+        pub master: &'a mut StereoBuffer,
+        pub tracks: &'a mut [StereoBuffer],
+        pub midi_in: &'a [(u32, MidiMessage)],
+        pub midi_out: &'a mut Vec<(u32, MidiMessage)>,
+    }
+
+    pub type Renderer = Box<dyn FnMut(&mut JackCycle) + Send>;
+
+    ///JackSettings: tracks is the amount of stereo track outputs registered
+    ///next to the master output. With connect set the master output is
+    ///connected to the first two physical playback ports.
+    #[derive(Clone, Debug)]
+    pub struct JackSettings
+    {
+        pub name: String,
+        pub tracks: usize,
+        pub midi_in: bool,
+        pub midi_out: bool,
+        pub connect: bool,
+    }
+
+    impl JackSettings
+    {
+        ///JackSettings::new(name: &str) -> JackSettings: Master output only,
+        ///connected to the system playback ports.
+        pub fn new(name: &str) -> JackSettings
+        {
+            JackSettings{
+                name: name.to_string(),
+                tracks: 0,
+                midi_in: false,
+                midi_out: false,
+                connect: true,
+            }
+        }
+
+        ///JackSettings.tracks(tracks: usize) -> JackSettings
+        pub fn tracks(mut self, tracks: usize) -> JackSettings
+        {
+            self.tracks = tracks;
+            self
+        }
+
+        ///JackSettings.midi(input: bool, output: bool) -> JackSettings
+        pub fn midi(mut self, input: bool, output: bool) -> JackSettings
+        {
+            self.midi_in = input;
+            self.midi_out = output;
+            self
+        }
+
+        ///JackSettings.connect(connect: bool) -> JackSettings
+        pub fn connect(mut self, connect: bool) -> JackSettings
+        {
+            self.connect = connect;
+            self
+        }
+    }
+
+    struct Notifications
+    {
+        xruns: Arc<AtomicUsize>,
+        running: Arc<AtomicBool>,
+    }
+
+    impl NotificationHandler for Notifications
+    {
+        fn shutdown(&mut self, _status: ClientStatus, _reason: &str)
+        {
+            self.running.store(false, Ordering::SeqCst);
+        }
+
+        fn xrun(&mut self, _: &Client) -> Control
+        {
+            self.xruns.fetch_add(1, Ordering::SeqCst);
+            Control::Continue
+        }
+    }
+
+    struct Process
+    {
+        master: (Port<AudioOut>, Port<AudioOut>),
+        tracks: Vec<(Port<AudioOut>, Port<AudioOut>)>,
+        midi_in: Option<Port<MidiIn>>,
+        midi_out: Option<Port<MidiOut>>,
+        master_buffer: StereoBuffer,
+        track_buffers: Vec<StereoBuffer>,
+        incoming: Vec<(u32, MidiMessage)>,
+        outgoing: Vec<(u32, MidiMessage)>,
+        renderer: Renderer,
+    }
+
+    fn write_port(port: &mut Port<AudioOut>, scope: &ProcessScope, samples: &[f32])
+    {
+        let out = port.as_mut_slice(scope);
+        let count = out.len().min(samples.len());
+        out[..count].copy_from_slice(&samples[..count]);
+    }
+
+    impl ProcessHandler for Process
+    {
+        fn process(&mut self, client: &Client, scope: &ProcessScope) -> Control
+        {
+            let frames = scope.n_frames() as usize;
+            let (rolling, position) = match client.transport().query()
+            {
+                Ok(x) => (x.state == TransportState::Rolling, x.pos.frame() as u64),
+                Err(_) => (false, 0),
+            };
+
+            self.incoming.clear();
+            if let Some(ref port) = self.midi_in
+            {
+                for event in port.iter(scope)
+                {
+                    let mut parser = MidiParser::new();
+                    for byte in event.bytes
+                    {
+                        if let Some(message) = parser.push(*byte)
+                        {
+                            self.incoming.push((event.time, message));
+                        }
+                    }
+                }
+            }
+
+            self.master_buffer.resize(frames);
+            self.master_buffer.clear();
+            for i in self.track_buffers.iter_mut()
+            {
+                i.resize(frames);
+                i.clear();
+            }
+            self.outgoing.clear();
+            {
+                let mut cycle = JackCycle{
+                    frames,
+                    rolling,
+                    position,
+                    master: &mut self.master_buffer,
+                    tracks: &mut self.track_buffers,
+                    midi_in: &self.incoming,
+                    midi_out: &mut self.outgoing,
+                };
+                (self.renderer)(&mut cycle);
+            }
+
+            write_port(&mut self.master.0, scope, &self.master_buffer.left);
+            write_port(&mut self.master.1, scope, &self.master_buffer.right);
+            for (ports, buffer) in self.tracks.iter_mut().zip(self.track_buffers.iter())
+            {
+                write_port(&mut ports.0, scope, &buffer.left);
+                write_port(&mut ports.1, scope, &buffer.right);
+            }
+
+            if let Some(ref mut port) = self.midi_out
+            {
+                //Events have to be written in order.
+                self.outgoing.sort_by_key(|x| x.0);
+                let mut writer = port.writer(scope);
+                for &(time, message) in self.outgoing.iter()
+                {
+                    let bytes = message.to_bytes();
+                    writer.write(&RawMidi{ time: time.min(frames.max(1) as u32 - 1), bytes: &bytes }).ok();
+                }
+            }
+            Control::Continue
+        }
+
+        fn buffer_size(&mut self, _: &Client, size: Frames) -> Control
+        {
+            self.master_buffer.resize(size as usize);
+            for i in self.track_buffers.iter_mut()
+            {
+                i.resize(size as usize);
+            }
+            Control::Continue
+        }
+    }
+
+    pub struct JackBackend
+    {
+        client: AsyncClient<Notifications, Process>,
+        xruns: Arc<AtomicUsize>,
+        running: Arc<AtomicBool>,
+    }
+
+    impl JackBackend
+    {
+        ///JackBackend::start<F>(settings: JackSettings, renderer: F) -> Result<JackBackend, jack::Error>:
+        ///Connects to a running server (one is never started for us),
+        ///registers the ports and starts calling renderer every cycle.
+        pub fn start<F>(settings: JackSettings, renderer: F) -> Result<JackBackend, jack::Error>
+            where F: FnMut(&mut JackCycle) + Send + 'static
+        {
+            let (client, _) = Client::new(&settings.name, ClientOptions::NO_START_SERVER)?;
+            let master = (client.register_port("master_left", AudioOut)?,
+                          client.register_port("master_right", AudioOut)?);
+            let mut tracks = Vec::new();
+            for i in 0..settings.tracks
+            {
+                tracks.push((client.register_port(&format!("track_{}_left", i), AudioOut)?,
+                             client.register_port(&format!("track_{}_right", i), AudioOut)?));
+            }
+            let midi_in = if settings.midi_in { Some(client.register_port("midi_in", MidiIn)?) } else { None };
+            let midi_out = if settings.midi_out { Some(client.register_port("midi_out", MidiOut)?) } else { None };
+            let master_names = (master.0.name()?, master.1.name()?);
+            let frames = client.buffer_size() as usize;
+
+            let xruns = Arc::new(AtomicUsize::new(0));
+            let running = Arc::new(AtomicBool::new(true));
+            let notifications = Notifications{
+                xruns: xruns.clone(),
+                running: running.clone(),
+            };
+            let process = Process{
+                master,
+                track_buffers: (0..tracks.len()).map(|_| StereoBuffer::new(frames)).collect(),
+                tracks,
+                midi_in,
+                midi_out,
+                master_buffer: StereoBuffer::new(frames),
+                incoming: Vec::with_capacity(256),
+                outgoing: Vec::with_capacity(256),
+                renderer: Box::new(renderer),
+            };
+            let client = client.activate_async(notifications, process)?;
+
+            if settings.connect
+            {
+                let playback = client.as_client().ports(None, Some("audio"), PortFlags::IS_INPUT | PortFlags::IS_PHYSICAL);
+                if playback.len() >= 2
+                {
+                    client.as_client().connect_ports_by_name(&master_names.0, &playback[0])?;
+                    client.as_client().connect_ports_by_name(&master_names.1, &playback[1])?;
+                }
+            }
+
+            Ok(JackBackend{
+                client,
+                xruns,
+                running,
+            })
+        }
+
+        pub fn sample_rate(&self) -> usize
+        {
+            self.client.as_client().sample_rate()
+        }
+
+        pub fn buffer_size(&self) -> usize
+        {
+            self.client.as_client().buffer_size() as usize
+        }
+
+        ///JackBackend.xruns() -> usize: Buffer under or overruns reported by
+        ///the server since the client was started.
+        pub fn xruns(&self) -> usize
+        {
+            self.xruns.load(Ordering::SeqCst)
+        }
+
+        ///JackBackend.is_running() -> bool: False once the server has shut
+        ///the client down.
+        pub fn is_running(&self) -> bool
+        {
+            self.running.load(Ordering::SeqCst)
+        }
+
+        ///JackBackend.connect(source: &str, destination: &str) -> Result<(), jack::Error>:
+        ///Connects two ports by their full names.
+        pub fn connect(&self, source: &str, destination: &str) -> Result<(), jack::Error>
+        {
+            self.client.as_client().connect_ports_by_name(source, destination)
+        }
+
+        ///JackBackend.start_transport() -> Result<(), jack::Error>
+        pub fn start_transport(&self) -> Result<(), jack::Error>
+        {
+            self.client.as_client().transport().start()
+        }
+
+        ///JackBackend.stop_transport() -> Result<(), jack::Error>
+        pub fn stop_transport(&self) -> Result<(), jack::Error>
+        {
+            self.client.as_client().transport().stop()
+        }
+
+        ///JackBackend.locate(ms: u64) -> Result<(), jack::Error>: Moves the
+        ///JACK transport.
+        pub fn locate(&self, ms: u64) -> Result<(), jack::Error>
+        {
+            let frame = ms * self.sample_rate() as u64 / 1000;
+            self.client.as_client().transport().locate(frame as Frames)
+        }
+
+        ///JackBackend.sync(transport: &mut Transport) -> Result<(), jack::Error>:
+        ///Makes transport follow the JACK transport: starting, stopping and
+        ///relocating it when they drift more than SYNC_TOLERANCE_MS apart.
+        pub fn sync(&self, transport: &mut Transport) -> Result<(), jack::Error>
+        {
+            let state = self.client.as_client().transport().query()?;
+            let ms = state.pos.frame() as u64 * 1000 / self.sample_rate().max(1) as u64;
+            let rolling = state.state == TransportState::Rolling;
+            let position = transport.position();
+            let drift = position.abs_diff(ms);
+
+            if rolling != transport.is_playing() || drift > SYNC_TOLERANCE_MS
+            {
+                if rolling
+                {
+                    transport.resume();
+                }
+                else
+                {
+                    transport.stop();
+                }
+                transport.locate(ms);
+            }
+            Ok(())
+        }
+
+        ///JackBackend.close() -> Result<(), jack::Error>: Deactivates the
+        ///client and closes its connection to the server.
+        pub fn close(self) -> Result<(), jack::Error>
+        {
+            self.client.deactivate().map(|_| ())
+        }
+    }
+
+    fn jack_error(e: jack::Error) -> BackendError
+    {
+        BackendError::Device(e.to_string())
+    }
+
+    ///JackSink: The master output of a JackBackend used as an AudioBackend.
+    ///Written audio is queued in a ring buffer that the process callback
+    ///drains, with silence played whenever the queue runs dry.
+    pub struct JackSink
+    {
+        backend: JackBackend,
+        writer: jack::RingBufferWriter,
+        bytes: Vec<u8>,
+    }
+
+    impl JackSink
+    {
+        ///JackSink::start(settings: JackSettings) -> Result<JackSink, BackendError>:
+        ///Starts a client with the master output only; track outputs and
+        ///MIDI ports in settings are ignored.
+        pub fn start(settings: JackSettings) -> Result<JackSink, BackendError>
+        {
+            let settings = settings.tracks(0).midi(false, false);
+            let size = (SAMPLE_RATE as f32 * SINK_SECONDS) as usize * 8;
+            let (mut reader, writer) = RingBuffer::new(size).map_err(jack_error)?.into_reader_writer();
+            let mut bytes: Vec<u8> = Vec::with_capacity(size);
+
+            let backend = JackBackend::start(settings, move |cycle: &mut JackCycle| {
+                let wanted = (reader.space() / 8).min(cycle.frames) * 8;
+                bytes.resize(wanted, 0);
+                let read = reader.read_buffer(&mut bytes);
+                for (i, frame) in bytes[..read].chunks(8).enumerate()
+                {
+                    cycle.master.left[i] = f32::from_bits(u32::from_ne_bytes([frame[0], frame[1], frame[2], frame[3]]));
+                    cycle.master.right[i] = f32::from_bits(u32::from_ne_bytes([frame[4], frame[5], frame[6], frame[7]]));
+                }
+            }).map_err(jack_error)?;
+
+            Ok(JackSink{
+                backend,
+                writer,
+                bytes: Vec::new(),
+            })
+        }
+
+        ///JackSink.backend() -> &JackBackend: For transport control and
+        ///xrun counts.
+        pub fn backend(&self) -> &JackBackend
+        {
+            &self.backend
+        }
+    }
+
+    impl AudioBackend for JackSink
+    {
+        fn sample_rate(&self) -> u32
+        {
+            self.backend.sample_rate() as u32
+        }
+
+        fn write(&mut self, buffer: &StereoBuffer) -> Result<(), BackendError>
+        {
+            self.bytes.clear();
+            for i in 0..buffer.len()
+            {
+                self.bytes.extend_from_slice(&buffer.left[i].to_bits().to_ne_bytes());
+                self.bytes.extend_from_slice(&buffer.right[i].to_bits().to_ne_bytes());
+            }
+
+            let mut done = 0;
+            while done < self.bytes.len()
+            {
+                if !self.backend.is_running()
+                {
+                    return Err(BackendError::Device("jack server shut the client down".to_string()));
+                }
+                //Only whole frames are queued so the reader never sees half of one.
+                let room = self.writer.space() / 8 * 8;
+                if room == 0
+                {
+                    thread::sleep(Duration::from_millis(1));
+                    continue;
+                }
+                let end = (done + room).min(self.bytes.len());
+                done += self.writer.write_buffer(&self.bytes[done..end]);
+            }
+            Ok(())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use jack::Client;
+    use jack::ClientOptions;
+    use jack::PortFlags;
+    use processing::backend::jack::JackBackend::JackBackend;
+    use processing::backend::jack::JackBackend::JackSettings;
+    use sequencing::timing::transport::Transport::Transport;
+    use std::sync::Arc;
+    use std::sync::atomic::AtomicUsize;
+    use std::sync::atomic::Ordering;
+    use std::thread;
+    use std::time::Duration;
+
+    #[test]
+    fn settings_default_to_a_connected_master_output()
+    {
+        let settings = JackSettings::new("jaru3");
+        assert_eq!((settings.tracks, settings.midi_in, settings.midi_out, settings.connect), (0, false, false, true));
+        let settings = settings.tracks(4).midi(true, false).connect(false);
+        assert_eq!((settings.tracks, settings.midi_in, settings.midi_out, settings.connect), (4, true, false, false));
+    }
+
+    //Needs a server to connect to, started with: jackd -d dummy -r 44100
+    #[test]
+    #[ignore]
+    fn runs_against_a_dummy_server()
+    {
+        let calls = Arc::new(AtomicUsize::new(0));
+        let counter = calls.clone();
+        let settings = JackSettings::new("jaru3_test").tracks(2).midi(true, true).connect(false);
+        let backend = JackBackend::start(settings, move |cycle| {
+            counter.fetch_add(1, Ordering::SeqCst);
+            assert_eq!(cycle.master.len(), cycle.frames);
+            assert_eq!(cycle.tracks.len(), 2);
+        }).unwrap();
+
+        let (probe, _) = Client::new("jaru3_probe", ClientOptions::NO_START_SERVER).unwrap();
+        let mut ports = probe.ports(Some("jaru3_test:"), None, PortFlags::empty());
+        ports.sort();
+        assert_eq!(ports, vec![
+            "jaru3_test:master_left", "jaru3_test:master_right", "jaru3_test:midi_in", "jaru3_test:midi_out",
+            "jaru3_test:track_0_left", "jaru3_test:track_0_right", "jaru3_test:track_1_left", "jaru3_test:track_1_right",
+        ]);
+
+        thread::sleep(Duration::from_millis(250));
+        assert!(calls.load(Ordering::SeqCst) > 0);
+        assert!(backend.is_running());
+        assert_eq!(backend.xruns(), 0);
+
+        let mut transport = Transport::new();
+        backend.stop_transport().unwrap();
+        backend.locate(1000).unwrap();
+        thread::sleep(Duration::from_millis(250));
+        backend.sync(&mut transport).unwrap();
+        assert!(!transport.is_playing());
+        assert_eq!(transport.position(), 1000);
+
+        backend.start_transport().unwrap();
+        thread::sleep(Duration::from_millis(250));
+        backend.sync(&mut transport).unwrap();
+        assert!(transport.is_playing());
+        assert!(transport.position() > 1000);
+
+        backend.stop_transport().unwrap();
+        backend.close().unwrap();
+    }
+}
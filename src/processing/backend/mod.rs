@@ -0,0 +1,6 @@
+#[allow(clippy::module_inception)]
+pub mod backend;
+#[cfg(feature = "alsa")]
+pub mod alsa;
+#[cfg(feature = "jack")]
+pub mod jack;
@@ -0,0 +1,114 @@
+///AudioBuffer: Primitive containers for rendered audio. Everything that
+///produces or consumes sound inside of the engine (mixer, effects and
+///eventually instruments) passes blocks of samples around using the
+///structures in this module.
+#[allow(non_snake_case)]
+pub mod AudioBuffer
+{
+    ///Sample rate that all internal processing is done at.
+    pub const SAMPLE_RATE: u32 = 44100;
+
+    ///Default amount of frames that are processed in a single block.
+    pub const BLOCK_SIZE: usize = 256;
+
+    ///StereoBuffer: Simple non-interleaved two channel buffer of f32
+    ///samples. Left and right are always kept at the same length.
+    #[derive(Clone, Debug)]
+    pub struct StereoBuffer
+    {
+        pub left: Vec<f32>,
+        pub right: Vec<f32>,
+    }
+
+    impl StereoBuffer
+    {
+        ///StereoBuffer::new(frames: usize) -> StereoBuffer: Generates a
+        ///silent buffer that holds frames samples per channel.
+        pub fn new(frames: usize) -> StereoBuffer
+        {
+            StereoBuffer{
+                left: vec![0.0; frames],
+                right: vec![0.0; frames],
+            }
+        }
+
+        ///StereoBuffer.len() -> usize: Amount of frames inside of the buffer.
+        pub fn len(&self) -> usize
+        {
+            self.left.len()
+        }
+
+        ///StereoBuffer.is_empty() -> bool: True if the buffer holds no frames.
+        pub fn is_empty(&self) -> bool
+        {
+            self.left.is_empty()
+        }
+
+        ///StereoBuffer.clear(): Zeroes out every sample without changing
+        ///the size of the buffer.
+        pub fn clear(&mut self)
+        {
+            for i in self.left.iter_mut()
+            {
+                *i = 0.0;
+            }
+            for i in self.right.iter_mut()
+            {
+                *i = 0.0;
+            }
+        }
+
+        ///StereoBuffer.resize(frames: usize): Changes the amount of frames
+        ///held by the buffer, filling any new space with silence.
+        pub fn resize(&mut self, frames: usize)
+        {
+            self.left.resize(frames, 0.0);
+            self.right.resize(frames, 0.0);
+        }
+
+        ///StereoBuffer.copy_from(other: &StereoBuffer): Overwrites this
+        ///buffer with the contents of other, up to the shorter length.
+        pub fn copy_from(&mut self, other: &StereoBuffer)
+        {
+            let frames = if self.len() < other.len() { self.len() } else { other.len() };
+            self.left[..frames].copy_from_slice(&other.left[..frames]);
+            self.right[..frames].copy_from_slice(&other.right[..frames]);
+        }
+
+        ///StereoBuffer.mix_from(other: &StereoBuffer, left_gain: f32, right_gain: f32):
+        ///Sums other into this buffer, scaling each channel separately. Used
+        ///for panning and bus summing.
+        pub fn mix_from(&mut self, other: &StereoBuffer, left_gain: f32, right_gain: f32)
+        {
+            let frames = if self.len() < other.len() { self.len() } else { other.len() };
+            for i in 0..frames
+            {
+                self.left[i] += other.left[i] * left_gain;
+                self.right[i] += other.right[i] * right_gain;
+            }
+        }
+
+        ///StereoBuffer.scale(left_gain: f32, right_gain: f32): Multiplies
+        ///every sample of each channel in place.
+        pub fn scale(&mut self, left_gain: f32, right_gain: f32)
+        {
+            for i in self.left.iter_mut()
+            {
+                *i *= left_gain;
+            }
+            for i in self.right.iter_mut()
+            {
+                *i *= right_gain;
+            }
+        }
+
+        ///StereoBuffer.peak() -> f32: Returns the largest absolute sample
+        ///value across both channels.
+        pub fn peak(&self) -> f32
+        {
+            self.left.iter().chain(self.right.iter()).fold(0.0, |x, y| {
+                if y.abs() > x { y.abs() } else { x }
+            })
+        }
+    }
+}
@@ -0,0 +1,1202 @@
+///Effect: The interface every audio processor implements along with the
+///insert chain that holds them. Effects work in place on a StereoBuffer
+///so they can be run in real time or offline over a rendered file.
+#[allow(non_snake_case)]
+pub mod Effect
+{
+    use processing::buffer::buffer::AudioBuffer::StereoBuffer;
+    use processing::effects::effect::Delay::Delay;
+    use processing::effects::effect::Distortion::SoftClip;
+    use processing::effects::effect::Dynamics::Compressor;
+    use processing::effects::effect::Filter::Biquad;
+    use processing::effects::effect::Filter::Equalizer;
+    use processing::effects::effect::Filter::FilterType;
+    use processing::effects::effect::Reverb::Reverb;
+    use serde::Deserialize;
+    use serde::Serialize;
+    use std::fmt::Debug;
+    use std::fmt::Error;
+    use std::fmt::Formatter;
+
+    pub trait Effect: Send
+    {
+        ///Effect.process(buffer: &mut StereoBuffer): Processes one block of
+        ///audio in place.
+        fn process(&mut self, buffer: &mut StereoBuffer);
+
+        ///Effect.reset(): Clears any internal state (delay lines, filter
+        ///history, envelopes) so a tail does not leak into the next render.
+        fn reset(&mut self)
+        {
+        }
+
+        ///Effect.set_parameter(name: &str, value: f32) -> bool: Changes a
+        ///named parameter, used by automation. Returns false if the effect
+        ///has no parameter called name.
+        fn set_parameter(&mut self, _name: &str, _value: f32) -> bool
+        {
+            false
+        }
+
+        ///Effect.name() -> &str: Human readable name of the effect.
+        fn name(&self) -> &str;
+
+        ///Effect.settings() -> Option<EffectSettings>: What the effect can
+        ///be built again from, or None for effects that can't be saved.
+        fn settings(&self) -> Option<EffectSettings>
+        {
+            None
+        }
+    }
+
+    ///EffectSettings: A built-in effect as it is saved, the arguments and
+    ///public fields it is built from without its state.
+    #[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+    pub enum EffectSettings
+    {
+        Biquad{ kind: FilterType, frequency: f32, q: f32 },
+        Equalizer{ bands: Vec<(FilterType, f32, f32)> },
+        Delay{ time_ms: u32, feedback: f32, mix: f32, ping_pong: bool },
+        Reverb{ room_size: f32, damping: f32, wet: f32, dry: f32, width: f32 },
+        Compressor{ threshold_db: f32, ratio: f32, attack_ms: f32, release_ms: f32, makeup_db: f32 },
+        SoftClip{ drive: f32, mix: f32, output: f32 },
+    }
+
+    impl EffectSettings
+    {
+        ///EffectSettings.build() -> Box<Effect>: A new effect with these
+        ///settings.
+        pub fn build(&self) -> Box<dyn Effect>
+        {
+            match *self
+            {
+                EffectSettings::Biquad{ kind, frequency, q } => Box::new(Biquad::new(kind, frequency, q)),
+                EffectSettings::Equalizer{ ref bands } =>
+                {
+                    Box::new(bands.iter().fold(Equalizer::new(), |eq, x| eq.band(x.0, x.1, x.2)))
+                },
+                EffectSettings::Delay{ time_ms, feedback, mix, ping_pong } =>
+                {
+                    let mut delay = Delay::new(time_ms, feedback, mix);
+                    delay.ping_pong = ping_pong;
+                    Box::new(delay)
+                },
+                EffectSettings::Reverb{ room_size, damping, wet, dry, width } =>
+                {
+                    let mut reverb = Reverb::new(room_size, damping, wet);
+                    reverb.dry = dry;
+                    reverb.width = width;
+                    Box::new(reverb)
+                },
+                EffectSettings::Compressor{ threshold_db, ratio, attack_ms, release_ms, makeup_db } =>
+                {
+                    let mut compressor = Compressor::new(threshold_db, ratio, attack_ms, release_ms);
+                    compressor.makeup_db = makeup_db;
+                    Box::new(compressor)
+                },
+                EffectSettings::SoftClip{ drive, mix, output } =>
+                {
+                    let mut clip = SoftClip::new(drive);
+                    clip.mix = mix;
+                    clip.output = output;
+                    Box::new(clip)
+                },
+            }
+        }
+    }
+
+    ///EffectChain: Ordered list of insert effects. Audio runs through the
+    ///effects from first to last.
+    pub struct EffectChain
+    {
+        effects: Vec<Box<dyn Effect>>,
+        pub bypass: bool,
+    }
+
+    impl EffectChain
+    {
+        ///EffectChain::new() -> EffectChain: Generates an empty chain.
+        pub fn new() -> EffectChain
+        {
+            EffectChain{
+                effects: Vec::new(),
+                bypass: false,
+            }
+        }
+
+        ///EffectChain.push(effect: Box<Effect>): Appends an effect to the
+        ///end of the chain.
+        pub fn push(&mut self, effect: Box<dyn Effect>)
+        {
+            self.effects.push(effect);
+        }
+
+        ///EffectChain.insert(index: usize, effect: Box<Effect>): Places an
+        ///effect at index, shifting the rest down the chain. Effects placed
+        ///past the end are appended.
+        pub fn insert(&mut self, index: usize, effect: Box<dyn Effect>)
+        {
+            if index >= self.effects.len()
+            {
+                self.effects.push(effect);
+            }
+            else
+            {
+                self.effects.insert(index, effect);
+            }
+        }
+
+        ///EffectChain.remove(index: usize) -> Option<Box<Effect>>: Takes an
+        ///effect out of the chain.
+        pub fn remove(&mut self, index: usize) -> Option<Box<dyn Effect>>
+        {
+            if index < self.effects.len()
+            {
+                Some(self.effects.remove(index))
+            }
+            else
+            {
+                None
+            }
+        }
+
+        ///EffectChain.get(index: usize) -> Option<&mut Box<Effect>>: Access
+        ///to a single effect in the chain.
+        pub fn get(&mut self, index: usize) -> Option<&mut Box<dyn Effect>>
+        {
+            self.effects.get_mut(index)
+        }
+
+        ///EffectChain.settings() -> Result<Vec<EffectSettings>, usize>: The
+        ///settings of every effect in order, or the index of the first one
+        ///that can't be saved.
+        pub fn settings(&self) -> Result<Vec<EffectSettings>, usize>
+        {
+            self.effects.iter().enumerate().map(|(i, x)| x.settings().ok_or(i)).collect()
+        }
+
+        ///EffectChain.len() -> usize: Amount of effects in the chain.
+        pub fn len(&self) -> usize
+        {
+            self.effects.len()
+        }
+
+        ///EffectChain.is_empty() -> bool: True if the chain holds no effects.
+        pub fn is_empty(&self) -> bool
+        {
+            self.effects.is_empty()
+        }
+
+        ///EffectChain.process(buffer: &mut StereoBuffer): Runs buffer
+        ///through every effect unless the chain is bypassed.
+        pub fn process(&mut self, buffer: &mut StereoBuffer)
+        {
+            if self.bypass
+            {
+                return;
+            }
+            for i in self.effects.iter_mut()
+            {
+                i.process(buffer);
+            }
+        }
+
+        ///EffectChain.reset(): Resets every effect in the chain.
+        pub fn reset(&mut self)
+        {
+            for i in self.effects.iter_mut()
+            {
+                i.reset();
+            }
+        }
+    }
+
+    impl Default for EffectChain
+    {
+        fn default() -> EffectChain
+        {
+            EffectChain::new()
+        }
+    }
+
+    ///EffectChain::fmt()...: Prints the names of the effects in order.
+    impl Debug for EffectChain
+    {
+        fn fmt(&self, f: &mut Formatter) -> Result<(), Error>
+        {
+            let names: Vec<&str> = self.effects.iter().map(|x| x.name()).collect();
+            write!(f, "EffectChain {{ effects: {:?}, bypass: {} }}", names, self.bypass)
+        }
+    }
+}
+
+///Filter: Biquad filters based on the RBJ audio EQ cookbook and a
+///parametric equalizer built from a set of them.
+#[allow(non_snake_case)]
+pub mod Filter
+{
+    use processing::buffer::buffer::AudioBuffer::SAMPLE_RATE;
+    use processing::buffer::buffer::AudioBuffer::StereoBuffer;
+    use processing::effects::effect::Effect::Effect;
+    use processing::effects::effect::Effect::EffectSettings;
+    use serde::Deserialize;
+    use serde::Serialize;
+    use std::f32::consts::PI;
+
+    ///FilterType: Response of a biquad. Peak and shelf filters carry
+    ///their gain in decibels.
+    #[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+    pub enum FilterType
+    {
+        LowPass,
+        HighPass,
+        BandPass,
+        Notch,
+        Peak(f32),
+        LowShelf(f32),
+        HighShelf(f32),
+    }
+
+    ///Coefficients of a normalized biquad (a0 is always 1.0).
+    #[derive(Clone, Copy, Debug)]
+    struct Coefficients
+    {
+        b0: f32,
+        b1: f32,
+        b2: f32,
+        a1: f32,
+        a2: f32,
+    }
+
+    ///History of a single channel (transposed direct form II).
+    #[derive(Clone, Copy, Debug)]
+    struct State
+    {
+        z1: f32,
+        z2: f32,
+    }
+
+    impl State
+    {
+        fn new() -> State
+        {
+            State{ z1: 0.0, z2: 0.0 }
+        }
+
+        fn tick(&mut self, c: &Coefficients, x: f32) -> f32
+        {
+            let y = c.b0 * x + self.z1;
+            self.z1 = c.b1 * x - c.a1 * y + self.z2;
+            self.z2 = c.b2 * x - c.a2 * y;
+            y
+        }
+    }
+
+    ///Biquad: Stereo second order filter.
+    #[derive(Clone, Debug)]
+    pub struct Biquad
+    {
+        kind: FilterType,
+        frequency: f32,
+        q: f32,
+        coefficients: Coefficients,
+        left: State,
+        right: State,
+    }
+
+    ///calculate(...) -> Coefficients: Cookbook formulas for every filter
+    ///type at the engine sample rate.
+    fn calculate(kind: FilterType, frequency: f32, q: f32) -> Coefficients
+    {
+        let nyquist = SAMPLE_RATE as f32 / 2.0;
+        let freq = if frequency < 1.0 { 1.0 } else if frequency > nyquist * 0.99 { nyquist * 0.99 } else { frequency };
+        let q = if q < 0.01 { 0.01 } else { q };
+        let w0 = 2.0 * PI * freq / SAMPLE_RATE as f32;
+        let (sin, cos) = (w0.sin(), w0.cos());
+        let alpha = sin / (2.0 * q);
+
+        let (b0, b1, b2, a0, a1, a2) = match kind
+        {
+            FilterType::LowPass =>
+            {
+                ((1.0 - cos) / 2.0, 1.0 - cos, (1.0 - cos) / 2.0, 1.0 + alpha, -2.0 * cos, 1.0 - alpha)
+            },
+            FilterType::HighPass =>
+            {
+                ((1.0 + cos) / 2.0, -(1.0 + cos), (1.0 + cos) / 2.0, 1.0 + alpha, -2.0 * cos, 1.0 - alpha)
+            },
+            FilterType::BandPass =>
+            {
+                (alpha, 0.0, -alpha, 1.0 + alpha, -2.0 * cos, 1.0 - alpha)
+            },
+            FilterType::Notch =>
+            {
+                (1.0, -2.0 * cos, 1.0, 1.0 + alpha, -2.0 * cos, 1.0 - alpha)
+            },
+            FilterType::Peak(gain) =>
+            {
+                let a = 10f32.powf(gain / 40.0);
+                (1.0 + alpha * a, -2.0 * cos, 1.0 - alpha * a, 1.0 + alpha / a, -2.0 * cos, 1.0 - alpha / a)
+            },
+            FilterType::LowShelf(gain) =>
+            {
+                let a = 10f32.powf(gain / 40.0);
+                let root = 2.0 * a.sqrt() * alpha;
+                (a * ((a + 1.0) - (a - 1.0) * cos + root),
+                 2.0 * a * ((a - 1.0) - (a + 1.0) * cos),
+                 a * ((a + 1.0) - (a - 1.0) * cos - root),
+                 (a + 1.0) + (a - 1.0) * cos + root,
+                 -2.0 * ((a - 1.0) + (a + 1.0) * cos),
+                 (a + 1.0) + (a - 1.0) * cos - root)
+            },
+            FilterType::HighShelf(gain) =>
+            {
+                let a = 10f32.powf(gain / 40.0);
+                let root = 2.0 * a.sqrt() * alpha;
+                (a * ((a + 1.0) + (a - 1.0) * cos + root),
+                 -2.0 * a * ((a - 1.0) + (a + 1.0) * cos),
+                 a * ((a + 1.0) + (a - 1.0) * cos - root),
+                 (a + 1.0) - (a - 1.0) * cos + root,
+                 2.0 * ((a - 1.0) - (a + 1.0) * cos),
+                 (a + 1.0) - (a - 1.0) * cos - root)
+            },
+        };
+
+        Coefficients{
+            b0: b0 / a0,
+            b1: b1 / a0,
+            b2: b2 / a0,
+            a1: a1 / a0,
+            a2: a2 / a0,
+        }
+    }
+
+    impl Biquad
+    {
+        ///Biquad::new(kind: FilterType, frequency: f32, q: f32) -> Biquad:
+        ///Generates a filter with a cutoff/center of frequency herz.
+        pub fn new(kind: FilterType, frequency: f32, q: f32) -> Biquad
+        {
+            Biquad{
+                kind,
+                frequency,
+                q,
+                coefficients: calculate(kind, frequency, q),
+                left: State::new(),
+                right: State::new(),
+            }
+        }
+
+        ///Biquad.set(kind: FilterType, frequency: f32, q: f32): Recalculates
+        ///the filter while keeping its history so it can be swept.
+        pub fn set(&mut self, kind: FilterType, frequency: f32, q: f32)
+        {
+            self.kind = kind;
+            self.frequency = frequency;
+            self.q = q;
+            self.coefficients = calculate(kind, frequency, q);
+        }
+
+        ///Biquad.set_frequency(frequency: f32): Moves the cutoff/center.
+        pub fn set_frequency(&mut self, frequency: f32)
+        {
+            let (kind, q) = (self.kind, self.q);
+            self.set(kind, frequency, q);
+        }
+
+        pub fn frequency(&self) -> f32
+        {
+            self.frequency
+        }
+
+        ///Biquad.tick(left: f32, right: f32) -> (f32, f32): Filters a single
+        ///stereo frame.
+        pub fn tick(&mut self, left: f32, right: f32) -> (f32, f32)
+        {
+            (self.left.tick(&self.coefficients, left), self.right.tick(&self.coefficients, right))
+        }
+    }
+
+    impl Effect for Biquad
+    {
+        fn process(&mut self, buffer: &mut StereoBuffer)
+        {
+            for i in 0..buffer.len()
+            {
+                buffer.left[i] = self.left.tick(&self.coefficients, buffer.left[i]);
+                buffer.right[i] = self.right.tick(&self.coefficients, buffer.right[i]);
+            }
+        }
+
+        fn reset(&mut self)
+        {
+            self.left = State::new();
+            self.right = State::new();
+        }
+
+        fn set_parameter(&mut self, name: &str, value: f32) -> bool
+        {
+            let (kind, frequency, q) = (self.kind, self.frequency, self.q);
+            match name
+            {
+                "frequency" => self.set(kind, value, q),
+                "q" => self.set(kind, frequency, value),
+                _ => return false,
+            }
+            true
+        }
+
+        fn name(&self) -> &str
+        {
+            "Biquad"
+        }
+
+        fn settings(&self) -> Option<EffectSettings>
+        {
+            Some(EffectSettings::Biquad{ kind: self.kind, frequency: self.frequency, q: self.q })
+        }
+    }
+
+    ///Equalizer: Parametric equalizer made of any amount of biquad bands
+    ///run in series.
+    #[derive(Clone, Debug)]
+    pub struct Equalizer
+    {
+        pub bands: Vec<Biquad>,
+    }
+
+    impl Equalizer
+    {
+        ///Equalizer::new() -> Equalizer: Generates a flat equalizer.
+        pub fn new() -> Equalizer
+        {
+            Equalizer{
+                bands: Vec::new(),
+            }
+        }
+
+        ///Equalizer.band(kind: FilterType, frequency: f32, q: f32) -> Equalizer:
+        ///Builder style way of adding a band.
+        pub fn band(mut self, kind: FilterType, frequency: f32, q: f32) -> Equalizer
+        {
+            self.bands.push(Biquad::new(kind, frequency, q));
+            self
+        }
+    }
+
+    impl Default for Equalizer
+    {
+        fn default() -> Equalizer
+        {
+            Equalizer::new()
+        }
+    }
+
+    impl Effect for Equalizer
+    {
+        fn process(&mut self, buffer: &mut StereoBuffer)
+        {
+            for i in self.bands.iter_mut()
+            {
+                i.process(buffer);
+            }
+        }
+
+        fn reset(&mut self)
+        {
+            for i in self.bands.iter_mut()
+            {
+                i.reset();
+            }
+        }
+
+        fn name(&self) -> &str
+        {
+            "Equalizer"
+        }
+
+        fn settings(&self) -> Option<EffectSettings>
+        {
+            Some(EffectSettings::Equalizer{ bands: self.bands.iter().map(|x| (x.kind, x.frequency, x.q)).collect() })
+        }
+    }
+}
+
+///Delay: Stereo feedback delay with an optional ping pong mode where the
+///repeats alternate between the left and right channel.
+#[allow(non_snake_case)]
+pub mod Delay
+{
+    use processing::buffer::buffer::AudioBuffer::SAMPLE_RATE;
+    use processing::buffer::buffer::AudioBuffer::StereoBuffer;
+    use processing::effects::effect::Effect::Effect;
+    use processing::effects::effect::Effect::EffectSettings;
+
+    ///Longest delay time that can be set, in milliseconds.
+    pub const MAX_DELAY_MS: u32 = 4000;
+
+    #[derive(Clone, Debug)]
+    pub struct Delay
+    {
+        pub feedback: f32,
+        pub mix: f32,
+        pub ping_pong: bool,
+        time_ms: u32,
+        delay_samples: usize,
+        left: Vec<f32>,
+        right: Vec<f32>,
+        position: usize,
+    }
+
+    impl Delay
+    {
+        ///Delay::new(time_ms: u32, feedback: f32, mix: f32) -> Delay: time_ms
+        ///is clamped to MAX_DELAY_MS, mix goes from 0.0 (dry) to 1.0 (wet).
+        pub fn new(time_ms: u32, feedback: f32, mix: f32) -> Delay
+        {
+            let capacity = (MAX_DELAY_MS as usize * SAMPLE_RATE as usize) / 1000 + 1;
+            let mut delay = Delay{
+                feedback,
+                mix,
+                ping_pong: false,
+                time_ms: 0,
+                delay_samples: 1,
+                left: vec![0.0; capacity],
+                right: vec![0.0; capacity],
+                position: 0,
+            };
+            delay.set_time(time_ms);
+            delay
+        }
+
+        ///Delay.set_time(time_ms: u32): Changes the delay time.
+        pub fn set_time(&mut self, time_ms: u32)
+        {
+            let clamped = if time_ms > MAX_DELAY_MS { MAX_DELAY_MS } else { time_ms };
+            self.time_ms = clamped;
+            let samples = (clamped as usize * SAMPLE_RATE as usize) / 1000;
+            self.delay_samples = if samples < 1 { 1 } else { samples };
+        }
+    }
+
+    impl Effect for Delay
+    {
+        fn process(&mut self, buffer: &mut StereoBuffer)
+        {
+            let capacity = self.left.len();
+            for i in 0..buffer.len()
+            {
+                let read = (self.position + capacity - self.delay_samples) % capacity;
+                let (dl, dr) = (self.left[read], self.right[read]);
+                let (inl, inr) = (buffer.left[i], buffer.right[i]);
+
+                if self.ping_pong
+                {
+                    self.left[self.position] = (inl + inr) * 0.5 + dr * self.feedback;
+                    self.right[self.position] = dl * self.feedback;
+                }
+                else
+                {
+                    self.left[self.position] = inl + dl * self.feedback;
+                    self.right[self.position] = inr + dr * self.feedback;
+                }
+
+                buffer.left[i] = inl * (1.0 - self.mix) + dl * self.mix;
+                buffer.right[i] = inr * (1.0 - self.mix) + dr * self.mix;
+                self.position = (self.position + 1) % capacity;
+            }
+        }
+
+        fn reset(&mut self)
+        {
+            for i in self.left.iter_mut().chain(self.right.iter_mut())
+            {
+                *i = 0.0;
+            }
+            self.position = 0;
+        }
+
+        fn set_parameter(&mut self, name: &str, value: f32) -> bool
+        {
+            match name
+            {
+                "time" => self.set_time(value as u32),
+                "feedback" => self.feedback = value,
+                "mix" => self.mix = value,
+                _ => return false,
+            }
+            true
+        }
+
+        fn name(&self) -> &str
+        {
+            "Delay"
+        }
+
+        fn settings(&self) -> Option<EffectSettings>
+        {
+            Some(EffectSettings::Delay{ time_ms: self.time_ms, feedback: self.feedback, mix: self.mix, ping_pong: self.ping_pong })
+        }
+    }
+}
+
+///Reverb: Algorithmic reverb using the classic Schroeder/Moorer layout of
+///parallel damped comb filters feeding series allpass filters.
+#[allow(non_snake_case)]
+pub mod Reverb
+{
+    use processing::buffer::buffer::AudioBuffer::SAMPLE_RATE;
+    use processing::buffer::buffer::AudioBuffer::StereoBuffer;
+    use processing::effects::effect::Effect::Effect;
+    use processing::effects::effect::Effect::EffectSettings;
+
+    ///Comb and allpass tunings in samples at 44.1khz.
+    const COMB_TUNING: [usize; 8] = [1116, 1188, 1277, 1356, 1422, 1491, 1557, 1617];
+    const ALLPASS_TUNING: [usize; 4] = [556, 441, 341, 225];
+    const STEREO_SPREAD: usize = 23;
+    const INPUT_GAIN: f32 = 0.015;
+
+    fn scaled(samples: usize) -> usize
+    {
+        samples * SAMPLE_RATE as usize / 44100
+    }
+
+    #[derive(Clone, Debug)]
+    struct Comb
+    {
+        buffer: Vec<f32>,
+        position: usize,
+        store: f32,
+    }
+
+    impl Comb
+    {
+        fn new(size: usize) -> Comb
+        {
+            Comb{ buffer: vec![0.0; size], position: 0, store: 0.0 }
+        }
+
+        fn tick(&mut self, input: f32, feedback: f32, damping: f32) -> f32
+        {
+            let output = self.buffer[self.position];
+            self.store = output * (1.0 - damping) + self.store * damping;
+            self.buffer[self.position] = input + self.store * feedback;
+            self.position = (self.position + 1) % self.buffer.len();
+            output
+        }
+    }
+
+    #[derive(Clone, Debug)]
+    struct Allpass
+    {
+        buffer: Vec<f32>,
+        position: usize,
+    }
+
+    impl Allpass
+    {
+        fn new(size: usize) -> Allpass
+        {
+            Allpass{ buffer: vec![0.0; size], position: 0 }
+        }
+
+        fn tick(&mut self, input: f32) -> f32
+        {
+            let delayed = self.buffer[self.position];
+            let output = delayed - input;
+            self.buffer[self.position] = input + delayed * 0.5;
+            self.position = (self.position + 1) % self.buffer.len();
+            output
+        }
+    }
+
+    ///Reverb: room_size and damping go from 0.0 to 1.0, wet and dry are
+    ///output gains and width controls the stereo spread of the tail.
+    #[derive(Clone, Debug)]
+    pub struct Reverb
+    {
+        pub room_size: f32,
+        pub damping: f32,
+        pub wet: f32,
+        pub dry: f32,
+        pub width: f32,
+        combs_left: Vec<Comb>,
+        combs_right: Vec<Comb>,
+        allpasses_left: Vec<Allpass>,
+        allpasses_right: Vec<Allpass>,
+    }
+
+    impl Reverb
+    {
+        ///Reverb::new(room_size: f32, damping: f32, wet: f32) -> Reverb:
+        ///Generates a reverb with full width and a unity dry signal.
+        pub fn new(room_size: f32, damping: f32, wet: f32) -> Reverb
+        {
+            Reverb{
+                room_size,
+                damping,
+                wet,
+                dry: 1.0,
+                width: 1.0,
+                combs_left: COMB_TUNING.iter().map(|x| Comb::new(scaled(*x))).collect(),
+                combs_right: COMB_TUNING.iter().map(|x| Comb::new(scaled(*x + STEREO_SPREAD))).collect(),
+                allpasses_left: ALLPASS_TUNING.iter().map(|x| Allpass::new(scaled(*x))).collect(),
+                allpasses_right: ALLPASS_TUNING.iter().map(|x| Allpass::new(scaled(*x + STEREO_SPREAD))).collect(),
+            }
+        }
+    }
+
+    impl Effect for Reverb
+    {
+        fn process(&mut self, buffer: &mut StereoBuffer)
+        {
+            let feedback = 0.7 + self.room_size * 0.28;
+            let damping = self.damping * 0.4;
+            let wet1 = self.wet * (self.width / 2.0 + 0.5);
+            let wet2 = self.wet * ((1.0 - self.width) / 2.0);
+
+            for i in 0..buffer.len()
+            {
+                let input = (buffer.left[i] + buffer.right[i]) * INPUT_GAIN;
+                let mut out_left = 0.0;
+                let mut out_right = 0.0;
+
+                for comb in self.combs_left.iter_mut()
+                {
+                    out_left += comb.tick(input, feedback, damping);
+                }
+                for comb in self.combs_right.iter_mut()
+                {
+                    out_right += comb.tick(input, feedback, damping);
+                }
+                for allpass in self.allpasses_left.iter_mut()
+                {
+                    out_left = allpass.tick(out_left);
+                }
+                for allpass in self.allpasses_right.iter_mut()
+                {
+                    out_right = allpass.tick(out_right);
+                }
+
+                buffer.left[i] = out_left * wet1 + out_right * wet2 + buffer.left[i] * self.dry;
+                buffer.right[i] = out_right * wet1 + out_left * wet2 + buffer.right[i] * self.dry;
+            }
+        }
+
+        fn reset(&mut self)
+        {
+            for comb in self.combs_left.iter_mut().chain(self.combs_right.iter_mut())
+            {
+                *comb = Comb::new(comb.buffer.len());
+            }
+            for allpass in self.allpasses_left.iter_mut().chain(self.allpasses_right.iter_mut())
+            {
+                *allpass = Allpass::new(allpass.buffer.len());
+            }
+        }
+
+        fn set_parameter(&mut self, name: &str, value: f32) -> bool
+        {
+            match name
+            {
+                "room_size" => self.room_size = value,
+                "damping" => self.damping = value,
+                "wet" => self.wet = value,
+                "dry" => self.dry = value,
+                "width" => self.width = value,
+                _ => return false,
+            }
+            true
+        }
+
+        fn name(&self) -> &str
+        {
+            "Reverb"
+        }
+
+        fn settings(&self) -> Option<EffectSettings>
+        {
+            Some(EffectSettings::Reverb{
+                room_size: self.room_size,
+                damping: self.damping,
+                wet: self.wet,
+                dry: self.dry,
+                width: self.width,
+            })
+        }
+    }
+}
+
+///Dynamics: Feed forward compressor with a stereo linked peak detector.
+#[allow(non_snake_case)]
+pub mod Dynamics
+{
+    use processing::buffer::buffer::AudioBuffer::SAMPLE_RATE;
+    use processing::buffer::buffer::AudioBuffer::StereoBuffer;
+    use processing::effects::effect::Effect::Effect;
+    use processing::effects::effect::Effect::EffectSettings;
+    use processing::mixing::mixer::MixerPrimitives;
+
+    ///time_to_coefficient(ms: f32) -> f32: One pole smoothing coefficient
+    ///for an attack or release time.
+    fn time_to_coefficient(ms: f32) -> f32
+    {
+        if ms <= 0.0
+        {
+            0.0
+        }
+        else
+        {
+            (-1.0 / (ms * 0.001 * SAMPLE_RATE as f32)).exp()
+        }
+    }
+
+    #[derive(Clone, Debug)]
+    pub struct Compressor
+    {
+        pub threshold_db: f32,
+        pub ratio: f32,
+        pub makeup_db: f32,
+        //Times as given, the coefficients are what processing uses.
+        attack_ms: f32,
+        release_ms: f32,
+        attack: f32,
+        release: f32,
+        envelope_db: f32,
+    }
+
+    impl Compressor
+    {
+        ///Compressor::new(threshold_db: f32, ratio: f32, attack_ms: f32, release_ms: f32) -> Compressor:
+        ///Generates a compressor with no makeup gain.
+        pub fn new(threshold_db: f32, ratio: f32, attack_ms: f32, release_ms: f32) -> Compressor
+        {
+            Compressor{
+                threshold_db,
+                ratio: if ratio < 1.0 { 1.0 } else { ratio },
+                makeup_db: 0.0,
+                attack_ms,
+                release_ms,
+                attack: time_to_coefficient(attack_ms),
+                release: time_to_coefficient(release_ms),
+                envelope_db: -120.0,
+            }
+        }
+
+        ///Compressor.set_times(attack_ms: f32, release_ms: f32): Changes
+        ///how fast the gain reduction reacts.
+        pub fn set_times(&mut self, attack_ms: f32, release_ms: f32)
+        {
+            self.attack_ms = attack_ms;
+            self.release_ms = release_ms;
+            self.attack = time_to_coefficient(attack_ms);
+            self.release = time_to_coefficient(release_ms);
+        }
+
+        ///Compressor.gain_reduction_db() -> f32: Current amount of gain
+        ///reduction, useful for metering.
+        pub fn gain_reduction_db(&self) -> f32
+        {
+            let over = self.envelope_db - self.threshold_db;
+            if over > 0.0 { over - over / self.ratio } else { 0.0 }
+        }
+    }
+
+    impl Effect for Compressor
+    {
+        fn process(&mut self, buffer: &mut StereoBuffer)
+        {
+            let makeup = MixerPrimitives::db_to_gain(self.makeup_db);
+            for i in 0..buffer.len()
+            {
+                let peak = buffer.left[i].abs().max(buffer.right[i].abs());
+                let level = MixerPrimitives::gain_to_db(peak);
+                let coefficient = if level > self.envelope_db { self.attack } else { self.release };
+                self.envelope_db = level + coefficient * (self.envelope_db - level);
+
+                let gain = MixerPrimitives::db_to_gain(-self.gain_reduction_db()) * makeup;
+                buffer.left[i] *= gain;
+                buffer.right[i] *= gain;
+            }
+        }
+
+        fn reset(&mut self)
+        {
+            self.envelope_db = -120.0;
+        }
+
+        fn set_parameter(&mut self, name: &str, value: f32) -> bool
+        {
+            match name
+            {
+                "threshold" => self.threshold_db = value,
+                "ratio" => self.ratio = if value < 1.0 { 1.0 } else { value },
+                "makeup" => self.makeup_db = value,
+                _ => return false,
+            }
+            true
+        }
+
+        fn name(&self) -> &str
+        {
+            "Compressor"
+        }
+
+        fn settings(&self) -> Option<EffectSettings>
+        {
+            Some(EffectSettings::Compressor{
+                threshold_db: self.threshold_db,
+                ratio: self.ratio,
+                attack_ms: self.attack_ms,
+                release_ms: self.release_ms,
+                makeup_db: self.makeup_db,
+            })
+        }
+    }
+}
+
+///Distortion: Waveshaping effects.
+#[allow(non_snake_case)]
+pub mod Distortion
+{
+    use processing::buffer::buffer::AudioBuffer::StereoBuffer;
+    use processing::effects::effect::Effect::Effect;
+    use processing::effects::effect::Effect::EffectSettings;
+
+    ///SoftClip: tanh saturation. drive is the linear input gain, mix goes
+    ///from 0.0 (dry) to 1.0 (fully clipped) and output is the final gain.
+    #[derive(Clone, Debug)]
+    pub struct SoftClip
+    {
+        pub drive: f32,
+        pub mix: f32,
+        pub output: f32,
+    }
+
+    impl SoftClip
+    {
+        ///SoftClip::new(drive: f32) -> SoftClip: Fully wet clipper whose
+        ///output gain compensates for the drive.
+        pub fn new(drive: f32) -> SoftClip
+        {
+            let drive = if drive < 1.0 { 1.0 } else { drive };
+            SoftClip{
+                drive,
+                mix: 1.0,
+                output: 1.0 / drive.tanh(),
+            }
+        }
+
+        //The output gain brings full scale back to full scale, so anything
+        //hotter than that is held at full scale instead of going over it.
+        fn shape(&self, x: f32) -> f32
+        {
+            let wet = ((x * self.drive).tanh() * self.output).clamp(-1.0, 1.0);
+            wet * self.mix + x * (1.0 - self.mix)
+        }
+    }
+
+    impl Effect for SoftClip
+    {
+        fn process(&mut self, buffer: &mut StereoBuffer)
+        {
+            for i in 0..buffer.len()
+            {
+                buffer.left[i] = self.shape(buffer.left[i]);
+                buffer.right[i] = self.shape(buffer.right[i]);
+            }
+        }
+
+        fn set_parameter(&mut self, name: &str, value: f32) -> bool
+        {
+            match name
+            {
+                "drive" =>
+                {
+                    //Keeps the compensation SoftClip::new sets up.
+                    self.drive = if value < 1.0 { 1.0 } else { value };
+                    self.output = 1.0 / self.drive.tanh();
+                },
+                "mix" => self.mix = value,
+                "output" => self.output = value,
+                _ => return false,
+            }
+            true
+        }
+
+        fn name(&self) -> &str
+        {
+            "SoftClip"
+        }
+
+        fn settings(&self) -> Option<EffectSettings>
+        {
+            Some(EffectSettings::SoftClip{ drive: self.drive, mix: self.mix, output: self.output })
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use processing::buffer::buffer::AudioBuffer::SAMPLE_RATE;
+    use processing::buffer::buffer::AudioBuffer::StereoBuffer;
+    use processing::effects::effect::Delay::Delay;
+    use processing::effects::effect::Distortion::SoftClip;
+    use processing::effects::effect::Dynamics::Compressor;
+    use processing::effects::effect::Effect::Effect;
+    use processing::effects::effect::Effect::EffectChain;
+    use processing::effects::effect::Effect::EffectSettings;
+    use processing::effects::effect::Filter::Biquad;
+    use processing::effects::effect::Filter::FilterType;
+
+    fn impulse(frames: usize) -> StereoBuffer
+    {
+        let mut buffer = StereoBuffer::new(frames);
+        buffer.left[0] = 1.0;
+        buffer.right[0] = 1.0;
+        buffer
+    }
+
+    #[test]
+    fn lowpass_impulse_response_keeps_dc()
+    {
+        let mut filter = Biquad::new(FilterType::LowPass, 1000.0, 0.707);
+        let mut buffer = impulse(4096);
+        filter.process(&mut buffer);
+        let sum: f32 = buffer.left.iter().sum();
+        assert!((sum - 1.0).abs() < 0.01, "dc gain {}", sum);
+        assert!(buffer.left[4095].abs() < 1e-6);
+        assert_eq!(buffer.left, buffer.right);
+    }
+
+    #[test]
+    fn highpass_impulse_response_removes_dc()
+    {
+        let mut filter = Biquad::new(FilterType::HighPass, 1000.0, 0.707);
+        let mut buffer = impulse(4096);
+        filter.process(&mut buffer);
+        let sum: f32 = buffer.left.iter().sum();
+        assert!(sum.abs() < 0.01, "dc gain {}", sum);
+    }
+
+    #[test]
+    fn delay_impulse_response_repeats_with_feedback()
+    {
+        let frames = (SAMPLE_RATE / 100) as usize;
+        let mut delay = Delay::new(10, 0.5, 1.0);
+        let mut buffer = impulse(frames * 3 + 1);
+        delay.process(&mut buffer);
+        for (i, x) in buffer.left.iter().enumerate()
+        {
+            let expected = match i
+            {
+                i if i == frames => 1.0,
+                i if i == frames * 2 => 0.5,
+                i if i == frames * 3 => 0.25,
+                _ => 0.0,
+            };
+            assert!((x - expected).abs() < 1e-6, "frame {}: {}", i, x);
+        }
+    }
+
+    #[test]
+    fn compressor_reduces_gain_above_threshold()
+    {
+        let mut compressor = Compressor::new(-20.0, 4.0, 0.0, 100.0);
+        let mut buffer = StereoBuffer::new(64);
+        for i in 0..64
+        {
+            buffer.left[i] = 1.0;
+            buffer.right[i] = 1.0;
+        }
+        compressor.process(&mut buffer);
+        assert!((compressor.gain_reduction_db() - 15.0).abs() < 0.01);
+        let expected = 10f32.powf(-15.0 / 20.0);
+        assert!((buffer.left[63] - expected).abs() < 1e-3, "{}", buffer.left[63]);
+    }
+
+    #[test]
+    fn compressor_leaves_quiet_signal_alone()
+    {
+        let mut compressor = Compressor::new(-20.0, 4.0, 0.0, 100.0);
+        let mut buffer = StereoBuffer::new(64);
+        for i in 0..64
+        {
+            buffer.left[i] = 0.05;
+            buffer.right[i] = 0.05;
+        }
+        compressor.process(&mut buffer);
+        assert_eq!(compressor.gain_reduction_db(), 0.0);
+        assert!((buffer.left[63] - 0.05).abs() < 1e-6);
+    }
+
+    #[test]
+    fn softclip_stays_within_full_scale()
+    {
+        for drive in [1.0, 2.0, 8.0].iter()
+        {
+            let mut clip = SoftClip::new(*drive);
+            let mut buffer = StereoBuffer::new(801);
+            for i in 0..801
+            {
+                buffer.left[i] = (i as f32 - 400.0) / 100.0;
+                buffer.right[i] = -buffer.left[i];
+            }
+            clip.process(&mut buffer);
+            assert!(buffer.peak() <= 1.0, "drive {} peaks at {}", drive, buffer.peak());
+            assert!((buffer.left[500] - 1.0).abs() < 1e-6);
+        }
+    }
+
+    #[test]
+    fn softclip_drive_automation_keeps_the_output_level()
+    {
+        let mut clip = SoftClip::new(2.0);
+        assert!(clip.set_parameter("drive", 8.0));
+        assert_eq!(clip.output, SoftClip::new(8.0).output);
+        let mut buffer = StereoBuffer::new(1);
+        buffer.left[0] = 1.0;
+        clip.process(&mut buffer);
+        assert!((buffer.left[0] - 1.0).abs() < 1e-6);
+    }
+
+    #[test]
+    fn effects_are_built_from_their_settings()
+    {
+        let settings = vec![
+            EffectSettings::Biquad{ kind: FilterType::Peak(-4.0), frequency: 800.0, q: 2.0 },
+            EffectSettings::Equalizer{ bands: vec![(FilterType::HighPass, 40.0, 0.707)] },
+            EffectSettings::Delay{ time_ms: 7, feedback: 0.5, mix: 0.25, ping_pong: true },
+            EffectSettings::Reverb{ room_size: 0.5, damping: 0.5, wet: 0.3, dry: 0.8, width: 0.6 },
+            EffectSettings::Compressor{ threshold_db: -12.0, ratio: 3.0, attack_ms: 2.0, release_ms: 150.0, makeup_db: 1.5 },
+            EffectSettings::SoftClip{ drive: 4.0, mix: 0.7, output: 1.2 },
+        ];
+        let mut chain = EffectChain::new();
+        for i in settings.iter()
+        {
+            assert_eq!(i.build().settings().as_ref(), Some(i));
+            chain.push(i.build());
+        }
+        assert_eq!(chain.settings(), Ok(settings));
+        let mut compressor = Compressor::new(0.0, 2.0, 1.0, 1.0);
+        compressor.set_times(10.0, 20.0);
+        assert!(matches!(compressor.settings(), Some(EffectSettings::Compressor{ attack_ms, release_ms, .. }) if attack_ms == 10.0 && release_ms == 20.0));
+    }
+
+    #[test]
+    fn empty_chain_passes_audio_through()
+    {
+        let mut chain = EffectChain::new();
+        let mut buffer = StereoBuffer::new(16);
+        for i in 0..16
+        {
+            buffer.left[i] = i as f32 * 0.1 - 0.8;
+            buffer.right[i] = 0.3;
+        }
+        let original = buffer.clone();
+        chain.process(&mut buffer);
+        assert_eq!(buffer.left, original.left);
+        assert_eq!(buffer.right, original.right);
+    }
+}
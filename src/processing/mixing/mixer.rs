@@ -0,0 +1,583 @@
+///MixerPrimitives: Small values that describe routing inside of the
+///mixer: where a channel outputs to, where a send taps its signal and
+///the pan law that is used for every strip.
+#[allow(non_snake_case)]
+pub mod MixerPrimitives
+{
+    use serde::Deserialize;
+    use serde::Serialize;
+    use std::f32::consts::PI;
+
+    ///Destination: Where a track or sub-group sums its post fader signal.
+    #[derive(Clone, Copy, Debug, Eq, PartialEq, Serialize, Deserialize)]
+    pub enum Destination
+    {
+        Master,
+        Group(usize),
+    }
+
+    ///SendPosition: Whether an aux send is tapped before or after the
+    ///channel fader (gain, pan and mute).
+    #[derive(Clone, Copy, Debug, Eq, PartialEq, Serialize, Deserialize)]
+    pub enum SendPosition
+    {
+        PreFader,
+        PostFader,
+    }
+
+    ///AuxSend: A single aux send from a channel to a shared return bus.
+    #[derive(Clone, Copy, Debug, Serialize, Deserialize)]
+    pub struct AuxSend
+    {
+        pub bus: usize,
+        pub level: f32,
+        pub position: SendPosition,
+    }
+
+    ///pan_to_gains(pan: f32) -> (f32, f32): Constant power pan law. pan
+    ///goes from -1.0 (hard left) to 1.0 (hard right) and returns the left
+    ///and right gain as a tuple.
+    pub fn pan_to_gains(pan: f32) -> (f32, f32)
+    {
+        let clamped = pan.clamp(-1.0, 1.0);
+        let angle = (clamped + 1.0) * PI / 4.0;
+        (angle.cos(), angle.sin())
+    }
+
+    ///db_to_gain(db: f32) -> f32: Converts decibels into a linear gain.
+    pub fn db_to_gain(db: f32) -> f32
+    {
+        10f32.powf(db / 20.0)
+    }
+
+    ///gain_to_db(gain: f32) -> f32: Converts a linear gain into decibels.
+    pub fn gain_to_db(gain: f32) -> f32
+    {
+        20.0 * gain.max(1e-9).log10()
+    }
+}
+
+///Channels: The strips that make up the mixer. Tracks take the output of
+///a PartialSequencer, sub-groups sum tracks together before the master and
+///return buses receive aux sends so several tracks can share one effect.
+#[allow(non_snake_case)]
+pub mod Channels
+{
+    use processing::effects::effect::Effect::EffectChain;
+    use processing::mixing::mixer::MixerPrimitives::Destination;
+    use processing::mixing::mixer::MixerPrimitives::AuxSend;
+    use processing::mixing::mixer::MixerPrimitives::SendPosition;
+
+    ///ChannelStrip: Insert effects, gain, pan, mute and solo for a single
+    ///track along with its aux sends and its output destination.
+    #[derive(Debug)]
+    pub struct ChannelStrip
+    {
+        pub inserts: EffectChain,
+        pub gain: f32,
+        pub pan: f32,
+        pub mute: bool,
+        pub solo: bool,
+        pub sends: Vec<AuxSend>,
+        pub output: Destination,
+    }
+
+    impl ChannelStrip
+    {
+        ///ChannelStrip::new() -> ChannelStrip: Unity gain, centered strip
+        ///that outputs straight to the master bus.
+        pub fn new() -> ChannelStrip
+        {
+            ChannelStrip{
+                inserts: EffectChain::new(),
+                gain: 1.0,
+                pan: 0.0,
+                mute: false,
+                solo: false,
+                sends: Vec::new(),
+                output: Destination::Master,
+            }
+        }
+
+        ///ChannelStrip.send(bus: usize, level: f32, position: SendPosition):
+        ///Adds an aux send to the return bus at index bus, or updates the
+        ///level and position of the send if one already exists.
+        pub fn send(&mut self, bus: usize, level: f32, position: SendPosition)
+        {
+            for i in self.sends.iter_mut()
+            {
+                if i.bus == bus
+                {
+                    i.level = level;
+                    i.position = position;
+                    return;
+                }
+            }
+            self.sends.push(AuxSend{ bus, level, position });
+        }
+
+        ///ChannelStrip.remove_send(bus: usize): Removes the send to bus if
+        ///it exists.
+        pub fn remove_send(&mut self, bus: usize)
+        {
+            self.sends.retain(|x| x.bus != bus);
+        }
+    }
+
+    impl Default for ChannelStrip
+    {
+        fn default() -> ChannelStrip
+        {
+            ChannelStrip::new()
+        }
+    }
+
+    ///GroupBus: A sub-group that sums every track routed into it, applies
+    ///its own fader and sends, and then outputs into the master bus.
+    #[derive(Debug)]
+    pub struct GroupBus
+    {
+        pub inserts: EffectChain,
+        pub gain: f32,
+        pub pan: f32,
+        pub mute: bool,
+        pub sends: Vec<AuxSend>,
+    }
+
+    impl GroupBus
+    {
+        ///GroupBus::new() -> GroupBus: Unity gain, centered group bus.
+        pub fn new() -> GroupBus
+        {
+            GroupBus{
+                inserts: EffectChain::new(),
+                gain: 1.0,
+                pan: 0.0,
+                mute: false,
+                sends: Vec::new(),
+            }
+        }
+    }
+
+    impl Default for GroupBus
+    {
+        fn default() -> GroupBus
+        {
+            GroupBus::new()
+        }
+    }
+
+    ///ReturnBus: Shared bus that receives aux sends, usually holding a
+    ///reverb or delay in its inserts. Returns always output into the
+    ///master bus and are not affected by track solo.
+    #[derive(Debug)]
+    pub struct ReturnBus
+    {
+        pub inserts: EffectChain,
+        pub gain: f32,
+        pub pan: f32,
+        pub mute: bool,
+    }
+
+    impl ReturnBus
+    {
+        ///ReturnBus::new() -> ReturnBus: Unity gain, centered return bus.
+        pub fn new() -> ReturnBus
+        {
+            ReturnBus{
+                inserts: EffectChain::new(),
+                gain: 1.0,
+                pan: 0.0,
+                mute: false,
+            }
+        }
+    }
+
+    impl Default for ReturnBus
+    {
+        fn default() -> ReturnBus
+        {
+            ReturnBus::new()
+        }
+    }
+}
+
+///Mixer: Sums a set of track buffers through sub-groups and return buses
+///into a single master buffer. Each PartialSequencer in a session maps to
+///one track, in the order it was added.
+#[allow(non_snake_case)]
+pub mod Mixer
+{
+    use processing::buffer::buffer::AudioBuffer::StereoBuffer;
+    use processing::effects::effect::Effect::EffectChain;
+    use processing::mixing::mixer::Channels::ChannelStrip;
+    use processing::mixing::mixer::Channels::GroupBus;
+    use processing::mixing::mixer::Channels::ReturnBus;
+    use processing::mixing::mixer::MixerPrimitives;
+    use processing::mixing::mixer::MixerPrimitives::Destination;
+    use processing::mixing::mixer::MixerPrimitives::AuxSend;
+    use processing::mixing::mixer::MixerPrimitives::SendPosition;
+
+    pub struct Mixer
+    {
+        tracks: Vec<ChannelStrip>,
+        groups: Vec<GroupBus>,
+        returns: Vec<ReturnBus>,
+        pub master_inserts: EffectChain,
+        pub master_gain: f32,
+        group_buffers: Vec<StereoBuffer>,
+        return_buffers: Vec<StereoBuffer>,
+        pre: StereoBuffer,
+        scratch: StereoBuffer,
+    }
+
+    ///apply_sends(...): Sums source into every return buffer referenced
+    ///by sends. The pre fader signal is used for PreFader sends, the post
+    ///fader signal otherwise.
+    fn apply_sends(sends: &[AuxSend], pre: &StereoBuffer, post: &StereoBuffer, returns: &mut [StereoBuffer])
+    {
+        for i in sends
+        {
+            if let Some(bus) = returns.get_mut(i.bus)
+            {
+                match i.position
+                {
+                    SendPosition::PreFader => bus.mix_from(pre, i.level, i.level),
+                    SendPosition::PostFader => bus.mix_from(post, i.level, i.level),
+                }
+            }
+        }
+    }
+
+    impl Mixer
+    {
+        ///Mixer::new() -> Mixer: Generates an empty mixer with a unity gain
+        ///master bus.
+        pub fn new() -> Mixer
+        {
+            Mixer{
+                tracks: Vec::new(),
+                groups: Vec::new(),
+                returns: Vec::new(),
+                master_inserts: EffectChain::new(),
+                master_gain: 1.0,
+                group_buffers: Vec::new(),
+                return_buffers: Vec::new(),
+                pre: StereoBuffer::new(0),
+                scratch: StereoBuffer::new(0),
+            }
+        }
+
+        ///Mixer.add_track() -> usize: Adds a new channel strip and returns
+        ///its index.
+        pub fn add_track(&mut self) -> usize
+        {
+            self.tracks.push(ChannelStrip::new());
+            self.tracks.len() - 1
+        }
+
+        ///Mixer.add_group() -> usize: Adds a new sub-group and returns its
+        ///index.
+        pub fn add_group(&mut self) -> usize
+        {
+            self.groups.push(GroupBus::new());
+            self.group_buffers.push(StereoBuffer::new(0));
+            self.groups.len() - 1
+        }
+
+        ///Mixer.add_return() -> usize: Adds a new return bus and returns its
+        ///index.
+        pub fn add_return(&mut self) -> usize
+        {
+            self.returns.push(ReturnBus::new());
+            self.return_buffers.push(StereoBuffer::new(0));
+            self.returns.len() - 1
+        }
+
+        ///Mixer.track(index: usize) -> Option<&mut ChannelStrip>: Access to
+        ///a channel strip for changing gain, pan, sends, etc.
+        pub fn track(&mut self, index: usize) -> Option<&mut ChannelStrip>
+        {
+            self.tracks.get_mut(index)
+        }
+
+        ///Mixer.group(index: usize) -> Option<&mut GroupBus>: Access to a
+        ///sub-group.
+        pub fn group(&mut self, index: usize) -> Option<&mut GroupBus>
+        {
+            self.groups.get_mut(index)
+        }
+
+        ///Mixer.return_bus(index: usize) -> Option<&mut ReturnBus>: Access
+        ///to a return bus.
+        pub fn return_bus(&mut self, index: usize) -> Option<&mut ReturnBus>
+        {
+            self.returns.get_mut(index)
+        }
+
+        ///Mixer.track_count() -> usize: Amount of channel strips.
+        pub fn track_count(&self) -> usize
+        {
+            self.tracks.len()
+        }
+
+        ///Mixer.route(track: usize, destination: Destination) -> bool: Sets
+        ///the output of a track. Returns false if either the track or the
+        ///group does not exist.
+        pub fn route(&mut self, track: usize, destination: Destination) -> bool
+        {
+            match destination
+            {
+                Destination::Group(x) if x >= self.groups.len() =>
+                {
+                    return false;
+                },
+                _ =>
+                {
+                },
+            }
+            match self.tracks.get_mut(track)
+            {
+                Some(strip) =>
+                {
+                    strip.output = destination;
+                    true
+                },
+                None =>
+                {
+                    false
+                }
+            }
+        }
+
+        ///Mixer.is_soloing() -> bool: Whether any track currently has solo
+        ///enabled, in which case all non soloed tracks are silenced.
+        pub fn is_soloing(&self) -> bool
+        {
+            self.tracks.iter().any(|x| x.solo)
+        }
+
+        ///Mixer.process(inputs: &[StereoBuffer], output: &mut StereoBuffer):
+        ///Mixes one block. inputs[n] is the audio for track n; tracks without
+        ///an input are treated as silent. Insert effects run before the
+        ///fader, so pre fader sends hear them. output is overwritten with
+        ///the master bus.
+        pub fn process(&mut self, inputs: &[StereoBuffer], output: &mut StereoBuffer)
+        {
+            let frames = output.len();
+            let soloing = self.is_soloing();
+
+            output.clear();
+            self.pre.resize(frames);
+            self.scratch.resize(frames);
+            for i in self.group_buffers.iter_mut().chain(self.return_buffers.iter_mut())
+            {
+                i.resize(frames);
+                i.clear();
+            }
+
+            for (index, strip) in self.tracks.iter_mut().enumerate()
+            {
+                let input = match inputs.get(index)
+                {
+                    Some(x) => x,
+                    None => continue,
+                };
+                //Mute and solo act on the fader, so a silenced strip still
+                //feeds its pre fader sends.
+                let silenced = strip.mute || (soloing && !strip.solo);
+
+                //Inputs shorter than the block leave the rest silent.
+                self.pre.clear();
+                self.pre.copy_from(input);
+                strip.inserts.process(&mut self.pre);
+
+                let (left, right) = MixerPrimitives::pan_to_gains(strip.pan);
+                self.scratch.clear();
+                if !silenced
+                {
+                    self.scratch.mix_from(&self.pre, left * strip.gain, right * strip.gain);
+                }
+                apply_sends(&strip.sends, &self.pre, &self.scratch, &mut self.return_buffers);
+                if silenced
+                {
+                    continue;
+                }
+
+                match strip.output
+                {
+                    Destination::Group(x) if x < self.group_buffers.len() =>
+                    {
+                        self.group_buffers[x].mix_from(&self.scratch, 1.0, 1.0);
+                    },
+                    _ =>
+                    {
+                        output.mix_from(&self.scratch, 1.0, 1.0);
+                    },
+                }
+            }
+
+            for (index, group) in self.groups.iter_mut().enumerate()
+            {
+                //A muted group is only heard through its pre fader sends,
+                //so without any its inserts have nothing to do.
+                if group.mute && !group.sends.iter().any(|x| x.position == SendPosition::PreFader)
+                {
+                    continue;
+                }
+                group.inserts.process(&mut self.group_buffers[index]);
+                let (left, right) = MixerPrimitives::pan_to_gains(group.pan);
+                self.scratch.clear();
+                if !group.mute
+                {
+                    self.scratch.mix_from(&self.group_buffers[index], left * group.gain, right * group.gain);
+                }
+                apply_sends(&group.sends, &self.group_buffers[index], &self.scratch, &mut self.return_buffers);
+                if !group.mute
+                {
+                    output.mix_from(&self.scratch, 1.0, 1.0);
+                }
+            }
+
+            for (index, bus) in self.returns.iter_mut().enumerate()
+            {
+                if bus.mute
+                {
+                    continue;
+                }
+                bus.inserts.process(&mut self.return_buffers[index]);
+                let (left, right) = MixerPrimitives::pan_to_gains(bus.pan);
+                output.mix_from(&self.return_buffers[index], left * bus.gain, right * bus.gain);
+            }
+
+            self.master_inserts.process(output);
+            output.scale(self.master_gain, self.master_gain);
+        }
+    }
+
+    impl Default for Mixer
+    {
+        fn default() -> Mixer
+        {
+            Mixer::new()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use processing::buffer::buffer::AudioBuffer::StereoBuffer;
+    use processing::effects::effect::Effect::Effect;
+    use processing::mixing::mixer::Mixer::Mixer;
+    use processing::mixing::mixer::MixerPrimitives::AuxSend;
+    use processing::mixing::mixer::MixerPrimitives::Destination;
+    use processing::mixing::mixer::MixerPrimitives::SendPosition;
+    use std::sync::Arc;
+    use std::sync::atomic::AtomicUsize;
+    use std::sync::atomic::Ordering;
+
+    //Counts the blocks it is given.
+    struct Counter(Arc<AtomicUsize>);
+
+    impl Effect for Counter
+    {
+        fn process(&mut self, _buffer: &mut StereoBuffer)
+        {
+            self.0.fetch_add(1, Ordering::SeqCst);
+        }
+
+        fn name(&self) -> &str
+        {
+            "counter"
+        }
+    }
+
+    fn ones(frames: usize) -> StereoBuffer
+    {
+        let mut ret = StereoBuffer::new(frames);
+        for i in 0..frames
+        {
+            ret.left[i] = 1.0;
+            ret.right[i] = 1.0;
+        }
+        ret
+    }
+
+    //One track with a send into a unity return, so everything that reaches
+    //the master comes through the return.
+    fn send_only(position: SendPosition) -> Mixer
+    {
+        let mut mixer = Mixer::new();
+        let track = mixer.add_track();
+        let bus = mixer.add_return();
+        let strip = mixer.track(track).unwrap();
+        strip.send(bus, 0.5, position);
+        strip.mute = true;
+        mixer
+    }
+
+    #[test]
+    fn muted_track_keeps_pre_fader_sends()
+    {
+        let mut mixer = send_only(SendPosition::PreFader);
+        let mut output = StereoBuffer::new(4);
+        mixer.process(&[ones(4)], &mut output);
+        assert!(output.peak() > 0.3);
+    }
+
+    #[test]
+    fn muted_track_silences_post_fader_sends()
+    {
+        let mut mixer = send_only(SendPosition::PostFader);
+        let mut output = StereoBuffer::new(4);
+        mixer.process(&[ones(4)], &mut output);
+        assert_eq!(output.peak(), 0.0);
+    }
+
+    #[test]
+    fn solo_silences_other_tracks()
+    {
+        let mut mixer = Mixer::new();
+        mixer.add_track();
+        let soloed = mixer.add_track();
+        mixer.track(soloed).unwrap().solo = true;
+        let mut output = StereoBuffer::new(4);
+        mixer.process(&[ones(4), StereoBuffer::new(4)], &mut output);
+        assert_eq!(output.peak(), 0.0);
+    }
+
+    #[test]
+    fn short_inputs_do_not_repeat_the_last_block()
+    {
+        let mut mixer = Mixer::new();
+        mixer.add_track();
+        let mut output = StereoBuffer::new(4);
+        mixer.process(&[ones(4)], &mut output);
+        mixer.process(&[ones(2)], &mut output);
+        assert_eq!(output.left[1], output.left[0]);
+        assert_eq!((output.left[2], output.right[3]), (0.0, 0.0));
+    }
+
+    #[test]
+    fn muted_groups_skip_inserts_unless_a_pre_fader_send_needs_them()
+    {
+        let blocks = Arc::new(AtomicUsize::new(0));
+        let mut mixer = Mixer::new();
+        let track = mixer.add_track();
+        let group = mixer.add_group();
+        let bus = mixer.add_return();
+        mixer.route(track, Destination::Group(group));
+        mixer.group(group).unwrap().inserts.push(Box::new(Counter(blocks.clone())));
+        mixer.group(group).unwrap().mute = true;
+        let mut output = StereoBuffer::new(4);
+        mixer.process(&[ones(4)], &mut output);
+        assert_eq!(blocks.load(Ordering::SeqCst), 0);
+        assert_eq!(output.peak(), 0.0);
+
+        mixer.group(group).unwrap().sends.push(AuxSend{ bus, level: 1.0, position: SendPosition::PreFader });
+        mixer.process(&[ones(4)], &mut output);
+        assert_eq!(blocks.load(Ordering::SeqCst), 1);
+        assert!(output.peak() > 0.25);
+    }
+}
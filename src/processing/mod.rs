@@ -0,0 +1,7 @@
+pub mod automation;
+pub mod backend;
+pub mod buffer;
+pub mod effects;
+pub mod mixing;
+pub mod sample;
+pub mod synthesis;
@@ -0,0 +1,625 @@
+///SampleData: Decoded audio held in memory, independent of the file
+///format it was read from.
+#[allow(non_snake_case)]
+pub mod SampleData
+{
+    use std::fmt::Display;
+    use std::fmt::Error;
+    use std::fmt::Formatter;
+    use std::io;
+
+    ///SampleError: Reason a sample could not be loaded.
+    #[derive(Debug)]
+    pub enum SampleError
+    {
+        Io(io::Error),
+        Format(String),
+    }
+
+    impl Display for SampleError
+    {
+        fn fmt(&self, f: &mut Formatter) -> Result<(), Error>
+        {
+            match *self
+            {
+                SampleError::Io(ref e) => write!(f, "io error: {}", e),
+                SampleError::Format(ref s) => write!(f, "format error: {}", s),
+            }
+        }
+    }
+
+    impl From<io::Error> for SampleError
+    {
+        fn from(e: io::Error) -> SampleError
+        {
+            SampleError::Io(e)
+        }
+    }
+
+    ///SampleData: channels holds one Vec of samples (-1.0 to 1.0) per
+    ///channel. loop_points are the start and end frame of a loop stored in
+    ///the file itself, if any.
+    #[derive(Clone, Debug)]
+    pub struct SampleData
+    {
+        pub channels: Vec<Vec<f32>>,
+        pub sample_rate: u32,
+        pub loop_points: Option<(usize, usize)>,
+    }
+
+    impl SampleData
+    {
+        ///SampleData::new(channels: Vec<Vec<f32>>, sample_rate: u32) -> SampleData
+        pub fn new(channels: Vec<Vec<f32>>, sample_rate: u32) -> SampleData
+        {
+            SampleData{
+                channels,
+                sample_rate,
+                loop_points: None,
+            }
+        }
+
+        ///SampleData.frames() -> usize: Length of the sample in frames.
+        pub fn frames(&self) -> usize
+        {
+            self.channels.first().map(|x| x.len()).unwrap_or(0)
+        }
+
+        ///SampleData.frame(index: usize) -> (f32, f32): Stereo frame at index.
+        ///Mono samples are copied to both sides, frames past the end are
+        ///silent.
+        pub fn frame(&self, index: usize) -> (f32, f32)
+        {
+            match self.channels.len()
+            {
+                0 => (0.0, 0.0),
+                1 =>
+                {
+                    let x = self.channels[0].get(index).cloned().unwrap_or(0.0);
+                    (x, x)
+                },
+                _ =>
+                {
+                    (self.channels[0].get(index).cloned().unwrap_or(0.0),
+                     self.channels[1].get(index).cloned().unwrap_or(0.0))
+                },
+            }
+        }
+    }
+}
+
+///Decoding: Shared helpers for pulling PCM data out of byte slices.
+#[allow(non_snake_case)]
+pub mod Decoding
+{
+    use processing::sample::sample::SampleData::SampleError;
+
+    ///Endian: Byte order of the PCM data.
+    #[derive(Clone, Copy, Debug, Eq, PartialEq)]
+    pub enum Endian
+    {
+        Little,
+        Big,
+    }
+
+    pub fn u16_at(data: &[u8], at: usize, endian: Endian) -> Option<u16>
+    {
+        let b = data.get(at..at + 2)?;
+        Some(match endian
+        {
+            Endian::Little => (b[0] as u16) | ((b[1] as u16) << 8),
+            Endian::Big => (b[1] as u16) | ((b[0] as u16) << 8),
+        })
+    }
+
+    pub fn u32_at(data: &[u8], at: usize, endian: Endian) -> Option<u32>
+    {
+        let b = data.get(at..at + 4)?;
+        Some(match endian
+        {
+            Endian::Little => (b[0] as u32) | ((b[1] as u32) << 8) | ((b[2] as u32) << 16) | ((b[3] as u32) << 24),
+            Endian::Big => (b[3] as u32) | ((b[2] as u32) << 8) | ((b[1] as u32) << 16) | ((b[0] as u32) << 24),
+        })
+    }
+
+    ///decode(...) -> Result<Vec<Vec<f32>>, SampleError>: Splits interleaved
+    ///PCM into channels. bits is the sample width; float selects IEEE
+    ///float decoding for 32 and 64 bit data. 8 bit data is unsigned when
+    ///unsigned_8 is set (WAV) and signed otherwise (AIFF).
+    pub fn decode(data: &[u8], channels: usize, bits: u32, float: bool, endian: Endian, unsigned_8: bool) -> Result<Vec<Vec<f32>>, SampleError>
+    {
+        if channels == 0
+        {
+            return Err(SampleError::Format("sample has no channels".to_string()));
+        }
+        let width = bits.div_ceil(8) as usize;
+        if width == 0 || width > 8
+        {
+            return Err(SampleError::Format(format!("unsupported bit depth {}", bits)));
+        }
+        let frames = data.len() / (width * channels);
+        let mut ret = vec![Vec::with_capacity(frames); channels];
+
+        for frame in 0..frames
+        {
+            for (channel, samples) in ret.iter_mut().enumerate()
+            {
+                let at = (frame * channels + channel) * width;
+                let mut bytes = [0u8; 8];
+                bytes[..width].copy_from_slice(&data[at..at + width]);
+                if endian == Endian::Big
+                {
+                    bytes[..width].reverse();
+                }
+                let raw = bytes.iter().enumerate().fold(0u64, |x, (i, b)| x | ((*b as u64) << (i * 8)));
+
+                let value = match (float, width)
+                {
+                    (true, 4) => f32::from_bits(raw as u32),
+                    (true, 8) => f64::from_bits(raw) as f32,
+                    (true, _) => return Err(SampleError::Format(format!("unsupported float width {}", bits))),
+                    (false, 1) if unsigned_8 => (raw as f32 - 128.0) / 128.0,
+                    (false, _) =>
+                    {
+                        let shift = 64 - width * 8;
+                        let signed = ((raw << shift) as i64) >> shift;
+                        signed as f32 / (1u64 << (width * 8 - 1)) as f32
+                    },
+                };
+                samples.push(value);
+            }
+        }
+        Ok(ret)
+    }
+}
+
+///Wav: Reader for RIFF WAVE files: integer PCM from 8 to 32 bits, 32/64
+///bit float, WAVE_FORMAT_EXTENSIBLE headers and loops from the smpl chunk.
+#[allow(non_snake_case)]
+pub mod Wav
+{
+    use processing::sample::sample::Decoding;
+    use processing::sample::sample::Decoding::Endian;
+    use processing::sample::sample::SampleData::SampleData;
+    use processing::sample::sample::SampleData::SampleError;
+    use std::fs::File;
+    use std::io::Read;
+
+    const FORMAT_PCM: u16 = 1;
+    const FORMAT_FLOAT: u16 = 3;
+    const FORMAT_EXTENSIBLE: u16 = 0xfffe;
+
+    ///Wav::header(channels: u16, sample_rate: u32, float: bool, frames: u32) -> Vec<u8>:
+    ///The RIFF, fmt and data chunk headers of a file holding frames of
+    ///16 bit PCM, or 32 bit float if float is set. The samples follow it
+    ///interleaved and little endian.
+    pub fn header(channels: u16, sample_rate: u32, float: bool, frames: u32) -> Vec<u8>
+    {
+        let (tag, bytes) = if float { (FORMAT_FLOAT, 4u16) } else { (FORMAT_PCM, 2u16) };
+        let block = channels as u32 * bytes as u32;
+        let size = frames.saturating_mul(block);
+
+        let mut ret = Vec::with_capacity(44);
+        ret.extend_from_slice(b"RIFF");
+        ret.extend_from_slice(&size.saturating_add(36).to_le_bytes());
+        ret.extend_from_slice(b"WAVEfmt ");
+        ret.extend_from_slice(&16u32.to_le_bytes());
+        ret.extend_from_slice(&tag.to_le_bytes());
+        ret.extend_from_slice(&channels.to_le_bytes());
+        ret.extend_from_slice(&sample_rate.to_le_bytes());
+        ret.extend_from_slice(&(sample_rate * block).to_le_bytes());
+        ret.extend_from_slice(&(block as u16).to_le_bytes());
+        ret.extend_from_slice(&(bytes * 8).to_le_bytes());
+        ret.extend_from_slice(b"data");
+        ret.extend_from_slice(&size.to_le_bytes());
+        ret
+    }
+
+    ///Wav::load(path: &str) -> Result<SampleData, SampleError>
+    pub fn load(path: &str) -> Result<SampleData, SampleError>
+    {
+        let mut data = Vec::new();
+        File::open(path)?.read_to_end(&mut data)?;
+        from_bytes(&data)
+    }
+
+    ///Wav::from_bytes(data: &[u8]) -> Result<SampleData, SampleError>
+    pub fn from_bytes(data: &[u8]) -> Result<SampleData, SampleError>
+    {
+        let bad = |s: &str| SampleError::Format(s.to_string());
+        if data.len() < 12 || &data[0..4] != b"RIFF" || &data[8..12] != b"WAVE"
+        {
+            return Err(bad("not a RIFF WAVE file"));
+        }
+
+        let mut format: Option<(u16, usize, u32, u32)> = None;
+        let mut pcm: Option<&[u8]> = None;
+        let mut loop_points = None;
+        let mut at = 12;
+
+        while at + 8 <= data.len()
+        {
+            let id = &data[at..at + 4];
+            let size = Decoding::u32_at(data, at + 4, Endian::Little).unwrap() as usize;
+            let body = &data[at + 8..(at + 8 + size).min(data.len())];
+
+            match id
+            {
+                b"fmt " =>
+                {
+                    let mut tag = Decoding::u16_at(body, 0, Endian::Little).ok_or_else(|| bad("short fmt chunk"))?;
+                    let channels = Decoding::u16_at(body, 2, Endian::Little).ok_or_else(|| bad("short fmt chunk"))? as usize;
+                    let rate = Decoding::u32_at(body, 4, Endian::Little).ok_or_else(|| bad("short fmt chunk"))?;
+                    let bits = Decoding::u16_at(body, 14, Endian::Little).ok_or_else(|| bad("short fmt chunk"))? as u32;
+                    if tag == FORMAT_EXTENSIBLE
+                    {
+                        tag = Decoding::u16_at(body, 24, Endian::Little).ok_or_else(|| bad("short extensible fmt chunk"))?;
+                    }
+                    format = Some((tag, channels, rate, bits));
+                },
+                b"data" =>
+                {
+                    pcm = Some(body);
+                },
+                b"smpl" =>
+                {
+                    let loops = Decoding::u32_at(body, 28, Endian::Little).unwrap_or(0);
+                    if loops > 0
+                    {
+                        if let (Some(start), Some(end)) = (Decoding::u32_at(body, 44, Endian::Little), Decoding::u32_at(body, 48, Endian::Little))
+                        {
+                            loop_points = Some((start as usize, end as usize + 1));
+                        }
+                    }
+                },
+                _ =>
+                {
+                },
+            }
+            at += 8 + size + (size & 1);
+        }
+
+        let (tag, channels, rate, bits) = format.ok_or_else(|| bad("missing fmt chunk"))?;
+        let pcm = pcm.ok_or_else(|| bad("missing data chunk"))?;
+        let float = match tag
+        {
+            FORMAT_PCM => false,
+            FORMAT_FLOAT => true,
+            _ => return Err(SampleError::Format(format!("unsupported wave format {}", tag))),
+        };
+
+        let mut sample = SampleData::new(Decoding::decode(pcm, channels, bits, float, Endian::Little, true)?, rate);
+        sample.loop_points = loop_points;
+        Ok(sample)
+    }
+}
+
+///Aiff: Reader for AIFF and uncompressed AIFF-C files (NONE, sowt, fl32
+///and fl64 compression types) including the sustain loop of the INST
+///chunk.
+#[allow(non_snake_case)]
+pub mod Aiff
+{
+    use processing::sample::sample::Decoding;
+    use processing::sample::sample::Decoding::Endian;
+    use processing::sample::sample::SampleData::SampleData;
+    use processing::sample::sample::SampleData::SampleError;
+    use std::fs::File;
+    use std::io::Read;
+
+    ///extended_to_f64(b: &[u8]) -> f64: Converts the 80 bit IEEE extended
+    ///float used for the AIFF sample rate.
+    fn extended_to_f64(b: &[u8]) -> f64
+    {
+        let sign = if b[0] & 0x80 != 0 { -1.0 } else { 1.0 };
+        let exponent = (((b[0] & 0x7f) as i32) << 8) | b[1] as i32;
+        let mantissa = b[2..10].iter().fold(0u64, |x, y| (x << 8) | *y as u64);
+        if exponent == 0 && mantissa == 0
+        {
+            return 0.0;
+        }
+        sign * mantissa as f64 * 2f64.powi(exponent - 16383 - 63)
+    }
+
+    ///Aiff::load(path: &str) -> Result<SampleData, SampleError>
+    pub fn load(path: &str) -> Result<SampleData, SampleError>
+    {
+        let mut data = Vec::new();
+        File::open(path)?.read_to_end(&mut data)?;
+        from_bytes(&data)
+    }
+
+    ///Aiff::from_bytes(data: &[u8]) -> Result<SampleData, SampleError>
+    pub fn from_bytes(data: &[u8]) -> Result<SampleData, SampleError>
+    {
+        let bad = |s: &str| SampleError::Format(s.to_string());
+        if data.len() < 12 || &data[0..4] != b"FORM" || (&data[8..12] != b"AIFF" && &data[8..12] != b"AIFC")
+        {
+            return Err(bad("not an AIFF file"));
+        }
+
+        let mut common: Option<(usize, u32, u32, [u8; 4])> = None;
+        let mut pcm: Option<&[u8]> = None;
+        let mut markers: Vec<(u16, u32)> = Vec::new();
+        let mut sustain_loop: Option<(u16, u16)> = None;
+        let mut at = 12;
+
+        while at + 8 <= data.len()
+        {
+            let id = &data[at..at + 4];
+            let size = Decoding::u32_at(data, at + 4, Endian::Big).unwrap() as usize;
+            let body = &data[at + 8..(at + 8 + size).min(data.len())];
+
+            match id
+            {
+                b"COMM" =>
+                {
+                    if body.len() < 18
+                    {
+                        return Err(bad("short COMM chunk"));
+                    }
+                    let channels = Decoding::u16_at(body, 0, Endian::Big).unwrap() as usize;
+                    let bits = Decoding::u16_at(body, 6, Endian::Big).unwrap() as u32;
+                    let rate = extended_to_f64(&body[8..18]) as u32;
+                    let mut compression = *b"NONE";
+                    if body.len() >= 22
+                    {
+                        compression.copy_from_slice(&body[18..22]);
+                    }
+                    common = Some((channels, bits, rate, compression));
+                },
+                b"SSND" =>
+                {
+                    let offset = Decoding::u32_at(body, 0, Endian::Big).unwrap_or(0) as usize;
+                    pcm = body.get(8 + offset..);
+                },
+                b"MARK" =>
+                {
+                    let count = Decoding::u16_at(body, 0, Endian::Big).unwrap_or(0);
+                    let mut position = 2;
+                    for _ in 0..count
+                    {
+                        match (Decoding::u16_at(body, position, Endian::Big), Decoding::u32_at(body, position + 2, Endian::Big))
+                        {
+                            (Some(id), Some(frame)) => markers.push((id, frame)),
+                            _ => break,
+                        }
+                        //Marker names are pascal strings padded to an even length.
+                        let name = body.get(position + 6).cloned().unwrap_or(0) as usize;
+                        position += 6 + 1 + name + ((name + 1) & 1);
+                    }
+                },
+                b"INST" =>
+                {
+                    //Sustain loop: play mode, begin marker, end marker.
+                    match (Decoding::u16_at(body, 8, Endian::Big), Decoding::u16_at(body, 10, Endian::Big), Decoding::u16_at(body, 12, Endian::Big))
+                    {
+                        (Some(mode), Some(begin), Some(end)) if mode != 0 => sustain_loop = Some((begin, end)),
+                        _ => {},
+                    }
+                },
+                _ =>
+                {
+                },
+            }
+            at += 8 + size + (size & 1);
+        }
+
+        let (channels, bits, rate, compression) = common.ok_or_else(|| bad("missing COMM chunk"))?;
+        let pcm = pcm.ok_or_else(|| bad("missing SSND chunk"))?;
+        let decoded = match &compression
+        {
+            b"NONE" | b"twos" => Decoding::decode(pcm, channels, bits, false, Endian::Big, false)?,
+            b"sowt" => Decoding::decode(pcm, channels, bits, false, Endian::Little, false)?,
+            b"fl32" | b"FL32" => Decoding::decode(pcm, channels, 32, true, Endian::Big, false)?,
+            b"fl64" | b"FL64" => Decoding::decode(pcm, channels, 64, true, Endian::Big, false)?,
+            _ => return Err(SampleError::Format(format!("unsupported AIFF-C compression {}", String::from_utf8_lossy(&compression)))),
+        };
+
+        let mut sample = SampleData::new(decoded, rate);
+        if let Some((begin, end)) = sustain_loop
+        {
+            let find = |id: u16| markers.iter().find(|x| x.0 == id).map(|x| x.1 as usize);
+            match (find(begin), find(end))
+            {
+                (Some(start), Some(end)) if end > start => sample.loop_points = Some((start, end)),
+                _ => {},
+            }
+        }
+        Ok(sample)
+    }
+}
+
+///Loader: Picks a reader based on the contents of a file.
+#[allow(non_snake_case)]
+pub mod Loader
+{
+    use processing::sample::sample::Aiff;
+    use processing::sample::sample::SampleData::SampleData;
+    use processing::sample::sample::SampleData::SampleError;
+    use processing::sample::sample::Wav;
+    use std::fs::File;
+    use std::io::Read;
+
+    ///Loader::load(path: &str) -> Result<SampleData, SampleError>: Loads a
+    ///WAV or AIFF file.
+    pub fn load(path: &str) -> Result<SampleData, SampleError>
+    {
+        let mut data = Vec::new();
+        File::open(path)?.read_to_end(&mut data)?;
+        match data.get(0..4)
+        {
+            Some(b"RIFF") => Wav::from_bytes(&data),
+            Some(b"FORM") => Aiff::from_bytes(&data),
+            _ => Err(SampleError::Format(format!("{} is not a WAV or AIFF file", path))),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use processing::sample::sample::Aiff;
+    use processing::sample::sample::SampleData::SampleError;
+    use processing::sample::sample::Wav;
+
+    fn chunk(id: &[u8], body: &[u8], big_endian: bool) -> Vec<u8>
+    {
+        let size = body.len() as u32;
+        let mut ret = id.to_vec();
+        if big_endian
+        {
+            ret.extend_from_slice(&size.to_be_bytes());
+        }
+        else
+        {
+            ret.extend_from_slice(&size.to_le_bytes());
+        }
+        ret.extend_from_slice(body);
+        if body.len() & 1 == 1
+        {
+            ret.push(0);
+        }
+        ret
+    }
+
+    fn wav(tag: u16, channels: u16, rate: u32, bits: u16, pcm: &[u8]) -> Vec<u8>
+    {
+        let align = channels * bits.div_ceil(8);
+        let mut fmt = Vec::new();
+        fmt.extend_from_slice(&tag.to_le_bytes());
+        fmt.extend_from_slice(&channels.to_le_bytes());
+        fmt.extend_from_slice(&rate.to_le_bytes());
+        fmt.extend_from_slice(&(rate * align as u32).to_le_bytes());
+        fmt.extend_from_slice(&align.to_le_bytes());
+        fmt.extend_from_slice(&bits.to_le_bytes());
+
+        let mut body = b"WAVE".to_vec();
+        body.extend(chunk(b"fmt ", &fmt, false));
+        body.extend(chunk(b"data", pcm, false));
+        chunk(b"RIFF", &body, false)
+    }
+
+    //80 bit IEEE extended representation of a whole sample rate.
+    fn extended(rate: u32) -> [u8; 10]
+    {
+        let zeros = (rate as u64).leading_zeros();
+        let exponent = (16383 + 63 - zeros) as u16;
+        let mantissa = (rate as u64) << zeros;
+        let mut ret = [0u8; 10];
+        ret[..2].copy_from_slice(&exponent.to_be_bytes());
+        ret[2..].copy_from_slice(&mantissa.to_be_bytes());
+        ret
+    }
+
+    fn aiff(channels: u16, rate: u32, bits: u16, compression: Option<&[u8]>, pcm: &[u8]) -> Vec<u8>
+    {
+        let frames = pcm.len() as u32 / (channels as u32 * bits.div_ceil(8) as u32);
+        let mut comm = Vec::new();
+        comm.extend_from_slice(&channels.to_be_bytes());
+        comm.extend_from_slice(&frames.to_be_bytes());
+        comm.extend_from_slice(&bits.to_be_bytes());
+        comm.extend_from_slice(&extended(rate));
+        if let Some(x) = compression
+        {
+            comm.extend_from_slice(x);
+            comm.push(0);
+        }
+
+        let mut ssnd = vec![0u8; 8];
+        ssnd.extend_from_slice(pcm);
+
+        let mut body = if compression.is_some() { b"AIFC".to_vec() } else { b"AIFF".to_vec() };
+        body.extend(chunk(b"COMM", &comm, true));
+        body.extend(chunk(b"SSND", &ssnd, true));
+        chunk(b"FORM", &body, true)
+    }
+
+    fn close(a: f32, b: f32) -> bool
+    {
+        (a - b).abs() < 1e-4
+    }
+
+    #[test]
+    fn wav_8_bit_is_unsigned()
+    {
+        let sample = Wav::from_bytes(&wav(1, 1, 8000, 8, &[0, 128, 192])).unwrap();
+        assert_eq!(sample.sample_rate, 8000);
+        assert_eq!(sample.channels[0], vec![-1.0, 0.0, 0.5]);
+    }
+
+    #[test]
+    fn wav_16_bit_stereo_is_split_into_channels()
+    {
+        let mut pcm = Vec::new();
+        for i in [-32768i16, 16384, 8192, -16384].iter()
+        {
+            pcm.extend_from_slice(&i.to_le_bytes());
+        }
+        let sample = Wav::from_bytes(&wav(1, 2, 44100, 16, &pcm)).unwrap();
+        assert_eq!(sample.frames(), 2);
+        assert_eq!(sample.channels[0], vec![-1.0, 0.25]);
+        assert_eq!(sample.channels[1], vec![0.5, -0.5]);
+    }
+
+    #[test]
+    fn wav_24_and_32_bit_integers()
+    {
+        let sample = Wav::from_bytes(&wav(1, 1, 48000, 24, &[0x00, 0x00, 0x40, 0x00, 0x00, 0x80])).unwrap();
+        assert_eq!(sample.channels[0], vec![0.5, -1.0]);
+
+        let mut pcm = Vec::new();
+        pcm.extend_from_slice(&0x4000_0000i32.to_le_bytes());
+        pcm.extend_from_slice(&(-0x2000_0000i32).to_le_bytes());
+        let sample = Wav::from_bytes(&wav(1, 1, 48000, 32, &pcm)).unwrap();
+        assert_eq!(sample.channels[0], vec![0.5, -0.25]);
+    }
+
+    #[test]
+    fn wav_float_samples()
+    {
+        let mut pcm = Vec::new();
+        pcm.extend_from_slice(&0.25f32.to_le_bytes());
+        pcm.extend_from_slice(&(-0.75f32).to_le_bytes());
+        let sample = Wav::from_bytes(&wav(3, 1, 96000, 32, &pcm)).unwrap();
+        assert_eq!(sample.sample_rate, 96000);
+        assert_eq!(sample.channels[0], vec![0.25, -0.75]);
+    }
+
+    #[test]
+    fn wav_rejects_other_formats()
+    {
+        match Wav::from_bytes(b"RIFX\0\0\0\0WAVE")
+        {
+            Err(SampleError::Format(_)) =>
+            {
+            },
+            x => panic!("expected a format error, got {:?}", x),
+        }
+        assert!(Wav::from_bytes(&wav(2, 1, 44100, 4, &[0, 0])).is_err());
+    }
+
+    #[test]
+    fn aiff_reads_extended_sample_rates()
+    {
+        for rate in [22050u32, 44100, 48000, 96000].iter()
+        {
+            let sample = Aiff::from_bytes(&aiff(1, *rate, 16, None, &[0x40, 0x00, 0xc0, 0x00])).unwrap();
+            assert_eq!(sample.sample_rate, *rate);
+            assert_eq!(sample.channels[0], vec![0.5, -0.5]);
+        }
+    }
+
+    #[test]
+    fn aiff_c_little_endian_and_float()
+    {
+        let sample = Aiff::from_bytes(&aiff(1, 44100, 16, Some(b"sowt"), &[0x00, 0x40])).unwrap();
+        assert_eq!(sample.channels[0], vec![0.5]);
+
+        let sample = Aiff::from_bytes(&aiff(1, 44100, 32, Some(b"fl32"), &0.125f32.to_be_bytes())).unwrap();
+        assert!(close(sample.channels[0][0], 0.125));
+
+        let sample = Aiff::from_bytes(&aiff(1, 44100, 24, None, &[0x20, 0x00, 0x00])).unwrap();
+        assert_eq!(sample.channels[0], vec![0.25]);
+    }
+}
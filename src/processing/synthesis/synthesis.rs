@@ -0,0 +1,464 @@
+///Oscillator: Band limited (polyBLEP) waveform generators used by the
+///internal instruments for both audio rate oscillators and LFOs.
+#[allow(non_snake_case)]
+pub mod Oscillator
+{
+    use processing::buffer::buffer::AudioBuffer::SAMPLE_RATE;
+    use serde::Deserialize;
+    use serde::Serialize;
+    use std::f32::consts::PI;
+
+    ///Waveform: Shape an oscillator generates.
+    #[derive(Clone, Copy, Debug, Eq, PartialEq, Serialize, Deserialize)]
+    pub enum Waveform
+    {
+        Sine,
+        Saw,
+        Square,
+        Triangle,
+        Noise,
+    }
+
+    ///waveform_from_str(s: &str) -> Option<Waveform>: Parses the lower case
+    ///name of a waveform.
+    pub fn waveform_from_str(s: &str) -> Option<Waveform>
+    {
+        match s
+        {
+            "sine" => {Some(Waveform::Sine)},
+            "saw" => {Some(Waveform::Saw)},
+            "square" => {Some(Waveform::Square)},
+            "triangle" => {Some(Waveform::Triangle)},
+            "noise" => {Some(Waveform::Noise)},
+            _ => {None},
+        }
+    }
+
+    ///waveform_to_str(w: Waveform) -> &'static str: Inverse of waveform_from_str.
+    pub fn waveform_to_str(w: Waveform) -> &'static str
+    {
+        match w
+        {
+            Waveform::Sine => "sine",
+            Waveform::Saw => "saw",
+            Waveform::Square => "square",
+            Waveform::Triangle => "triangle",
+            Waveform::Noise => "noise",
+        }
+    }
+
+    ///poly_blep(t: f32, dt: f32) -> f32: Correction applied around the
+    ///discontinuities of saw and square waves to reduce aliasing.
+    fn poly_blep(t: f32, dt: f32) -> f32
+    {
+        if t < dt
+        {
+            let x = t / dt;
+            x + x - x * x - 1.0
+        }
+        else if t > 1.0 - dt
+        {
+            let x = (t - 1.0) / dt;
+            x * x + x + x + 1.0
+        }
+        else
+        {
+            0.0
+        }
+    }
+
+    ///Oscillator: Phase accumulator for a single waveform.
+    #[derive(Clone, Copy, Debug)]
+    pub struct Oscillator
+    {
+        pub waveform: Waveform,
+        phase: f32,
+        seed: u32,
+    }
+
+    impl Oscillator
+    {
+        ///Oscillator::new(waveform: Waveform) -> Oscillator: Oscillator that
+        ///starts at phase zero.
+        pub fn new(waveform: Waveform) -> Oscillator
+        {
+            Oscillator{
+                waveform,
+                phase: 0.0,
+                seed: 0x9e3779b9,
+            }
+        }
+
+        ///Oscillator.reset(phase: f32): Moves the phase, 0.0 to 1.0.
+        pub fn reset(&mut self, phase: f32)
+        {
+            self.phase = phase - phase.floor();
+        }
+
+        ///Oscillator.tick(frequency: f32) -> f32: Generates the next sample,
+        ///in the range -1.0 to 1.0, and advances the phase.
+        pub fn tick(&mut self, frequency: f32) -> f32
+        {
+            let dt = (frequency / SAMPLE_RATE as f32).abs().min(0.5);
+            let t = self.phase;
+            let value = match self.waveform
+            {
+                Waveform::Sine =>
+                {
+                    (2.0 * PI * t).sin()
+                },
+                Waveform::Saw =>
+                {
+                    2.0 * t - 1.0 - poly_blep(t, dt)
+                },
+                Waveform::Square =>
+                {
+                    let naive = if t < 0.5 { 1.0 } else { -1.0 };
+                    let shifted = t + 0.5 - (t + 0.5).floor();
+                    naive + poly_blep(t, dt) - poly_blep(shifted, dt)
+                },
+                Waveform::Triangle =>
+                {
+                    1.0 - 4.0 * (t - 0.5).abs()
+                },
+                Waveform::Noise =>
+                {
+                    self.seed ^= self.seed << 13;
+                    self.seed ^= self.seed >> 17;
+                    self.seed ^= self.seed << 5;
+                    (self.seed as f32 / u32::MAX as f32) * 2.0 - 1.0
+                },
+            };
+
+            self.phase += frequency / SAMPLE_RATE as f32;
+            self.phase -= self.phase.floor();
+            value
+        }
+    }
+}
+
+///Envelope: Linear ADSR envelope generator. Times are in milliseconds and
+///sustain is a level from 0.0 to 1.0.
+#[allow(non_snake_case)]
+pub mod Envelope
+{
+    use processing::buffer::buffer::AudioBuffer::SAMPLE_RATE;
+    use serde::Deserialize;
+    use serde::Serialize;
+
+    ///Adsr: Settings shared by every voice playing a patch.
+    #[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+    pub struct Adsr
+    {
+        pub attack: f32,
+        pub decay: f32,
+        pub sustain: f32,
+        pub release: f32,
+    }
+
+    impl Adsr
+    {
+        ///Adsr::new(attack: f32, decay: f32, sustain: f32, release: f32) -> Adsr
+        pub fn new(attack: f32, decay: f32, sustain: f32, release: f32) -> Adsr
+        {
+            Adsr{
+                attack,
+                decay,
+                sustain,
+                release,
+            }
+        }
+    }
+
+    ///Stage: Where the envelope currently is.
+    #[derive(Clone, Copy, Debug, Eq, PartialEq)]
+    pub enum Stage
+    {
+        Idle,
+        Attack,
+        Decay,
+        Sustain,
+        Release,
+    }
+
+    ///ms_to_step(ms: f32, distance: f32) -> f32: Per sample increment that
+    ///covers distance in ms milliseconds.
+    fn ms_to_step(ms: f32, distance: f32) -> f32
+    {
+        let samples = ms * 0.001 * SAMPLE_RATE as f32;
+        if samples < 1.0 { distance } else { distance / samples }
+    }
+
+    ///EnvelopeGenerator: Running state of one envelope.
+    #[derive(Clone, Copy, Debug)]
+    pub struct EnvelopeGenerator
+    {
+        pub settings: Adsr,
+        stage: Stage,
+        level: f32,
+        release_step: f32,
+    }
+
+    impl EnvelopeGenerator
+    {
+        pub fn new(settings: Adsr) -> EnvelopeGenerator
+        {
+            EnvelopeGenerator{
+                settings,
+                stage: Stage::Idle,
+                level: 0.0,
+                release_step: 0.0,
+            }
+        }
+
+        ///EnvelopeGenerator.gate_on(): Starts the attack from the current
+        ///level so retriggering does not click.
+        pub fn gate_on(&mut self)
+        {
+            self.stage = Stage::Attack;
+        }
+
+        ///EnvelopeGenerator.gate_off(): Starts the release stage. Does nothing
+        ///if the envelope is already releasing or idle.
+        pub fn gate_off(&mut self)
+        {
+            if self.stage != Stage::Idle && self.stage != Stage::Release
+            {
+                self.stage = Stage::Release;
+                self.release_step = ms_to_step(self.settings.release, self.level);
+            }
+        }
+
+        ///EnvelopeGenerator.kill(): Silences the envelope immediately.
+        pub fn kill(&mut self)
+        {
+            self.stage = Stage::Idle;
+            self.level = 0.0;
+        }
+
+        pub fn stage(&self) -> Stage
+        {
+            self.stage
+        }
+
+        pub fn level(&self) -> f32
+        {
+            self.level
+        }
+
+        ///EnvelopeGenerator.is_active() -> bool: False once the release has
+        ///finished.
+        pub fn is_active(&self) -> bool
+        {
+            self.stage != Stage::Idle
+        }
+
+        ///EnvelopeGenerator.tick() -> f32: Advances one sample and returns
+        ///the new level.
+        pub fn tick(&mut self) -> f32
+        {
+            match self.stage
+            {
+                Stage::Idle =>
+                {
+                },
+                Stage::Attack =>
+                {
+                    self.level += ms_to_step(self.settings.attack, 1.0);
+                    if self.level >= 1.0
+                    {
+                        self.level = 1.0;
+                        self.stage = Stage::Decay;
+                    }
+                },
+                Stage::Decay =>
+                {
+                    self.level -= ms_to_step(self.settings.decay, 1.0 - self.settings.sustain);
+                    if self.level <= self.settings.sustain
+                    {
+                        self.level = self.settings.sustain;
+                        self.stage = Stage::Sustain;
+                    }
+                },
+                Stage::Sustain =>
+                {
+                    self.level = self.settings.sustain;
+                },
+                Stage::Release =>
+                {
+                    self.level -= self.release_step;
+                    if self.level <= 0.0
+                    {
+                        self.level = 0.0;
+                        self.stage = Stage::Idle;
+                    }
+                },
+            }
+            self.level
+        }
+    }
+}
+
+///Portamento: Pitch that slides exponentially towards a target, used by
+///instruments that support legato playing.
+#[allow(non_snake_case)]
+pub mod Portamento
+{
+    use processing::buffer::buffer::AudioBuffer::SAMPLE_RATE;
+
+    #[derive(Clone, Copy, Debug)]
+    pub struct Glide
+    {
+        current: f32,
+        target: f32,
+        ratio: f32,
+    }
+
+    impl Glide
+    {
+        ///Glide::new(hz: f32) -> Glide: Glide resting at hz.
+        pub fn new(hz: f32) -> Glide
+        {
+            Glide{
+                current: hz,
+                target: hz,
+                ratio: 1.0,
+            }
+        }
+
+        ///Glide.glide_to(hz: f32, ms: f32): Slides to hz over ms
+        ///milliseconds, jumping straight there if ms is 0.
+        pub fn glide_to(&mut self, hz: f32, ms: f32)
+        {
+            let samples = ms * 0.001 * SAMPLE_RATE as f32;
+            self.target = hz;
+            if samples < 1.0 || self.current <= 0.0 || hz <= 0.0
+            {
+                self.current = hz;
+                self.ratio = 1.0;
+            }
+            else
+            {
+                self.ratio = (hz / self.current).powf(1.0 / samples);
+            }
+        }
+
+        pub fn target(&self) -> f32
+        {
+            self.target
+        }
+
+        ///Glide.tick() -> f32: Advances one sample and returns the pitch.
+        pub fn tick(&mut self) -> f32
+        {
+            if self.ratio != 1.0
+            {
+                self.current *= self.ratio;
+                if (self.ratio > 1.0 && self.current >= self.target) || (self.ratio < 1.0 && self.current <= self.target)
+                {
+                    self.current = self.target;
+                    self.ratio = 1.0;
+                }
+            }
+            self.current
+        }
+    }
+}
+
+///VoiceFilter: Resonant state variable filter (topology preserving
+///transform) that can have its cutoff modulated every sample.
+#[allow(non_snake_case)]
+pub mod VoiceFilter
+{
+    use processing::buffer::buffer::AudioBuffer::SAMPLE_RATE;
+    use serde::Deserialize;
+    use serde::Serialize;
+    use std::f32::consts::PI;
+
+    ///FilterMode: Which output of the state variable filter is used.
+    #[derive(Clone, Copy, Debug, Eq, PartialEq, Serialize, Deserialize)]
+    pub enum FilterMode
+    {
+        LowPass,
+        HighPass,
+        BandPass,
+    }
+
+    ///filter_mode_from_str(s: &str) -> Option<FilterMode>
+    pub fn filter_mode_from_str(s: &str) -> Option<FilterMode>
+    {
+        match s
+        {
+            "lowpass" => {Some(FilterMode::LowPass)},
+            "highpass" => {Some(FilterMode::HighPass)},
+            "bandpass" => {Some(FilterMode::BandPass)},
+            _ => {None},
+        }
+    }
+
+    ///filter_mode_to_str(m: FilterMode) -> &'static str
+    pub fn filter_mode_to_str(m: FilterMode) -> &'static str
+    {
+        match m
+        {
+            FilterMode::LowPass => "lowpass",
+            FilterMode::HighPass => "highpass",
+            FilterMode::BandPass => "bandpass",
+        }
+    }
+
+    #[derive(Clone, Copy, Debug)]
+    pub struct StateVariable
+    {
+        pub mode: FilterMode,
+        ic1: f32,
+        ic2: f32,
+    }
+
+    impl StateVariable
+    {
+        pub fn new(mode: FilterMode) -> StateVariable
+        {
+            StateVariable{
+                mode,
+                ic1: 0.0,
+                ic2: 0.0,
+            }
+        }
+
+        pub fn reset(&mut self)
+        {
+            self.ic1 = 0.0;
+            self.ic2 = 0.0;
+        }
+
+        ///StateVariable.tick(input: f32, cutoff: f32, resonance: f32) -> f32:
+        ///Filters one sample. cutoff is in herz and resonance goes from 0.0
+        ///to 1.0 (self oscillation is avoided).
+        pub fn tick(&mut self, input: f32, cutoff: f32, resonance: f32) -> f32
+        {
+            let nyquist = SAMPLE_RATE as f32 / 2.0;
+            let cutoff = cutoff.clamp(10.0, nyquist * 0.95);
+            let resonance = resonance.clamp(0.0, 1.0);
+
+            let g = (PI * cutoff / SAMPLE_RATE as f32).tan();
+            let k = 2.0 - 1.96 * resonance;
+            let a1 = 1.0 / (1.0 + g * (g + k));
+            let a2 = g * a1;
+            let a3 = g * a2;
+
+            let v3 = input - self.ic2;
+            let v1 = a1 * self.ic1 + a2 * v3;
+            let v2 = self.ic2 + a2 * self.ic1 + a3 * v3;
+            self.ic1 = 2.0 * v1 - self.ic1;
+            self.ic2 = 2.0 * v2 - self.ic2;
+
+            match self.mode
+            {
+                FilterMode::LowPass => v2,
+                FilterMode::BandPass => v1,
+                FilterMode::HighPass => input - k * v1 - v2,
+            }
+        }
+    }
+}
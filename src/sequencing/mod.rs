@@ -1,3 +1,4 @@
+#[allow(clippy::module_inception)]
 pub mod sequencing;
 pub mod timing;
 pub mod tonation;
@@ -0,0 +1,143 @@
+///Driver: Plays sequencers from a Transport. While the transport runs
+///undisturbed every block is rendered from the frame after the previous
+///one. Whenever it is started, stopped, resumed or located, held notes
+///are released and playback picks up from the transport's position.
+#[allow(non_snake_case)]
+pub mod Driver
+{
+    use instrumentation::interface::instrument::Instrument;
+    use processing::buffer::buffer::AudioBuffer::SAMPLE_RATE;
+    use processing::buffer::buffer::AudioBuffer::StereoBuffer;
+    use sequencing::sequencing::partialsequencer::PartialSequencer::PartialSequencer;
+    use sequencing::timing::transport::Transport::Transport;
+    use std::time::Instant;
+
+    pub struct Driver
+    {
+        transport: Option<Transport>,
+        position: u64,
+    }
+
+    impl Driver
+    {
+        ///Driver::new() -> Driver: A driver that takes its position from
+        ///the first transport it is given.
+        pub fn new() -> Driver
+        {
+            Driver{
+                transport: None,
+                position: 0,
+            }
+        }
+
+        ///Driver.position() -> u64: The frame the next block is rendered
+        ///from while the transport keeps playing.
+        pub fn position(&self) -> u64
+        {
+            self.position
+        }
+
+        ///Driver.render<T>(transport: &Transport, now: Instant, sequencers, tracks) -> Option<u64>:
+        ///Fills one track buffer per sequencer with the next block. now is
+        ///the time the transport position is read at when playback has to
+        ///pick it up again. Returns the frame the block was rendered from,
+        ///or None while the transport is stopped, in which case the
+        ///instruments only play out their release tails.
+        pub fn render<T: Instrument::PlayableInstrument>(&mut self, transport: &Transport, now: Instant, sequencers: &mut [PartialSequencer<T>], tracks: &mut [StereoBuffer]) -> Option<u64>
+        {
+            if self.transport != Some(*transport)
+            {
+                if self.transport.is_some_and(|x| x.is_playing())
+                {
+                    for sequencer in sequencers.iter_mut()
+                    {
+                        sequencer.stop();
+                    }
+                }
+                self.transport = Some(*transport);
+                self.position = transport.position_at(now) * SAMPLE_RATE as u64 / 1000;
+            }
+
+            if !transport.is_playing()
+            {
+                for (sequencer, track) in sequencers.iter_mut().zip(tracks.iter_mut())
+                {
+                    sequencer.render_idle(track);
+                }
+                return None;
+            }
+
+            let position = self.position;
+            for (sequencer, track) in sequencers.iter_mut().zip(tracks.iter_mut())
+            {
+                sequencer.render(position, track);
+            }
+            self.position += tracks.first().map_or(0, |x| x.len()) as u64;
+            Some(position)
+        }
+    }
+
+    impl Default for Driver
+    {
+        fn default() -> Driver
+        {
+            Driver::new()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use instrumentation::interface::instrument::Probe::Probe;
+    use processing::buffer::buffer::AudioBuffer::SAMPLE_RATE;
+    use processing::buffer::buffer::AudioBuffer::StereoBuffer;
+    use sequencing::sequencing::driver::Driver::Driver;
+    use sequencing::sequencing::partialsequencer::PartialSequencer::PartialSequencer;
+    use sequencing::timing::transport::Transport::Transport;
+    use sequencing::tonation::note::NoteCollections::RawNote;
+    use sequencing::tonation::note::NotePrimitives::Register;
+    use sequencing::tonation::note::NotePrimitives::Semitone;
+    use std::time::Duration;
+    use std::time::Instant;
+
+    #[test]
+    fn transport_commands_change_what_is_rendered()
+    {
+        //The probe outputs the amount of held notes. The note starts 250ms
+        //into the 500ms bar and is held for 100ms. Reading the transport
+        //at a time before it was started gives its exact start position.
+        let mut sequencers = vec![PartialSequencer::new(120.0, Probe::new())];
+        sequencers[0].push_time_to_note(2, 4, RawNote::new(Semitone::C, Register::C4, 0.0, 100.0, 100));
+        let mut tracks = vec![StereoBuffer::new(256)];
+        let mut transport = Transport::new();
+        let mut driver = Driver::new();
+        let before = Instant::now();
+
+        transport.locate(250);
+        assert_eq!(driver.render(&transport, before, &mut sequencers, &mut tracks), None);
+        assert_eq!(tracks[0].left[0], 0.0);
+
+        transport.resume();
+        let start = SAMPLE_RATE as u64 / 4;
+        assert_eq!(driver.render(&transport, before, &mut sequencers, &mut tracks), Some(start));
+        assert_eq!(tracks[0].left[0], 1.0);
+        assert_eq!(driver.render(&transport, Instant::now(), &mut sequencers, &mut tracks), Some(start + 256));
+        assert_eq!(tracks[0].left[255], 1.0);
+
+        //Locating releases the held note and skips the rest of it.
+        transport.locate(400);
+        let later = Instant::now();
+        let position = driver.render(&transport, later, &mut sequencers, &mut tracks).unwrap();
+        assert!(position >= SAMPLE_RATE as u64 * 2 / 5);
+        assert_eq!(tracks[0].left[0], 0.0);
+
+        transport.stop();
+        assert_eq!(driver.render(&transport, Instant::now(), &mut sequencers, &mut tracks), None);
+        transport.start();
+        transport.locate(250);
+        let before = Instant::now() - Duration::from_secs(1);
+        assert_eq!(driver.render(&transport, before, &mut sequencers, &mut tracks), Some(start));
+        assert_eq!(tracks[0].left[0], 1.0);
+    }
+}
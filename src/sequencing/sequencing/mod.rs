@@ -1 +1,4 @@
+pub mod driver;
 pub mod partialsequencer;
+pub mod pattern;
+pub mod recorder;
@@ -1,28 +1,64 @@
+#[allow(non_snake_case)]
 pub mod PartialSequencer
 {
     use std::collections::HashMap;
 
+    use sequencing::sequencing::pattern::Pattern::Pattern;
+    use sequencing::sequencing::pattern::Pattern::PatternEvent;
     use sequencing::tonation::note::NoteCollections::RawNote;
-    use sequencing::tonation::note::NoteGeneration::NoteBuilder;
 
     use instrumentation::interface::instrument::InstrumentWrapper;
-    use instrumentation::interface::instrument::TestPlugin;
     use instrumentation::interface::instrument::Instrument;
+    use instrumentation::interface::instrument::Instrument::PlayError;
 
     use sequencing::timing::timing::Beat::BeatValue;
-    use schedule_recv::periodic_ms;
-    use schedule_recv::oneshot_ms;
-
-    extern crate timer;
-    use std::sync::mpsc::channel;
-    use std::thread;
+    use processing::automation::automation::Automation::CONTROL_BLOCK;
+    use processing::automation::automation::AutomationPrimitives::AutomationTarget;
+    use processing::automation::automation::Lane::AutomationLane;
+    use processing::buffer::buffer::AudioBuffer::BLOCK_SIZE;
+    use processing::buffer::buffer::AudioBuffer::SAMPLE_RATE;
+    use processing::buffer::buffer::AudioBuffer::StereoBuffer;
 
     pub struct PartialSequencer<T: Instrument::PlayableInstrument>
     {
         pub local_beat_builder: BeatValue,
-        local_note_builder: NoteBuilder,
         instrument: InstrumentWrapper::InstrumentWrapper<T>,
-        pub note_hash: HashMap<u64, RawNote>
+        pub note_hash: HashMap<u64, Vec<RawNote>>,
+        ///Length of the loop in bars.
+        pub bars: u64,
+        patterns: Vec<(HashMap<u64, Vec<RawNote>>, u64)>,
+        automation: Vec<AutomationLane>,
+        errors: Vec<(u64, PlayError)>,
+        note_offs: Vec<(u64, u8)>,
+        block: StereoBuffer,
+    }
+
+    fn ms_to_frames(ms: u64) -> u64
+    {
+        ms * SAMPLE_RATE as u64 / 1000
+    }
+
+    fn frames_to_ms(frames: u64) -> u64
+    {
+        frames * 1000 / SAMPLE_RATE as u64
+    }
+
+    ///rescale(notes, ratio) -> HashMap<u64, Vec<RawNote>>: notes with their
+    ///start times and lengths multiplied by ratio.
+    fn rescale(notes: &HashMap<u64, Vec<RawNote>>, ratio: f64) -> HashMap<u64, Vec<RawNote>>
+    {
+        let mut ret: HashMap<u64, Vec<RawNote>> = HashMap::new();
+        for (time, x) in notes.iter()
+        {
+            let entry = ret.entry((*time as f64 * ratio).round() as u64).or_default();
+            for note in x.iter()
+            {
+                let mut note = *note;
+                note.length = (note.length as f64 * ratio).round() as u32;
+                entry.push(note);
+            }
+        }
+        ret
     }
 
     impl<T: Instrument::PlayableInstrument>PartialSequencer<T>
@@ -32,35 +68,394 @@ pub mod PartialSequencer
             PartialSequencer
             {
                 local_beat_builder: BeatValue::from_bpm(bpm),
-                local_note_builder: NoteBuilder::new(),
                 instrument: InstrumentWrapper::InstrumentWrapper::new(instrument),
                 note_hash: HashMap::new(),
+                bars: 1,
+                patterns: Vec::new(),
+                automation: Vec::new(),
+                errors: Vec::new(),
+                note_offs: Vec::new(),
+                block: StereoBuffer::new(BLOCK_SIZE),
             }
         }
 
         pub fn push_time_to_note(&mut self, bar: u64, bar_divisions: u64, note: RawNote)
         {
-            self.note_hash.insert(self.local_beat_builder.u64_from_beats(bar_divisions, bar).unwrap(), note);
+            let time = self.local_beat_builder.u64_from_beats(bar_divisions, bar).unwrap();
+            self.push_note(time, note);
         }
 
-        pub fn play_sequence_DEBUG(&mut self)
+        ///PartialSequencer.push_note(time: u64, note: RawNote): Adds note at
+        ///time ms, next to any notes already starting there.
+        pub fn push_note(&mut self, time: u64, note: RawNote)
+        {
+            self.note_hash.entry(time).or_default().push(note);
+        }
+
+        ///PartialSequencer.loop_length() -> u64: Length of the loop in ms.
+        pub fn loop_length(&self) -> u64
+        {
+            self.local_beat_builder.u64_from_beats(1, 1).unwrap_or(0) * self.bars
+        }
+
+        ///PartialSequencer.to_pattern() -> Pattern: The notes as a pattern
+        ///as long as the loop, the inverse of load_pattern.
+        pub fn to_pattern(&self) -> Pattern
+        {
+            let bar = self.bar_ms().max(1.0);
+            let mut events = Vec::new();
+            for (time, notes) in self.note_hash.iter()
+            {
+                for note in notes.iter()
+                {
+                    events.push(PatternEvent{
+                        start: *time as f64 / bar,
+                        length: note.length as f64 / bar,
+                        key: note.key(),
+                        velocity: note.velocity(),
+                    });
+                }
+            }
+            events.sort_by(|a, b| a.start.partial_cmp(&b.start).unwrap().then(a.key.cmp(&b.key)));
+            Pattern{
+                events,
+                length: self.bars as f64,
+            }
+        }
+
+        ///PartialSequencer.load_pattern(pattern: &Pattern): Replaces the
+        ///current notes with pattern, at the current tempo, and makes the
+        ///loop as many bars long as the pattern.
+        pub fn load_pattern(&mut self, pattern: &Pattern)
+        {
+            self.note_hash.clear();
+            self.bars = pattern.bars();
+            for (time, note) in pattern.to_notes(&self.local_beat_builder)
+            {
+                self.push_note(time, note);
+            }
+        }
+
+        ///PartialSequencer.bar_ms() -> f64: Length of one bar in ms.
+        fn bar_ms(&self) -> f64
+        {
+            let duration = self.local_beat_builder.duration();
+            duration.as_secs() as f64 * 1000.0 + duration.subsec_nanos() as f64 / 1e6
+        }
+
+        ///PartialSequencer.set_tempo(bpm: f32): Changes the tempo. The notes,
+        ///including those of stored patterns, keep their position and
+        ///length in bars.
+        pub fn set_tempo(&mut self, bpm: f32)
+        {
+            let old = self.bar_ms();
+            self.local_beat_builder = BeatValue::from_bpm(bpm);
+            let ratio = self.bar_ms() / old;
+            if !ratio.is_finite() || ratio <= 0.0
+            {
+                return;
+            }
+            self.note_hash = rescale(&self.note_hash, ratio);
+            for pattern in self.patterns.iter_mut()
+            {
+                pattern.0 = rescale(&pattern.0, ratio);
+            }
+        }
+
+        ///PartialSequencer.store_pattern() -> usize: Saves the current notes
+        ///as a pattern that can be brought back with trigger_pattern, and
+        ///returns its index.
+        pub fn store_pattern(&mut self) -> usize
+        {
+            self.patterns.push((self.note_hash.clone(), self.bars));
+            self.patterns.len() - 1
+        }
+
+        ///PartialSequencer.trigger_pattern(index: usize) -> bool: Replaces the
+        ///current notes with a stored pattern. Notes already sounding are
+        ///left to end on their own. False if there is no such pattern.
+        pub fn trigger_pattern(&mut self, index: usize) -> bool
+        {
+            match self.patterns.get(index)
+            {
+                Some(x) =>
+                {
+                    self.note_hash = x.0.clone();
+                    self.bars = x.1;
+                    true
+                },
+                None =>
+                {
+                    false
+                },
+            }
+        }
+
+        ///PartialSequencer.push_automation(lane: AutomationLane) -> bool: Attaches
+        ///an automation lane to the instrument of this sequencer. Only lanes
+        ///targeting AutomationTarget::Instrument are accepted, mixer lanes
+        ///belong to processing::automation::automation::Automation.
+        pub fn push_automation(&mut self, lane: AutomationLane) -> bool
+        {
+            match lane.target
+            {
+                AutomationTarget::Instrument(_) =>
+                {
+                    self.automation.push(lane);
+                    true
+                },
+                _ =>
+                {
+                    false
+                }
+            }
+        }
+
+        ///PartialSequencer.instrument() -> &mut InstrumentWrapper<T>: The
+        ///wrapped instrument, for its voice settings and parameters.
+        pub fn instrument(&mut self) -> &mut InstrumentWrapper::InstrumentWrapper<T>
+        {
+            &mut self.instrument
+        }
+
+        ///PartialSequencer.automation() -> &Vec<AutomationLane>: The lanes
+        ///attached with push_automation.
+        pub fn automation(&self) -> &Vec<AutomationLane>
+        {
+            &self.automation
+        }
+
+        ///PartialSequencer.apply_automation(time: f64): Evaluates every
+        ///instrument lane at time milliseconds and hands the values to the
+        ///instrument.
+        pub fn apply_automation(&mut self, time: f64)
         {
-            let mut time = 0;
-            for j in 0..self.local_beat_builder.u64_from_beats(1, 1).unwrap()
+            for lane in self.automation.iter()
             {
-                match self.note_hash.get(&time)
+                if let (AutomationTarget::Instrument(name), Some(value)) = (&lane.target, lane.value_at(time))
                 {
-                    Some(x) =>
+                    self.instrument.set_parameter(name, value);
+                }
+            }
+        }
+
+        ///PartialSequencer.errors() -> &Vec<(u64, PlayError)>: Notes the
+        ///instrument refused to play, as the time (ms) they were due and
+        ///the reason.
+        pub fn errors(&self) -> &Vec<(u64, PlayError)>
+        {
+            &self.errors
+        }
+
+        ///PartialSequencer.take_errors() -> Vec<(u64, PlayError)>: Returns
+        ///and clears the collected errors.
+        pub fn take_errors(&mut self) -> Vec<(u64, PlayError)>
+        {
+            self.errors.drain(..).collect()
+        }
+
+        ///PartialSequencer.render(position: u64, buffer: &mut StereoBuffer):
+        ///Fills buffer with the instrument's output starting position frames
+        ///into playback, looping every loop_length ms. Notes are sent as note
+        ///on and note off events on the exact frame they are due, so the
+        ///instrument renders its own voices between them.
+        pub fn render(&mut self, position: u64, buffer: &mut StereoBuffer)
+        {
+            let bar = ms_to_frames(self.loop_length()).max(1);
+            let starts: Vec<(u64, u64)> = self.note_hash.keys().map(|x| (ms_to_frames(*x), *x)).collect();
+            let mut done = 0;
+
+            while done < buffer.len()
+            {
+                let frame = position + done as u64;
+                let in_bar = frame % bar;
+                self.apply_automation(frames_to_ms(in_bar) as f64);
+
+                let mut due: Vec<u8> = Vec::new();
+                self.note_offs.retain(|x| if x.0 <= frame { due.push(x.1); false } else { true });
+                for key in due
+                {
+                    self.instrument.note_off(key);
+                }
+                for &(_, ms) in starts.iter().filter(|x| x.0 == in_bar)
+                {
+                    for note in self.note_hash[&ms].iter()
                     {
-                        self.instrument.play(x);
-                    },
-                    None =>
+                        match self.instrument.note_on(note.key(), note.velocity())
+                        {
+                            Ok(()) => self.note_offs.push((frame + ms_to_frames(note.length as u64).max(1), note.key())),
+                            Err(e) => self.errors.push((ms, e)),
+                        }
+                    }
+                }
+
+                //Render up to the next event, applying automation at the
+                //same control rate Automation.render does.
+                let mut next = frame + (buffer.len() - done).min(CONTROL_BLOCK) as u64;
+                for &(start, _) in starts.iter()
+                {
+                    let at = if start > in_bar { frame - in_bar + start } else { frame - in_bar + bar + start };
+                    next = next.min(at);
+                }
+                for &(at, _) in self.note_offs.iter()
+                {
+                    next = next.min(at);
+                }
+
+                let count = (next - frame) as usize;
+                self.block.resize(count);
+                self.instrument.process(&mut self.block);
+                buffer.left[done..done + count].copy_from_slice(&self.block.left);
+                buffer.right[done..done + count].copy_from_slice(&self.block.right);
+                done += count;
+            }
+        }
+
+        ///PartialSequencer.render_idle(buffer: &mut StereoBuffer): Fills
+        ///buffer with the instrument's output without playing any notes, so
+        ///release tails ring out while playback is stopped.
+        pub fn render_idle(&mut self, buffer: &mut StereoBuffer)
+        {
+            self.instrument.process(buffer);
+        }
+
+        ///PartialSequencer.stop(): Releases every note that is still held.
+        pub fn stop(&mut self)
+        {
+            self.note_offs.clear();
+            self.instrument.all_notes_off();
+        }
+
+        pub fn play_sequence_DEBUG(&mut self)
+        {
+            for time in 0..self.loop_length()
+            {
+                self.apply_automation(time as f64);
+                if let Some(x) = self.note_hash.get(&time)
+                {
+                    for note in x.iter()
                     {
-                    },
-                };
-                time = time + 1;
+                        if let Err(e) = self.instrument.try_play(note)
+                        {
+                            self.errors.push((time, e));
+                        }
+                    }
+                }
             }
         }
 
     }
 }
+
+#[cfg(test)]
+mod tests
+{
+    use instrumentation::interface::instrument::Instrument::PlayError;
+    use instrumentation::interface::instrument::Probe::Probe;
+    use processing::automation::automation::Automation::CONTROL_BLOCK;
+    use processing::automation::automation::AutomationPrimitives::AutomationTarget;
+    use processing::automation::automation::AutomationPrimitives::Curve;
+    use processing::automation::automation::Lane::AutomationLane;
+    use processing::buffer::buffer::AudioBuffer::SAMPLE_RATE;
+    use processing::buffer::buffer::AudioBuffer::StereoBuffer;
+    use sequencing::sequencing::partialsequencer::PartialSequencer::PartialSequencer;
+    use sequencing::sequencing::pattern::Pattern::Pattern;
+    use sequencing::tonation::note::NoteCollections::RawNote;
+    use sequencing::tonation::note::NotePrimitives::Register;
+    use sequencing::tonation::note::NotePrimitives::Semitone;
+
+    #[test]
+    fn refused_notes_are_collected_with_their_time()
+    {
+        let mut sequencer = PartialSequencer::new(120.0, Probe::with_range(60, 64));
+        sequencer.push_time_to_note(0, 4, RawNote::new(Semitone::C, Register::C4, 0.0, 100.0, 100));
+        sequencer.push_time_to_note(2, 4, RawNote::new(Semitone::A, Register::C4, 0.0, 100.0, 100));
+        sequencer.play_sequence_DEBUG();
+        assert_eq!(sequencer.errors(), &vec![(250, PlayError::OutOfRange(69))]);
+        assert_eq!(sequencer.take_errors().len(), 1);
+        assert!(sequencer.errors().is_empty());
+    }
+
+    #[test]
+    fn render_holds_notes_for_their_length()
+    {
+        //The probe outputs the amount of held notes. 250ms into a 500ms
+        //bar is frame 11025, and 100ms is 4410 frames.
+        let mut sequencer = PartialSequencer::new(120.0, Probe::new());
+        sequencer.push_time_to_note(2, 4, RawNote::new(Semitone::C, Register::C4, 0.0, 100.0, 100));
+        let mut buffer = StereoBuffer::new(SAMPLE_RATE as usize / 2);
+        sequencer.render(0, &mut buffer);
+        assert_eq!(buffer.left[11024], 0.0);
+        assert_eq!(buffer.left[11025], 1.0);
+        assert_eq!(buffer.left[15434], 1.0);
+        assert_eq!(buffer.left[15435], 0.0);
+    }
+
+    #[test]
+    fn render_reports_errors_at_the_note_time()
+    {
+        //One bar at 120 bpm is 500ms, so the second pass of the loop is
+        //rendered here.
+        let mut sequencer = PartialSequencer::new(120.0, Probe::with_range(0, 0));
+        sequencer.push_time_to_note(2, 4, RawNote::new(Semitone::C, Register::C4, 0.0, 100.0, 100));
+        let frames = SAMPLE_RATE as usize / 2;
+        let mut buffer = StereoBuffer::new(frames);
+        sequencer.render(frames as u64, &mut buffer);
+        let errors: Vec<u64> = sequencer.take_errors().iter().map(|x| x.0).collect();
+        assert_eq!(errors, vec![250]);
+    }
+
+    #[test]
+    fn stored_patterns_can_be_triggered_again()
+    {
+        let mut sequencer = PartialSequencer::new(120.0, Probe::new());
+        sequencer.push_time_to_note(0, 4, RawNote::new(Semitone::C, Register::C4, 0.0, 100.0, 100));
+        assert_eq!(sequencer.store_pattern(), 0);
+        sequencer.note_hash.clear();
+        sequencer.push_time_to_note(1, 4, RawNote::new(Semitone::D, Register::C4, 0.0, 100.0, 100));
+        assert_eq!(sequencer.store_pattern(), 1);
+        assert!(sequencer.trigger_pattern(0));
+        assert_eq!(sequencer.note_hash.keys().collect::<Vec<&u64>>(), vec![&0]);
+        assert!(!sequencer.trigger_pattern(2));
+        assert_eq!(sequencer.note_hash.len(), 1);
+    }
+
+    #[test]
+    fn tempo_changes_keep_notes_in_their_bar_position()
+    {
+        let mut sequencer = PartialSequencer::new(120.0, Probe::new());
+        sequencer.push_time_to_note(1, 4, RawNote::new(Semitone::D, Register::C4, 0.0, 100.0, 50));
+        sequencer.store_pattern();
+        sequencer.note_hash.clear();
+        sequencer.push_time_to_note(2, 4, RawNote::new(Semitone::C, Register::C4, 0.0, 100.0, 100));
+
+        sequencer.set_tempo(60.0);
+        assert_eq!(sequencer.loop_length(), 1000);
+        assert_eq!(sequencer.note_hash.keys().collect::<Vec<&u64>>(), vec![&500]);
+        assert_eq!(sequencer.note_hash[&500][0].length, 200);
+        assert!(sequencer.trigger_pattern(0));
+        assert_eq!(sequencer.note_hash.keys().collect::<Vec<&u64>>(), vec![&250]);
+        assert_eq!(sequencer.note_hash[&250][0].length, 100);
+    }
+
+    #[test]
+    fn to_pattern_is_the_inverse_of_load_pattern()
+    {
+        let pattern = Pattern::parse("[C4 E]/4 G/8@60 r:5/8 D:1/2").unwrap();
+        let mut sequencer = PartialSequencer::new(60.0, Probe::new());
+        sequencer.load_pattern(&pattern);
+        assert_eq!(sequencer.to_pattern(), Pattern{ length: 2.0, ..pattern });
+    }
+
+    #[test]
+    fn render_applies_automation_at_the_control_rate()
+    {
+        let mut sequencer = PartialSequencer::new(120.0, Probe::new());
+        sequencer.push_automation(AutomationLane::new(AutomationTarget::Instrument("cutoff".to_string()))
+            .point(0, 0.0, Curve::Linear)
+            .point(500, 1.0, Curve::Linear));
+        let mut buffer = StereoBuffer::new(CONTROL_BLOCK * 4);
+        sequencer.render(0, &mut buffer);
+        assert_eq!(sequencer.instrument().reveal_internal_instrument_DEBUG().parameters.len(), 4);
+    }
+}
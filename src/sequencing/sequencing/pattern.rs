@@ -0,0 +1,820 @@
+///Pattern: A small text notation for writing sequences by hand. A pattern
+///is a list of items separated by whitespace, bar lines (|) being ignored:
+///
+///```text
+///    C4 D#4 Eb F          notes, with # and b for sharps and flats and the
+///                         octave (0-9) after them; the octave carries over
+///                         from the previous note, starting at 4
+///    r                    a rest
+///    [C4 E G]             a chord
+///    (C4 D E)             a group, only useful with a repeat
+///    {C4 D E}             a subdivision: its items are squeezed into one
+///                         length, so {C D E}/4 is a quarter note triplet
+///```
+///
+///Items take modifiers, written straight after them:
+///
+///```text
+///    /8  :3/8  :2         length as a fraction of a bar, /8 being short
+///                         for :1/8 and :2 for two bars; every dot after it
+///                         adds half of the previous addition (/4. = :3/8)
+///    @90                  velocity, 0-127
+///    *4                   repeat the item 4 times, at most MAX_REPEAT
+///    ~                    tie the note into the next note of the same pitch
+///```
+///
+///A pattern holds at most MAX_EVENTS notes, repeats included.
+///
+///Length and velocity carry over to the following items until given again,
+///starting at /4 and @100. Inside a subdivision lengths are only relative;
+///the subdivision itself lasts the length given after it, or the length
+///current where it was opened.
+///
+///Lengths are in bars of the sequencer (see BeatValue), so a pattern can be
+///written once and played at any tempo.
+#[allow(non_snake_case)]
+pub mod Pattern
+{
+    use sequencing::timing::timing::Beat::BeatValue;
+    use sequencing::tonation::note::NoteCollections::RawNote;
+    use sequencing::tonation::note::NotePrimitives;
+    use serde::Deserialize;
+    use serde::Serialize;
+    use std::fmt::Display;
+    use std::fmt::Error;
+    use std::fmt::Formatter;
+
+    pub const DEFAULT_OCTAVE: u8 = 4;
+    pub const DEFAULT_LENGTH: f64 = 0.25;
+    pub const DEFAULT_VELOCITY: u8 = 100;
+    pub const MAX_REPEAT: u32 = 1024;
+    pub const MAX_EVENTS: usize = 65536;
+
+    //Slack allowed when matching the end of a tied note with the start of
+    //the next one.
+    const EPSILON: f64 = 1e-9;
+
+    ///PatternError: What is wrong with a pattern and where, counted in
+    ///characters from 1.
+    #[derive(Clone, Debug, PartialEq)]
+    pub struct PatternError
+    {
+        pub line: usize,
+        pub column: usize,
+        pub message: String,
+    }
+
+    impl Display for PatternError
+    {
+        fn fmt(&self, f: &mut Formatter) -> Result<(), Error>
+        {
+            write!(f, "line {}, column {}: {}", self.line, self.column, self.message)
+        }
+    }
+
+    ///PatternEvent: One note of a pattern. start and length are in bars.
+    #[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+    pub struct PatternEvent
+    {
+        pub start: f64,
+        pub length: f64,
+        pub key: u8,
+        pub velocity: u8,
+    }
+
+    ///VoiceElement: A chord, note or rest of Pattern.voice: start and length
+    ///in grid steps, and the keys with their velocities.
+    pub type VoiceElement = (u64, u64, Vec<(u8, u8)>);
+
+    ///Pattern: The notes of a parsed pattern, ordered by start, and its
+    ///length in bars, rests included.
+    #[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+    pub struct Pattern
+    {
+        pub events: Vec<PatternEvent>,
+        pub length: f64,
+    }
+
+    impl Pattern
+    {
+        ///Pattern::parse(s: &str) -> Result<Pattern, PatternError>
+        pub fn parse(s: &str) -> Result<Pattern, PatternError>
+        {
+            let mut parser = Parser{
+                chars: s.chars().collect(),
+                position: 0,
+                octave: DEFAULT_OCTAVE,
+                length: DEFAULT_LENGTH,
+                velocity: DEFAULT_VELOCITY,
+            };
+            let (events, length) = parser.sequence(None)?;
+            let tied = events.iter().map(|x| (PatternEvent{
+                start: x.start,
+                length: x.length,
+                key: x.key,
+                velocity: x.velocity,
+            }, x.tied)).collect();
+            let (joined, dangling) = Pattern::join_ties(tied);
+            if let Some(&i) = dangling.first()
+            {
+                return Err(parser.error(events[i].position, "tie is not followed by the same note"));
+            }
+            Ok(Pattern{
+                events: joined,
+                length,
+            })
+        }
+
+        ///Pattern::join_ties(events: Vec<(PatternEvent, bool)>) -> (Vec<PatternEvent>, Vec<usize>):
+        ///Orders events by start and joins every event marked as tied with
+        ///the next event of the same key that starts where it ends. Ties
+        ///leading nowhere are left as they are, and their indexes in events
+        ///returned.
+        pub fn join_ties(events: Vec<(PatternEvent, bool)>) -> (Vec<PatternEvent>, Vec<usize>)
+        {
+            let mut events: Vec<(PatternEvent, bool, usize)> = events.into_iter().enumerate().map(|(i, x)| (x.0, x.1, i)).collect();
+            events.sort_by(|a, b| a.0.start.total_cmp(&b.0.start).then(a.0.key.cmp(&b.0.key)));
+            let mut dangling = Vec::new();
+            let mut i = 0;
+            while i < events.len()
+            {
+                if events[i].1
+                {
+                    let end = events[i].0.start + events[i].0.length;
+                    let next = (i + 1..events.len()).find(|&j| events[j].0.key == events[i].0.key && (events[j].0.start - end).abs() < EPSILON);
+                    match next
+                    {
+                        Some(j) =>
+                        {
+                            let next = events.remove(j);
+                            events[i].0.length += next.0.length;
+                            events[i].1 = next.1;
+                            events[i].2 = next.2;
+                            continue;
+                        },
+                        None =>
+                        {
+                            dangling.push(events[i].2);
+                        },
+                    }
+                }
+                i += 1;
+            }
+            (events.into_iter().map(|x| x.0).collect(), dangling)
+        }
+
+        ///Pattern.bars() -> u64: Whole bars the pattern takes, at least 1.
+        pub fn bars(&self) -> u64
+        {
+            (self.length - EPSILON).ceil().max(1.0) as u64
+        }
+
+        ///Pattern.voice(grid: u64) -> Vec<VoiceElement>: The pattern as a
+        ///single voice on a grid of grid steps per bar, the way notation
+        ///without several voices needs it: the start and length in steps
+        ///and the keys and velocities sounding for every start. Notes
+        ///starting together form a chord as long as its longest note, and
+        ///everything is cut where the next start is. Gaps are rests.
+        pub fn voice(&self, grid: u64) -> Vec<VoiceElement>
+        {
+            let total = self.bars() * grid;
+            let mut ret: Vec<VoiceElement> = Vec::new();
+            for event in self.events.iter()
+            {
+                let start = (event.start * grid as f64).round() as u64;
+                let length = ((event.length * grid as f64).round() as u64).max(1);
+                if start >= total
+                {
+                    continue;
+                }
+                match ret.last_mut()
+                {
+                    Some(last) if last.0 == start =>
+                    {
+                        last.1 = last.1.max(length);
+                        if !last.2.iter().any(|x| x.0 == event.key)
+                        {
+                            last.2.push((event.key, event.velocity));
+                        }
+                        continue;
+                    },
+                    _ =>
+                    {
+                    },
+                }
+                ret.push((start, length, vec![(event.key, event.velocity)]));
+            }
+            for i in 0..ret.len()
+            {
+                let next = ret.get(i + 1).map_or(total, |x| x.0);
+                ret[i].1 = ret[i].1.min(next - ret[i].0);
+                ret[i].2.sort();
+            }
+            ret
+        }
+
+        ///Pattern.to_notes(beat: &BeatValue) -> Vec<(u64, RawNote)>: The notes
+        ///with their start, both in ms at the tempo of beat.
+        pub fn to_notes(&self, beat: &BeatValue) -> Vec<(u64, RawNote)>
+        {
+            let bar = beat.duration().as_secs() as f64 * 1000.0 + beat.duration().subsec_nanos() as f64 / 1e6;
+            let mut ret = Vec::new();
+            for event in self.events.iter()
+            {
+                if let Some((p, r)) = NotePrimitives::midi_to_primitives(event.key)
+                {
+                    let length = (event.length * bar).round().max(1.0) as u32;
+                    ret.push(((event.start * bar).round() as u64, RawNote::new(p, r, 0.0, event.velocity as f32, length)));
+                }
+            }
+            ret
+        }
+    }
+
+    #[derive(Clone, Copy)]
+    struct Event
+    {
+        start: f64,
+        length: f64,
+        key: u8,
+        velocity: u8,
+        tied: bool,
+        position: usize,
+    }
+
+    #[derive(Default)]
+    struct Modifiers
+    {
+        length: Option<f64>,
+        velocity: Option<u8>,
+        repeat: Option<u32>,
+        repeat_at: usize,
+        tie: bool,
+    }
+
+    struct Parser
+    {
+        chars: Vec<char>,
+        position: usize,
+        octave: u8,
+        length: f64,
+        velocity: u8,
+    }
+
+    impl Parser
+    {
+        fn error(&self, position: usize, message: &str) -> PatternError
+        {
+            let mut line = 1;
+            let mut column = 1;
+            for c in self.chars.iter().take(position)
+            {
+                if *c == '\n'
+                {
+                    line += 1;
+                    column = 1;
+                }
+                else
+                {
+                    column += 1;
+                }
+            }
+            PatternError{
+                line,
+                column,
+                message: message.to_string(),
+            }
+        }
+
+        fn peek(&self) -> Option<char>
+        {
+            self.chars.get(self.position).cloned()
+        }
+
+        fn skip_space(&mut self)
+        {
+            while let Some(c) = self.peek()
+            {
+                if !c.is_whitespace() && c != '|'
+                {
+                    break;
+                }
+                self.position += 1;
+            }
+        }
+
+        fn number(&mut self) -> Result<u32, PatternError>
+        {
+            let start = self.position;
+            let mut ret: u32 = 0;
+            while let Some(digit) = self.peek().and_then(|c| c.to_digit(10))
+            {
+                ret = match ret.checked_mul(10).and_then(|x| x.checked_add(digit))
+                {
+                    Some(x) => x,
+                    None => return Err(self.error(start, "number is too large")),
+                };
+                self.position += 1;
+            }
+            if self.position == start
+            {
+                return Err(self.error(start, "expected a number"));
+            }
+            Ok(ret)
+        }
+
+        fn sequence(&mut self, close: Option<char>) -> Result<(Vec<Event>, f64), PatternError>
+        {
+            let open = self.position.saturating_sub(1);
+            let mut events = Vec::new();
+            let mut time = 0.0;
+            loop
+            {
+                self.skip_space();
+                match self.peek()
+                {
+                    None =>
+                    {
+                        if close.is_some()
+                        {
+                            return Err(self.error(open, "this is never closed"));
+                        }
+                        return Ok((events, time));
+                    },
+                    Some(c) if Some(c) == close =>
+                    {
+                        self.position += 1;
+                        return Ok((events, time));
+                    },
+                    Some(c) if c == ')' || c == ']' || c == '}' =>
+                    {
+                        return Err(self.error(self.position, &format!("unexpected '{}'", c)));
+                    },
+                    Some(_) =>
+                    {
+                        let start = self.position;
+                        let (item, length) = self.item()?;
+                        if events.len() + item.len() > MAX_EVENTS
+                        {
+                            return Err(self.error(start, &format!("pattern has more than {} notes", MAX_EVENTS)));
+                        }
+                        for mut event in item
+                        {
+                            event.start += time;
+                            events.push(event);
+                        }
+                        time += length;
+                    },
+                }
+            }
+        }
+
+        fn item(&mut self) -> Result<(Vec<Event>, f64), PatternError>
+        {
+            let start = self.position;
+            let c = self.peek().unwrap();
+            let (events, length, modifiers) = match c
+            {
+                '[' =>
+                {
+                    self.position += 1;
+                    let mut notes = Vec::new();
+                    loop
+                    {
+                        self.skip_space();
+                        match self.peek()
+                        {
+                            Some(']') =>
+                            {
+                                self.position += 1;
+                                break;
+                            },
+                            Some(c) if is_note(c) =>
+                            {
+                                let position = self.position;
+                                let key = self.pitch()?;
+                                let own = self.modifiers(false, true, false, true)?;
+                                notes.push((key, own, position));
+                            },
+                            Some(c) =>
+                            {
+                                return Err(self.error(self.position, &format!("expected a note in the chord, not '{}'", c)));
+                            },
+                            None =>
+                            {
+                                return Err(self.error(start, "this is never closed"));
+                            },
+                        }
+                    }
+                    if notes.is_empty()
+                    {
+                        return Err(self.error(start, "empty chord"));
+                    }
+                    let modifiers = self.modifiers(true, true, true, true)?;
+                    let length = self.length;
+                    let events = notes.into_iter().map(|(key, own, position)| Event{
+                        start: 0.0,
+                        length,
+                        key,
+                        velocity: own.velocity.unwrap_or(self.velocity),
+                        tied: own.tie || modifiers.tie,
+                        position,
+                    }).collect();
+                    (events, length, modifiers)
+                },
+                '(' =>
+                {
+                    self.position += 1;
+                    let (events, length) = self.sequence(Some(')'))?;
+                    if events.is_empty() && length == 0.0
+                    {
+                        return Err(self.error(start, "empty group"));
+                    }
+                    (events, length, self.modifiers(false, false, true, false)?)
+                },
+                '{' =>
+                {
+                    self.position += 1;
+                    let default = self.length;
+                    let (mut events, inner) = self.sequence(Some('}'))?;
+                    if inner == 0.0
+                    {
+                        return Err(self.error(start, "empty subdivision"));
+                    }
+                    let modifiers = self.modifiers(true, false, true, false)?;
+                    let length = modifiers.length.unwrap_or(default);
+                    let scale = length / inner;
+                    for event in events.iter_mut()
+                    {
+                        event.start *= scale;
+                        event.length *= scale;
+                    }
+                    (events, length, modifiers)
+                },
+                'r' =>
+                {
+                    self.position += 1;
+                    let modifiers = self.modifiers(true, false, true, false)?;
+                    (Vec::new(), self.length, modifiers)
+                },
+                c if is_note(c) =>
+                {
+                    let key = self.pitch()?;
+                    let modifiers = self.modifiers(true, true, true, true)?;
+                    let event = Event{
+                        start: 0.0,
+                        length: self.length,
+                        key,
+                        velocity: self.velocity,
+                        tied: modifiers.tie,
+                        position: start,
+                    };
+                    (vec![event], self.length, modifiers)
+                },
+                c =>
+                {
+                    return Err(self.error(start, &format!("unexpected '{}'", c)));
+                },
+            };
+
+            let repeat = modifiers.repeat.unwrap_or(1);
+            if events.len().saturating_mul(repeat as usize) > MAX_EVENTS
+            {
+                return Err(self.error(modifiers.repeat_at, &format!("repeat makes more than {} notes", MAX_EVENTS)));
+            }
+            let mut ret = Vec::with_capacity(events.len() * repeat as usize);
+            for i in 1..repeat
+            {
+                for event in events.iter()
+                {
+                    ret.push(Event{
+                        start: event.start + length * i as f64,
+                        ..*event
+                    });
+                }
+            }
+            ret.extend(events);
+            Ok((ret, length * repeat as f64))
+        }
+
+        fn pitch(&mut self) -> Result<u8, PatternError>
+        {
+            let start = self.position;
+            let semitone = match self.peek().map(|c| c.to_ascii_uppercase())
+            {
+                Some('C') => 0,
+                Some('D') => 2,
+                Some('E') => 4,
+                Some('F') => 5,
+                Some('G') => 7,
+                Some('A') => 9,
+                _ => 11,
+            };
+            self.position += 1;
+            let mut accidental: i32 = 0;
+            loop
+            {
+                match self.peek()
+                {
+                    Some('#') => accidental += 1,
+                    Some('b') => accidental -= 1,
+                    _ => break,
+                }
+                self.position += 1;
+            }
+            if let Some(digit) = self.peek().and_then(|c| c.to_digit(10))
+            {
+                self.octave = digit as u8;
+                self.position += 1;
+                if let Some(c) = self.peek().filter(|c| *c == '#' || *c == 'b')
+                {
+                    return Err(self.error(self.position, &format!("'{}' must come before the octave", c)));
+                }
+            }
+            let key = 12 * (self.octave as i32 + 1) + semitone + accidental;
+            if !(12..=127).contains(&key)
+            {
+                return Err(self.error(start, "note is out of range"));
+            }
+            Ok(key as u8)
+        }
+
+        fn modifiers(&mut self, length: bool, velocity: bool, repeat: bool, tie: bool) -> Result<Modifiers, PatternError>
+        {
+            let mut ret = Modifiers::default();
+            loop
+            {
+                let start = self.position;
+                match self.peek()
+                {
+                    Some('/') | Some(':') =>
+                    {
+                        if !length
+                        {
+                            return Err(self.error(start, "no length can be given here"));
+                        }
+                        if ret.length.is_some()
+                        {
+                            return Err(self.error(start, "length is given twice"));
+                        }
+                        let fraction = self.peek() == Some('/');
+                        self.position += 1;
+                        let (numerator, denominator) = if fraction
+                        {
+                            (1, self.number()?)
+                        }
+                        else
+                        {
+                            let numerator = self.number()?;
+                            if self.peek() == Some('/')
+                            {
+                                self.position += 1;
+                                (numerator, self.number()?)
+                            }
+                            else
+                            {
+                                (numerator, 1)
+                            }
+                        };
+                        if numerator == 0 || denominator == 0
+                        {
+                            return Err(self.error(start, "length must not be zero"));
+                        }
+                        let mut value = numerator as f64 / denominator as f64;
+                        let mut dot = value;
+                        while self.peek() == Some('.')
+                        {
+                            dot /= 2.0;
+                            value += dot;
+                            self.position += 1;
+                        }
+                        ret.length = Some(value);
+                        self.length = value;
+                    },
+                    Some('@') =>
+                    {
+                        if !velocity
+                        {
+                            return Err(self.error(start, "no velocity can be given here"));
+                        }
+                        if ret.velocity.is_some()
+                        {
+                            return Err(self.error(start, "velocity is given twice"));
+                        }
+                        self.position += 1;
+                        let value = self.number()?;
+                        if value > 127
+                        {
+                            return Err(self.error(start, "velocity must be 0-127"));
+                        }
+                        ret.velocity = Some(value as u8);
+                        self.velocity = value as u8;
+                    },
+                    Some('*') =>
+                    {
+                        if !repeat
+                        {
+                            return Err(self.error(start, "nothing can be repeated here"));
+                        }
+                        if ret.repeat.is_some()
+                        {
+                            return Err(self.error(start, "repeat is given twice"));
+                        }
+                        self.position += 1;
+                        let value = self.number()?;
+                        if value == 0
+                        {
+                            return Err(self.error(start, "repeat must not be zero"));
+                        }
+                        if value > MAX_REPEAT
+                        {
+                            return Err(self.error(start, &format!("repeat must be at most {}", MAX_REPEAT)));
+                        }
+                        ret.repeat = Some(value);
+                        ret.repeat_at = start;
+                    },
+                    Some('~') =>
+                    {
+                        if !tie
+                        {
+                            return Err(self.error(start, "only notes can be tied"));
+                        }
+                        self.position += 1;
+                        ret.tie = true;
+                    },
+                    _ =>
+                    {
+                        return Ok(ret);
+                    },
+                }
+            }
+        }
+    }
+
+    fn is_note(c: char) -> bool
+    {
+        matches!(c.to_ascii_uppercase(), 'A'..='G')
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use instrumentation::interface::instrument::Probe::Probe;
+    use sequencing::sequencing::partialsequencer::PartialSequencer::PartialSequencer;
+    use sequencing::sequencing::pattern::Pattern::Pattern;
+    use sequencing::sequencing::pattern::Pattern::PatternEvent;
+    use sequencing::timing::timing::Beat::BeatValue;
+
+    fn event(start: f64, length: f64, key: u8, velocity: u8) -> PatternEvent
+    {
+        PatternEvent{
+            start,
+            length,
+            key,
+            velocity,
+        }
+    }
+
+    fn keys(pattern: &Pattern) -> Vec<u8>
+    {
+        pattern.events.iter().map(|x| x.key).collect()
+    }
+
+    #[test]
+    fn notes_carry_octave_length_and_velocity()
+    {
+        let pattern = Pattern::parse("C4 D#/8 Eb5@90 | r F").unwrap();
+        assert_eq!(pattern.events, vec![
+            event(0.0, 0.25, 60, 100),
+            event(0.25, 0.125, 63, 100),
+            event(0.375, 0.125, 75, 90),
+            event(0.625, 0.125, 77, 90),
+        ]);
+        assert_eq!(pattern.length, 0.75);
+        assert_eq!(pattern.bars(), 1);
+    }
+
+    #[test]
+    fn lengths_take_fractions_bars_and_dots()
+    {
+        let pattern = Pattern::parse("C:3/8 D/4. E:2").unwrap();
+        let lengths: Vec<f64> = pattern.events.iter().map(|x| x.length).collect();
+        assert_eq!(lengths, vec![0.375, 0.375, 2.0]);
+        assert_eq!(pattern.bars(), 3);
+    }
+
+    #[test]
+    fn chords_repeats_and_subdivisions()
+    {
+        let pattern = Pattern::parse("[C4 E G]/2 (A/8 B)*2 {C D E}/4").unwrap();
+        assert_eq!(keys(&pattern), vec![60, 64, 67, 69, 71, 69, 71, 60, 62, 64]);
+        let starts: Vec<f64> = pattern.events.iter().map(|x| x.start).collect();
+        assert_eq!(&starts[..7], &[0.0, 0.0, 0.0, 0.5, 0.625, 0.75, 0.875]);
+        assert!((pattern.events[8].start - (1.0 + 1.0 / 12.0)).abs() < 1e-9);
+        assert!((pattern.events[9].length - 1.0 / 12.0).abs() < 1e-9);
+        assert!((pattern.length - 1.25).abs() < 1e-9);
+    }
+
+    #[test]
+    fn ties_join_notes_of_the_same_pitch()
+    {
+        let pattern = Pattern::parse("C~ C/8 D").unwrap();
+        assert_eq!(pattern.events, vec![event(0.0, 0.375, 60, 100), event(0.375, 0.125, 62, 100)]);
+        assert!(Pattern::parse("C~ D").is_err());
+    }
+
+    #[test]
+    fn errors_point_at_the_problem()
+    {
+        let error = Pattern::parse("C D\n  [E X]").unwrap_err();
+        assert_eq!((error.line, error.column), (2, 6));
+        let error = Pattern::parse("C D@200").unwrap_err();
+        assert_eq!((error.line, error.column, error.message.as_str()), (1, 4, "velocity must be 0-127"));
+        assert!(Pattern::parse("(C D").is_err());
+        assert!(Pattern::parse("C*0").is_err());
+        assert!(Pattern::parse("r@90").is_err());
+    }
+
+    #[test]
+    fn dotted_grouped_and_repeated_items_last_as_written()
+    {
+        let pattern = Pattern::parse("C/4.. (D/8 E)*3 [F A]/2. r/16*2").unwrap();
+        assert_eq!(pattern.events, vec![
+            event(0.0, 0.4375, 60, 100),
+            event(0.4375, 0.125, 62, 100),
+            event(0.5625, 0.125, 64, 100),
+            event(0.6875, 0.125, 62, 100),
+            event(0.8125, 0.125, 64, 100),
+            event(0.9375, 0.125, 62, 100),
+            event(1.0625, 0.125, 64, 100),
+            event(1.1875, 0.75, 65, 100),
+            event(1.1875, 0.75, 69, 100),
+        ]);
+        assert_eq!(pattern.length, 2.0625);
+    }
+
+    #[test]
+    fn repeats_are_limited_where_they_are_written()
+    {
+        let error = Pattern::parse("C D*2000").unwrap_err();
+        assert_eq!((error.line, error.column, error.message.as_str()), (1, 4, "repeat must be at most 1024"));
+        let error = Pattern::parse("C\n(D*1000)*1000").unwrap_err();
+        assert_eq!((error.line, error.column, error.message.as_str()), (2, 9, "repeat makes more than 65536 notes"));
+        let error = Pattern::parse("(C*1000)*60 (D*1000)*10").unwrap_err();
+        assert_eq!((error.line, error.column, error.message.as_str()), (1, 13, "pattern has more than 65536 notes"));
+    }
+
+    #[test]
+    fn accidentals_after_the_octave_are_errors()
+    {
+        let error = Pattern::parse("Db4 C4b").unwrap_err();
+        assert_eq!((error.line, error.column, error.message.as_str()), (1, 7, "'b' must come before the octave"));
+        let error = Pattern::parse("C4#").unwrap_err();
+        assert_eq!((error.line, error.column), (1, 3));
+        assert_eq!(keys(&Pattern::parse("C4 b").unwrap()), vec![60, 71]);
+    }
+
+    #[test]
+    fn patterns_load_into_the_sequencer()
+    {
+        //One bar at 120 bpm is 500ms, see BeatValue::from_bpm.
+        let pattern = Pattern::parse("[C E] r:1 G/2").unwrap();
+        let beat = BeatValue::from_bpm(120.0);
+        let bar = beat.u64_from_beats(1, 1).unwrap();
+        let notes = pattern.to_notes(&beat);
+        assert_eq!(notes.iter().map(|x| (x.0, x.1.key())).collect::<Vec<(u64, u8)>>(),
+                   vec![(0, 60), (0, 64), (bar * 5 / 4, 67)]);
+
+        let mut sequencer = PartialSequencer::new(120.0, Probe::new());
+        sequencer.load_pattern(&pattern);
+        assert_eq!(sequencer.bars, 2);
+        assert_eq!(sequencer.loop_length(), bar * 2);
+        assert_eq!(sequencer.note_hash[&0].len(), 2);
+        assert_eq!(sequencer.note_hash[&(bar * 5 / 4)][0].length as u64, bar / 2);
+    }
+
+    #[test]
+    fn voices_merge_chords_and_cut_overlaps()
+    {
+        let mut pattern = Pattern::parse("[C4 E]/4 G/8@60 r/8").unwrap();
+        pattern.events.insert(0, event(0.0, 0.5, 64, 80));
+        assert_eq!(pattern.voice(16), vec![
+            (0, 4, vec![(60, 100), (64, 80)]),
+            (4, 2, vec![(67, 60)]),
+        ]);
+    }
+
+    #[test]
+    fn ties_are_joined_even_next_to_nan_starts()
+    {
+        let (joined, dangling) = Pattern::join_ties(vec![
+            (event(0.0, 0.25, 60, 100), true),
+            (event(f64::NAN, 0.25, 62, 100), false),
+            (event(0.25, 0.25, 60, 100), false),
+        ]);
+        assert_eq!(joined.len(), 2);
+        assert_eq!(joined[0].length, 0.5);
+        assert!(dangling.is_empty());
+    }
+}
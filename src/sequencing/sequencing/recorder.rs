@@ -0,0 +1,267 @@
+///Recorder: Turns incoming MIDI notes into sequencer notes. Messages are
+///placed on the timeline with the transport position they arrived at and
+///collected into a take, which is written into a PartialSequencer with
+///commit.
+#[allow(non_snake_case)]
+pub mod Recorder
+{
+    use instrumentation::external::midi::MidiMessage::MidiMessage;
+    use instrumentation::interface::instrument::Instrument;
+    use sequencing::sequencing::partialsequencer::PartialSequencer::PartialSequencer;
+    use sequencing::timing::transport::Transport::Transport;
+    use sequencing::tonation::note::NoteCollections::RawNote;
+    use sequencing::tonation::note::NotePrimitives;
+    use std::collections::HashMap;
+    use std::sync::mpsc::Receiver;
+    use std::time::Instant;
+
+    ///RecordMode: Overdub adds the take to the notes already in the
+    ///sequence, Replace clears the sequence first.
+    #[derive(Clone, Copy, Debug, Eq, PartialEq)]
+    pub enum RecordMode
+    {
+        Overdub,
+        Replace,
+    }
+
+    pub struct Recorder
+    {
+        pub mode: RecordMode,
+        pub quantize: Option<u64>,
+        pub channel: Option<u8>,
+        held: HashMap<u8, (u64, u8)>,
+        take: Vec<(u64, RawNote)>,
+    }
+
+    impl Recorder
+    {
+        ///Recorder::new(mode: RecordMode) -> Recorder: Records from every
+        ///channel without quantizing.
+        pub fn new(mode: RecordMode) -> Recorder
+        {
+            Recorder{
+                mode,
+                quantize: None,
+                channel: None,
+                held: HashMap::new(),
+                take: Vec::new(),
+            }
+        }
+
+        ///Recorder.quantize(grid: u64) -> Recorder: Builder style input
+        ///quantize. Note starts are moved to the nearest multiple of grid ms.
+        pub fn quantize(mut self, grid: u64) -> Recorder
+        {
+            self.quantize = if grid > 0 { Some(grid) } else { None };
+            self
+        }
+
+        ///Recorder.channel(channel: u8) -> Recorder: Builder style channel
+        ///filter. Messages on other channels are ignored.
+        pub fn channel(mut self, channel: u8) -> Recorder
+        {
+            self.channel = Some(channel);
+            self
+        }
+
+        ///Recorder.take() -> &Vec<(u64, RawNote)>: Notes recorded since the
+        ///last commit, keyed by their (unquantized) start in ms.
+        pub fn take(&self) -> &Vec<(u64, RawNote)>
+        {
+            &self.take
+        }
+
+        ///Recorder.held() -> usize: Notes that have started but not ended.
+        pub fn held(&self) -> usize
+        {
+            self.held.len()
+        }
+
+        ///Recorder.message(message: MidiMessage, time: u64): Records a message
+        ///that arrived at time ms. A note on with velocity 0 is a note off.
+        ///Keys outside of the registers we have are dropped.
+        pub fn message(&mut self, message: MidiMessage, time: u64)
+        {
+            match message
+            {
+                MidiMessage::NoteOn{ channel, key, velocity } if velocity > 0 && self.listens_to(channel) =>
+                {
+                    self.end(key, time);
+                    self.held.insert(key, (time, velocity));
+                },
+                MidiMessage::NoteOn{ channel, key, .. } | MidiMessage::NoteOff{ channel, key, .. } if self.listens_to(channel) =>
+                {
+                    self.end(key, time);
+                },
+                _ =>
+                {
+                },
+            }
+        }
+
+        ///Recorder.receive(receiver: &Receiver<(Instant, MidiMessage)>, transport: &Transport) -> usize:
+        ///Records every message waiting on receiver, as returned by
+        ///MidiIn.listen, placing it at the transport position it arrived at.
+        ///Returns the amount of messages handled.
+        pub fn receive(&mut self, receiver: &Receiver<(Instant, MidiMessage)>, transport: &Transport) -> usize
+        {
+            let mut count = 0;
+            while let Ok((time, message)) = receiver.try_recv()
+            {
+                self.message(message, transport.position_at(time));
+                count += 1;
+            }
+            count
+        }
+
+        ///Recorder.release_all(time: u64): Ends every held note at time ms,
+        ///as when recording stops with keys still down.
+        pub fn release_all(&mut self, time: u64)
+        {
+            let keys: Vec<u8> = self.held.keys().cloned().collect();
+            for key in keys
+            {
+                self.end(key, time);
+            }
+        }
+
+        ///Recorder.commit(sequencer: &mut PartialSequencer<T>) -> usize: Writes
+        ///the take into sequencer, wrapping it around the sequencer's loop,
+        ///and starts a new take. Returns the amount of notes written.
+        pub fn commit<T: Instrument::PlayableInstrument>(&mut self, sequencer: &mut PartialSequencer<T>) -> usize
+        {
+            let bar = sequencer.loop_length().max(1);
+            if self.mode == RecordMode::Replace
+            {
+                sequencer.note_hash.clear();
+            }
+            let count = self.take.len();
+            for (time, note) in self.take.drain(..)
+            {
+                let time = match self.quantize
+                {
+                    Some(grid) => (time + grid / 2) / grid * grid,
+                    None => time,
+                };
+                sequencer.push_note(time % bar, note);
+            }
+            count
+        }
+
+        fn listens_to(&self, channel: u8) -> bool
+        {
+            self.channel.is_none_or(|x| x == channel)
+        }
+
+        fn end(&mut self, key: u8, time: u64)
+        {
+            let (start, velocity) = match self.held.remove(&key)
+            {
+                Some(x) => x,
+                None => return,
+            };
+            if let Some((p, r)) = NotePrimitives::midi_to_primitives(key)
+            {
+                let length = if time > start { time - start } else { 1 };
+                self.take.push((start, RawNote::new(p, r, 0.0, velocity as f32, length as u32)));
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use instrumentation::external::midi::Loopback;
+    use instrumentation::external::midi::MidiInput::MidiIn;
+    use instrumentation::external::midi::MidiMessage::MidiMessage;
+    use instrumentation::external::midi::MidiOutput::MidiOut;
+    use instrumentation::interface::instrument::Probe::Probe;
+    use sequencing::sequencing::partialsequencer::PartialSequencer::PartialSequencer;
+    use sequencing::sequencing::recorder::Recorder::RecordMode;
+    use sequencing::sequencing::recorder::Recorder::Recorder;
+    use sequencing::tonation::note::NoteCollections::RawNote;
+    use sequencing::tonation::note::NotePrimitives::Register;
+    use sequencing::tonation::note::NotePrimitives::Semitone;
+
+    ///Sends each message over a loopback and records what comes out of
+    ///the other end at the time given with it.
+    fn record(recorder: &mut Recorder, messages: &[(u64, MidiMessage)])
+    {
+        let (output, input) = Loopback::pair();
+        let mut output = MidiOut::new(output);
+        for &(_, message) in messages.iter()
+        {
+            output.send(message).unwrap();
+        }
+        drop(output);
+
+        let mut input = MidiIn::new(input);
+        for &(time, _) in messages.iter()
+        {
+            let message = input.read().unwrap().unwrap();
+            recorder.message(message, time);
+        }
+        assert!(input.read().unwrap().is_none());
+    }
+
+    fn note(key: u8, on: u64, off: u64) -> Vec<(u64, MidiMessage)>
+    {
+        vec![
+            (on, MidiMessage::NoteOn{ channel: 0, key, velocity: 100 }),
+            (off, MidiMessage::NoteOff{ channel: 0, key, velocity: 0 }),
+        ]
+    }
+
+    //One bar at 120 bpm is a 500ms loop.
+    fn sequencer() -> PartialSequencer<Probe>
+    {
+        let mut sequencer = PartialSequencer::new(120.0, Probe::new());
+        sequencer.push_time_to_note(0, 4, RawNote::new(Semitone::C, Register::C4, 0.0, 100.0, 100));
+        sequencer
+    }
+
+    fn starts(sequencer: &PartialSequencer<Probe>) -> Vec<u64>
+    {
+        let mut ret: Vec<u64> = sequencer.note_hash.keys().cloned().collect();
+        ret.sort();
+        ret
+    }
+
+    #[test]
+    fn overdub_keeps_the_notes_already_there()
+    {
+        let mut sequencer = sequencer();
+        let mut recorder = Recorder::new(RecordMode::Overdub);
+        record(&mut recorder, &note(64, 100, 200));
+        assert_eq!(recorder.commit(&mut sequencer), 1);
+        assert_eq!(starts(&sequencer), vec![0, 100]);
+        assert_eq!(sequencer.note_hash[&100][0].length, 100);
+        assert_eq!(sequencer.note_hash[&100][0].key(), 64);
+    }
+
+    #[test]
+    fn replace_clears_the_sequence_first()
+    {
+        let mut sequencer = sequencer();
+        let mut recorder = Recorder::new(RecordMode::Replace);
+        record(&mut recorder, &note(64, 100, 200));
+        recorder.commit(&mut sequencer);
+        assert_eq!(starts(&sequencer), vec![100]);
+    }
+
+    #[test]
+    fn input_quantize_moves_starts_to_the_grid_and_wraps()
+    {
+        let mut sequencer = sequencer();
+        let mut recorder = Recorder::new(RecordMode::Replace).quantize(125);
+        let mut messages = note(64, 130, 240);
+        messages.extend(note(67, 490, 600));
+        record(&mut recorder, &messages);
+        assert_eq!(recorder.held(), 0);
+        assert_eq!(recorder.commit(&mut sequencer), 2);
+        assert_eq!(starts(&sequencer), vec![0, 125]);
+        assert_eq!(sequencer.note_hash[&0][0].key(), 67);
+        assert_eq!(sequencer.note_hash[&125][0].length, 110);
+    }
+}
@@ -0,0 +1,529 @@
+///ClockPrimitives: Conversions between MIDI clock ticks, song position
+///pointers and our milliseconds. MIDI clock runs at 24 ticks per beat and
+///song positions count sixteenth notes (6 ticks).
+#[allow(non_snake_case)]
+pub mod ClockPrimitives
+{
+    use sequencing::timing::timing::Beat::BeatValue;
+    use std::time::Duration;
+
+    ///Clock ticks per beat.
+    pub const PPQN: u64 = 24;
+    ///Clock ticks per song position step.
+    pub const TICKS_PER_POSITION: u64 = 6;
+
+    fn seconds(duration: Duration) -> f64
+    {
+        duration.as_secs() as f64 + duration.subsec_nanos() as f64 / 1e9
+    }
+
+    ///ClockPrimitives::tick_ms(beat: &BeatValue) -> f64: Time between two
+    ///clock ticks at the tempo of beat.
+    pub fn tick_ms(beat: &BeatValue) -> f64
+    {
+        seconds(beat.duration()) * 1000.0 / PPQN as f64
+    }
+
+    ///ClockPrimitives::ms_to_ticks(ms: u64, beat: &BeatValue) -> u64: Ticks
+    ///that have passed ms into the song, rounded down.
+    pub fn ms_to_ticks(ms: u64, beat: &BeatValue) -> u64
+    {
+        let tick = tick_ms(beat);
+        if tick > 0.0 { (ms as f64 / tick) as u64 } else { 0 }
+    }
+
+    ///ClockPrimitives::ticks_to_ms(ticks: u64, beat: &BeatValue) -> u64
+    pub fn ticks_to_ms(ticks: u64, beat: &BeatValue) -> u64
+    {
+        (ticks as f64 * tick_ms(beat)) as u64
+    }
+
+    ///ClockPrimitives::song_position(ms: u64, beat: &BeatValue) -> u16: The
+    ///song position at or before ms, clamped to what the message can hold.
+    pub fn song_position(ms: u64, beat: &BeatValue) -> u16
+    {
+        (ms_to_ticks(ms, beat) / TICKS_PER_POSITION).min(0x3fff) as u16
+    }
+
+    ///ClockPrimitives::bpm_from_interval(interval: Duration) -> f32: Tempo of
+    ///a clock ticking every interval.
+    pub fn bpm_from_interval(interval: Duration) -> f32
+    {
+        let secs = seconds(interval);
+        if secs > 0.0 { (60.0 / (secs * PPQN as f64)) as f32 } else { 0.0 }
+    }
+}
+
+///ClockMaster: Generates MIDI clock from a Transport. poll is called from
+///the playback loop (at least once per tick, about every 20ms at 120 bpm)
+///and returns the messages due since the last call: Start when playback
+///starts at the top, a song position and Continue when it starts anywhere
+///else or is relocated, Stop when it stops, and a Clock for every tick
+///passed while playing.
+#[allow(non_snake_case)]
+pub mod ClockMaster
+{
+    use instrumentation::external::midi::MidiMessage::MidiMessage;
+    use instrumentation::external::midi::MidiOutput::MidiOut;
+    use sequencing::timing::clock::ClockPrimitives;
+    use sequencing::timing::clock::ClockPrimitives::TICKS_PER_POSITION;
+    use sequencing::timing::timing::Beat::BeatValue;
+    use sequencing::timing::transport::Transport::Transport;
+    use std::io;
+    use std::io::Write;
+
+    pub struct ClockMaster
+    {
+        beat: BeatValue,
+        playing: bool,
+        //Tick and position (ms) the tick count is measured from.
+        base_tick: u64,
+        base_ms: u64,
+        ticks: u64,
+        last_position: u64,
+    }
+
+    impl ClockMaster
+    {
+        pub fn new(beat: BeatValue) -> ClockMaster
+        {
+            ClockMaster{
+                beat,
+                playing: false,
+                base_tick: 0,
+                base_ms: 0,
+                ticks: 0,
+                last_position: 0,
+            }
+        }
+
+        pub fn beat(&self) -> BeatValue
+        {
+            self.beat
+        }
+
+        ///ClockMaster.set_beat(beat: BeatValue): Changes the tempo from the
+        ///next tick on.
+        pub fn set_beat(&mut self, beat: BeatValue)
+        {
+            //The last tick sent is the one at last_position.
+            self.base_ms = self.last_position;
+            self.base_tick = self.ticks.saturating_sub(1);
+            self.beat = beat;
+        }
+
+        ///ClockMaster.ticks() -> u64: Clock ticks sent since the song start.
+        pub fn ticks(&self) -> u64
+        {
+            self.ticks
+        }
+
+        fn tick_at(&self, ms: u64) -> u64
+        {
+            if ms < self.base_ms
+            {
+                return self.base_tick;
+            }
+            self.base_tick + ClockPrimitives::ms_to_ticks(ms - self.base_ms, &self.beat)
+        }
+
+        fn relocate(&mut self, ms: u64, messages: &mut Vec<MidiMessage>)
+        {
+            let position = ClockPrimitives::song_position(ms, &self.beat);
+            self.ticks = position as u64 * TICKS_PER_POSITION;
+            self.base_tick = self.ticks;
+            self.base_ms = ClockPrimitives::ticks_to_ms(self.ticks, &self.beat);
+            messages.push(MidiMessage::SongPosition(position));
+        }
+
+        ///ClockMaster.poll(transport: &Transport) -> Vec<MidiMessage>
+        pub fn poll(&mut self, transport: &Transport) -> Vec<MidiMessage>
+        {
+            let mut ret = Vec::new();
+            let position = transport.position();
+
+            match (self.playing, transport.is_playing())
+            {
+                (false, true) =>
+                {
+                    if position == 0
+                    {
+                        self.ticks = 0;
+                        self.base_tick = 0;
+                        self.base_ms = 0;
+                        ret.push(MidiMessage::Start);
+                    }
+                    else
+                    {
+                        self.relocate(position, &mut ret);
+                        ret.push(MidiMessage::Continue);
+                    }
+                    //The first tick follows Start right away.
+                    ret.push(MidiMessage::Clock);
+                    self.ticks += 1;
+                    self.playing = true;
+                },
+                (true, false) =>
+                {
+                    ret.push(MidiMessage::Stop);
+                    self.playing = false;
+                },
+                (true, true) =>
+                {
+                    let tick = ClockPrimitives::tick_ms(&self.beat) as u64;
+                    if position < self.last_position || position > self.last_position + tick.max(1) * ClockPrimitives::PPQN
+                    {
+                        ret.push(MidiMessage::Stop);
+                        self.relocate(position, &mut ret);
+                        ret.push(MidiMessage::Continue);
+                    }
+                },
+                (false, false) =>
+                {
+                },
+            }
+
+            if self.playing
+            {
+                //Ticks are numbered from 0 so tick n is due at its start.
+                let due = self.tick_at(position) + 1;
+                while self.ticks < due
+                {
+                    ret.push(MidiMessage::Clock);
+                    self.ticks += 1;
+                }
+            }
+            self.last_position = position;
+            ret
+        }
+
+        ///ClockMaster.send<W: Write>(transport: &Transport, output: &mut MidiOut<W>) -> io::Result<usize>:
+        ///Polls and sends the messages to output, returning how many were
+        ///sent.
+        pub fn send<W: Write>(&mut self, transport: &Transport, output: &mut MidiOut<W>) -> io::Result<usize>
+        {
+            let messages = self.poll(transport);
+            for i in messages.iter()
+            {
+                output.send(*i)?;
+            }
+            Ok(messages.len())
+        }
+    }
+}
+
+///ClockSlave: Follows incoming MIDI clock. The time between ticks is
+///smoothed to get a steady tempo out of a jittery clock, and start, stop,
+///continue and song position messages move a transport along with it.
+#[allow(non_snake_case)]
+pub mod ClockSlave
+{
+    use instrumentation::external::midi::MidiMessage::MidiMessage;
+    use instrumentation::interface::instrument::Instrument;
+    use sequencing::sequencing::partialsequencer::PartialSequencer::PartialSequencer;
+    use sequencing::timing::clock::ClockPrimitives;
+    use sequencing::timing::clock::ClockPrimitives::TICKS_PER_POSITION;
+    use sequencing::timing::timing::Beat::BeatValue;
+    use sequencing::timing::transport::Transport::Transport;
+    use std::time::Duration;
+    use std::time::Instant;
+
+    ///Default weight of a new tick interval in the smoothed interval.
+    pub const DEFAULT_SMOOTHING: f32 = 0.1;
+    ///Longest gap between ticks before the clock is considered stopped.
+    pub const TIMEOUT_MS: u64 = 1000;
+
+    pub struct ClockSlave
+    {
+        pub smoothing: f32,
+        interval: Option<f64>,
+        last_tick: Option<Instant>,
+        playing: bool,
+        ticks: u64,
+        outliers: u64,
+        //Set when the next clock is the tick at ticks itself, after a start
+        //or song position.
+        pending: bool,
+    }
+
+    fn seconds(duration: Duration) -> f64
+    {
+        duration.as_secs() as f64 + duration.subsec_nanos() as f64 / 1e9
+    }
+
+    impl ClockSlave
+    {
+        pub fn new() -> ClockSlave
+        {
+            ClockSlave{
+                smoothing: DEFAULT_SMOOTHING,
+                interval: None,
+                last_tick: None,
+                playing: false,
+                ticks: 0,
+                pending: true,
+                outliers: 0,
+            }
+        }
+
+        ///ClockSlave.smoothing(smoothing: f32) -> ClockSlave: Builder style
+        ///smoothing from 0.0 (exclusive, never follows) to 1.0 (no
+        ///smoothing).
+        pub fn smoothing(mut self, smoothing: f32) -> ClockSlave
+        {
+            self.smoothing = smoothing.clamp(0.001, 1.0);
+            self
+        }
+
+        ///ClockSlave.message(message: MidiMessage, time: Instant): Handles a
+        ///message received at time. Anything but clock messages is ignored.
+        pub fn message(&mut self, message: MidiMessage, time: Instant)
+        {
+            match message
+            {
+                MidiMessage::Clock =>
+                {
+                    if let Some(last) = self.last_tick
+                    {
+                        let interval = if time > last { seconds(time - last) } else { 0.0 };
+                        if interval * 1000.0 > TIMEOUT_MS as f64
+                        {
+                            self.interval = None;
+                        }
+                        else if interval > 0.0
+                        {
+                            self.interval = match self.interval
+                            {
+                                //Ticks more than twice as far apart or as close
+                                //as usual are jitter or a burst, unless a whole
+                                //beat of them says the tempo really changed.
+                                Some(x) if (interval > x * 2.0 || interval < x / 2.0) && self.outliers < ClockPrimitives::PPQN =>
+                                {
+                                    self.outliers += 1;
+                                    Some(x)
+                                },
+                                Some(x) if self.outliers < ClockPrimitives::PPQN =>
+                                {
+                                    self.outliers = 0;
+                                    Some(x + (interval - x) * self.smoothing as f64)
+                                },
+                                _ =>
+                                {
+                                    self.outliers = 0;
+                                    Some(interval)
+                                },
+                            };
+                        }
+                    }
+                    self.last_tick = Some(time);
+                    if self.playing && self.pending
+                    {
+                        self.pending = false;
+                    }
+                    else if self.playing
+                    {
+                        self.ticks += 1;
+                    }
+                },
+                MidiMessage::Start =>
+                {
+                    self.ticks = 0;
+                    self.pending = true;
+                    self.playing = true;
+                },
+                MidiMessage::Continue =>
+                {
+                    self.playing = true;
+                },
+                MidiMessage::Stop =>
+                {
+                    self.playing = false;
+                },
+                MidiMessage::SongPosition(position) =>
+                {
+                    self.ticks = position as u64 * TICKS_PER_POSITION;
+                    self.pending = true;
+                },
+                _ =>
+                {
+                },
+            }
+        }
+
+        ///ClockSlave.is_playing() -> bool
+        pub fn is_playing(&self) -> bool
+        {
+            self.playing
+        }
+
+        ///ClockSlave.ticks() -> u64: The tick the song is at.
+        pub fn ticks(&self) -> u64
+        {
+            self.ticks
+        }
+
+        ///ClockSlave.bpm() -> Option<f32>: The smoothed tempo, None until two
+        ///ticks have been received.
+        pub fn bpm(&self) -> Option<f32>
+        {
+            self.interval.map(|x| ClockPrimitives::bpm_from_interval(Duration::from_nanos((x * 1e9) as u64)))
+        }
+
+        ///ClockSlave.beat_value() -> Option<BeatValue>: The smoothed tempo as a
+        ///BeatValue.
+        pub fn beat_value(&self) -> Option<BeatValue>
+        {
+            self.interval.map(|x| BeatValue::from_ms(Duration::from_nanos((x * 1e9) as u64 * ClockPrimitives::PPQN)))
+        }
+
+        ///ClockSlave.position() -> Option<u64>: The song position in ms at the
+        ///current tempo.
+        pub fn position(&self) -> Option<u64>
+        {
+            self.beat_value().map(|x| ClockPrimitives::ticks_to_ms(self.ticks, &x))
+        }
+
+        ///ClockSlave.apply<T>(sequencer: &mut PartialSequencer<T>) -> bool:
+        ///Moves the sequencer to the clock's tempo if it is known and has
+        ///changed by more than 0.05 bpm.
+        pub fn apply<T: Instrument::PlayableInstrument>(&self, sequencer: &mut PartialSequencer<T>) -> bool
+        {
+            match self.bpm()
+            {
+                Some(bpm) if (bpm - sequencer.local_beat_builder.bpm()).abs() > 0.05 =>
+                {
+                    sequencer.set_tempo(bpm);
+                    true
+                },
+                _ =>
+                {
+                    false
+                },
+            }
+        }
+
+        ///ClockSlave.sync(transport: &mut Transport): Starts, stops and
+        ///relocates transport to follow the clock.
+        pub fn sync(&self, transport: &mut Transport)
+        {
+            if self.playing == transport.is_playing()
+            {
+                return;
+            }
+            let position = self.position().unwrap_or(0);
+            if self.playing
+            {
+                transport.locate(position);
+                transport.resume();
+            }
+            else
+            {
+                transport.stop();
+                transport.locate(position);
+            }
+        }
+    }
+
+    impl Default for ClockSlave
+    {
+        fn default() -> ClockSlave
+        {
+            ClockSlave::new()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use instrumentation::external::midi::MidiMessage::MidiMessage;
+    use sequencing::timing::clock::ClockMaster::ClockMaster;
+    use sequencing::timing::clock::ClockPrimitives;
+    use sequencing::timing::clock::ClockSlave::ClockSlave;
+    use sequencing::timing::timing::Beat::BeatValue;
+    use sequencing::timing::transport::Transport::Transport;
+    use std::time::Duration;
+    use std::time::Instant;
+
+    fn clocks(messages: &[MidiMessage]) -> usize
+    {
+        messages.iter().filter(|x| **x == MidiMessage::Clock).count()
+    }
+
+    #[test]
+    fn conversions_follow_the_tempo()
+    {
+        //At 125 bpm a beat is 480ms, so a tick is 20ms.
+        let beat = BeatValue::from_bpm(125.0);
+        assert_eq!(ClockPrimitives::tick_ms(&beat), 20.0);
+        assert_eq!(ClockPrimitives::ms_to_ticks(119, &beat), 5);
+        assert_eq!(ClockPrimitives::ticks_to_ms(24, &beat), 480);
+        assert_eq!(ClockPrimitives::song_position(960, &beat), 8);
+        assert!((ClockPrimitives::bpm_from_interval(Duration::from_millis(20)) - 125.0).abs() < 0.01);
+    }
+
+    #[test]
+    fn master_sends_start_ticks_and_stop()
+    {
+        let mut master = ClockMaster::new(BeatValue::from_bpm(125.0));
+        let mut transport = Transport::new();
+        assert!(master.poll(&transport).is_empty());
+
+        transport.start();
+        assert_eq!(master.poll(&transport), vec![MidiMessage::Start, MidiMessage::Clock]);
+        transport.locate(110);
+        let messages = master.poll(&transport);
+        assert_eq!(clocks(&messages), 5);
+        assert_eq!(master.ticks(), 6);
+
+        transport.stop();
+        assert_eq!(master.poll(&transport), vec![MidiMessage::Stop]);
+    }
+
+    #[test]
+    fn master_relocates_with_a_song_position()
+    {
+        let mut master = ClockMaster::new(BeatValue::from_bpm(125.0));
+        let mut transport = Transport::new();
+        transport.locate(960);
+        transport.resume();
+        assert_eq!(master.poll(&transport), vec![MidiMessage::SongPosition(8), MidiMessage::Continue, MidiMessage::Clock]);
+        assert_eq!(master.ticks(), 49);
+    }
+
+    #[test]
+    fn slave_follows_tempo_and_position()
+    {
+        let mut slave = ClockSlave::new();
+        let mut transport = Transport::new();
+        let start = Instant::now();
+        slave.message(MidiMessage::Start, start);
+        for i in 0..25
+        {
+            slave.message(MidiMessage::Clock, start + Duration::from_millis(20 * i));
+        }
+        assert!(slave.is_playing());
+        assert_eq!(slave.ticks(), 24);
+        assert!((slave.bpm().unwrap() - 125.0).abs() < 0.01);
+        assert_eq!(slave.position(), Some(480));
+
+        slave.sync(&mut transport);
+        assert!(transport.is_playing());
+        assert!(transport.position() >= 480 && transport.position() < 500);
+
+        slave.message(MidiMessage::Stop, start);
+        slave.sync(&mut transport);
+        assert!(!transport.is_playing());
+    }
+
+    #[test]
+    fn slave_ignores_a_single_late_tick()
+    {
+        let mut slave = ClockSlave::new().smoothing(1.0);
+        let start = Instant::now();
+        slave.message(MidiMessage::Clock, start);
+        slave.message(MidiMessage::Clock, start + Duration::from_millis(20));
+        slave.message(MidiMessage::Clock, start + Duration::from_millis(80));
+        assert!((slave.bpm().unwrap() - 125.0).abs() < 0.01);
+    }
+}
@@ -1 +1,4 @@
+#[allow(clippy::module_inception)]
 pub mod timing;
+pub mod transport;
+pub mod clock;
@@ -2,7 +2,12 @@
 ///relative timing of a beat: that being, for example, 16ths, 8ths,
 ///etc. This will also be used at a later point in conjunction with
 ///beat for using weird time signatures (3/4, etc)
+#[allow(non_snake_case)]
 pub mod BeatPrimitives {
+    use std::fmt::Display;
+    use std::fmt::Error;
+    use std::fmt::Formatter;
+
     ///Division: Simple divisior based on common note divisions.
     ///Note that this also includes the ability to multiply notes
     ///into spanning times (4 quarternotes = full note)
@@ -19,6 +24,10 @@ pub mod BeatPrimitives {
         TwoHundredFiftySixth(u32),
     }
 
+    ///MAX_COUNT: Largest count whose length in 256th notes fits a u32,
+    ///whatever the division.
+    pub const MAX_COUNT: u32 = u32::MAX / 256;
+
     ///division_from_str(s: &str, r: u32) -> Option<BeatPrimitives::Division>: Easy
     ///way to generate a duration of time from a string and a u32. s should be a
     ///number 1 / 2^n up to 256.
@@ -37,44 +46,92 @@ pub mod BeatPrimitives {
         }
     }
 
+    ///division_from_fraction(s: &str) -> Option<BeatPrimitives::Division>: Reads
+    ///a division written as Division's Display does, "3/8" being
+    ///Eighth(3) and "2" Whole(2), through division_from_str. Counts above
+    ///MAX_COUNT are refused so Division.length() can't overflow.
+    pub fn division_from_fraction(s: &str) -> Option<Division> {
+        let (count, denominator) = match s.find('/') {
+            Some(x) => (&s[..x], &s[x..]),
+            None => (s, ""),
+        };
+        let count = count.trim().parse::<u32>().ok().filter(|x| *x <= MAX_COUNT)?;
+        division_from_str(&format!("1{}", denominator.trim()), count)
+    }
+
+    impl Division
+    {
+        ///Division.length() -> u32: Length of the division counted in 256th
+        ///notes, the smallest division we have.
+        pub fn length(&self) -> u32
+        {
+            match *self
+            {
+                Division::Whole(n) => n * 256,
+                Division::Half(n) => n * 128,
+                Division::Quarter(n) => n * 64,
+                Division::Eighth(n) => n * 32,
+                Division::Sixteenth(n) => n * 16,
+                Division::ThirtySecond(n) => n * 8,
+                Division::SixtyFourth(n) => n * 4,
+                Division::OneHundredTwentyEighth(n) => n * 2,
+                Division::TwoHundredFiftySixth(n) => n,
+            }
+        }
+
+        ///Division.count() -> u32: How many of the division there are.
+        pub fn count(&self) -> u32
+        {
+            match *self
+            {
+                Division::Whole(n) |
+                Division::Half(n) |
+                Division::Quarter(n) |
+                Division::Eighth(n) |
+                Division::Sixteenth(n) |
+                Division::ThirtySecond(n) |
+                Division::SixtyFourth(n) |
+                Division::OneHundredTwentyEighth(n) |
+                Division::TwoHundredFiftySixth(n) => n,
+            }
+        }
+    }
 
-    ///To implement: to f64 value...
+    ///Division::fmt()...: Writes the division as a fraction of a whole
+    ///note, "3/8" for Eighth(3). Whole notes are written as just the count.
+    impl Display for Division
+    {
+        fn fmt(&self, f: &mut Formatter) -> Result<(), Error>
+        {
+            let denominator = match *self
+            {
+                Division::Whole(_) => 1,
+                Division::Half(_) => 2,
+                Division::Quarter(_) => 4,
+                Division::Eighth(_) => 8,
+                Division::Sixteenth(_) => 16,
+                Division::ThirtySecond(_) => 32,
+                Division::SixtyFourth(_) => 64,
+                Division::OneHundredTwentyEighth(_) => 128,
+                Division::TwoHundredFiftySixth(_) => 256,
+            };
+            match denominator
+            {
+                1 => write!(f, "{}", self.count()),
+                x => write!(f, "{}/{}", self.count(), x),
+            }
+        }
+    }
 }
 
 ///BeatGeneration: This module which derives from BeatPrimitive directly acts
 ///as a generation facility for beats that allows us to easily use a builder
 ///pattern for building actual beats.
+#[allow(non_snake_case)]
 pub mod BeatGeneration {
-    use sequencing::timing::timing::BeatPrimitives::Division;
-    use sequencing::timing::timing::BeatPrimitives::division_from_str;
     use sequencing::timing::timing::BeatCollections::IncompleteBeat;
     use sequencing::timing::timing::BeatCollections::BeatResult;
 
-    ///Setter macro that allows us to use a builder pattern to return a
-    ///object that has been modified from it's previous call with a
-    ///single attribute: think about JavaScript returning objects.
-    ///Takes $name and $typ, which is the name of the internal variable
-    ///and the type of the internal variable and generates said function.
-    macro_rules! setter {
-        ($( $name: ident, $typ: ty), *) => (
-            $(
-                pub fn $name(mut self, $name: $typ) -> NoteBuilder
-                {
-                    self.internal_note.$name = Some($name);
-                    self
-                }
-            )*
-        )
-    }
-
-    ///Clearer macro allows us to simply clear our BeatBuilder of
-    ///Cruft accumulated after building of an arbitrary note.
-    macro_rules! clearer {
-        ( $name: ident) => (
-            self.internal_note.$name = None;
-        );
-    }
-
     ///BeatBuilder: This is an object that allows us to use builder style
     ///construction of beat objects. Has multiple functions for maintaining
     ///and creating beats.
@@ -106,12 +163,25 @@ pub mod BeatGeneration {
             self.internal_beat.two_hundred_fifty_sixth = None;
         }
 
+        ///BeatBuilder.build() -> BeatResult: Like NoteBuilder.build, a
+        ///Complete beat once at least one division has been set.
         pub fn build(&mut self) -> BeatResult {
-            
+            match self.internal_beat.to_raw()
+            {
+                Some(beat) => BeatResult::Complete(beat),
+                None => BeatResult::Incomplete(self.internal_beat),
+            }
+        }
+    }
+
+    impl Default for BeatBuilder {
+        fn default() -> BeatBuilder {
+            BeatBuilder::new()
         }
     }
 }
 
+#[allow(non_snake_case)]
 pub mod BeatCollections {
     use sequencing::timing::timing::BeatPrimitives;
     use std::collections::LinkedList;
@@ -157,13 +227,20 @@ pub mod BeatCollections {
 
         ///BeatCollections::RawBeat::total_time(&self):
         ///Returns the total amount of time that all of the beat
-        ///primitives will add up to.
+        ///primitives will add up to, in 256th notes.
         pub fn total_time(&self) -> i32 {
             self.beat_stack.iter().fold(0, |x, y| {
+                x + y.length() as i32
             })
         }
     }
 
+    impl Default for RawBeat {
+        fn default() -> RawBeat {
+            RawBeat::new()
+        }
+    }
+
     #[derive(Debug, Copy, Clone)]
     pub struct IncompleteBeat {
         pub whole: Option<BeatPrimitives::Division>,
@@ -199,7 +276,18 @@ pub mod BeatCollections {
 
         }
 
+        ///IncompleteBeat.to_raw() -> Option<RawBeat>: Every division that
+        ///has been set, longest first. None if none are.
         pub fn to_raw(&self) -> Option<RawBeat> {
+            let mut ret = RawBeat::new();
+            for i in self.iterable_fields()
+            {
+                if let Some(division) = *i
+                {
+                    ret.push_beat(division);
+                }
+            }
+            if ret.beat_stack.is_empty() { None } else { Some(ret) }
         }
 
         //TODO: Implement add for BeatPrimitives::Division so we can check
@@ -207,76 +295,99 @@ pub mod BeatCollections {
         //does, then we can actually ADD what we are pushing into our primitive.
         pub fn push_division(&mut self, division: BeatPrimitives::Division) {
             match division {
-                BeatPrimitives::Division::Whole(num) => {
+                BeatPrimitives::Division::Whole(_) => {
                     self.whole = Some(division);
                 },
-                BeatPrimitives::Division::Half(num) => {
+                BeatPrimitives::Division::Half(_) => {
                     self.half = Some(division);
                 },
-                BeatPrimitives::Division::Quarter(num) => {
+                BeatPrimitives::Division::Quarter(_) => {
                     self.quarter = Some(division);
                 },
-                BeatPrimitives::Division::Eighth(num) => {
+                BeatPrimitives::Division::Eighth(_) => {
                     self.eighth = Some(division);
                 },
-                BeatPrimitives::Division::Sixteenth(num) => {
+                BeatPrimitives::Division::Sixteenth(_) => {
                     self.sixteenth = Some(division);
                 },
-                BeatPrimitives::Division::ThirtySecond(num) => {
+                BeatPrimitives::Division::ThirtySecond(_) => {
                     self.thirty_second = Some(division);
                 },
-                BeatPrimitives::Division::SixtyFourth(num) => {
+                BeatPrimitives::Division::SixtyFourth(_) => {
                     self.sixty_fourth = Some(division);
                 },
-                BeatPrimitives::Division::OneHundredTwentyEighth(num) => {
+                BeatPrimitives::Division::OneHundredTwentyEighth(_) => {
                     self.one_hundred_twenty_eighth = Some(division);
                 },
-                BeatPrimitives::Division::TwoHundredFiftySixth(num) => {
+                BeatPrimitives::Division::TwoHundredFiftySixth(_) => {
                     self.two_hundred_fifty_sixth = Some(division);
                 },
             }
         }
+    }
+
+    impl Default for IncompleteBeat {
+        fn default() -> IncompleteBeat {
+            IncompleteBeat::new()
+        }
+    }
 }
 
+///Beat: Tempo. BeatValue holds the length of one beat (a quarter note),
+///which is what sequencers count bars and divisions in.
+#[allow(non_snake_case)]
 pub mod Beat
 {
     use std::time::Duration;
 
-    #[derive(Copy, Clone, Hash, PartialEq, Eq)]
-    pub struct BeatPrimitive
+    #[derive(Copy, Clone, Hash, PartialEq, Eq, Debug)]
+    pub struct BeatValue
     {
-        duration_per_bar: Duration,
+        time_per_bar: Duration,
     }
 
     impl BeatValue
     {
         pub fn from_ms(time: Duration) -> BeatValue {
             BeatValue {
-            duration_per_bar: time;
+                time_per_bar: time,
             }
         }
 
         pub fn from_bpm(time: f32) -> BeatValue
         {
-            let to_ms = (60000.0 / time) as u64;
-            println!("MS: {:?}", to_ms);
+            let to_ns = (60_000_000_000.0 / time as f64) as u64;
             BeatValue
             {
-                time_per_bar: Timing::Time::from_ms(to_ms),
+                time_per_bar: Duration::from_nanos(to_ns),
             }
         }
 
+        ///BeatValue.bpm() -> f32: Inverse of from_bpm.
+        pub fn bpm(&self) -> f32
+        {
+            let secs = self.time_per_bar.as_secs() as f64 + self.time_per_bar.subsec_nanos() as f64 / 1e9;
+            if secs > 0.0 { (60.0 / secs) as f32 } else { 0.0 }
+        }
+
+        ///BeatValue.duration() -> Duration: Length of one beat.
+        pub fn duration(&self) -> Duration
+        {
+            self.time_per_bar
+        }
+
         pub fn u64_from_beats(&self, prec: u64, beats: u64) -> Option<u64>
         {
-            match self.time_per_bar.as_ms()
+            let x = self.time_per_bar.as_secs() * 1000 + self.time_per_bar.subsec_millis() as u64;
+            match prec
             {
-                Some(x) =>
+                0 =>
                 {
-                    Some((x / prec ) * beats)
+                    None
                 }
-                None =>
+                _ =>
                 {
-                    None
+                    Some((x / prec) * beats)
                 }
             }
         }
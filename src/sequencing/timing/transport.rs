@@ -0,0 +1,139 @@
+///Transport: The shared playback clock. Positions are in milliseconds
+///like the keys of PartialSequencer.note_hash, and are measured against
+///Instant so that events arriving from other threads can be placed on
+///the timeline using the time they were received at.
+#[allow(non_snake_case)]
+pub mod Transport
+{
+    use std::time::Duration;
+    use std::time::Instant;
+
+    fn duration_to_ms(duration: Duration) -> u64
+    {
+        duration.as_secs() * 1000 + duration.subsec_millis() as u64
+    }
+
+    #[derive(Clone, Copy, Debug, Eq, PartialEq)]
+    pub struct Transport
+    {
+        started: Option<Instant>,
+        offset: u64,
+    }
+
+    impl Transport
+    {
+        ///Transport::new() -> Transport: A stopped transport at position 0.
+        pub fn new() -> Transport
+        {
+            Transport{
+                started: None,
+                offset: 0,
+            }
+        }
+
+        ///Transport.start(): Starts playback from position 0.
+        pub fn start(&mut self)
+        {
+            self.offset = 0;
+            self.started = Some(Instant::now());
+        }
+
+        ///Transport.stop(): Stops playback, keeping the current position.
+        pub fn stop(&mut self)
+        {
+            self.offset = self.position();
+            self.started = None;
+        }
+
+        ///Transport.resume(): Continues playback from the current position.
+        pub fn resume(&mut self)
+        {
+            if self.started.is_none()
+            {
+                self.started = Some(Instant::now());
+            }
+        }
+
+        ///Transport.locate(ms: u64): Moves the position to ms without
+        ///changing whether the transport is playing.
+        pub fn locate(&mut self, ms: u64)
+        {
+            self.offset = ms;
+            if self.started.is_some()
+            {
+                self.started = Some(Instant::now());
+            }
+        }
+
+        ///Transport.is_playing() -> bool
+        pub fn is_playing(&self) -> bool
+        {
+            self.started.is_some()
+        }
+
+        ///Transport.position() -> u64: The current position in ms.
+        pub fn position(&self) -> u64
+        {
+            self.position_at(Instant::now())
+        }
+
+        ///Transport.position_at(time: Instant) -> u64: The position the
+        ///transport had (or will have) at time. Times before the transport
+        ///was started clamp to the start position.
+        pub fn position_at(&self, time: Instant) -> u64
+        {
+            match self.started
+            {
+                Some(started) if time > started =>
+                {
+                    self.offset + duration_to_ms(time - started)
+                },
+                _ =>
+                {
+                    self.offset
+                },
+            }
+        }
+    }
+
+    impl Default for Transport
+    {
+        fn default() -> Transport
+        {
+            Transport::new()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use sequencing::timing::transport::Transport::Transport;
+    use std::time::Duration;
+    use std::time::Instant;
+
+    #[test]
+    fn stopped_transport_stays_where_it_was_located()
+    {
+        let mut transport = Transport::new();
+        assert_eq!(transport.position(), 0);
+        transport.locate(250);
+        assert!(!transport.is_playing());
+        assert_eq!(transport.position_at(Instant::now() + Duration::from_millis(100)), 250);
+    }
+
+    #[test]
+    fn playing_transport_follows_the_clock()
+    {
+        let mut transport = Transport::new();
+        transport.locate(1000);
+        transport.resume();
+        let later = Instant::now() + Duration::from_millis(300);
+        let position = transport.position_at(later);
+        assert!((1300..1350).contains(&position), "{}", position);
+        assert_eq!(transport.position_at(Instant::now() - Duration::from_millis(300)), 1000);
+
+        transport.start();
+        assert!(transport.position() < 100);
+    }
+}
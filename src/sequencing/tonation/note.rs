@@ -5,6 +5,7 @@
 ///scales. This could be done by creating a STUCTURE instead that provides
 ///a function that returns a tuple of herz and maybe a string from an implemented
 ///scale object.
+#[allow(non_snake_case)]
 pub mod NotePrimitives
 {
     ///Semitone: Temporary note pitch object that is used to define the western
@@ -58,6 +59,10 @@ pub mod NotePrimitives
         C3,
         C4,
         C5,
+        C6,
+        C7,
+        C8,
+        C9,
     }
 
     ///register_from_str(s: &str) -> Option<Register>: Takes a string
@@ -73,6 +78,10 @@ pub mod NotePrimitives
             "C3" => {Some(Register::C3)},
             "C4" => {Some(Register::C4)},
             "C5" => {Some(Register::C5)},
+            "C6" => {Some(Register::C6)},
+            "C7" => {Some(Register::C7)},
+            "C8" => {Some(Register::C8)},
+            "C9" => {Some(Register::C9)},
             _ => {None},
         }
     }
@@ -104,22 +113,191 @@ pub mod NotePrimitives
             Register::C3 => {8.0},
             Register::C4 => {16.0},
             Register::C5 => {32.0},
+            Register::C6 => {64.0},
+            Register::C7 => {128.0},
+            Register::C8 => {256.0},
+            Register::C9 => {512.0},
         };
 
         base_pitch * base_register
     }
+
+    ///semitone_to_index(p: Semitone) -> u8: Position of the semitone inside
+    ///of an octave, C being 0 and B being 11.
+    pub fn semitone_to_index(p: Semitone) -> u8
+    {
+        match p
+        {
+            Semitone::C => {0},
+            Semitone::CSharp => {1},
+            Semitone::D => {2},
+            Semitone::DSharp => {3},
+            Semitone::E => {4},
+            Semitone::F => {5},
+            Semitone::FSharp => {6},
+            Semitone::G => {7},
+            Semitone::GSharp => {8},
+            Semitone::A => {9},
+            Semitone::ASharp => {10},
+            Semitone::B => {11},
+        }
+    }
+
+    ///semitone_from_index(i: u8) -> Semitone: Inverse of semitone_to_index,
+    ///wrapping around every octave.
+    pub fn semitone_from_index(i: u8) -> Semitone
+    {
+        match i % 12
+        {
+            0 => {Semitone::C},
+            1 => {Semitone::CSharp},
+            2 => {Semitone::D},
+            3 => {Semitone::DSharp},
+            4 => {Semitone::E},
+            5 => {Semitone::F},
+            6 => {Semitone::FSharp},
+            7 => {Semitone::G},
+            8 => {Semitone::GSharp},
+            9 => {Semitone::A},
+            10 => {Semitone::ASharp},
+            _ => {Semitone::B},
+        }
+    }
+
+    ///register_to_index(r: Register) -> u8: Octave number of a register.
+    pub fn register_to_index(r: Register) -> u8
+    {
+        match r
+        {
+            Register::C0 => {0},
+            Register::C1 => {1},
+            Register::C2 => {2},
+            Register::C3 => {3},
+            Register::C4 => {4},
+            Register::C5 => {5},
+            Register::C6 => {6},
+            Register::C7 => {7},
+            Register::C8 => {8},
+            Register::C9 => {9},
+        }
+    }
+
+    ///register_from_index(i: u8) -> Option<Register>: Inverse of
+    ///register_to_index.
+    pub fn register_from_index(i: u8) -> Option<Register>
+    {
+        match i
+        {
+            0 => {Some(Register::C0)},
+            1 => {Some(Register::C1)},
+            2 => {Some(Register::C2)},
+            3 => {Some(Register::C3)},
+            4 => {Some(Register::C4)},
+            5 => {Some(Register::C5)},
+            6 => {Some(Register::C6)},
+            7 => {Some(Register::C7)},
+            8 => {Some(Register::C8)},
+            9 => {Some(Register::C9)},
+            _ => {None},
+        }
+    }
+
+    ///primitives_to_midi(p: Semitone, r: Register) -> u8: Midi note number
+    ///of a pitch, where C4 is 60.
+    pub fn primitives_to_midi(p: Semitone, r: Register) -> u8
+    {
+        12 * (register_to_index(r) + 1) + semitone_to_index(p)
+    }
+
+    ///midi_to_primitives(n: u8) -> Option<(Semitone, Register)>: Inverse of
+    ///primitives_to_midi. None for notes outside of the registers we have.
+    pub fn midi_to_primitives(n: u8) -> Option<(Semitone, Register)>
+    {
+        if n < 12
+        {
+            return None;
+        }
+        register_from_index(n / 12 - 1).map(|r| (semitone_from_index(n), r))
+    }
+
+    ///semitone_name(p: Semitone) -> &'static str: Name of a semitone written
+    ///with a sharp, "C#" for CSharp.
+    pub fn semitone_name(p: Semitone) -> &'static str
+    {
+        ["C", "C#", "D", "D#", "E", "F", "F#", "G", "G#", "A", "A#", "B"][semitone_to_index(p) as usize]
+    }
+
+    ///name_to_index(s: &str) -> Option<i32>: Index of a note name with an
+    ///optional sharp (# or S, as semitone_from_str takes) or flat (b). Cb
+    ///gives -1 and B# 12, so the octave can be moved along.
+    fn name_to_index(s: &str) -> Option<i32>
+    {
+        let mut chars = s.chars();
+        let base = chars.next().and_then(|x| semitone_from_str(&x.to_string()))?;
+        let accidental = match chars.as_str()
+        {
+            "" => 0,
+            "#" | "S" => 1,
+            "b" => -1,
+            _ => return None,
+        };
+        Some(semitone_to_index(base) as i32 + accidental)
+    }
+
+    ///semitone_from_name(s: &str) -> Option<Semitone>: Reads a semitone
+    ///written as semitone_name does, with a flat (Db) or as semitone_from_str
+    ///takes it (CS).
+    pub fn semitone_from_name(s: &str) -> Option<Semitone>
+    {
+        name_to_index(s).map(|x| semitone_from_index((x + 12) as u8))
+    }
+
+    ///pitch_name(p: Semitone, r: Register) -> String: Name of a pitch with
+    ///its octave, "C#3" for CSharp in C3.
+    pub fn pitch_name(p: Semitone, r: Register) -> String
+    {
+        format!("{}{}", semitone_name(p), register_to_index(r))
+    }
+
+    ///pitch_from_str(s: &str) -> Option<(Semitone, Register)>: Inverse of
+    ///pitch_name, also taking the names semitone_from_name does. A flat or
+    ///sharp that crosses an octave moves the register, so Cb4 is B3.
+    pub fn pitch_from_str(s: &str) -> Option<(Semitone, Register)>
+    {
+        let split = s.find(|x: char| x.is_ascii_digit())?;
+        let octave = s[split..].parse::<i32>().ok()?;
+        let n = 12 * (octave + 1) + name_to_index(&s[..split])?;
+        if !(0..=255).contains(&n)
+        {
+            return None;
+        }
+        midi_to_primitives(n as u8)
+    }
+
+    ///midi_to_herz(n: f32) -> f32: Equal tempered frequency of a (possibly
+    ///fractional) midi note, A4 (69) being 440hz.
+    pub fn midi_to_herz(n: f32) -> f32
+    {
+        440.0 * 2f32.powf((n - 69.0) / 12.0)
+    }
+
+    ///herz_to_midi(hz: f32) -> f32: Inverse of midi_to_herz.
+    pub fn herz_to_midi(hz: f32) -> f32
+    {
+        69.0 + 12.0 * (hz / 440.0).log2()
+    }
 }
 
 ///NoteGeneration: This module which derives from Note directly acts
 ///as a generate facility for notes that allows us to easily create
 ///return note objects.
+#[allow(non_snake_case)]
 pub mod NoteGeneration
 {
     use sequencing::tonation::note::NotePrimitives::Semitone;
     use sequencing::tonation::note::NotePrimitives::Register;
     use sequencing::tonation::note::NotePrimitives;
     use sequencing::tonation::note::NoteCollections::IncompleteNote;
-    use sequencing::tonation::note::NoteCollections::RawNote;
     use sequencing::tonation::note::NoteCollections::NoteResult;
 
     ///Setter macro that allows us to use a builder pattern to return a
@@ -139,15 +317,6 @@ pub mod NoteGeneration
         )
     }
 
-    //Macro clearer: Simple clearer macro for single variables inside
-    //of our note builder so that we can automatically clear out our
-    //builder and make new things
-    macro_rules! clearer {
-        ( $name: ident) => (
-            self.internal_note.$name = None;
-        );
-    }
-
     ///NoteBuilder: This is an object that allows us to use builder style
     ///construction of note objects. Has multiple functions for maintaining
     ///and creating notes.
@@ -180,19 +349,6 @@ pub mod NoteGeneration
             self.internal_note.length = None;
         }
 
-        ///NoteBuilder.build_sequence_DEBUG(arg: &str) -> Vec<RawNote>: Builds a simple
-        ///sequence for testing.
-        pub fn build_sequence_DEBUG(&mut self, arg: &str) -> Vec<RawNote>
-        {
-            let mut ret_val = Vec::<RawNote>::new();
-            let x : Vec<&str> = arg.split(":").collect();
-            for i in x
-            {
-                ret_val.push(RawNote::new(NotePrimitives::semitone_from_str(i).unwrap(), NotePrimitives::Register::C3, 0.0, 15.0, self.internal_note.length.unwrap()));
-            }
-            ret_val
-        }
-
         ///NoteBuider.build() -> NoteResult: Attempts to build
         ///the note that is currently in our internal_note variable
         ///if it fails, it will return a IncompleteNote (actually a
@@ -201,15 +357,9 @@ pub mod NoteGeneration
         ///unwrapped options.
         pub fn build(&mut self) -> NoteResult
         {
-            match (self.internal_note.semitone, self.internal_note.register)
+            if let (Some(x), Some(y)) = (self.internal_note.semitone, self.internal_note.register)
             {
-                (Some(x), Some(y)) =>
-                {
-                    self.internal_note.pitch_hz = Some(NotePrimitives::primitives_to_herz(x, y))
-                }
-                _ =>
-                {
-                }
+                self.internal_note.pitch_hz = Some(NotePrimitives::primitives_to_herz(x, y))
             }
             match self.internal_note.to_raw()
             {
@@ -219,29 +369,38 @@ pub mod NoteGeneration
                 },
                 None =>
                 {
-                    NoteResult::Incomplete(self.internal_note.clone())
+                    NoteResult::Incomplete(self.internal_note)
                 }
             }
         }
 
-        ///NoteBuilder.semitone(semitone: Semitone): Generalized setter for semitone.
+        //NoteBuilder.semitone(semitone: Semitone): Generalized setter for semitone.
         setter!(semitone, Semitone);
 
-        ///NoteBuilder.register(register: Register): Generalized setter for offset.
+        //NoteBuilder.register(register: Register): Generalized setter for offset.
         setter!(register, Register);
 
-        ///NoteBuilder.offset(offset: f32): Generalized setter for register.
+        //NoteBuilder.offset(offset: f32): Generalized setter for register.
         setter!(offset, f32);
 
-        ///NoteBuilder.amplitude(amplitude: f32): Generalized setter for amplitude.
+        //NoteBuilder.amplitude(amplitude: f32): Generalized setter for amplitude.
         setter!(amplitude, f32);
 
-        ///NoteBuilder.length(length: u32): Generalized setter for length.
+        //NoteBuilder.length(length: u32): Generalized setter for length.
         setter!(length, u32);
     }
 
+    impl Default for NoteBuilder
+    {
+        fn default() -> NoteBuilder
+        {
+            NoteBuilder::new()
+        }
+    }
+
 }
 
+#[allow(non_snake_case)]
 pub mod NoteCollections{
     use sequencing::tonation::note::NotePrimitives::Semitone;
     use sequencing::tonation::note::NotePrimitives::Register;
@@ -252,6 +411,10 @@ pub mod NoteCollections{
     use std::fmt::Formatter;
     use std::fmt::Display;
 
+    ///Amplitude that is treated as full velocity by instruments, mirroring
+    ///the 0-127 range of midi velocity.
+    pub const MAX_AMPLITUDE: f32 = 127.0;
+
     ///NoteResult: Enum which handles whether a note is complete or not.
     pub enum NoteResult
     {
@@ -363,6 +526,14 @@ pub mod NoteCollections{
         }
     }
 
+    impl Default for IncompleteNote
+    {
+        fn default() -> IncompleteNote
+        {
+            IncompleteNote::new()
+        }
+    }
+
     ///Raw note is the type that is returned when our notebuilder has been passed
     ///good information: it contains raw data that resembles midi data that can
     ///at a later point actually be placing into a sequencer and converted into
@@ -396,6 +567,19 @@ pub mod NoteCollections{
                 length: l,
             }
         }
+
+        ///RawNote.key() -> u8: Midi note number of the semitone and register.
+        pub fn key(&self) -> u8
+        {
+            NotePrimitives::primitives_to_midi(self.semitone, self.register)
+        }
+
+        ///RawNote.velocity() -> u8: Amplitude clamped into the 0-127 midi
+        ///velocity range.
+        pub fn velocity(&self) -> u8
+        {
+            self.amplitude.clamp(0.0, MAX_AMPLITUDE).round() as u8
+        }
     }
 
     ///RawNote::fmt()...: Trait that allows us to print a formatted raw note.
@@ -403,11 +587,11 @@ pub mod NoteCollections{
     {
         fn fmt(&self, f: &mut Formatter) -> Result<(), Error>
         {
-            writeln!(f, "Pitch: {:?}", self.semitone);
-            writeln!(f, "Pitch as herz: {}", self.pitch_hz);
-            writeln!(f, "Register: {:?}", self.register);
-            writeln!(f, "Offset: {}", self.offset);
-            writeln!(f, "Amplitude: {}", self.amplitude);
+            writeln!(f, "Pitch: {:?}", self.semitone)?;
+            writeln!(f, "Pitch as herz: {}", self.pitch_hz)?;
+            writeln!(f, "Register: {:?}", self.register)?;
+            writeln!(f, "Offset: {}", self.offset)?;
+            writeln!(f, "Amplitude: {}", self.amplitude)?;
             writeln!(f, "Length: {}", self.length)
         }
     }